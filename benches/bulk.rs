@@ -0,0 +1,55 @@
+//! Benchmarks for adding many entries to a group, as done when importing from other password
+//! managers, comparing the bulk API with adding the entries one by one.
+
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use keepass::db::{Entry, Group, InternedStr, Value};
+
+fn fields(count: usize) -> Vec<HashMap<InternedStr, Value>> {
+    (0..count)
+        .map(|i| {
+            let mut fields = HashMap::new();
+            fields.insert("Title".into(), Value::Unprotected(format!("Entry {}", i)));
+            fields.insert(
+                "Password".into(),
+                Value::Protected(format!("password {}", i).as_bytes().into()),
+            );
+            fields
+        })
+        .collect()
+}
+
+fn bench_add_entries(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_entries");
+
+    for count in [1_000, 10_000] {
+        let input = fields(count);
+
+        group.bench_with_input(BenchmarkId::new("one_by_one", count), &input, |b, input| {
+            b.iter(|| {
+                let mut group = Group::new("Imported");
+                for fields in input.iter().cloned() {
+                    let mut entry = Entry::new();
+                    entry.fields = fields;
+                    group.add_child(entry);
+                }
+                group
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("bulk", count), &input, |b, input| {
+            b.iter(|| {
+                let mut group = Group::new("Imported");
+                group.add_entries_bulk(input.iter().cloned());
+                group
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_add_entries);
+criterion_main!(benches);
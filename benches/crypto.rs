@@ -0,0 +1,97 @@
+//! Benchmarks for opening and saving databases with the different outer ciphers, to catch
+//! performance regressions in the cryptography code.
+//!
+//! The key derivation is set to a minimum so that the measurements are dominated by the ciphers.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use keepass::{
+    config::{DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    db::{Entry, Value},
+    Database, DatabaseKey,
+};
+
+fn database(outer_cipher_config: OuterCipherConfig) -> Database {
+    let mut db = Database::new(DatabaseConfig {
+        outer_cipher_config,
+        inner_cipher_config: InnerCipherConfig::ChaCha20,
+        kdf_config: KdfConfig::Aes { rounds: 1 },
+        ..Default::default()
+    });
+
+    for i in 0..1000 {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".into(), Value::Unprotected(format!("Entry {}", i)));
+        entry.fields.insert(
+            "Password".into(),
+            Value::Protected(format!("password {}", i).as_bytes().into()),
+        );
+        db.root.add_child(entry);
+    }
+
+    db
+}
+
+fn outer_ciphers() -> Vec<(&'static str, OuterCipherConfig)> {
+    vec![
+        ("AES256", OuterCipherConfig::AES256),
+        ("Twofish", OuterCipherConfig::Twofish),
+        ("ChaCha20", OuterCipherConfig::ChaCha20),
+    ]
+}
+
+fn bench_save(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save");
+
+    for (name, cipher) in outer_ciphers() {
+        let db = database(cipher);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &db, |b, db| {
+            b.iter(|| {
+                let mut buffer = Vec::new();
+                db.save(&mut buffer, DatabaseKey::new().with_password("bench"))
+                    .unwrap();
+                buffer
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_open(c: &mut Criterion) {
+    let mut group = c.benchmark_group("open");
+
+    for (name, cipher) in outer_ciphers() {
+        let mut buffer = Vec::new();
+        database(cipher)
+            .save(&mut buffer, DatabaseKey::new().with_password("bench"))
+            .unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &buffer, |b, buffer| {
+            b.iter(|| {
+                Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("bench")).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_aes_kdf(c: &mut Criterion) {
+    let mut db = database(OuterCipherConfig::AES256);
+    db.root.children.clear();
+    db.config.kdf_config = KdfConfig::Aes { rounds: 100_000 };
+
+    let mut buffer = Vec::new();
+    db.save(&mut buffer, DatabaseKey::new().with_password("bench"))
+        .unwrap();
+
+    c.bench_function("aes_kdf_100000_rounds", |b| {
+        b.iter(|| Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("bench")).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_save, bench_open, bench_aes_kdf);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+//! Benchmarks for merging two versions of a large database, where every node of the source
+//! database has to be located in the destination database.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use keepass::{
+    db::{Entry, Group, Node, Times, Value},
+    Database,
+};
+
+const ENTRIES: usize = 50_000;
+const GROUPS: usize = 100;
+
+fn database() -> Database {
+    let mut db = Database::new(Default::default());
+    for g in 0..GROUPS {
+        let mut group = Group::new(&format!("Group {}", g));
+        for i in 0..ENTRIES / GROUPS {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".into(), Value::Unprotected(format!("Entry {}/{}", g, i)));
+            group.add_child(entry);
+        }
+        db.root.add_child(group);
+    }
+    db
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let destination = database();
+
+    // the source database has a modified copy of every 100th entry
+    let mut source = destination.clone();
+    for node in source.root.children.iter_mut() {
+        if let Node::Group(group) = node {
+            for node in group.children.iter_mut().step_by(100) {
+                if let Node::Entry(entry) = node {
+                    entry
+                        .fields
+                        .insert("UserName".into(), Value::Unprotected("changed".to_string()));
+                    entry
+                        .times
+                        .set_last_modification(Times::now() + chrono::Duration::seconds(1));
+                }
+            }
+        }
+    }
+
+    c.bench_function("merge_50k_entries", |b| {
+        b.iter_batched(
+            || destination.clone(),
+            |mut db| db.merge(&source).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_merge
+}
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+#![no_main]
+
+use keepass::{Database, DatabaseKey};
+use libfuzzer_sys::fuzz_target;
+
+/// KDBX identifier, followed by the KeePass 1 signature
+const KDB_PREFIX: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x65, 0xfb, 0x4b, 0xb5];
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = KDB_PREFIX.to_vec();
+    buf.extend_from_slice(data);
+
+    let _ = Database::parse(&buf, DatabaseKey::new().with_password("fuzz"));
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use keepass::{Database, DatabaseKey};
+use libfuzzer_sys::fuzz_target;
+
+/// KDBX identifier, followed by the KeePass 2 signature and version 4.0
+const KDBX4_PREFIX: [u8; 12] = [
+    0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5, 0x00, 0x00, 0x04, 0x00,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = KDBX4_PREFIX.to_vec();
+    buf.extend_from_slice(data);
+
+    let _ = Database::parse(&buf, DatabaseKey::new().with_password("fuzz"));
+});
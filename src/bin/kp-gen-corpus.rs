@@ -0,0 +1,323 @@
+/// utility to generate a corpus of valid and malformed KeePass databases, e.g. for fuzzing
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use keepass::{
+    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    db::{Entry, Group, Value},
+    Database, DatabaseKey,
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Directory to write the corpus to
+    out_dir: String,
+
+    /// Password of the generated databases
+    #[arg(short = 'p', long, default_value = "demopass")]
+    password: String,
+
+    /// Additional databases to derive malformed files from, e.g. KDBX 3.1 databases, which
+    /// cannot be generated
+    #[arg(short = 's', long)]
+    seed: Vec<String>,
+
+    /// Only generate the valid databases
+    #[arg(long)]
+    valid_only: bool,
+
+    /// Leave out the signatures and version numbers at the start of the files, as expected by
+    /// the `kdbx4` fuzz target, which adds them itself
+    #[arg(long)]
+    strip_fixed_header: bool,
+}
+
+/// Size of the signatures and version numbers at the start of KDBX files
+const FIXED_HEADER_SIZE: usize = 12;
+
+/// Size of the SHA-256 hash and HMAC that follow the outer header of KDBX 4 files
+const HEADER_CHECKS_SIZE: usize = 64;
+
+/// A field of the outer header of a KDBX file
+struct HeaderField {
+    field_type: u8,
+
+    /// Offset of the length of the field
+    length_offset: usize,
+
+    /// Size of the length of the field, 2 bytes for KDBX 3 and 4 bytes for KDBX 4
+    length_size: usize,
+
+    /// Offset of the end of the field
+    end: usize,
+}
+
+/// Locate the fields of the outer header of a KDBX 3 or KDBX 4 file
+fn header_fields(data: &[u8]) -> Option<Vec<HeaderField>> {
+    let major_version = u16::from_le_bytes([*data.get(10)?, *data.get(11)?]);
+    let length_size = match major_version {
+        3 => 2,
+        4 => 4,
+        _ => return None,
+    };
+
+    let mut fields = Vec::new();
+    let mut pos = FIXED_HEADER_SIZE;
+    loop {
+        let field_type = *data.get(pos)?;
+        let length_offset = pos + 1;
+        let length = data.get(length_offset..length_offset + length_size)?;
+        let length = length
+            .iter()
+            .rev()
+            .fold(0usize, |acc, byte| (acc << 8) | *byte as usize);
+        let end = length_offset + length_size + length;
+        if end > data.len() {
+            return None;
+        }
+
+        fields.push(HeaderField {
+            field_type,
+            length_offset,
+            length_size,
+            end,
+        });
+        pos = end;
+
+        if field_type == 0 {
+            return Some(fields);
+        }
+    }
+}
+
+fn flip_byte(data: &[u8], offset: usize) -> Vec<u8> {
+    let mut data = data.to_vec();
+    data[offset] ^= 0xff;
+    data
+}
+
+/// Derive malformed files from a valid KDBX 3 or KDBX 4 file
+fn mutations(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut mutations = vec![
+        ("bad-signature1".to_string(), flip_byte(data, 0)),
+        ("bad-signature2".to_string(), flip_byte(data, 4)),
+    ];
+
+    let mut version = data.to_vec();
+    version[10..12].copy_from_slice(&99u16.to_le_bytes());
+    mutations.push(("bad-major-version".to_string(), version));
+
+    let fields = match header_fields(data) {
+        Some(fields) => fields,
+        None => return mutations,
+    };
+    let header_end = fields.last().map_or(FIXED_HEADER_SIZE, |f| f.end);
+
+    for (index, field) in fields.iter().enumerate() {
+        let name = format!("field{}-type{}", index, field.field_type);
+        let start = field.length_offset - 1;
+
+        let mut removed = data[..start].to_vec();
+        removed.extend_from_slice(&data[field.end..]);
+        mutations.push((format!("{}-removed", name), removed));
+
+        let mut oversized = data.to_vec();
+        for byte in &mut oversized[field.length_offset..field.length_offset + field.length_size] {
+            *byte = 0xff;
+        }
+        mutations.push((format!("{}-oversized-length", name), oversized));
+
+        let content_start = field.length_offset + field.length_size;
+        if field.end > content_start {
+            let mut empty = data[..field.length_offset].to_vec();
+            empty.resize(content_start, 0);
+            empty.extend_from_slice(&data[field.end..]);
+            mutations.push((format!("{}-empty", name), empty));
+
+            // flip the first byte rather than the last one, so that little-endian numbers such as
+            // the KDF rounds only change slightly instead of making the key derivation run forever
+            mutations.push((format!("{}-flipped", name), flip_byte(data, content_start)));
+        }
+    }
+
+    let is_kdbx4 = fields.first().is_some_and(|f| f.length_size == 4);
+    if is_kdbx4 && data.len() > header_end + HEADER_CHECKS_SIZE + 36 {
+        let blocks = header_end + HEADER_CHECKS_SIZE;
+        mutations.push(("header-hash".to_string(), flip_byte(data, header_end)));
+        mutations.push(("header-hmac".to_string(), flip_byte(data, header_end + 32)));
+        mutations.push(("block-hmac".to_string(), flip_byte(data, blocks)));
+        mutations.push(("block-data".to_string(), flip_byte(data, blocks + 36)));
+
+        let mut length = data.to_vec();
+        length[blocks + 32..blocks + 36].copy_from_slice(&u32::MAX.to_le_bytes());
+        mutations.push(("block-oversized-length".to_string(), length));
+
+        // the empty block that ends the block stream consists of its HMAC and its length
+        mutations.push((
+            "missing-final-block".to_string(),
+            data[..data.len() - 36].to_vec(),
+        ));
+        mutations.push(("truncated-first-block".to_string(), data[..blocks + 40].to_vec()));
+    } else {
+        mutations.push(("payload".to_string(), flip_byte(data, data.len() - 1)));
+    }
+
+    for (name, len) in [
+        ("truncated-fixed-header", FIXED_HEADER_SIZE - 1),
+        ("truncated-header", header_end - 1),
+        ("truncated-after-header", header_end),
+        ("truncated-half", data.len() / 2),
+        ("truncated-last-byte", data.len() - 1),
+    ]
+    .iter()
+    {
+        mutations.push((name.to_string(), data[..*len].to_vec()));
+    }
+
+    mutations
+}
+
+fn sample_database(config: DatabaseConfig) -> Database {
+    let mut db = Database::new(config);
+    db.meta.database_name = Some("Corpus".to_string());
+
+    let mut entry = Entry::new();
+    entry.set_title("Sample Entry");
+    entry.set_username("user");
+    entry.set_password("secret");
+    entry.set_url("https://example.com");
+    entry.fields.insert(
+        "Protected".into(),
+        Value::Protected("protected value".as_bytes().into()),
+    );
+    db.root.add_child(entry);
+
+    let mut group = Group::new("Group");
+    let mut entry = Entry::new();
+    entry.set_title("Entry in Group");
+    entry
+        .fields
+        .insert("Notes".into(), Value::Unprotected("x".repeat(5000)));
+    group.add_child(entry);
+    db.root.add_child(group);
+
+    db
+}
+
+/// Configurations of the generated databases, with a KDF that is cheap to evaluate
+fn configs() -> Vec<(String, DatabaseConfig)> {
+    let kdfs = vec![
+        ("aeskdf", KdfConfig::Aes { rounds: 100 }),
+        (
+            "argon2d",
+            KdfConfig::Argon2 {
+                iterations: 1,
+                memory: 64 * 1024,
+                parallelism: 1,
+                version: argon2::Version::Version13,
+                secret: None,
+                associated_data: None,
+            },
+        ),
+        (
+            "argon2id",
+            KdfConfig::Argon2id {
+                iterations: 1,
+                memory: 64 * 1024,
+                parallelism: 1,
+                version: argon2::Version::Version13,
+                secret: None,
+                associated_data: None,
+            },
+        ),
+    ];
+    let ciphers = [
+        ("aes", OuterCipherConfig::AES256),
+        ("twofish", OuterCipherConfig::Twofish),
+        ("chacha20", OuterCipherConfig::ChaCha20),
+    ];
+    let compressions = [
+        ("gzip", CompressionConfig::GZip),
+        ("uncompressed", CompressionConfig::None),
+    ];
+
+    let mut configs = Vec::new();
+    for (kdf_name, kdf) in &kdfs {
+        for (cipher_name, cipher) in ciphers.iter() {
+            for (compression_name, compression) in compressions.iter() {
+                configs.push((
+                    format!("kdbx4-{}-{}-{}", kdf_name, cipher_name, compression_name),
+                    DatabaseConfig {
+                        outer_cipher_config: cipher.clone(),
+                        compression_config: compression.clone(),
+                        inner_cipher_config: InnerCipherConfig::ChaCha20,
+                        kdf_config: kdf.clone(),
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+    }
+    configs
+}
+
+fn write(args: &Args, name: &str, data: &[u8]) -> Result<()> {
+    let path: PathBuf = Path::new(&args.out_dir).join(format!("{}.kdbx", name));
+    if args.strip_fixed_header {
+        fs::write(path, data.get(FIXED_HEADER_SIZE..).unwrap_or_default())?;
+    } else {
+        fs::write(path, data)?;
+    }
+    Ok(())
+}
+
+pub fn main() -> Result<()> {
+    let args = Args::parse();
+    let out_dir = Path::new(&args.out_dir);
+    fs::create_dir_all(out_dir)?;
+
+    let key = DatabaseKey::new().with_password(&args.password);
+    let mut sources = Vec::new();
+
+    for (name, config) in configs() {
+        let mut data = Vec::new();
+        sample_database(config).save(&mut data, key.clone())?;
+
+        // make sure that the generated database can be opened again
+        if let Err(e) = Database::open(&mut data.as_slice(), key.clone()) {
+            bail!("Generated database {} cannot be opened: {}", name, e);
+        }
+
+        write(&args, &format!("valid-{}", name), &data)?;
+        sources.push((name, data));
+    }
+
+    for seed in &args.seed {
+        let path = Path::new(seed);
+        let name = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => bail!("Invalid seed file name: {}", seed),
+        };
+        sources.push((name, fs::read(path)?));
+    }
+
+    let mut count = sources.len() - args.seed.len();
+    if !args.valid_only {
+        for (name, data) in &sources {
+            for (mutation, mutated) in mutations(data) {
+                write(&args, &format!("malformed-{}-{}", name, mutation), &mutated)?;
+                count += 1;
+            }
+        }
+    }
+
+    println!("Wrote {} files to {}", count, out_dir.display());
+
+    Ok(())
+}
@@ -51,9 +51,9 @@ pub fn main() -> Result<()> {
 
     let db = Database::open(&mut source, key.clone())?;
 
-    let yubikey = ChallengeResponseKey::get_yubikey(args.serial_number)?;
+    let challenge_response_key = ChallengeResponseKey::for_yubikey_slot(args.serial_number, &args.slot)?;
 
-    let new_key = key.with_challenge_response_key(ChallengeResponseKey::YubikeyChallenge(yubikey, args.slot));
+    let new_key = key.with_challenge_response_key(challenge_response_key);
 
     let mut out_file = File::create(args.out_kdbx)?;
 
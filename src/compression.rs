@@ -3,14 +3,17 @@ use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 #[cfg(feature = "save_kdbx4")]
 use flate2::Compression as Flate2Compression;
-use std::io::Read;
 #[cfg(feature = "save_kdbx4")]
 use std::io::Write;
+use std::io::{Cursor, Read};
 
 pub trait Compression {
     #[cfg(feature = "save_kdbx4")]
     fn compress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error>;
     fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error>;
+
+    /// Get a reader that decompresses `in_buffer` while it is read
+    fn decompress_reader(&self, in_buffer: Vec<u8>) -> Box<dyn Read>;
 }
 
 pub struct NoCompression;
@@ -23,6 +26,9 @@ impl Compression for NoCompression {
     fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error> {
         Ok(in_buffer.to_vec())
     }
+    fn decompress_reader(&self, in_buffer: Vec<u8>) -> Box<dyn Read> {
+        Box::new(Cursor::new(in_buffer))
+    }
 }
 
 pub struct GZipCompression;
@@ -43,4 +49,76 @@ impl Compression for GZipCompression {
         decoder.read_to_end(&mut res)?;
         Ok(res)
     }
+    fn decompress_reader(&self, in_buffer: Vec<u8>) -> Box<dyn Read> {
+        Box::new(GzDecoder::new(Cursor::new(in_buffer)))
+    }
+}
+
+/// A reader that fails once more than a given number of bytes have been read from it, to guard
+/// against payloads that decompress to huge sizes
+pub(crate) struct LimitedReader<R> {
+    inner: R,
+    limit: Option<usize>,
+    read: usize,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub(crate) fn new(inner: R, limit: Option<usize>) -> Self {
+        LimitedReader {
+            inner,
+            limit,
+            read: 0,
+        }
+    }
+
+    /// Get the limit if it has been exceeded
+    pub(crate) fn exceeded(&self) -> Option<usize> {
+        self.limit.filter(|limit| self.read > *limit)
+    }
+
+    /// Number of bytes read so far
+    pub(crate) fn position(&self) -> usize {
+        self.read
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n;
+
+        match self.exceeded() {
+            Some(limit) => Err(std::io::Error::other(format!(
+                "Decompressed payload exceeds {} bytes",
+                limit
+            ))),
+            None => Ok(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use std::io::Read;
+
+    use super::LimitedReader;
+
+    #[test]
+    fn limited_reader() {
+        let data = [0u8; 100];
+
+        let mut out = Vec::new();
+        let mut reader = LimitedReader::new(&data[..], Some(100));
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out.len(), 100);
+        assert_eq!(reader.exceeded(), None);
+
+        let mut reader = LimitedReader::new(&data[..], Some(99));
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(reader.exceeded(), Some(99));
+
+        let mut reader = LimitedReader::new(&data[..], None);
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(reader.position(), 100);
+    }
 }
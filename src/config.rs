@@ -15,7 +15,7 @@ use crate::{
     },
     error::{
         CompressionConfigError, CryptographyError, InnerCipherConfigError, KdfConfigError,
-        OuterCipherConfigError,
+        OuterCipherConfigError, VariantDictionaryError,
     },
     format::KDBX4_CURRENT_MINOR_VERSION,
     variant_dictionary::VariantDictionary,
@@ -49,6 +49,18 @@ pub struct DatabaseConfig {
 
     /// Settings for the Key Derivation Function (KDF)
     pub kdf_config: KdfConfig,
+
+    /// Unencrypted custom data stored by applications in the outer header (KDBX4 only)
+    pub public_custom_data: PublicCustomData,
+
+    /// How long to keep the records of deleted objects before they are pruned automatically when
+    /// merging, see [`Database::prune_deleted_objects`](crate::Database::prune_deleted_objects).
+    /// The records are kept forever if this is `None`, which is what KeePass does.
+    ///
+    /// This is not stored in the database file, so it has to be set every time a database is
+    /// opened.
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub deleted_objects_retention: Option<chrono::Duration>,
 }
 
 /// Sensible default configuration for new databases
@@ -64,7 +76,49 @@ impl Default for DatabaseConfig {
                 memory: 1024 * 1024,
                 parallelism: 4,
                 version: argon2::Version::Version13,
+                secret: None,
+                associated_data: None,
             },
+            public_custom_data: PublicCustomData::default(),
+            deleted_objects_retention: None,
+        }
+    }
+}
+
+/// Default limit for the size of the decompressed payload of a database, 512 MiB
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 512 * 1024 * 1024;
+
+/// Options for opening a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenOptions {
+    /// Maximum size in bytes of the payload after decompression, or `None` for no limit. Opening
+    /// a database with a larger payload fails with
+    /// [`DatabaseOpenError::PayloadTooLarge`](crate::error::DatabaseOpenError::PayloadTooLarge).
+    pub max_decompressed_size: Option<usize>,
+
+    /// Use the top-level "Backup" group of KeePass 1.x databases, which holds deleted entries,
+    /// as the recycle bin of the opened database
+    pub kdb_backup_as_recycle_bin: bool,
+
+    /// Give new UUIDs to nodes whose UUID is already used by another node, see
+    /// [`Database::repair_duplicate_uuids`](crate::Database::repair_duplicate_uuids). Without
+    /// this, duplicates are reported by
+    /// [`Database::verify_integrity`](crate::Database::verify_integrity).
+    pub repair_duplicate_uuids: bool,
+
+    /// Keep protected values encrypted until they are accessed, see
+    /// [`EncryptedValue`](crate::db::EncryptedValue). This only affects KDBX3 and KDBX4
+    /// databases.
+    pub lazy_protected_values: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            max_decompressed_size: Some(DEFAULT_MAX_DECOMPRESSED_SIZE),
+            kdb_backup_as_recycle_bin: false,
+            repair_duplicate_uuids: false,
+            lazy_protected_values: false,
         }
     }
 }
@@ -183,12 +237,14 @@ const KDF_SALT: &str = "S";
 const KDF_ITERATIONS: &str = "I";
 const KDF_PARALLELISM: &str = "P";
 const KDF_VERSION: &str = "V";
+const KDF_SECRET: &str = "K";
+const KDF_ASSOCIATED_DATA: &str = "A";
 // KDF fields used by AES.
 const KDF_SEED: &str = "S";
 const KDF_ROUNDS: &str = "R";
 
 /// Choices for Key Derivation Functions (KDFs)
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub enum KdfConfig {
     /// Derive keys with repeated AES encryption
@@ -201,6 +257,13 @@ pub enum KdfConfig {
 
         #[cfg_attr(feature = "serialization", serde(serialize_with = "serialize_argon2_version"))]
         version: argon2::Version,
+
+        /// Secret key (`K`) that is mixed into the key derivation, as used by some hardened forks
+        #[cfg_attr(feature = "serialization", serde(skip_serializing))]
+        secret: Option<Vec<u8>>,
+
+        /// Associated data (`A`) that is mixed into the key derivation
+        associated_data: Option<Vec<u8>>,
     },
     /// Derive keys with Argon2id
     Argon2id {
@@ -210,6 +273,13 @@ pub enum KdfConfig {
 
         #[cfg_attr(feature = "serialization", serde(serialize_with = "serialize_argon2_version"))]
         version: argon2::Version,
+
+        /// Secret key (`K`) that is mixed into the key derivation, as used by some hardened forks
+        #[cfg_attr(feature = "serialization", serde(skip_serializing))]
+        secret: Option<Vec<u8>>,
+
+        /// Associated data (`A`) that is mixed into the key derivation
+        associated_data: Option<Vec<u8>>,
     },
 }
 
@@ -221,7 +291,264 @@ fn serialize_argon2_version<S: serde::Serializer>(
     serializer.serialize_u32(version.as_u32())
 }
 
+impl std::fmt::Debug for KdfConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, iterations, memory, parallelism, version, secret, associated_data) = match self {
+            KdfConfig::Aes { rounds } => return f.debug_struct("Aes").field("rounds", rounds).finish(),
+            KdfConfig::Argon2 {
+                iterations,
+                memory,
+                parallelism,
+                version,
+                secret,
+                associated_data,
+            } => (
+                "Argon2",
+                iterations,
+                memory,
+                parallelism,
+                version,
+                secret,
+                associated_data,
+            ),
+            KdfConfig::Argon2id {
+                iterations,
+                memory,
+                parallelism,
+                version,
+                secret,
+                associated_data,
+            } => (
+                "Argon2id",
+                iterations,
+                memory,
+                parallelism,
+                version,
+                secret,
+                associated_data,
+            ),
+        };
+
+        f.debug_struct(name)
+            .field("iterations", iterations)
+            .field("memory", memory)
+            .field("parallelism", parallelism)
+            .field("version", version)
+            .field("secret", &secret.as_ref().map(crate::redact::secret))
+            .field("associated_data", associated_data)
+            .finish()
+    }
+}
+
+/// Minimum number of AES-KDF rounds that is not considered weak
+pub const MIN_AES_KDF_ROUNDS: u64 = 100_000;
+
+/// Minimum Argon2 memory in bytes that is not considered weak, as recommended by OWASP
+pub const MIN_ARGON2_MEMORY: u64 = 19 * 1024 * 1024;
+
+/// Minimum number of Argon2 iterations that is not considered weak, as recommended by OWASP
+pub const MIN_ARGON2_ITERATIONS: u64 = 2;
+
+/// A weakness of the key derivation settings of a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum KdfAdvisory {
+    /// AES-KDF is not memory-hard, which makes brute-forcing the key on GPUs or ASICs cheap
+    NotMemoryHard,
+
+    /// AES-KDF is used with fewer than [`MIN_AES_KDF_ROUNDS`] rounds
+    TooFewRounds { rounds: u64 },
+
+    /// Argon2 is used with less than [`MIN_ARGON2_MEMORY`] bytes of memory
+    TooLittleMemory { memory: u64 },
+
+    /// Argon2 is used with fewer than [`MIN_ARGON2_ITERATIONS`] iterations
+    TooFewIterations { iterations: u64 },
+}
+
+impl std::fmt::Display for KdfAdvisory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdfAdvisory::NotMemoryHard => write!(f, "AES-KDF is not memory-hard, consider switching to Argon2"),
+            KdfAdvisory::TooFewRounds { rounds } => write!(
+                f,
+                "AES-KDF uses only {} rounds, at least {} are recommended",
+                rounds, MIN_AES_KDF_ROUNDS
+            ),
+            KdfAdvisory::TooLittleMemory { memory } => write!(
+                f,
+                "Argon2 uses only {} bytes of memory, at least {} are recommended",
+                memory, MIN_ARGON2_MEMORY
+            ),
+            KdfAdvisory::TooFewIterations { iterations } => write!(
+                f,
+                "Argon2 uses only {} iterations, at least {} are recommended",
+                iterations, MIN_ARGON2_ITERATIONS
+            ),
+        }
+    }
+}
+
+/// How urgently a finding of [`DatabaseConfig::security_assessment`] should be addressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum Severity {
+    /// The setting is outdated, but does not weaken the protection of the database
+    Info,
+
+    /// The setting makes attacks on the database easier than necessary
+    Warning,
+
+    /// The setting leaves the database poorly protected
+    Critical,
+}
+
+/// An outdated or weak setting found by [`DatabaseConfig::security_assessment`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum SecurityFinding {
+    /// The key derivation settings are weak
+    Kdf(KdfAdvisory),
+
+    /// Protected fields are encrypted with Salsa20, which KDBX 4 replaced by ChaCha20
+    Salsa20InnerCipher,
+
+    /// Protected fields are not encrypted within the database
+    PlainInnerCipher,
+
+    /// The database uses a file format older than KDBX 4, e.g. without integrity protection of
+    /// the header
+    OutdatedFormat(DatabaseVersion),
+
+    /// The payload is not compressed, which makes the file larger than necessary
+    NoCompression,
+}
+
+impl SecurityFinding {
+    /// How urgently the finding should be addressed
+    pub fn severity(&self) -> Severity {
+        match self {
+            SecurityFinding::Kdf(KdfAdvisory::TooFewRounds { .. }) => Severity::Critical,
+            SecurityFinding::Kdf(_) => Severity::Warning,
+            SecurityFinding::Salsa20InnerCipher => Severity::Info,
+            SecurityFinding::PlainInnerCipher => Severity::Warning,
+            SecurityFinding::OutdatedFormat(DatabaseVersion::KDB3(_)) => Severity::Warning,
+            SecurityFinding::OutdatedFormat(_) => Severity::Critical,
+            SecurityFinding::NoCompression => Severity::Info,
+        }
+    }
+
+    /// A suggestion how to address the finding
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            SecurityFinding::Kdf(KdfAdvisory::NotMemoryHard) => {
+                "Switch the key derivation to Argon2id, e.g. with KdfConfig::recommended()"
+            }
+            SecurityFinding::Kdf(KdfAdvisory::TooFewRounds { .. }) => {
+                "Increase the number of AES-KDF rounds or switch to Argon2id"
+            }
+            SecurityFinding::Kdf(KdfAdvisory::TooLittleMemory { .. }) => "Increase the memory used by Argon2",
+            SecurityFinding::Kdf(KdfAdvisory::TooFewIterations { .. }) => {
+                "Increase the number of Argon2 iterations"
+            }
+            SecurityFinding::Salsa20InnerCipher | SecurityFinding::PlainInnerCipher => {
+                "Encrypt protected fields with ChaCha20"
+            }
+            SecurityFinding::OutdatedFormat(_) => "Save the database in the KDBX 4 format",
+            SecurityFinding::NoCompression => "Enable GZip compression",
+        }
+    }
+}
+
+impl std::fmt::Display for SecurityFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecurityFinding::Kdf(advisory) => write!(f, "{}", advisory),
+            SecurityFinding::Salsa20InnerCipher => write!(f, "Protected fields are encrypted with Salsa20"),
+            SecurityFinding::PlainInnerCipher => write!(f, "Protected fields are not encrypted"),
+            SecurityFinding::OutdatedFormat(version) => {
+                write!(f, "The database uses the outdated {} format", version.to_string())
+            }
+            SecurityFinding::NoCompression => write!(f, "The database is not compressed"),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Check the settings for weak or outdated choices, e.g. to suggest upgrading the database
+    /// to the user. Findings are sorted from the most to the least severe.
+    pub fn security_assessment(&self) -> Vec<SecurityFinding> {
+        let mut findings: Vec<SecurityFinding> = self
+            .kdf_config
+            .advisories()
+            .into_iter()
+            .map(SecurityFinding::Kdf)
+            .collect();
+
+        match self.inner_cipher_config {
+            InnerCipherConfig::Salsa20 => findings.push(SecurityFinding::Salsa20InnerCipher),
+            InnerCipherConfig::Plain => findings.push(SecurityFinding::PlainInnerCipher),
+            InnerCipherConfig::ChaCha20 => {}
+        }
+
+        if !matches!(self.version, DatabaseVersion::KDB4(_)) {
+            findings.push(SecurityFinding::OutdatedFormat(self.version.clone()));
+        }
+
+        if self.compression_config == CompressionConfig::None {
+            findings.push(SecurityFinding::NoCompression);
+        }
+
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity()));
+        findings
+    }
+}
+
 impl KdfConfig {
+    /// Key derivation settings for new databases and upgrades of existing ones, following the
+    /// defaults of KeePassXC: Argon2id with 64 MiB of memory
+    pub fn recommended() -> KdfConfig {
+        KdfConfig::Argon2id {
+            iterations: 10,
+            memory: 64 * 1024 * 1024,
+            parallelism: 2,
+            version: argon2::Version::Version13,
+            secret: None,
+            associated_data: None,
+        }
+    }
+
+    /// Check the settings for known weaknesses
+    pub fn advisories(&self) -> Vec<KdfAdvisory> {
+        let mut advisories = Vec::new();
+
+        match self {
+            KdfConfig::Aes { rounds } => {
+                advisories.push(KdfAdvisory::NotMemoryHard);
+                if *rounds < MIN_AES_KDF_ROUNDS {
+                    advisories.push(KdfAdvisory::TooFewRounds { rounds: *rounds });
+                }
+            }
+            KdfConfig::Argon2 {
+                iterations, memory, ..
+            }
+            | KdfConfig::Argon2id {
+                iterations, memory, ..
+            } => {
+                if *memory < MIN_ARGON2_MEMORY {
+                    advisories.push(KdfAdvisory::TooLittleMemory { memory: *memory });
+                }
+                if *iterations < MIN_ARGON2_ITERATIONS {
+                    advisories.push(KdfAdvisory::TooFewIterations {
+                        iterations: *iterations,
+                    });
+                }
+            }
+        }
+
+        advisories
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn seed_size(&self) -> usize {
         match self {
@@ -255,6 +582,8 @@ impl KdfConfig {
                 iterations,
                 parallelism,
                 version,
+                secret,
+                associated_data,
             } => Box::new(kdf::Argon2Kdf {
                 memory: *memory,
                 salt: seed.to_vec(),
@@ -262,12 +591,16 @@ impl KdfConfig {
                 parallelism: *parallelism,
                 version: *version,
                 variant: argon2::Variant::Argon2d,
+                secret: secret.clone().unwrap_or_default(),
+                associated_data: associated_data.clone().unwrap_or_default(),
             }),
             KdfConfig::Argon2id {
                 memory,
                 iterations,
                 parallelism,
                 version,
+                secret,
+                associated_data,
             } => Box::new(kdf::Argon2Kdf {
                 memory: *memory,
                 salt: seed.to_vec(),
@@ -275,6 +608,8 @@ impl KdfConfig {
                 parallelism: *parallelism,
                 version: *version,
                 variant: argon2::Variant::Argon2id,
+                secret: secret.clone().unwrap_or_default(),
+                associated_data: associated_data.clone().unwrap_or_default(),
             }),
         }
     }
@@ -294,6 +629,8 @@ impl KdfConfig {
                 iterations,
                 parallelism,
                 version,
+                secret,
+                associated_data,
             } => {
                 vd.set(KDF_ID, KDF_ARGON2.to_vec());
                 vd.set(KDF_MEMORY, *memory);
@@ -301,12 +638,20 @@ impl KdfConfig {
                 vd.set(KDF_ITERATIONS, *iterations);
                 vd.set(KDF_PARALLELISM, *parallelism);
                 vd.set(KDF_VERSION, version.as_u32());
+                if let Some(secret) = secret {
+                    vd.set(KDF_SECRET, secret.clone());
+                }
+                if let Some(associated_data) = associated_data {
+                    vd.set(KDF_ASSOCIATED_DATA, associated_data.clone());
+                }
             }
             KdfConfig::Argon2id {
                 memory,
                 iterations,
                 parallelism,
                 version,
+                secret,
+                associated_data,
             } => {
                 vd.set(KDF_ID, KDF_ARGON2ID.to_vec());
                 vd.set(KDF_MEMORY, *memory);
@@ -314,6 +659,12 @@ impl KdfConfig {
                 vd.set(KDF_ITERATIONS, *iterations);
                 vd.set(KDF_PARALLELISM, *parallelism);
                 vd.set(KDF_VERSION, version.as_u32());
+                if let Some(secret) = secret {
+                    vd.set(KDF_SECRET, secret.clone());
+                }
+                if let Some(associated_data) = associated_data {
+                    vd.set(KDF_ASSOCIATED_DATA, associated_data.clone());
+                }
             }
         }
 
@@ -338,6 +689,8 @@ impl TryFrom<VariantDictionary> for (KdfConfig, Vec<u8>) {
             let iterations: u64 = *vd.get(KDF_ITERATIONS)?;
             let parallelism: u32 = *vd.get(KDF_PARALLELISM)?;
             let version: u32 = *vd.get(KDF_VERSION)?;
+            let secret: Option<Vec<u8>> = vd.get::<Vec<u8>>(KDF_SECRET).ok().cloned();
+            let associated_data: Option<Vec<u8>> = vd.get::<Vec<u8>>(KDF_ASSOCIATED_DATA).ok().cloned();
 
             let version = match version {
                 0x10 => argon2::Version::Version10,
@@ -351,6 +704,8 @@ impl TryFrom<VariantDictionary> for (KdfConfig, Vec<u8>) {
                     iterations,
                     parallelism,
                     version,
+                    secret,
+                    associated_data,
                 },
                 salt,
             ))
@@ -360,6 +715,8 @@ impl TryFrom<VariantDictionary> for (KdfConfig, Vec<u8>) {
             let iterations: u64 = *vd.get(KDF_ITERATIONS)?;
             let parallelism: u32 = *vd.get(KDF_PARALLELISM)?;
             let version: u32 = *vd.get(KDF_VERSION)?;
+            let secret: Option<Vec<u8>> = vd.get::<Vec<u8>>(KDF_SECRET).ok().cloned();
+            let associated_data: Option<Vec<u8>> = vd.get::<Vec<u8>>(KDF_ASSOCIATED_DATA).ok().cloned();
 
             let version = match version {
                 0x10 => argon2::Version::Version10,
@@ -373,6 +730,8 @@ impl TryFrom<VariantDictionary> for (KdfConfig, Vec<u8>) {
                     iterations,
                     parallelism,
                     version,
+                    secret,
+                    associated_data,
                 },
                 salt,
             ))
@@ -423,3 +782,115 @@ impl TryFrom<u32> for CompressionConfig {
         }
     }
 }
+
+/// KeePassXC: the preferred time in milliseconds that deriving the key should take
+pub const KPXC_DECRYPTION_TIME_PREFERENCE: &str = "KPXC_DECRYPTION_TIME_PREFERENCE";
+
+/// KeePassXC: random data that is stored to obfuscate the size of the database
+pub const KPXC_RANDOM_SLUG: &str = "KPXC_RANDOM_SLUG";
+
+/// Whether clients should open the database read-only, stored as a boolean in the public custom
+/// data. This lets the distributor of a reference database signal that it should not be edited
+/// locally.
+pub const READ_ONLY_RECOMMENDED: &str = "ReadOnlyRecommended";
+
+/// Custom data that applications store in the unencrypted outer header of KDBX4 databases.
+///
+/// The items are stored in a [`VariantDictionary`], so they keep their original order and items
+/// of types unknown to this library are written out again unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct PublicCustomData {
+    dictionary: VariantDictionary,
+}
+
+macro_rules! public_custom_data_accessors {
+    ($get:ident, $set:ident, $t:ty) => {
+        pub fn $get(&self, key: &str) -> Option<$t> {
+            self.dictionary.get::<$t>(key).ok().copied()
+        }
+
+        pub fn $set(&mut self, key: &str, value: $t) {
+            self.dictionary.set(key, value)
+        }
+    };
+}
+
+impl PublicCustomData {
+    pub(crate) fn parse(buffer: &[u8]) -> Result<PublicCustomData, VariantDictionaryError> {
+        Ok(PublicCustomData {
+            dictionary: VariantDictionary::parse(buffer)?,
+        })
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn dump(&self, writer: &mut dyn std::io::Write) -> Result<(), std::io::Error> {
+        self.dictionary.dump(writer)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dictionary.is_empty()
+    }
+
+    /// Get the keys of all items, in the order they are stored in
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.dictionary.iter().map(|(key, _)| key)
+    }
+
+    /// Remove an item, returning whether it was present
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.dictionary.remove(key).is_some()
+    }
+
+    public_custom_data_accessors!(get_u32, set_u32, u32);
+    public_custom_data_accessors!(get_u64, set_u64, u64);
+    public_custom_data_accessors!(get_i32, set_i32, i32);
+    public_custom_data_accessors!(get_i64, set_i64, i64);
+    public_custom_data_accessors!(get_bool, set_bool, bool);
+
+    pub fn get_string(&self, key: &str) -> Option<String> {
+        self.dictionary.get::<String>(key).ok().cloned()
+    }
+
+    pub fn set_string(&mut self, key: &str, value: &str) {
+        self.dictionary.set(key, value.to_string())
+    }
+
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.dictionary.get::<Vec<u8>>(key).ok().map(|v| v.as_slice())
+    }
+
+    pub fn set_bytes(&mut self, key: &str, value: &[u8]) {
+        self.dictionary.set(key, value.to_vec())
+    }
+
+    /// The time in milliseconds that KeePassXC should aim for when choosing KDF parameters
+    pub fn decryption_time_preference(&self) -> Option<i32> {
+        self.get_i32(KPXC_DECRYPTION_TIME_PREFERENCE)
+    }
+
+    pub fn set_decryption_time_preference(&mut self, milliseconds: i32) {
+        self.set_i32(KPXC_DECRYPTION_TIME_PREFERENCE, milliseconds)
+    }
+
+    /// Whether clients are recommended to open the database read-only, see
+    /// [`READ_ONLY_RECOMMENDED`]
+    pub fn read_only_recommended(&self) -> bool {
+        self.get_bool(READ_ONLY_RECOMMENDED).unwrap_or(false)
+    }
+
+    /// Recommend clients to open the database read-only. Clearing the recommendation removes the
+    /// item, so that databases that never used it are saved unchanged.
+    pub fn set_read_only_recommended(&mut self, read_only: bool) {
+        if read_only {
+            self.set_bool(READ_ONLY_RECOMMENDED, true)
+        } else {
+            self.remove(READ_ONLY_RECOMMENDED);
+        }
+    }
+
+    /// The random data KeePassXC adds to the header to obfuscate the size of the database
+    pub fn random_slug(&self) -> Option<String> {
+        self.get_string(KPXC_RANDOM_SLUG)
+    }
+}
@@ -1,19 +1,32 @@
+use std::sync::Arc;
+
 use aes::Aes256;
 #[cfg(feature = "save_kdbx4")]
 use cipher::BlockEncryptMut;
 use cipher::{block_padding::Pkcs7, generic_array::GenericArray, BlockDecryptMut};
 use salsa20::{
-    cipher::{KeyIvInit, StreamCipher},
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
     Salsa20,
 };
+use zeroize::Zeroizing;
 
-use crate::crypt::CryptographyError;
+use crate::{config::InnerCipherConfig, crypt::CryptographyError, db::EncryptedValue};
 
 pub(crate) trait Cipher {
     #[cfg(feature = "save_kdbx4")]
     fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError>;
     fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError>;
 
+    /// Move to `position` bytes into the key stream. Only stream ciphers can seek, other ciphers
+    /// ignore this.
+    fn seek(&mut self, _position: u64) {}
+
+    /// Keep a protected value encrypted instead of decrypting it, advancing the key stream as if
+    /// it had been decrypted. Only the decryptor of lazily opened databases does this.
+    fn defer(&mut self, _ciphertext: &[u8]) -> Option<EncryptedValue> {
+        None
+    }
+
     #[cfg(feature = "save_kdbx4")]
     /// The number of bytes expected by the cipher as an initialization vector.
     fn iv_size() -> usize
@@ -152,6 +165,10 @@ impl Cipher for Salsa20Cipher {
         Ok(buffer)
     }
 
+    fn seek(&mut self, position: u64) {
+        self.cipher.seek(position);
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn iv_size() -> usize {
         // or 16
@@ -202,6 +219,10 @@ impl Cipher for ChaCha20Cipher {
         Ok(buffer)
     }
 
+    fn seek(&mut self, position: u64) {
+        self.cipher.seek(position);
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn iv_size() -> usize {
         12
@@ -238,3 +259,100 @@ impl Cipher for PlainCipher {
         1
     }
 }
+
+/// A cipher that discards all data passed through it. Used to strip protected values from
+/// plaintext XML exports.
+#[cfg(feature = "save_kdbx4")]
+pub(crate) struct RedactingCipher;
+#[cfg(feature = "save_kdbx4")]
+impl Cipher for RedactingCipher {
+    fn encrypt(&mut self, _plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        Ok(Vec::new())
+    }
+    fn decrypt(&mut self, _ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        Ok(Vec::new())
+    }
+
+    fn iv_size() -> usize {
+        0
+    }
+
+    fn key_size() -> usize {
+        0
+    }
+}
+
+/// The inner stream of an opened database, which can decrypt protected values at any position
+pub(crate) struct InnerStream {
+    config: InnerCipherConfig,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl InnerStream {
+    pub(crate) fn new(config: InnerCipherConfig, key: &[u8]) -> Self {
+        InnerStream {
+            config,
+            key: Zeroizing::new(key.to_vec()),
+        }
+    }
+
+    /// Decrypt a value that starts `position` bytes into the key stream
+    pub(crate) fn decrypt_at(&self, position: u64, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        let mut cipher = self.config.get_cipher(&self.key)?;
+        cipher.seek(position);
+        cipher.decrypt(ciphertext)
+    }
+}
+
+/// An inner decryptor that keeps protected values encrypted, remembering their position in the
+/// key stream so that they can be decrypted on their own later
+pub(crate) struct LazyDecryptor {
+    stream: Arc<InnerStream>,
+    cipher: Box<dyn Cipher>,
+    position: u64,
+}
+
+impl LazyDecryptor {
+    pub(crate) fn new(config: InnerCipherConfig, key: &[u8]) -> Result<Self, CryptographyError> {
+        Ok(LazyDecryptor {
+            cipher: config.get_cipher(key)?,
+            stream: Arc::new(InnerStream::new(config, key)),
+            position: 0,
+        })
+    }
+}
+
+impl Cipher for LazyDecryptor {
+    #[cfg(feature = "save_kdbx4")]
+    fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.cipher.seek(self.position);
+        self.position += plaintext.len() as u64;
+        self.cipher.encrypt(plaintext)
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.cipher.seek(self.position);
+        self.position += ciphertext.len() as u64;
+        self.cipher.decrypt(ciphertext)
+    }
+
+    fn seek(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    fn defer(&mut self, ciphertext: &[u8]) -> Option<EncryptedValue> {
+        let value = EncryptedValue::new(ciphertext.to_vec(), self.position, self.stream.clone());
+        self.position += ciphertext.len() as u64;
+        Some(value)
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    fn iv_size() -> usize {
+        0
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    fn key_size() -> usize {
+        0
+    }
+}
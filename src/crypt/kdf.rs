@@ -24,7 +24,7 @@ impl Kdf for AesKdf {
         &self,
         composite_key: &GenericArray<u8, U32>,
     ) -> Result<GenericArray<u8, U32>, CryptographyError> {
-        let cipher = Aes256::new(&GenericArray::clone_from_slice(&self.seed));
+        let cipher = Aes256::new_from_slice(&self.seed)?;
         let mut block1 = GenericArray::clone_from_slice(&composite_key[..16]);
         let mut block2 = GenericArray::clone_from_slice(&composite_key[16..]);
         for _ in 0..self.rounds {
@@ -48,6 +48,8 @@ pub struct Argon2Kdf {
     pub parallelism: u32,
     pub version: argon2::Version,
     pub variant: argon2::Variant,
+    pub secret: Vec<u8>,
+    pub associated_data: Vec<u8>,
 }
 
 impl Kdf for Argon2Kdf {
@@ -56,11 +58,11 @@ impl Kdf for Argon2Kdf {
         composite_key: &GenericArray<u8, U32>,
     ) -> Result<GenericArray<u8, U32>, CryptographyError> {
         let config = argon2::Config {
-            ad: &[],
+            ad: &self.associated_data,
             hash_length: 32,
             lanes: self.parallelism,
             mem_cost: (self.memory / 1024) as u32,
-            secret: &[],
+            secret: &self.secret,
             time_cost: self.iterations as u32,
             variant: self.variant,
             version: self.version,
@@ -65,3 +65,60 @@ pub(crate) fn calculate_sha512(elements: &[&[u8]]) -> Result<GenericArray<u8, U6
 
     Ok(digest.finalize())
 }
+
+/// Hardware acceleration that is available to the cryptographic primitives on this machine, as
+/// returned by [`capabilities`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CryptoCapabilities {
+    /// The CPU has AES instructions (AES-NI on x86, the cryptography extension on ARMv8)
+    pub aes_instructions: bool,
+
+    /// AES encryption and AES-KDF use the AES instructions. The underlying `aes` crate selects
+    /// them automatically at runtime on x86, but only uses them on ARMv8 when built with
+    /// `RUSTFLAGS="--cfg aes_armv8"`.
+    pub aes_accelerated: bool,
+
+    /// SSE2 is available, which is used by Salsa20 and ChaCha20 on x86
+    pub sse2: bool,
+
+    /// AVX2 is available, which is used by ChaCha20 on x86
+    pub avx2: bool,
+
+    /// NEON is available on ARM
+    pub neon: bool,
+}
+
+/// Detect which hardware acceleration the cryptographic primitives can use on this machine
+pub fn capabilities() -> CryptoCapabilities {
+    #[allow(unused_mut)]
+    let mut capabilities = CryptoCapabilities::default();
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        capabilities.aes_instructions = std::is_x86_feature_detected!("aes");
+        capabilities.sse2 = std::is_x86_feature_detected!("sse2");
+        capabilities.avx2 = std::is_x86_feature_detected!("avx2");
+        capabilities.aes_accelerated = capabilities.aes_instructions && capabilities.sse2;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        capabilities.aes_instructions = std::arch::is_aarch64_feature_detected!("aes");
+        capabilities.neon = std::arch::is_aarch64_feature_detected!("neon");
+        capabilities.aes_accelerated = cfg!(aes_armv8) && capabilities.aes_instructions;
+    }
+
+    capabilities
+}
+
+#[cfg(test)]
+mod crypt_tests {
+    #[test]
+    fn capabilities() {
+        let capabilities = super::capabilities();
+        assert!(!capabilities.aes_accelerated || capabilities.aes_instructions);
+
+        #[cfg(target_arch = "x86_64")]
+        assert!(capabilities.sse2);
+    }
+}
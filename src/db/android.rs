@@ -0,0 +1,168 @@
+use crate::db::{Entry, Times, Value};
+
+/// Name of the field in which Keepass2Android stores the first additional URL of an entry.
+/// Further URLs are stored in the fields `KP2A_URL_1`, `KP2A_URL_2` and so on.
+pub const KP2A_URL_FIELD: &str = "KP2A_URL";
+
+/// Name of the field in which Android password managers store the package name of an app that
+/// the entry is used for. Further apps are stored in the fields `AndroidApp_1`, `AndroidApp_2`
+/// and so on.
+pub const ANDROID_APP_FIELD: &str = "AndroidApp";
+
+/// Scheme of the URLs with which Keepass2Android associates an entry with an Android app, e.g.
+/// `androidapp://com.example.app`
+pub const ANDROID_APP_URL_SCHEME: &str = "androidapp://";
+
+/// Get the index of a field named `base` (0) or `base_<n>` (n)
+fn field_index(name: &str, base: &str) -> Option<usize> {
+    match name.strip_prefix(base)? {
+        "" => Some(0),
+        rest => rest.strip_prefix('_')?.parse().ok(),
+    }
+}
+
+impl Entry {
+    /// Get the non-empty values of the fields `base`, `base_1`, `base_2`, ... in the order of
+    /// their numbers, allowing for gaps in the numbering
+    fn numbered_field_values(&self, base: &str) -> Vec<&str> {
+        let mut values: Vec<(usize, &str)> = self
+            .fields
+            .keys()
+            .filter_map(|name| Some((field_index(name, base)?, self.get(name)?)))
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        values.sort_by_key(|(index, _)| *index);
+        values.into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// Store `value` in the first of the fields `base`, `base_1`, `base_2`, ... that is not used
+    fn add_numbered_field(&mut self, base: &str, value: &str) {
+        let name = (0..)
+            .map(|i| match i {
+                0 => base.to_string(),
+                i => format!("{}_{}", base, i),
+            })
+            .find(|name| !self.fields.contains_key(name.as_str()))
+            .unwrap_or_default();
+
+        self.fields
+            .insert(name.into(), Value::Unprotected(value.to_string()));
+        self.times.set_last_modification(Times::now());
+    }
+
+    /// Get the additional URLs of the entry as stored by Keepass2Android, see [`KP2A_URL_FIELD`]
+    pub fn additional_urls(&self) -> Vec<&str> {
+        self.numbered_field_values(KP2A_URL_FIELD)
+    }
+
+    /// Add an additional URL to the entry in the way of Keepass2Android
+    pub fn add_additional_url(&mut self, url: &str) {
+        self.add_numbered_field(KP2A_URL_FIELD, url)
+    }
+
+    /// Get the package names of the Android apps that the entry is used for.
+    ///
+    /// These are read from the [`ANDROID_APP_FIELD`] fields, as well as from `androidapp://` URLs
+    /// in the URL field and the additional URLs of the entry, which Keepass2Android uses instead.
+    pub fn android_apps(&self) -> Vec<&str> {
+        let urls = self
+            .get_url()
+            .into_iter()
+            .chain(self.additional_urls())
+            .filter_map(|url| url.strip_prefix(ANDROID_APP_URL_SCHEME));
+
+        let mut apps: Vec<&str> = Vec::new();
+        for app in self
+            .numbered_field_values(ANDROID_APP_FIELD)
+            .into_iter()
+            .chain(urls)
+        {
+            if !apps.contains(&app) {
+                apps.push(app);
+            }
+        }
+        apps
+    }
+
+    /// Associate the entry with an Android app by its package name, unless it already is.
+    ///
+    /// The package name is stored in the [`ANDROID_APP_FIELD`] fields, which Keepass2Android and
+    /// KeePassDX both understand.
+    pub fn add_android_app(&mut self, package: &str) {
+        if !self.android_apps().contains(&package) {
+            self.add_numbered_field(ANDROID_APP_FIELD, package)
+        }
+    }
+}
+
+#[cfg(test)]
+mod android_tests {
+    use super::field_index;
+    use crate::db::{Database, Entry, NodeRef, Value};
+
+    #[test]
+    fn numbered_fields() {
+        assert_eq!(field_index("KP2A_URL", "KP2A_URL"), Some(0));
+        assert_eq!(field_index("KP2A_URL_12", "KP2A_URL"), Some(12));
+        assert_eq!(field_index("KP2A_URLS", "KP2A_URL"), None);
+        assert_eq!(field_index("KP2A_URL_x", "KP2A_URL"), None);
+
+        let mut entry = Entry::new();
+        entry.set_url("androidapp://com.example.app");
+        entry.add_additional_url("https://example.com");
+        entry.add_additional_url("https://example.org");
+        entry.add_android_app("com.example.app");
+        entry.add_android_app("com.example.other");
+
+        assert!(entry.fields.contains_key("KP2A_URL_1"));
+        assert_eq!(
+            entry.additional_urls(),
+            vec!["https://example.com", "https://example.org"]
+        );
+        assert_eq!(entry.android_apps(), vec!["com.example.other", "com.example.app"]);
+        assert!(!entry.fields.contains_key("AndroidApp_1"));
+    }
+
+    #[test]
+    fn keepass2android_fields() {
+        let xml = include_bytes!("../../tests/resources/inner_xml_keepass2android.xml");
+        let db = Database::import_xml(&mut &xml[..]).unwrap();
+
+        let entry = match db.root.get(&["Example Bank"]) {
+            Some(NodeRef::Entry(e)) => e,
+            _ => panic!("entry not found"),
+        };
+        assert_eq!(
+            entry.additional_urls(),
+            vec![
+                "https://login.example.com",
+                "androidapp://com.example.bank.wallet",
+                "https://m.example.com"
+            ]
+        );
+        assert_eq!(
+            entry.android_apps(),
+            vec![
+                "com.example.bank",
+                "com.example.bank.business",
+                "com.example.bank.wallet"
+            ]
+        );
+        assert_eq!(entry.get("PIN"), Some("4711"));
+        assert!(matches!(entry.fields["PIN"], Value::Protected(_)));
+
+        // all custom strings survive saving, including those unknown to this library
+        #[cfg(feature = "save_kdbx4")]
+        {
+            let mut exported = Vec::new();
+            db.export_xml(&mut exported, true).unwrap();
+            let reimported = Database::import_xml(&mut exported.as_slice()).unwrap();
+            assert_eq!(reimported, db);
+
+            let exported = String::from_utf8(exported).unwrap();
+            for field in entry.fields.keys() {
+                assert!(exported.contains(&format!("<Key>{}</Key>", field)));
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::NaiveDateTime;
+
+use crate::db::{Database, Entry, Group, Node};
+
+impl Entry {
+    /// Get the version of the entry that was current at the given time, i.e. the entry itself or
+    /// the newest history item that was last modified at or before `timestamp`. The returned
+    /// version only keeps the history items that are older than itself.
+    ///
+    /// Returns `None` if the entry was created after `timestamp`, or if the history does not go
+    /// back far enough.
+    pub fn as_of(&self, timestamp: NaiveDateTime) -> Option<Entry> {
+        let not_after = |e: &Entry| e.times.get_last_modification().is_none_or(|t| *t <= timestamp);
+
+        if self.times.get_creation().is_some_and(|t| *t > timestamp) {
+            return None;
+        }
+
+        if not_after(self) {
+            let mut current = self.clone();
+            if let Some(history) = current.history.as_mut() {
+                history.entries.retain(not_after);
+            }
+            return Some(current);
+        }
+
+        let mut history = self.history.clone()?;
+
+        // history items can be ordered either way (KeePass writes them from oldest to newest), so
+        // pick the version by its modification time. Iterating in reverse prefers the first of
+        // several items with the same time.
+        let (position, _) = history
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| not_after(e))
+            .rev()
+            .max_by_key(|(_, e)| e.times.get_last_modification())?;
+        let mut version = history.entries.remove(position);
+        history.entries.retain(not_after);
+        version.history = Some(history);
+        Some(version)
+    }
+}
+
+/// Replace the entries of `group` and its subgroups by their versions at `timestamp`, removing
+/// entries that did not exist yet and groups created later that end up empty
+fn group_as_of(group: &mut Group, timestamp: NaiveDateTime) {
+    group.children = std::mem::take(&mut group.children)
+        .into_iter()
+        .filter_map(|node| match node {
+            Node::Entry(e) => e.as_of(timestamp).map(Node::Entry),
+            Node::Group(mut g) => {
+                group_as_of(&mut g, timestamp);
+                let created_later = g.times.get_creation().is_some_and(|t| *t > timestamp);
+                (!created_later || !g.children.is_empty()).then_some(Node::Group(g))
+            }
+        })
+        .collect();
+}
+
+impl Database {
+    /// Reconstruct the state of the database at a past point in time from the histories of the
+    /// entries, e.g. to find out what a password was at that time.
+    ///
+    /// Every entry is replaced by its version that was current at `timestamp`, see
+    /// [`Entry::as_of`], and entries that were created later are left out. Groups are not
+    /// versioned, so they keep their current names and structure, except for groups created
+    /// later that contain no older entries. Entries that were deleted since cannot be recovered.
+    pub fn as_of(&self, timestamp: NaiveDateTime) -> Database {
+        let mut db = self.clone();
+        group_as_of(&mut db.root, timestamp);
+        db
+    }
+}
+
+#[cfg(test)]
+mod as_of_tests {
+    use chrono::{Duration, NaiveDateTime};
+
+    use crate::db::{Database, Entry, Group, History, Value};
+
+    fn version(title: &str, modified: NaiveDateTime) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".into(), Value::Unprotected(title.to_string()));
+        entry.times.set_creation(modified);
+        entry.times.set_last_modification(modified);
+        entry
+    }
+
+    #[test]
+    fn as_of() {
+        let start = NaiveDateTime::default() + Duration::days(365 * 50);
+        let day = |n: i64| start + Duration::days(n);
+
+        // an entry changed on day 2 and day 4
+        let mut entry = version("v3", day(4));
+        entry.times.set_creation(day(0));
+        let mut history = History::default();
+        for (title, modified) in [("v1", day(0)), ("v2", day(2))] {
+            let mut old = version(title, modified);
+            old.uuid = entry.uuid;
+            old.times.set_creation(day(0));
+            history.add_entry(old);
+        }
+        entry.history = Some(history);
+
+        let mut group = Group::new("New");
+        group.times.set_creation(day(3));
+        group.add_child(version("later", day(3)));
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry);
+        db.root.add_child(group);
+
+        let past = db.as_of(day(1));
+        assert_eq!(past.root.children.len(), 1);
+        let entry = past.root.entries()[0];
+        assert_eq!(entry.get_title(), Some("v1"));
+        assert_eq!(entry.history.as_ref().unwrap().get_entries().len(), 0);
+
+        let past = db.as_of(day(3));
+        assert_eq!(past.root.entries()[0].get_title(), Some("v2"));
+        assert_eq!(past.root.groups()[0].entries()[0].get_title(), Some("later"));
+
+        let past = db.as_of(day(5));
+        assert_eq!(past, db);
+
+        assert!(db.as_of(start - Duration::days(1)).root.children.is_empty());
+    }
+
+    #[test]
+    fn as_of_oldest_first_history() {
+        let start = NaiveDateTime::default() + Duration::days(365 * 50);
+        let day = |n: i64| start + Duration::days(n);
+
+        // KeePass writes history items from oldest to newest
+        let mut entry = version("v4", day(6));
+        entry.times.set_creation(day(0));
+        let mut history = History::default();
+        for (title, modified) in [("v1", day(0)), ("v2", day(2)), ("v3", day(4))] {
+            history.entries.push(version(title, modified));
+        }
+        entry.history = Some(history);
+
+        let past = entry.as_of(day(3)).unwrap();
+        assert_eq!(past.get_title(), Some("v2"));
+        let titles: Vec<_> = past
+            .history
+            .as_ref()
+            .unwrap()
+            .get_entries()
+            .iter()
+            .map(|e| e.get_title().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["v1"]);
+
+        assert_eq!(entry.as_of(day(5)).unwrap().get_title(), Some("v3"));
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::HashMap, io::Read, path::Path};
+
+use uuid::Uuid;
+
+use crate::{
+    config::DatabaseVersion,
+    db::{
+        AttachmentRef, BinaryAttachment, CustomDataItem, Database, Entry, HeaderAttachment, NodeRef, Times,
+        Value,
+    },
+    error::AttachmentError,
+};
+
+/// Prefix of the custom data keys that store the MIME type of an attachment, followed by the
+/// name of the attachment
+pub const ATTACHMENT_MIME_TYPE_PREFIX: &str = "AttachmentMimeType:";
+
+/// MIME type of attachments whose content could not be recognized
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Options for [`Database::attach_file`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AttachOptions {
+    /// Maximum size of the file in bytes
+    pub max_size: Option<u64>,
+
+    /// Whether to compress the attachment. This only has an effect for KDBX3 databases, which
+    /// store binaries individually compressed. KDBX4 databases compress their whole inner
+    /// payload according to the [`CompressionConfig`](crate::config::CompressionConfig).
+    pub compress: bool,
+}
+
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"-----BEGIN PGP", "application/pgp-keys"),
+    (b"-----BEGIN ", "application/x-pem-file"),
+    (b"\x03\xd9\xa2\x9a", "application/x-keepass2"),
+    (b"<?xml", "application/xml"),
+];
+
+/// Guess the MIME type of binary content from its first bytes
+pub fn sniff_mime_type(content: &[u8]) -> &'static str {
+    if let Some((_, mime_type)) = MAGIC_NUMBERS.iter().find(|(magic, _)| content.starts_with(magic)) {
+        return mime_type;
+    }
+
+    match std::str::from_utf8(content) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => "text/plain",
+        _ => DEFAULT_MIME_TYPE,
+    }
+}
+
+/// Make an attachment name unique among the names already taken on an entry by appending a
+/// counter to the file stem, like KeePass does, e.g. `notes (1).txt`
+fn unique_attachment_name(name: &str, taken_names: &[String]) -> String {
+    if !taken_names.iter().any(|n| n == name) {
+        return name.to_string();
+    }
+
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|i| format!("{} ({}){}", stem, i, extension))
+        .find(|candidate| !taken_names.contains(candidate))
+        .unwrap()
+}
+
+impl Entry {
+    /// Get the MIME type of an attachment, as recorded by [`Database::attach_file`]
+    pub fn attachment_mime_type(&self, name: &str) -> Option<&str> {
+        match self
+            .custom_data
+            .items
+            .get(&format!("{}{}", ATTACHMENT_MIME_TYPE_PREFIX, name))?
+            .value
+            .as_ref()?
+        {
+            Value::Unprotected(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl Database {
+    /// Read a file and attach it to the entry with the given UUID.
+    ///
+    /// The content is stored in the binary pool of the database, i.e. the header attachments for
+    /// KDBX4 and the metadata binaries otherwise. The file name becomes the name of the
+    /// attachment, and the MIME type guessed from the content is stored in the custom data of the
+    /// entry. If the entry already has an attachment with that name, a counter is appended to
+    /// it, e.g. `notes (1).txt`.
+    pub fn attach_file(
+        &mut self,
+        entry: Uuid,
+        path: impl AsRef<Path>,
+        options: &AttachOptions,
+    ) -> Result<AttachmentRef, AttachmentError> {
+        let path = path.as_ref();
+
+        let taken_names: Vec<String> = self
+            .root
+            .iter()
+            .find_map(|node| match node {
+                NodeRef::Entry(e) if e.uuid == entry => {
+                    Some(e.attachments.iter().map(|a| a.name.clone()).collect())
+                }
+                _ => None,
+            })
+            .ok_or(AttachmentError::EntryNotFound(entry))?;
+
+        let name = path
+            .file_name()
+            .map(|n| unique_attachment_name(&n.to_string_lossy(), &taken_names))
+            .ok_or_else(|| AttachmentError::InvalidPath(path.display().to_string()))?;
+
+        let mut file = std::fs::File::open(path)?;
+        if let Some(max_size) = options.max_size {
+            let size = file.metadata()?.len();
+            if size > max_size {
+                return Err(AttachmentError::TooLarge { size, max_size });
+            }
+        }
+
+        // the file might grow after checking its size, so never read more than allowed
+        let mut content = Vec::new();
+        match options.max_size {
+            Some(max_size) => {
+                (&mut file).take(max_size + 1).read_to_end(&mut content)?;
+                if content.len() as u64 > max_size {
+                    return Err(AttachmentError::TooLarge {
+                        size: content.len() as u64,
+                        max_size,
+                    });
+                }
+            }
+            None => {
+                file.read_to_end(&mut content)?;
+            }
+        }
+
+        let mime_type = sniff_mime_type(&content);
+        let attachment = AttachmentRef {
+            name,
+            identifier: self.add_binary(content, options.compress),
+        };
+
+        self.root.for_each_entry_mut(&mut |e| {
+            if e.uuid != entry {
+                return;
+            }
+
+            e.custom_data.items.insert(
+                format!("{}{}", ATTACHMENT_MIME_TYPE_PREFIX, attachment.name),
+                CustomDataItem {
+                    value: Some(Value::Unprotected(mime_type.to_string())),
+                    last_modification_time: Some(Times::now()),
+                },
+            );
+            e.attachments.push(attachment.clone());
+            e.times.set_last_modification(Times::now());
+        });
+
+        Ok(attachment)
+    }
+
+    /// Sizes in bytes of the binaries in the binary pool of the database, by their identifiers as
+    /// used in [`AttachmentRef::identifier`]
+    pub fn binary_sizes(&self) -> HashMap<String, usize> {
+        match self.config.version {
+            DatabaseVersion::KDB4(_) => self
+                .header_attachments
+                .iter()
+                .enumerate()
+                .map(|(i, attachment)| (i.to_string(), attachment.content.len()))
+                .collect(),
+            _ => self
+                .meta
+                .binaries
+                .binaries
+                .iter()
+                .filter_map(|b| Some((b.identifier.clone()?, b.content.len())))
+                .collect(),
+        }
+    }
+
+    /// Get the content of a binary in the binary pool of the database, and whether it should be
+    /// stored compressed
+    pub(crate) fn binary_content(&self, identifier: &str) -> Option<(&[u8], bool)> {
+        match self.config.version {
+            DatabaseVersion::KDB4(_) => {
+                let attachment = self.header_attachments.get(identifier.parse::<usize>().ok()?)?;
+                Some((&attachment.content, false))
+            }
+            _ => {
+                let binary = self
+                    .meta
+                    .binaries
+                    .binaries
+                    .iter()
+                    .find(|b| b.identifier.as_deref() == Some(identifier))?;
+                Some((&binary.content, binary.compressed))
+            }
+        }
+    }
+
+    /// Store binary content in the binary pool of the database, returning its identifier
+    pub(crate) fn add_binary(&mut self, content: Vec<u8>, compress: bool) -> String {
+        match self.config.version {
+            DatabaseVersion::KDB4(_) => {
+                self.header_attachments
+                    .push(HeaderAttachment { flags: 0, content });
+                (self.header_attachments.len() - 1).to_string()
+            }
+            _ => {
+                let binaries = &mut self.meta.binaries.binaries;
+                let identifier = binaries
+                    .iter()
+                    .filter_map(|b| b.identifier.as_ref()?.parse::<usize>().ok())
+                    .max()
+                    .map_or(0, |id| id + 1)
+                    .to_string();
+
+                binaries.push(BinaryAttachment {
+                    identifier: Some(identifier.clone()),
+                    compressed: compress,
+                    protected: false,
+                    content,
+                });
+                identifier
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod attachment_tests {
+    use super::{sniff_mime_type, unique_attachment_name, AttachOptions};
+    use crate::{
+        config::DatabaseVersion,
+        db::{Database, Entry},
+        error::AttachmentError,
+    };
+
+    #[test]
+    fn test_sniff_mime_type() {
+        assert_eq!(sniff_mime_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(sniff_mime_type(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(sniff_mime_type(b"hello world\n"), "text/plain");
+        assert_eq!(sniff_mime_type(&[0, 1, 2, 3]), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_unique_attachment_name() {
+        let taken = vec![
+            "notes.txt".to_string(),
+            "notes (1).txt".to_string(),
+            "README".to_string(),
+        ];
+        assert_eq!(unique_attachment_name("other.txt", &taken), "other.txt");
+        assert_eq!(unique_attachment_name("notes.txt", &taken), "notes (2).txt");
+        assert_eq!(unique_attachment_name("README", &taken), "README (1)");
+    }
+
+    #[test]
+    fn test_attach_file() {
+        let path = std::env::temp_dir().join(format!("keepass-attachment-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"some notes").unwrap();
+
+        let mut db = Database::new(Default::default());
+        let entry = Entry::new();
+        let uuid = entry.uuid;
+        db.root.add_child(entry);
+
+        let attachment = db.attach_file(uuid, &path, &AttachOptions::default()).unwrap();
+        assert_eq!(attachment.identifier, "0");
+        assert_eq!(db.header_attachments[0].content, b"some notes");
+
+        let entry = db.root.entries()[0];
+        assert_eq!(entry.attachments, vec![attachment.clone()]);
+        assert_eq!(entry.attachment_mime_type(&attachment.name), Some("text/plain"));
+
+        let options = AttachOptions {
+            max_size: Some(4),
+            compress: true,
+        };
+        assert!(matches!(
+            db.attach_file(uuid, &path, &options),
+            Err(AttachmentError::TooLarge {
+                size: 10,
+                max_size: 4
+            })
+        ));
+        assert!(matches!(
+            db.attach_file(uuid::Uuid::new_v4(), &path, &options),
+            Err(AttachmentError::EntryNotFound(_))
+        ));
+
+        // KDBX3 databases store compressed binaries in the metadata
+        db.config.version = DatabaseVersion::KDB3(1);
+        let options = AttachOptions {
+            max_size: Some(10),
+            compress: true,
+        };
+        let attachment = db.attach_file(uuid, &path, &options).unwrap();
+        assert_eq!(attachment.identifier, "0");
+        assert!(db.meta.binaries.binaries[0].compressed);
+
+        // the entry already has an attachment with the same name
+        let first_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let second_name = first_name.replace(".txt", " (1).txt");
+        assert_eq!(attachment.name, second_name);
+
+        let attachment = db.attach_file(uuid, &path, &options).unwrap();
+        assert_eq!(attachment.name, first_name.replace(".txt", " (2).txt"));
+
+        let entry = db.root.entries()[0];
+        let names: Vec<_> = entry.attachments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[1], second_name);
+        assert_eq!(entry.attachment_mime_type(&second_name), Some("text/plain"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::db::{tracked::entry_change_events, ChangeEvent, CustomData, Database, Entry, Group, Node, NodeRef};
+
+/// An entry that was added, removed or changed, as listed in a [`ChangeSummary`]. Values of
+/// fields are never included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct EntryChange {
+    pub uuid: Uuid,
+
+    /// Title of the entry, taken from the snapshot for removed entries
+    pub title: Option<String>,
+
+    /// Names of the string fields that were added, changed or removed
+    pub changed_fields: Vec<String>,
+
+    /// Whether anything other than the string fields changed, e.g. tags or attachments. Changes
+    /// to the times of the entry alone, e.g. when it was accessed, are not counted.
+    pub other_changes: bool,
+
+    /// Whether the entry was moved to another group
+    pub moved: bool,
+}
+
+/// A group that was added, removed or changed, as listed in a [`ChangeSummary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct GroupChange {
+    pub uuid: Uuid,
+    pub name: String,
+
+    /// Whether the properties of the group changed, e.g. its name or notes. Changes to its
+    /// children, its times and its state in the user interface are not counted.
+    pub modified: bool,
+
+    /// Whether the group was moved to another group
+    pub moved: bool,
+}
+
+/// Summary of the changes between a snapshot of a database and its current state, as returned
+/// by [`Database::change_summary_since`].
+///
+/// The summary identifies entries by their UUIDs and titles and lists the names of changed
+/// fields, but contains no field values, so that it can be written to audit logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct ChangeSummary {
+    pub entries_added: Vec<EntryChange>,
+    pub entries_removed: Vec<EntryChange>,
+    pub entries_changed: Vec<EntryChange>,
+
+    pub groups_added: Vec<GroupChange>,
+    pub groups_removed: Vec<GroupChange>,
+    pub groups_changed: Vec<GroupChange>,
+
+    /// Whether the metadata of the database changed, e.g. its name or settings
+    pub meta_changed: bool,
+}
+
+impl ChangeSummary {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        *self == ChangeSummary::default()
+    }
+}
+
+impl Database {
+    /// Summarize what changed since `snapshot` was taken, e.g. by cloning the database after
+    /// it was last saved, without revealing any secret values.
+    ///
+    /// Nodes are matched by their UUIDs. History items are not compared.
+    pub fn change_summary_since(&self, snapshot: &Database) -> ChangeSummary {
+        let mut before = Vec::new();
+        index_nodes(&snapshot.root, Uuid::nil(), &mut before);
+        let mut after = Vec::new();
+        index_nodes(&self.root, Uuid::nil(), &mut after);
+
+        let before_by_uuid: HashMap<Uuid, usize> = before
+            .iter()
+            .enumerate()
+            .map(|(i, (node, _))| (node_uuid(node), i))
+            .collect();
+        let after_uuids: HashSet<Uuid> = after.iter().map(|(node, _)| node_uuid(node)).collect();
+
+        let mut summary = ChangeSummary {
+            meta_changed: self.meta != snapshot.meta,
+            ..Default::default()
+        };
+
+        for (node, parent) in &after {
+            let old = before_by_uuid.get(&node_uuid(node)).and_then(|&i| before.get(i));
+            let moved = old.is_some_and(|(_, old_parent)| old_parent != parent);
+
+            match (node, old) {
+                (NodeRef::Entry(e), None) => summary.entries_added.push(EntryChange {
+                    uuid: e.uuid,
+                    title: e.get_title().map(str::to_string),
+                    changed_fields: Vec::new(),
+                    other_changes: false,
+                    moved: false,
+                }),
+                (NodeRef::Entry(e), Some((NodeRef::Entry(old), _))) => {
+                    let (changed_fields, other_changes) = entry_changes(old, e);
+                    if moved || other_changes || !changed_fields.is_empty() {
+                        summary.entries_changed.push(EntryChange {
+                            uuid: e.uuid,
+                            title: e.get_title().map(str::to_string),
+                            changed_fields,
+                            other_changes,
+                            moved,
+                        });
+                    }
+                }
+                (NodeRef::Group(g), None) => summary.groups_added.push(GroupChange {
+                    uuid: g.uuid,
+                    name: g.name.clone(),
+                    modified: false,
+                    moved: false,
+                }),
+                (NodeRef::Group(g), Some((NodeRef::Group(old), _))) => {
+                    let modified = group_properties(old) != group_properties(g);
+                    if moved || modified {
+                        summary.groups_changed.push(GroupChange {
+                            uuid: g.uuid,
+                            name: g.name.clone(),
+                            modified,
+                            moved,
+                        });
+                    }
+                }
+                // a node that turned from an entry into a group or vice versa is invalid and
+                // cannot be summarized sensibly
+                _ => {}
+            }
+        }
+
+        for (node, _) in &before {
+            if after_uuids.contains(&node_uuid(node)) {
+                continue;
+            }
+
+            match node {
+                NodeRef::Entry(e) => summary.entries_removed.push(EntryChange {
+                    uuid: e.uuid,
+                    title: e.get_title().map(str::to_string),
+                    changed_fields: Vec::new(),
+                    other_changes: false,
+                    moved: false,
+                }),
+                NodeRef::Group(g) => summary.groups_removed.push(GroupChange {
+                    uuid: g.uuid,
+                    name: g.name.clone(),
+                    modified: false,
+                    moved: false,
+                }),
+            }
+        }
+
+        summary
+    }
+}
+
+/// Collect the nodes of a tree in order, along with the UUIDs of their parents
+fn index_nodes<'a>(group: &'a Group, parent: Uuid, nodes: &mut Vec<(NodeRef<'a>, Uuid)>) {
+    nodes.push((NodeRef::Group(group), parent));
+    for node in &group.children {
+        match node {
+            Node::Group(g) => index_nodes(g, group.uuid, nodes),
+            Node::Entry(e) => nodes.push((NodeRef::Entry(e), group.uuid)),
+        }
+    }
+}
+
+fn node_uuid(node: &NodeRef) -> Uuid {
+    match node {
+        NodeRef::Entry(e) => e.uuid,
+        NodeRef::Group(g) => g.uuid,
+    }
+}
+
+/// Get the names of the changed string fields of an entry, and whether anything else changed
+fn entry_changes(before: &Entry, after: &Entry) -> (Vec<String>, bool) {
+    let changed_fields = entry_change_events(before, after)
+        .into_iter()
+        .filter_map(|event| match event {
+            ChangeEvent::FieldChanged { field, .. } => Some(field),
+            _ => None,
+        })
+        .collect();
+
+    let mut other_changes = after.clone();
+    other_changes.fields = before.fields.clone();
+    other_changes.times = before.times.clone();
+    other_changes.history = before.history.clone();
+
+    (changed_fields, other_changes != *before)
+}
+
+#[allow(clippy::type_complexity)]
+fn group_properties(
+    group: &Group,
+) -> (
+    &str,
+    &Option<String>,
+    Option<usize>,
+    Option<Uuid>,
+    &CustomData,
+    &Option<String>,
+    &Option<String>,
+    &Option<String>,
+) {
+    (
+        &group.name,
+        &group.notes,
+        group.icon_id,
+        group.custom_icon_uuid,
+        &group.custom_data,
+        &group.default_autotype_sequence,
+        &group.enable_autotype,
+        &group.enable_searching,
+    )
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use crate::db::{Database, Entry, Group, Node, NodeRefMut};
+
+    #[test]
+    fn change_summary_since() {
+        let mut db = Database::new(Default::default());
+        let mut group = Group::new("Group");
+        let group_uuid = group.uuid;
+        let mut changed = Entry::new();
+        changed.set_title("Changed");
+        changed.set_password("old secret");
+        let changed_uuid = changed.uuid;
+        let mut removed = Entry::new();
+        removed.set_title("Removed");
+        let removed_uuid = removed.uuid;
+        group.add_child(changed);
+        group.add_child(removed);
+        db.root.add_child(group);
+        db.root.add_child(Entry::new());
+
+        let snapshot = db.clone();
+        assert!(db.change_summary_since(&snapshot).is_empty());
+
+        let group = match db.root.get_mut(&["Group"]) {
+            Some(NodeRefMut::Group(g)) => g,
+            _ => panic!("group not found"),
+        };
+        group.name = "Renamed".to_string();
+        group
+            .children
+            .retain(|node| !matches!(node, Node::Entry(e) if e.uuid == removed_uuid));
+        let changed = group.entries_mut().pop().unwrap();
+        changed.set_password("new secret");
+        changed.tags.push("tag".into());
+
+        // accessing an entry is not a change
+        db.root.entries_mut()[0].touch_usage();
+
+        let mut added = Entry::new();
+        added.set_title("Added");
+        db.root.add_child(added);
+
+        let summary = db.change_summary_since(&snapshot);
+        assert_eq!(summary.entries_added.len(), 1);
+        assert_eq!(summary.entries_added[0].title.as_deref(), Some("Added"));
+        assert_eq!(summary.entries_removed.len(), 1);
+        assert_eq!(summary.entries_removed[0].uuid, removed_uuid);
+        assert_eq!(summary.entries_removed[0].title.as_deref(), Some("Removed"));
+
+        assert_eq!(summary.entries_changed.len(), 1);
+        let change = &summary.entries_changed[0];
+        assert_eq!(change.uuid, changed_uuid);
+        assert_eq!(change.changed_fields, vec!["Password".to_string()]);
+        assert!(change.other_changes);
+        assert!(!change.moved);
+
+        assert_eq!(summary.groups_changed.len(), 1);
+        assert_eq!(summary.groups_changed[0].uuid, group_uuid);
+        assert!(summary.groups_changed[0].modified);
+        assert!(!summary.meta_changed);
+
+        // no secret values are included
+        assert!(!format!("{:?}", summary).contains("secret"));
+    }
+}
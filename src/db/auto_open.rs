@@ -0,0 +1,206 @@
+use uuid::Uuid;
+
+use crate::db::{AttachmentRef, Database, Entry, Times};
+
+/// Name of the group whose entries describe databases that should be opened automatically
+pub const AUTO_OPEN_GROUP_NAME: &str = "AutoOpen";
+
+/// Name of the entry field restricting on which devices a child database is opened
+pub const IF_DEVICE_FIELD_NAME: &str = "IfDevice";
+
+const KDBX_SCHEME: &str = "kdbx://";
+
+/// Where the key file of a child database comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum KeyFileSource {
+    /// A path to a key file, as given by the user name of the AutoOpen entry
+    Path(String),
+
+    /// A key file that is attached to the AutoOpen entry
+    Attachment(AttachmentRef),
+}
+
+/// A child database that should be opened along with this database, as described by an entry in
+/// the `AutoOpen` group
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct AutoOpenSpec {
+    /// UUID of the entry describing the child database
+    pub uuid: Uuid,
+
+    /// Path or URL of the child database, with a `kdbx://` prefix removed. Placeholders like
+    /// `{DB_DIR}` are not expanded, since they depend on where this database is stored.
+    pub path: String,
+
+    /// Password of the child database, if any
+    pub password: Option<String>,
+
+    /// Key file of the child database, if any
+    pub keyfile: Option<KeyFileSource>,
+
+    /// Device names from the `IfDevice` field. Names prefixed with `!` exclude a device.
+    pub if_device: Vec<String>,
+}
+
+impl AutoOpenSpec {
+    fn from_entry(entry: &Entry) -> Option<AutoOpenSpec> {
+        let url = entry.get_url().map(str::trim).filter(|u| !u.is_empty())?;
+
+        let path = match url.get(..KDBX_SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(KDBX_SCHEME) => &url[KDBX_SCHEME.len()..],
+            _ => url,
+        };
+
+        let keyfile = match entry.get_username().filter(|u| !u.is_empty()) {
+            Some(path) => Some(KeyFileSource::Path(path.to_string())),
+            None => entry.attachments.first().cloned().map(KeyFileSource::Attachment),
+        };
+
+        let if_device = entry
+            .get(IF_DEVICE_FIELD_NAME)
+            .map(|devices| {
+                devices
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|d| !d.is_empty())
+                    .map(|d| d.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(AutoOpenSpec {
+            uuid: entry.uuid,
+            path: path.to_string(),
+            password: entry
+                .get_password()
+                .filter(|p| !p.is_empty())
+                .map(|p| p.to_string()),
+            keyfile,
+            if_device,
+        })
+    }
+
+    /// Whether the child database should be opened on the device with the given name.
+    ///
+    /// Like KeePass, a device is accepted if it is not explicitly excluded and either listed or
+    /// no devices are listed at all. Device names are compared case-insensitively.
+    pub fn applies_to_device(&self, device: &str) -> bool {
+        let mut has_inclusions = false;
+        let mut included = false;
+
+        for filter in &self.if_device {
+            match filter.strip_prefix('!') {
+                Some(excluded) => {
+                    if excluded.trim().eq_ignore_ascii_case(device) {
+                        return false;
+                    }
+                }
+                None => {
+                    has_inclusions = true;
+                    included |= filter.eq_ignore_ascii_case(device);
+                }
+            }
+        }
+
+        !has_inclusions || included
+    }
+}
+
+impl Database {
+    /// Get the child databases described by the entries in the top-level `AutoOpen` group.
+    ///
+    /// Entries without a URL and expired entries are skipped.
+    pub fn auto_open_specs(&self) -> Vec<AutoOpenSpec> {
+        let group = match self
+            .root
+            .groups()
+            .into_iter()
+            .find(|g| g.name == AUTO_OPEN_GROUP_NAME)
+        {
+            Some(group) => group,
+            None => return Vec::new(),
+        };
+
+        let now = Times::now();
+
+        group
+            .entries()
+            .into_iter()
+            .filter(|e| !(e.times.expires && matches!(e.get_expiry_time(), Some(t) if *t <= now)))
+            .filter_map(AutoOpenSpec::from_entry)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod auto_open_tests {
+    use super::{AutoOpenSpec, KeyFileSource, AUTO_OPEN_GROUP_NAME};
+    use crate::db::{AttachmentRef, Database, Entry, Group, Times, Value};
+
+    fn auto_open_entry(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry::new();
+        for (key, value) in fields {
+            entry
+                .fields
+                .insert((*key).into(), Value::Unprotected(value.to_string()));
+        }
+        entry
+    }
+
+    #[test]
+    fn auto_open_specs() {
+        let mut group = Group::new(AUTO_OPEN_GROUP_NAME);
+
+        group.add_child(auto_open_entry(&[
+            ("URL", "kdbx://{DB_DIR}/child.kdbx"),
+            ("Password", "secret"),
+            ("UserName", "child.keyx"),
+        ]));
+
+        let mut with_attachment = auto_open_entry(&[("URL", "/data/other.kdbx")]);
+        with_attachment.attachments.push(AttachmentRef {
+            name: "other.key".to_string(),
+            identifier: "0".to_string(),
+        });
+        group.add_child(with_attachment);
+
+        let mut expired = auto_open_entry(&[("URL", "/data/expired.kdbx")]);
+        expired.times.expires = true;
+        expired.times.set_expiry(Times::epoch());
+        group.add_child(expired);
+
+        group.add_child(auto_open_entry(&[("Title", "No URL")]));
+
+        let mut db = Database::new(Default::default());
+        assert!(db.auto_open_specs().is_empty());
+        db.root.add_child(group);
+
+        let specs = db.auto_open_specs();
+        assert_eq!(specs.len(), 2);
+
+        assert_eq!(specs[0].path, "{DB_DIR}/child.kdbx");
+        assert_eq!(specs[0].password.as_deref(), Some("secret"));
+        assert_eq!(
+            specs[0].keyfile,
+            Some(KeyFileSource::Path("child.keyx".to_string()))
+        );
+
+        assert_eq!(specs[1].path, "/data/other.kdbx");
+        assert_eq!(specs[1].password, None);
+        assert!(matches!(specs[1].keyfile, Some(KeyFileSource::Attachment(ref a)) if a.name == "other.key"));
+    }
+
+    #[test]
+    fn if_device() {
+        let spec = |devices: &str| {
+            AutoOpenSpec::from_entry(&auto_open_entry(&[("URL", "db.kdbx"), ("IfDevice", devices)])).unwrap()
+        };
+
+        assert!(spec("").applies_to_device("laptop"));
+        assert!(spec("Laptop, desktop").applies_to_device("laptop"));
+        assert!(!spec("desktop").applies_to_device("laptop"));
+        assert!(!spec("!laptop").applies_to_device("laptop"));
+        assert!(spec("!laptop").applies_to_device("desktop"));
+    }
+}
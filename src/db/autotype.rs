@@ -0,0 +1,283 @@
+use crate::db::{
+    launch::expand_entry_placeholders, tracked::find_with_ancestors, Database, Entry, EntryId, NodeRef,
+};
+
+/// The autotype sequence that KeePass uses if neither the entry nor any of its groups define one
+pub const DEFAULT_AUTOTYPE_SEQUENCE: &str = "{USERNAME}{TAB}{PASSWORD}{ENTER}";
+
+/// Treat empty sequences like missing ones, as KeePass does
+fn non_empty(sequence: Option<&String>) -> Option<&str> {
+    sequence.map(String::as_str).filter(|s| !s.is_empty())
+}
+
+/// Check whether a window title matches the window of an autotype association, as KeePass does.
+///
+/// Windows given as `//regex//` are matched by searching the title for the regular expression,
+/// all others are matched against the whole title with the wildcards `*` and `?`. Both are
+/// matched case-insensitively.
+pub fn window_matches(window: &str, title: &str) -> bool {
+    if window.len() > 4 && window.starts_with("//") && window.ends_with("//") {
+        return regex_lite::RegexBuilder::new(&window[2..window.len() - 2])
+            .case_insensitive(true)
+            .build()
+            .is_ok_and(|re| re.is_match(title));
+    }
+
+    let window: Vec<char> = window.to_lowercase().chars().collect();
+    let title: Vec<char> = title.to_lowercase().chars().collect();
+    glob_matches(&window, &title)
+}
+
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    // position after the last `*` in the pattern and the text, to backtrack to on a mismatch
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Entry {
+    /// Get the autotype sequence to type into the window with the given title, or `None` if
+    /// the entry should not be typed into that window.
+    ///
+    /// The window associations of the entry are checked in order using [`window_matches`], after
+    /// expanding placeholders like `{TITLE}` in their windows, and the sequence of the first
+    /// matching association is returned. If no association matches, windows whose title contains
+    /// the title of the entry match as well. Associations without a sequence of their own use the
+    /// sequence of the entry, or [`DEFAULT_AUTOTYPE_SEQUENCE`].
+    ///
+    /// Use [`Database::autotype_for_window`] to take the settings of the groups of the entry
+    /// into account.
+    pub fn autotype_for_window(&self, window_title: &str) -> Option<&str> {
+        if self.autotype.as_ref().is_some_and(|autotype| !autotype.enabled) {
+            return None;
+        }
+
+        let default = self
+            .autotype
+            .as_ref()
+            .and_then(|autotype| non_empty(autotype.sequence.as_ref()))
+            .unwrap_or(DEFAULT_AUTOTYPE_SEQUENCE);
+        self.match_window(window_title, default)
+    }
+
+    fn match_window<'a>(&'a self, window_title: &str, default: &'a str) -> Option<&'a str> {
+        if let Some(autotype) = &self.autotype {
+            for association in &autotype.associations {
+                let window = match association.window.as_deref() {
+                    Some(w) if !w.is_empty() => expand_entry_placeholders(self, w),
+                    _ => continue,
+                };
+                if window_matches(&window, window_title) {
+                    return Some(non_empty(association.sequence.as_ref()).unwrap_or(default));
+                }
+            }
+        }
+
+        match self.get_title() {
+            Some(title) if !title.is_empty() && window_title.to_lowercase().contains(&title.to_lowercase()) => {
+                Some(default)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Database {
+    /// Get the autotype sequence to type for the entry with the given identifier into the window
+    /// with the given title, see [`Entry::autotype_for_window`].
+    ///
+    /// Unlike [`Entry::autotype_for_window`], this takes into account whether autotype is
+    /// disabled for a group of the entry, and falls back to the default autotype sequences of
+    /// its groups as given by [`Database::effective_autotype_sequence`]. Returns `None` if there
+    /// is no such entry or it should not be typed into the window.
+    pub fn autotype_for_window(&self, id: EntryId, window_title: &str) -> Option<&str> {
+        let (entry, ancestors) = match find_with_ancestors(&self.root, id.as_uuid())? {
+            (NodeRef::Entry(e), ancestors) => (e, ancestors),
+            (NodeRef::Group(_), _) => return None,
+        };
+
+        if entry.autotype.as_ref().is_some_and(|autotype| !autotype.enabled) {
+            return None;
+        }
+
+        // the closest group that enables or disables autotype decides, "null" inherits
+        let group_enabled = ancestors.iter().rev().find_map(|g| {
+            match g.enable_autotype.as_deref().map(str::to_lowercase).as_deref() {
+                Some("true") => Some(true),
+                Some("false") => Some(false),
+                _ => None,
+            }
+        });
+        if group_enabled == Some(false) {
+            return None;
+        }
+
+        entry.match_window(window_title, self.effective_autotype_sequence(id)?)
+    }
+
+    /// Get the autotype sequence to type for the entry with the given identifier.
+    ///
+    /// This is the sequence of the entry itself if it has one, otherwise the default autotype
+    /// sequence of the closest group that has one, and [`DEFAULT_AUTOTYPE_SEQUENCE`] if no group
+    /// defines a sequence. Returns `None` if there is no such entry.
+    pub fn effective_autotype_sequence(&self, id: EntryId) -> Option<&str> {
+        let (entry, ancestors) = match find_with_ancestors(&self.root, id.as_uuid())? {
+            (NodeRef::Entry(e), ancestors) => (e, ancestors),
+            (NodeRef::Group(_), _) => return None,
+        };
+
+        let own = entry
+            .autotype
+            .as_ref()
+            .and_then(|autotype| non_empty(autotype.sequence.as_ref()));
+
+        Some(
+            own.or_else(|| {
+                ancestors
+                    .iter()
+                    .rev()
+                    .find_map(|g| non_empty(g.default_autotype_sequence.as_ref()))
+            })
+            .unwrap_or(DEFAULT_AUTOTYPE_SEQUENCE),
+        )
+    }
+}
+
+#[cfg(test)]
+mod autotype_tests {
+    use super::{window_matches, DEFAULT_AUTOTYPE_SEQUENCE};
+    use crate::db::{AutoType, AutoTypeAssociation, Database, Entry, Group};
+
+    #[test]
+    fn match_windows() {
+        assert!(window_matches("*Firefox*", "Example - Mozilla Firefox"));
+        assert!(window_matches("example - ?ozilla*", "Example - Mozilla Firefox"));
+        assert!(window_matches("Login", "login"));
+        assert!(!window_matches("Login", "Login - Example"));
+        assert!(!window_matches("*Chrome*", "Example - Mozilla Firefox"));
+        assert!(window_matches("*a*b*", "xaxxbx"));
+
+        assert!(window_matches(
+            "//mozilla (firefox|thunderbird)//",
+            "Example - Mozilla Firefox"
+        ));
+        assert!(!window_matches("//^Firefox//", "Example - Mozilla Firefox"));
+        assert!(!window_matches("//(//", "("));
+    }
+
+    #[test]
+    fn autotype_for_window() {
+        let mut entry = Entry::new();
+        entry.set_title("Example");
+        entry.autotype = Some(AutoType {
+            enabled: true,
+            sequence: Some("{PASSWORD}{ENTER}".to_string()),
+            associations: vec![
+                AutoTypeAssociation {
+                    window: Some("Login - *".to_string()),
+                    sequence: Some("{USERNAME}{ENTER}".to_string()),
+                },
+                AutoTypeAssociation {
+                    window: Some("*{TITLE} Portal*".to_string()),
+                    sequence: None,
+                },
+            ],
+        });
+
+        assert_eq!(
+            entry.autotype_for_window("Login - Browser"),
+            Some("{USERNAME}{ENTER}")
+        );
+        assert_eq!(
+            entry.autotype_for_window("The Example Portal"),
+            Some("{PASSWORD}{ENTER}")
+        );
+        assert_eq!(
+            entry.autotype_for_window("example.com - Browser"),
+            Some("{PASSWORD}{ENTER}")
+        );
+        assert_eq!(entry.autotype_for_window("Other - Browser"), None);
+
+        let mut db = Database::new(Default::default());
+        let mut group = Group::new("group");
+        group.default_autotype_sequence = Some("{USERNAME}{TAB}{PASSWORD}".to_string());
+        let mut inherits = Entry::new();
+        inherits.set_title("Example");
+        let inherits_id = inherits.id();
+        group.add_child(inherits);
+        db.root.add_child(group);
+
+        assert_eq!(
+            db.autotype_for_window(inherits_id, "Example"),
+            Some("{USERNAME}{TAB}{PASSWORD}")
+        );
+
+        db.root.groups_mut()[0].enable_autotype = Some("false".to_string());
+        assert_eq!(db.autotype_for_window(inherits_id, "Example"), None);
+    }
+
+    #[test]
+    fn effective_autotype_sequence() {
+        let mut db = Database::new(Default::default());
+
+        let mut outer = Group::new("outer");
+        outer.default_autotype_sequence = Some("{PASSWORD}{ENTER}".to_string());
+        let mut inner = Group::new("inner");
+        inner.default_autotype_sequence = Some(String::new());
+
+        let inherits = Entry::new();
+        let mut own = Entry::new();
+        own.autotype = Some(AutoType {
+            enabled: true,
+            sequence: Some("{USERNAME}{ENTER}".to_string()),
+            associations: Vec::new(),
+        });
+        let (inherits_id, own_id) = (inherits.id(), own.id());
+
+        inner.add_child(inherits);
+        inner.add_child(own);
+        let inner_id = inner.id();
+        outer.add_child(inner);
+        db.root.add_child(outer);
+
+        let top_level = Entry::new();
+        let top_level_id = top_level.id();
+        db.root.add_child(top_level);
+
+        assert_eq!(db.effective_autotype_sequence(own_id), Some("{USERNAME}{ENTER}"));
+        assert_eq!(
+            db.effective_autotype_sequence(inherits_id),
+            Some("{PASSWORD}{ENTER}")
+        );
+        assert_eq!(
+            db.effective_autotype_sequence(top_level_id),
+            Some(DEFAULT_AUTOTYPE_SEQUENCE)
+        );
+        assert_eq!(
+            db.effective_autotype_sequence(uuid::Uuid::from(inner_id).into()),
+            None
+        );
+    }
+}
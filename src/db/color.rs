@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use crate::{
+    db::{Entry, Meta, Times},
+    error::ParseColorError,
+};
+
+/// A color value for the Database, or Entry
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::from_rgb(0x00, 0x00, 0x00);
+    pub const WHITE: Color = Color::from_rgb(0xff, 0xff, 0xff);
+    pub const RED: Color = Color::from_rgb(0xff, 0x80, 0x80);
+    pub const ORANGE: Color = Color::from_rgb(0xff, 0xc0, 0x80);
+    pub const YELLOW: Color = Color::from_rgb(0xff, 0xff, 0x80);
+    pub const GREEN: Color = Color::from_rgb(0x80, 0xff, 0x80);
+    pub const CYAN: Color = Color::from_rgb(0x80, 0xff, 0xff);
+    pub const BLUE: Color = Color::from_rgb(0x80, 0x80, 0xff);
+    pub const MAGENTA: Color = Color::from_rgb(0xff, 0x80, 0xff);
+    pub const GRAY: Color = Color::from_rgb(0xc0, 0xc0, 0xc0);
+
+    /// Light colors for highlighting entries or databases, on which black text stays readable
+    pub const PALETTE: [Color; 8] = [
+        Color::RED,
+        Color::ORANGE,
+        Color::YELLOW,
+        Color::GREEN,
+        Color::CYAN,
+        Color::BLUE,
+        Color::MAGENTA,
+        Color::GRAY,
+    ];
+
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+
+    /// Relative luminance of the color as defined by WCAG 2, between 0 for black and 1 for white
+    pub fn luminance(&self) -> f64 {
+        let linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Contrast ratio between this color and another one as defined by WCAG 2, between 1 for
+    /// equal colors and 21 for black on white
+    pub fn contrast_ratio(&self, other: &Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Suggest black or white as the color of text on a background of this color, whichever
+    /// contrasts more
+    pub fn text_color(&self) -> Color {
+        if self.contrast_ratio(&Color::BLACK) >= self.contrast_ratio(&Color::WHITE) {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        }
+    }
+}
+
+impl From<(u8, u8, u8)> for Color {
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Color { r, g, b }
+    }
+}
+
+impl From<[u8; 3]> for Color {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        Color { r, g, b }
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl serde::Serialize for Color {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('#') || s.len() != 7 {
+            return Err(ParseColorError(s.to_string()));
+        }
+
+        let v =
+            u64::from_str_radix(s.trim_start_matches('#'), 16).map_err(|_e| ParseColorError(s.to_string()))?;
+
+        let r = ((v >> 16) & 0xff) as u8;
+        let g = ((v >> 8) & 0xff) as u8;
+        let b = (v & 0xff) as u8;
+
+        Ok(Self { r, g, b })
+    }
+}
+
+impl Entry {
+    /// Set the text color of the entry, or reset it with `None`
+    pub fn set_foreground_color(&mut self, color: Option<impl Into<Color>>) {
+        self.foreground_color = color.map(Into::into);
+        self.times.set_last_modification(Times::now());
+    }
+
+    /// Set the background color of the entry, or reset it with `None`
+    pub fn set_background_color(&mut self, color: Option<impl Into<Color>>) {
+        self.background_color = color.map(Into::into);
+        self.times.set_last_modification(Times::now());
+    }
+}
+
+impl Meta {
+    /// Set the color of the database, or reset it with `None`
+    pub fn set_color(&mut self, color: Option<impl Into<Color>>) {
+        self.color = color.map(Into::into);
+        self.settings_changed = Some(Times::now());
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::Color;
+    use crate::db::Entry;
+
+    #[test]
+    fn string_round_trip() {
+        let color = Color::from_rgb(0x0a, 0xb0, 0x01);
+        assert_eq!(color.to_string(), "#0ab001");
+        assert_eq!("#0AB001".parse::<Color>().unwrap(), color);
+        assert!("0ab001".parse::<Color>().is_err());
+        assert!("#0ab0".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn contrast() {
+        assert_eq!(Color::BLACK.contrast_ratio(&Color::WHITE).round(), 21.0);
+        assert_eq!(Color::RED.contrast_ratio(&Color::RED), 1.0);
+
+        assert_eq!(Color::from_rgb(0x20, 0x20, 0x60).text_color(), Color::WHITE);
+        for color in Color::PALETTE {
+            assert_eq!(color.text_color(), Color::BLACK);
+        }
+    }
+
+    #[test]
+    fn entry_colors() {
+        let mut entry = Entry::new();
+        entry.set_background_color(Some((0xff, 0x80, 0x80)));
+        entry.set_foreground_color(Some(Color::BLUE));
+        assert_eq!(entry.background_color, Some(Color::RED));
+        assert_eq!(entry.foreground_color, Some(Color::BLUE));
+
+        entry.set_background_color(None::<Color>);
+        assert_eq!(entry.background_color, None);
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&Color::GREEN).unwrap();
+        assert_eq!(json, "\"#80ff80\"");
+        assert_eq!(serde_json::from_str::<Color>(&json).unwrap(), Color::GREEN);
+    }
+}
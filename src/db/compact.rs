@@ -0,0 +1,428 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Duration;
+use uuid::Uuid;
+
+use crate::{
+    config::DatabaseVersion,
+    db::{
+        tracked::find_group_mut, Database, DeletedObject, Entry, Group, HistoryPruneReport, Node, NodeRef,
+        Times,
+    },
+};
+
+/// Options for [`Database::compact`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactOptions {
+    /// Permanently remove the items of the recycle bin that were moved there longer ago than
+    /// this. The recycle bin is left alone if this is `None`.
+    pub recycle_bin_max_age: Option<Duration>,
+
+    /// Prune the history of all entries according to the limits in the metadata, see
+    /// [`Database::apply_history_limits`]
+    pub prune_history: bool,
+
+    /// Remove the records of deleted objects that are older than this. Other copies of the
+    /// database that were not synchronized since then will bring the deleted objects back when
+    /// merged, so this should be well beyond the synchronization interval. The records are kept
+    /// if this is `None`.
+    pub tombstone_max_age: Option<Duration>,
+
+    /// Remove binaries from the binary pool that no entry or history item refers to
+    pub remove_orphaned_attachments: bool,
+
+    /// Remove custom icons that no group, entry or history item uses
+    pub remove_unused_icons: bool,
+}
+
+impl Default for CompactOptions {
+    fn default() -> Self {
+        CompactOptions {
+            recycle_bin_max_age: None,
+            prune_history: true,
+            tombstone_max_age: None,
+            remove_orphaned_attachments: true,
+            remove_unused_icons: true,
+        }
+    }
+}
+
+/// Summary of the changes made by [`Database::compact`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Number of entries, including those in removed groups, removed from the recycle bin
+    pub recycled_entries_removed: usize,
+
+    /// Number of groups, including nested groups, removed from the recycle bin
+    pub recycled_groups_removed: usize,
+
+    /// History items removed by pruning the history of the remaining entries
+    pub history: HistoryPruneReport,
+
+    /// Number of records of deleted objects that were removed
+    pub tombstones_removed: usize,
+
+    /// Number of binaries that were removed from the binary pool
+    pub attachments_removed: usize,
+
+    /// Number of custom icons that were removed
+    pub icons_removed: usize,
+
+    /// Approximate total size of everything that was removed in bytes
+    pub bytes_reclaimed: usize,
+}
+
+impl CompactReport {
+    /// Whether nothing was removed
+    pub fn is_empty(&self) -> bool {
+        *self == CompactReport::default()
+    }
+}
+
+impl Database {
+    /// Remove data that is no longer needed, e.g. as a scheduled maintenance job.
+    ///
+    /// The recycle bin is emptied first, so that attachments and icons that were only used by
+    /// recycled items are removed as well. Removed recycle bin items are recorded as deleted
+    /// objects, which are not affected by [`CompactOptions::tombstone_max_age`] right away.
+    pub fn compact(&mut self, options: CompactOptions) -> CompactReport {
+        let mut report = CompactReport::default();
+        let now = Times::now();
+
+        if let Some(max_age) = options.recycle_bin_max_age {
+            self.empty_recycle_bin(now - max_age, &mut report);
+        }
+
+        if options.prune_history {
+            report.history = self.apply_history_limits();
+            report.bytes_reclaimed += report.history.bytes_removed;
+        }
+
+        if let Some(max_age) = options.tombstone_max_age {
+            report.tombstones_removed = self.prune_deleted_objects(now - max_age);
+        }
+
+        if options.remove_orphaned_attachments {
+            self.remove_orphaned_binaries(&mut report);
+        }
+
+        if options.remove_unused_icons {
+            self.remove_unused_icons(&mut report);
+        }
+
+        report
+    }
+
+    /// Remove the records of objects that were deleted before `older_than`, returning how many
+    /// records were removed.
+    ///
+    /// Deleted objects are recorded so that merging with another copy of the database deletes
+    /// them there as well. Once a record is removed, copies that still contain the object bring
+    /// it back when merged, so records should only be removed once all copies have been
+    /// synchronized. To prune them automatically when merging, set
+    /// [`DatabaseConfig::deleted_objects_retention`](crate::config::DatabaseConfig::deleted_objects_retention).
+    pub fn prune_deleted_objects(&mut self, older_than: chrono::NaiveDateTime) -> usize {
+        let before = self.deleted_objects.objects.len();
+        self.deleted_objects
+            .objects
+            .retain(|object| object.deletion_time >= older_than);
+        before - self.deleted_objects.objects.len()
+    }
+
+    fn empty_recycle_bin(&mut self, cutoff: chrono::NaiveDateTime, report: &mut CompactReport) {
+        let binary_sizes = self.binary_sizes();
+
+        // KeePass stores the nil UUID if there is no recycle bin
+        let recycle_bin = match self.meta.recyclebin_uuid.filter(|uuid| !uuid.is_nil()) {
+            Some(uuid) => uuid,
+            None => return,
+        };
+        let recycle_bin = match find_group_mut(&mut self.root, &recycle_bin) {
+            Some(group) => group,
+            None => return,
+        };
+
+        // items moved to the recycle bin have their location changed at that time
+        let recycled_at = |node: &Node| {
+            let times = match node {
+                Node::Entry(e) => &e.times,
+                Node::Group(g) => &g.times,
+            };
+            times
+                .get_location_changed()
+                .or(times.get_last_modification())
+                .copied()
+        };
+
+        let (removed, kept): (Vec<Node>, Vec<Node>) = std::mem::take(&mut recycle_bin.children)
+            .into_iter()
+            .partition(|node| recycled_at(node).is_some_and(|time| time < cutoff));
+        recycle_bin.children = kept;
+
+        for node in removed {
+            let removed_nodes: Vec<NodeRef> = match &node {
+                Node::Entry(e) => vec![NodeRef::Entry(e)],
+                Node::Group(g) => g.iter().collect(),
+            };
+
+            for removed_node in removed_nodes {
+                let uuid = match removed_node {
+                    NodeRef::Entry(e) => {
+                        report.recycled_entries_removed += 1;
+                        report.bytes_reclaimed += e.approximate_size(&binary_sizes)
+                            + e.history
+                                .iter()
+                                .flat_map(|h| h.get_entries())
+                                .map(|h| h.approximate_size(&binary_sizes))
+                                .sum::<usize>();
+                        e.uuid
+                    }
+                    NodeRef::Group(g) => {
+                        report.recycled_groups_removed += 1;
+                        g.uuid
+                    }
+                };
+
+                self.deleted_objects.objects.push(DeletedObject {
+                    uuid,
+                    deletion_time: Times::now(),
+                });
+            }
+        }
+    }
+
+    fn remove_orphaned_binaries(&mut self, report: &mut CompactReport) {
+        let mut used = HashSet::new();
+        for_each_entry_and_history(&self.root, &mut |entry| {
+            used.extend(entry.attachments.iter().map(|a| a.identifier.clone()));
+        });
+
+        match self.config.version {
+            DatabaseVersion::KDB4(_) => {
+                // header attachments are referenced by their position, so the remaining ones need
+                // to be renumbered
+                let mut renumbered = HashMap::new();
+                let mut kept = Vec::new();
+                for (index, attachment) in std::mem::take(&mut self.header_attachments)
+                    .into_iter()
+                    .enumerate()
+                {
+                    if used.contains(&index.to_string()) {
+                        renumbered.insert(index.to_string(), kept.len().to_string());
+                        kept.push(attachment);
+                    } else {
+                        report.attachments_removed += 1;
+                        report.bytes_reclaimed += attachment.content.len();
+                    }
+                }
+                self.header_attachments = kept;
+
+                for_each_entry_and_history_mut(&mut self.root, &mut |entry| {
+                    for attachment in entry.attachments.iter_mut() {
+                        if let Some(identifier) = renumbered.get(&attachment.identifier) {
+                            attachment.identifier = identifier.clone();
+                        }
+                    }
+                });
+            }
+            _ => {
+                self.meta.binaries.binaries.retain(|binary| {
+                    let keep = binary.identifier.as_ref().is_some_and(|id| used.contains(id));
+                    if !keep {
+                        report.attachments_removed += 1;
+                        report.bytes_reclaimed += binary.content.len();
+                    }
+                    keep
+                });
+            }
+        }
+    }
+
+    fn remove_unused_icons(&mut self, report: &mut CompactReport) {
+        let mut used: HashSet<Uuid> = HashSet::new();
+        for node in self.root.iter() {
+            if let NodeRef::Group(g) = node {
+                used.extend(g.custom_icon_uuid);
+            }
+        }
+        for_each_entry_and_history(&self.root, &mut |entry| {
+            used.extend(entry.custom_icon_uuid);
+        });
+
+        self.meta.custom_icons.icons.retain(|icon| {
+            let keep = used.contains(&icon.uuid);
+            if !keep {
+                report.icons_removed += 1;
+                report.bytes_reclaimed += icon.data.len();
+            }
+            keep
+        });
+    }
+}
+
+fn for_each_entry_and_history<F: FnMut(&Entry)>(group: &Group, f: &mut F) {
+    for node in group.iter() {
+        if let NodeRef::Entry(e) = node {
+            f(e);
+            for item in e.history.iter().flat_map(|h| h.get_entries()) {
+                f(item);
+            }
+        }
+    }
+}
+
+fn for_each_entry_and_history_mut<F: FnMut(&mut Entry)>(group: &mut Group, f: &mut F) {
+    group.for_each_entry_mut(&mut |entry| {
+        f(entry);
+        if let Some(history) = entry.history.as_mut() {
+            for item in history.entries.iter_mut() {
+                f(item);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod compact_tests {
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    use super::CompactOptions;
+    use crate::{
+        config::{DatabaseConfig, DatabaseVersion},
+        db::{
+            AttachmentRef, BinaryAttachment, Database, DeletedObject, Entry, Group, HeaderAttachment, History,
+            Icon, Times,
+        },
+    };
+
+    fn attachment(identifier: &str) -> AttachmentRef {
+        AttachmentRef {
+            name: format!("{}.txt", identifier),
+            identifier: identifier.to_string(),
+        }
+    }
+
+    #[test]
+    fn compact() {
+        let mut db = Database::new(Default::default());
+        let long_ago = Times::now() - Duration::days(60);
+
+        for content in ["orphaned", "used", "used by history", "recycled"] {
+            db.header_attachments.push(HeaderAttachment {
+                flags: 0,
+                content: content.as_bytes().to_vec(),
+            });
+        }
+
+        let used_icon = Uuid::new_v4();
+        let unused_icon = Uuid::new_v4();
+        for uuid in [used_icon, unused_icon] {
+            db.meta.custom_icons.icons.push(Icon {
+                uuid,
+                data: vec![0; 10],
+            });
+        }
+
+        let mut entry = Entry::new();
+        entry.attachments.push(attachment("1"));
+        let mut old_version = entry.clone();
+        old_version.attachments = vec![attachment("2")];
+        old_version.custom_icon_uuid = Some(used_icon);
+        let mut history = History::default();
+        history.add_entry(old_version);
+        entry.history = Some(history);
+        let entry_id = entry.id();
+        db.root.add_child(entry);
+
+        let mut recycle_bin = Group::new("Recycle Bin");
+        let mut recycled = Group::new("Recycled");
+        recycled.times.set_location_changed(long_ago);
+        let mut recycled_entry = Entry::new();
+        recycled_entry.attachments.push(attachment("3"));
+        recycled_entry.custom_icon_uuid = Some(unused_icon);
+        let recycled_entry_uuid = recycled_entry.uuid;
+        recycled.add_child(recycled_entry);
+        let mut recent = Entry::new();
+        recent.times.set_location_changed(Times::now());
+        let recent_id = recent.id();
+        recycle_bin.add_child(recycled);
+        recycle_bin.add_child(recent);
+        db.meta.recyclebin_uuid = Some(recycle_bin.uuid);
+        db.root.add_child(recycle_bin);
+
+        let old_tombstone = Uuid::new_v4();
+        db.deleted_objects.objects.push(DeletedObject {
+            uuid: old_tombstone,
+            deletion_time: long_ago,
+        });
+
+        // the recycle bin and deleted objects are left alone by default
+        let mut unchanged = db.clone();
+        let report = unchanged.compact(CompactOptions {
+            remove_orphaned_attachments: false,
+            remove_unused_icons: false,
+            ..Default::default()
+        });
+        assert!(report.is_empty());
+        assert_eq!(unchanged, db);
+
+        let report = db.compact(CompactOptions {
+            recycle_bin_max_age: Some(Duration::days(30)),
+            tombstone_max_age: Some(Duration::days(30)),
+            ..Default::default()
+        });
+        assert_eq!(report.recycled_entries_removed, 1);
+        assert_eq!(report.recycled_groups_removed, 1);
+        assert_eq!(report.tombstones_removed, 1);
+        assert_eq!(report.attachments_removed, 2);
+        assert_eq!(report.icons_removed, 1);
+        assert!(report.bytes_reclaimed >= "orphaned".len() + "recycled".len() + 10);
+
+        // the remaining binaries are renumbered
+        assert_eq!(db.header_attachments.len(), 2);
+        let entry = db.entry_by_id(entry_id).unwrap();
+        assert_eq!(
+            db.binary_content(&entry.attachments[0].identifier).unwrap().0,
+            b"used"
+        );
+        let old_version = &entry.history.as_ref().unwrap().get_entries()[0];
+        assert_eq!(
+            db.binary_content(&old_version.attachments[0].identifier)
+                .unwrap()
+                .0,
+            b"used by history"
+        );
+
+        assert!(db.entry_by_id(recent_id).is_some());
+        let deleted: Vec<Uuid> = db.deleted_objects.objects.iter().map(|d| d.uuid).collect();
+        assert!(deleted.contains(&recycled_entry_uuid));
+        assert!(!deleted.contains(&old_tombstone));
+        assert_eq!(db.meta.custom_icons.icons.len(), 1);
+        assert!(db.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn compact_kdbx3_binaries() {
+        let mut db = Database::new(DatabaseConfig {
+            version: DatabaseVersion::KDB3(1),
+            ..Default::default()
+        });
+        for identifier in ["0", "1"] {
+            db.meta.binaries.binaries.push(BinaryAttachment {
+                identifier: Some(identifier.to_string()),
+                compressed: false,
+                protected: false,
+                content: b"content".to_vec(),
+            });
+        }
+        let mut entry = Entry::new();
+        entry.attachments.push(attachment("1"));
+        db.root.add_child(entry);
+
+        let report = db.compact(Default::default());
+        assert_eq!(report.attachments_removed, 1);
+        assert_eq!(db.meta.binaries.binaries[0].identifier.as_deref(), Some("1"));
+        assert!(db.verify_integrity().is_ok());
+    }
+}
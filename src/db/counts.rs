@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::db::{
+    index::ParentMap, tracked::find_with_ancestors, ChangeEvent, Database, Group, GroupId, Node, NodeRef,
+    TrackedDatabase,
+};
+
+/// Number of entries and groups below a group, including those in its subgroups
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Counts {
+    entries: usize,
+    groups: usize,
+}
+
+/// Cached recursive entry and group counts of every group of a database, e.g. for the badges of
+/// a tree view that should not walk the whole tree on every redraw.
+///
+/// Like [`SearchIndex`](crate::db::SearchIndex), the counts are built once with
+/// [`GroupCounts::build`] and can then be kept up to date by applying the [`ChangeEvent`]s of a
+/// [`TrackedDatabase`], which [`TrackedDatabase::enable_group_counts`] does automatically.
+/// Changes that are made to the database in other ways require the counts to be built again.
+///
+/// History items are not counted.
+#[derive(Debug, Clone, Default)]
+pub struct GroupCounts {
+    /// UUID of the root group
+    root: Uuid,
+
+    /// Counts of each group, including the root group
+    counts: HashMap<Uuid, Counts>,
+
+    /// Parent group of each group, except for the root group
+    parents: ParentMap,
+}
+
+impl GroupCounts {
+    /// Count the entries and groups below every group of a database
+    pub fn build(db: &Database) -> GroupCounts {
+        let mut counts = GroupCounts {
+            root: db.root.uuid,
+            ..Default::default()
+        };
+        counts.add_group(&db.root);
+        counts
+    }
+
+    /// Record the counts of `group` and its subgroups, returning the counts of `group`
+    fn add_group(&mut self, group: &Group) -> Counts {
+        let mut counts = Counts::default();
+        for node in &group.children {
+            match node {
+                Node::Entry(_) => counts.entries += 1,
+                Node::Group(g) => {
+                    self.parents.insert(g.uuid, group.uuid);
+                    let below = self.add_group(g);
+                    counts.entries += below.entries;
+                    counts.groups += below.groups + 1;
+                }
+            }
+        }
+        self.counts.insert(group.uuid, counts);
+        counts
+    }
+
+    /// Forget a group and everything below it, returning the counts of the group
+    fn remove_group(&mut self, uuid: Uuid) -> Counts {
+        let groups = self.parents.remove_group(uuid);
+        let removed = self.counts.get(&uuid).copied().unwrap_or_default();
+        for group in &groups {
+            self.counts.remove(group);
+        }
+        removed
+    }
+
+    /// Add `delta` to, or subtract it from, the counts of `group` and all its ancestors
+    fn update_chain(&mut self, group: Uuid, delta: Counts, add: bool) {
+        for g in self.parents.ancestors(group) {
+            if let Some(counts) = self.counts.get_mut(&g) {
+                if add {
+                    counts.entries += delta.entries;
+                    counts.groups += delta.groups;
+                } else {
+                    counts.entries = counts.entries.saturating_sub(delta.entries);
+                    counts.groups = counts.groups.saturating_sub(delta.groups);
+                }
+            }
+        }
+    }
+
+    /// Update the counts for a change that was made to `db`
+    pub fn apply(&mut self, db: &Database, event: &ChangeEvent) {
+        const ONE_ENTRY: Counts = Counts {
+            entries: 1,
+            groups: 0,
+        };
+
+        match event {
+            ChangeEvent::EntryAdded { parent, .. } => self.update_chain(*parent, ONE_ENTRY, true),
+            ChangeEvent::EntryRemoved { parent, .. } => self.update_chain(*parent, ONE_ENTRY, false),
+            ChangeEvent::EntryMoved { from, to, .. } => {
+                self.update_chain(*from, ONE_ENTRY, false);
+                self.update_chain(*to, ONE_ENTRY, true);
+            }
+            ChangeEvent::GroupAdded { uuid, parent } => {
+                if let Some((NodeRef::Group(g), _)) = find_with_ancestors(&db.root, uuid) {
+                    self.parents.insert(*uuid, *parent);
+                    let added = self.add_group(g);
+                    self.update_chain(*parent, with_group(added), true);
+                }
+            }
+            ChangeEvent::GroupRemoved { uuid, parent } => {
+                let removed = self.remove_group(*uuid);
+                self.update_chain(*parent, with_group(removed), false);
+            }
+            ChangeEvent::GroupMoved { uuid, from, to } => {
+                let moved = with_group(self.counts.get(uuid).copied().unwrap_or_default());
+                self.update_chain(*from, moved, false);
+                self.parents.insert(*uuid, *to);
+                self.update_chain(*to, moved, true);
+            }
+            ChangeEvent::FieldChanged { .. }
+            | ChangeEvent::EntryModified { .. }
+            | ChangeEvent::GroupModified { .. } => {}
+        }
+    }
+
+    /// Number of entries in the group `group` and its subgroups, or `None` if there is no such
+    /// group
+    pub fn recursive_entry_count(&self, group: GroupId) -> Option<usize> {
+        self.counts.get(group.as_uuid()).map(|c| c.entries)
+    }
+
+    /// Number of groups below the group `group`, not counting the group itself, or `None` if
+    /// there is no such group
+    pub fn recursive_group_count(&self, group: GroupId) -> Option<usize> {
+        self.counts.get(group.as_uuid()).map(|c| c.groups)
+    }
+
+    /// Number of entries in the whole database
+    pub fn total_entries(&self) -> usize {
+        self.counts.get(&self.root).map_or(0, |c| c.entries)
+    }
+}
+
+/// The counts of a group plus the group itself, as seen from its parent
+fn with_group(counts: Counts) -> Counts {
+    Counts {
+        entries: counts.entries,
+        groups: counts.groups + 1,
+    }
+}
+
+impl TrackedDatabase {
+    /// Count the entries and groups below every group, and keep the [`GroupCounts`] up to date
+    /// with every change made through the `TrackedDatabase`
+    pub fn enable_group_counts(&mut self) {
+        self.counts = Some(GroupCounts::build(&self.db));
+    }
+
+    /// Stop maintaining the group counts
+    pub fn disable_group_counts(&mut self) {
+        self.counts = None;
+    }
+
+    /// The group counts, if they were enabled with [`TrackedDatabase::enable_group_counts`]
+    pub fn group_counts(&self) -> Option<&GroupCounts> {
+        self.counts.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod counts_tests {
+    use super::GroupCounts;
+    use crate::db::{Database, Entry, Group, GroupId, TrackedDatabase};
+
+    fn counts(db: &TrackedDatabase, group: uuid::Uuid) -> (usize, usize) {
+        let counts = db.group_counts().unwrap();
+        (
+            counts.recursive_entry_count(group.into()).unwrap(),
+            counts.recursive_group_count(group.into()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let root = db.database().root.uuid;
+        let mut work = Group::new("Work");
+        work.add_child(Entry::new());
+        work.add_child(Group::new("Projects"));
+        let work = db.add_group(root, work).unwrap();
+        db.add_entry(root, Entry::new()).unwrap();
+        db.enable_group_counts();
+
+        assert_eq!(counts(&db, root), (2, 2));
+        assert_eq!(counts(&db, work), (1, 1));
+
+        let personal = db.add_group(work, Group::new("Personal")).unwrap();
+        let entry = db.add_entry(personal, Entry::new()).unwrap();
+        assert_eq!(counts(&db, root), (3, 3));
+        assert_eq!(counts(&db, work), (2, 2));
+        assert_eq!(counts(&db, personal), (1, 0));
+
+        db.move_node(personal, root).unwrap();
+        assert_eq!(counts(&db, root), (3, 3));
+        assert_eq!(counts(&db, work), (1, 1));
+
+        db.move_node(entry, work).unwrap();
+        assert_eq!(counts(&db, personal), (0, 0));
+        assert_eq!(counts(&db, work), (2, 1));
+
+        db.enable_undo(10);
+        db.remove(work).unwrap();
+        assert_eq!(counts(&db, root), (1, 1));
+        assert_eq!(
+            db.group_counts().unwrap().recursive_entry_count(work.into()),
+            None
+        );
+
+        // undoing changes updates the counts as well
+        db.undo();
+        assert_eq!(counts(&db, root), (3, 3));
+        assert_eq!(counts(&db, work), (2, 1));
+
+        // the incremental counts match freshly built ones
+        let built = GroupCounts::build(db.database());
+        let group_ids: Vec<GroupId> = [root, work, personal]
+            .iter()
+            .copied()
+            .map(GroupId::from)
+            .collect();
+        for group in group_ids {
+            assert_eq!(
+                built.recursive_entry_count(group),
+                db.group_counts().unwrap().recursive_entry_count(group)
+            );
+        }
+        assert_eq!(db.group_counts().unwrap().total_entries(), 3);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use crate::db::{
+    tracked::{find_entry_mut, take_node},
+    Database, DeletedObject, Entry, EntryId, History, Node, NodeRef, Times, Value,
+};
+
+/// Fields whose values identify duplicate entries in [`Database::find_duplicates`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DuplicateKey {
+    /// Entries with the same title, user name and URL, i.e. the same account stored several times
+    Identity,
+
+    /// Entries with the same password, to detect passwords that are re-used across accounts
+    Password,
+
+    /// Entries with the same values of all of the given fields
+    Fields(Vec<String>),
+}
+
+impl DuplicateKey {
+    fn fields(&self) -> Vec<&str> {
+        match self {
+            DuplicateKey::Identity => vec!["Title", "UserName", "URL"],
+            DuplicateKey::Password => vec!["Password"],
+            DuplicateKey::Fields(fields) => fields.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// Which entry of a cluster of duplicates is kept by [`Database::merge_duplicates`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateMergeStrategy {
+    /// Keep the entry that was modified last
+    KeepNewest,
+
+    /// Keep the entry that was modified first
+    KeepOldest,
+
+    /// Keep the first entry of the cluster
+    KeepFirst,
+}
+
+fn value_bytes(value: Option<&Value>) -> &[u8] {
+    match value {
+        Some(Value::Bytes(b)) => b,
+        Some(Value::Unprotected(s)) => s.as_bytes(),
+        Some(Value::Protected(p)) => p.unsecure(),
+        Some(v @ Value::Encrypted(_)) => v.protected_bytes().unwrap_or_default(),
+        None => &[],
+    }
+}
+
+impl Database {
+    /// Find entries that have the same values for the fields given by `key`, e.g. the same
+    /// account that was imported from several sources.
+    ///
+    /// Returns clusters of at least two entries in the order in which they appear in the
+    /// database. Entries for which all fields of the key are empty are not considered
+    /// duplicates, and history items are ignored.
+    pub fn find_duplicates(&self, key: &DuplicateKey) -> Vec<Vec<EntryId>> {
+        let fields = key.fields();
+        let mut clusters: Vec<Vec<EntryId>> = Vec::new();
+        let mut cluster_by_values: HashMap<Vec<&[u8]>, usize> = HashMap::new();
+
+        for entry in self.root.iter().filter_map(|node| match node {
+            NodeRef::Entry(e) => Some(e),
+            NodeRef::Group(_) => None,
+        }) {
+            let values: Vec<&[u8]> = fields
+                .iter()
+                .map(|field| value_bytes(entry.fields.get(*field)))
+                .collect();
+            if values.iter().all(|v| v.is_empty()) {
+                continue;
+            }
+
+            match cluster_by_values.get(&values) {
+                Some(&i) => clusters[i].push(entry.id()),
+                None => {
+                    cluster_by_values.insert(values, clusters.len());
+                    clusters.push(vec![entry.id()]);
+                }
+            }
+        }
+
+        clusters.retain(|cluster| cluster.len() > 1);
+        clusters
+    }
+
+    /// Merge a cluster of duplicate entries, e.g. one returned by [`Database::find_duplicates`],
+    /// into a single entry chosen by `strategy`, returning its identifier.
+    ///
+    /// The kept entry gains the fields, attachments and tags that only the other entries have.
+    /// The other entries are removed from the database and recorded as deleted, and their
+    /// current states and histories are added to the history of the kept entry.
+    ///
+    /// Entries of the cluster that are not in the database are ignored. Returns `None` if none
+    /// of them are.
+    pub fn merge_duplicates(
+        &mut self,
+        cluster: &[EntryId],
+        strategy: DuplicateMergeStrategy,
+    ) -> Option<EntryId> {
+        let mut entries: Vec<Entry> = cluster
+            .iter()
+            .filter_map(|id| self.entry_by_id(*id).cloned())
+            .collect();
+
+        match strategy {
+            DuplicateMergeStrategy::KeepNewest => entries.sort_by(|a, b| {
+                b.times
+                    .get_last_modification()
+                    .cmp(&a.times.get_last_modification())
+            }),
+            DuplicateMergeStrategy::KeepOldest => entries.sort_by(|a, b| {
+                a.times
+                    .get_last_modification()
+                    .cmp(&b.times.get_last_modification())
+            }),
+            DuplicateMergeStrategy::KeepFirst => {}
+        }
+
+        let mut entries = entries.into_iter();
+        let mut kept = entries.next()?;
+        let mut history: Vec<Entry> = kept.history.take().map(|h| h.entries).unwrap_or_default();
+
+        for mut other in entries {
+            if let Some(Node::Entry(_)) = take_node(&mut self.root, &other.uuid).map(|(node, _, _)| node) {
+                self.deleted_objects.objects.push(DeletedObject {
+                    uuid: other.uuid,
+                    deletion_time: Times::now(),
+                });
+            }
+
+            for (field, value) in &other.fields {
+                kept.fields.entry(field.clone()).or_insert_with(|| value.clone());
+            }
+            for attachment in &other.attachments {
+                if !kept.attachments.iter().any(|a| a.name == attachment.name) {
+                    kept.attachments.push(attachment.clone());
+                }
+            }
+            for tag in &other.tags {
+                if !kept.tags.contains(tag) {
+                    kept.tags.push(tag.clone());
+                }
+            }
+
+            history.extend(other.history.take().map(|h| h.entries).unwrap_or_default());
+            history.push(other);
+        }
+
+        // history items are ordered from newest to oldest and carry the UUID of their entry
+        for item in history.iter_mut() {
+            item.uuid = kept.uuid;
+        }
+        history.sort_by(|a, b| {
+            b.times
+                .get_last_modification()
+                .cmp(&a.times.get_last_modification())
+        });
+        history.dedup();
+        kept.history = Some(History { entries: history });
+        kept.times.set_last_modification(Times::now());
+
+        let id = kept.id();
+        *find_entry_mut(&mut self.root, id.as_uuid())? = kept;
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod duplicates_tests {
+    use chrono::Duration;
+    use secstr::SecStr;
+
+    use super::{DuplicateKey, DuplicateMergeStrategy};
+    use crate::db::{AttachmentRef, Database, Entry, Group, Times, Value};
+
+    fn entry(title: &str, username: &str, password: &str) -> Entry {
+        let mut entry = Entry::new();
+        for (field, value) in [
+            ("Title", title),
+            ("UserName", username),
+            ("URL", "https://example.com"),
+        ] {
+            entry
+                .fields
+                .insert(field.into(), Value::Unprotected(value.to_string()));
+        }
+        entry.fields.insert(
+            "Password".into(),
+            Value::Protected(SecStr::new(password.as_bytes().to_vec())),
+        );
+        entry
+    }
+
+    #[test]
+    fn find_duplicates() {
+        let mut db = Database::new(Default::default());
+        let first = entry("Example", "alice", "secret");
+        let second = entry("Example", "bob", "secret");
+        let mut group = Group::new("Imported");
+        let third = entry("Example", "alice", "other");
+        let (first_id, second_id, third_id) = (first.id(), second.id(), third.id());
+        group.add_child(third);
+        db.root.add_child(first);
+        db.root.add_child(second);
+        db.root.add_child(group);
+        db.root.add_child(Entry::new());
+        db.root.add_child(Entry::new());
+
+        assert_eq!(
+            db.find_duplicates(&DuplicateKey::Identity),
+            vec![vec![first_id, third_id]]
+        );
+        assert_eq!(
+            db.find_duplicates(&DuplicateKey::Password),
+            vec![vec![first_id, second_id]]
+        );
+        assert_eq!(
+            db.find_duplicates(&DuplicateKey::Fields(vec!["URL".to_string()])),
+            vec![vec![first_id, second_id, third_id]]
+        );
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        let mut db = Database::new(Default::default());
+
+        let mut old = entry("Example", "alice", "old");
+        old.times.set_last_modification(Times::now() - Duration::days(2));
+        old.fields
+            .insert("Notes".into(), Value::Unprotected("Notes".to_string()));
+        old.tags.push("imported".into());
+        let mut older = old.clone();
+        older
+            .times
+            .set_last_modification(Times::now() - Duration::days(3));
+        old.history.get_or_insert_with(Default::default).add_entry(older);
+
+        let mut new = entry("Example", "alice", "new");
+        new.times.set_last_modification(Times::now() - Duration::days(1));
+        new.attachments.push(AttachmentRef {
+            name: "key.txt".to_string(),
+            identifier: "0".to_string(),
+        });
+
+        let (old_id, new_id) = (old.id(), new.id());
+        db.root.add_child(old);
+        db.root.add_child(new);
+
+        let cluster = db.find_duplicates(&DuplicateKey::Identity).remove(0);
+        let kept = db
+            .merge_duplicates(&cluster, DuplicateMergeStrategy::KeepNewest)
+            .unwrap();
+        assert_eq!(kept, new_id);
+        assert!(db.entry_by_id(old_id).is_none());
+        assert!(db.deleted_objects.contains(*old_id.as_uuid()));
+
+        let merged = db.entry_by_id(kept).unwrap();
+        assert_eq!(merged.get_password(), Some("new"));
+        assert_eq!(merged.get("Notes"), Some("Notes"));
+        assert_eq!(merged.tags, vec!["imported".to_string()]);
+        assert_eq!(merged.attachments.len(), 1);
+
+        let history = merged.history.as_ref().unwrap().get_entries();
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|e| e.uuid == merged.uuid));
+        assert!(history[0].times.get_last_modification() > history[1].times.get_last_modification());
+
+        assert_eq!(
+            db.merge_duplicates(&[old_id], DuplicateMergeStrategy::KeepFirst),
+            None
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use base64::{engine::general_purpose as base64_engine, Engine as _};
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use crate::{db::Database, error::EmergencySheetError, key::DatabaseKey};
+
+/// First line of the armored data of an emergency sheet
+pub const EMERGENCY_SHEET_ARMOR_BEGIN: &str = "-----BEGIN KEEPASS EMERGENCY SHEET-----";
+
+/// Last line of the armored data of an emergency sheet
+pub const EMERGENCY_SHEET_ARMOR_END: &str = "-----END KEEPASS EMERGENCY SHEET-----";
+
+/// Number of random bytes in a recovery key
+const RECOVERY_KEY_LENGTH: usize = 20;
+
+/// Number of bytes of the SHA-256 hash of the encrypted data that are printed as checksum
+const CHECKSUM_LENGTH: usize = 4;
+
+/// Options for [`Database::export_emergency_sheet`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmergencySheetOptions {
+    /// Title printed on the sheet. Defaults to the name of the database.
+    pub title: Option<String>,
+
+    /// Whether to include the history of the entries
+    pub include_history: bool,
+
+    /// Whether to include attachments, which quickly make the encrypted data too long to be typed
+    /// in by hand
+    pub include_attachments: bool,
+
+    /// Number of characters per line of the encrypted data
+    pub line_width: usize,
+}
+
+impl Default for EmergencySheetOptions {
+    fn default() -> Self {
+        EmergencySheetOptions {
+            title: None,
+            include_history: false,
+            include_attachments: false,
+            line_width: 64,
+        }
+    }
+}
+
+/// A printable backup of selected entries, as created by [`Database::export_emergency_sheet`].
+///
+/// The entries are stored as a KDBX4 database that is encrypted with a random recovery key and
+/// armored as Base64 text. The database can be restored with [`Database::import_emergency_sheet`],
+/// or by decoding the Base64 text into a file and opening it in any KeePass client, using the
+/// recovery key as password.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EmergencySheet {
+    /// Title printed on the sheet
+    pub title: String,
+
+    /// When the sheet was created
+    pub created: NaiveDateTime,
+
+    /// Titles of the included entries, so that readers know what the sheet is good for
+    pub entry_titles: Vec<String>,
+
+    /// The key that the data is encrypted with, in groups of four hex digits
+    pub recovery_key: String,
+
+    /// The encrypted data, framed by [`EMERGENCY_SHEET_ARMOR_BEGIN`] and
+    /// [`EMERGENCY_SHEET_ARMOR_END`] and followed by a checksum line, e.g. `=1A2B3C4D`
+    pub armored_data: String,
+}
+
+impl std::fmt::Debug for EmergencySheet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmergencySheet")
+            .field("title", &self.title)
+            .field("created", &self.created)
+            .field("entry_titles", &self.entry_titles)
+            .field("recovery_key", crate::redact::secret(&self.recovery_key))
+            .field("armored_data", &self.armored_data)
+            .finish()
+    }
+}
+
+impl Drop for EmergencySheet {
+    fn drop(&mut self) {
+        self.recovery_key.zeroize();
+    }
+}
+
+impl EmergencySheet {
+    /// Lay out the sheet as plain text for printing, including the recovery key, instructions
+    /// for restoring the entries, and the encrypted data.
+    ///
+    /// To store the recovery key separately from the data, lay out the fields of the sheet
+    /// yourself instead.
+    pub fn render(&self) -> String {
+        let heading = "KEEPASS EMERGENCY SHEET";
+        let mut out = format!("{}\n{}\n\n", heading, "=".repeat(heading.len()));
+
+        out += &format!("Title:   {}\n", self.title);
+        out += &format!("Created: {} UTC\n", self.created.format("%Y-%m-%d %H:%M"));
+        out += &format!("Entries: {}\n", self.entry_titles.len());
+        for title in &self.entry_titles {
+            out += &format!("  - {}\n", title);
+        }
+
+        out += "\nRECOVERY KEY\n\n";
+        out += &format!("  {}\n", self.recovery_key);
+
+        out += "\nHOW TO RESTORE\n\n";
+        out += "  1. Type the encrypted data below into a text file, including the first\n";
+        out += "     and last line. The checksum in the line starting with '=' detects typos.\n";
+        out += "  2. Decode the lines between the first line and the checksum as Base64\n";
+        out += "     into a .kdbx file.\n";
+        out += "  3. Open the file in a KeePass client, using the recovery key as password.\n";
+        out += "     Dashes in the recovery key can be left out.\n";
+
+        out += "\nENCRYPTED DATA\n\n";
+        out += &self.armored_data;
+        out += "\n";
+
+        out
+    }
+}
+
+impl std::fmt::Display for EmergencySheet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// Turn a recovery key as printed on a sheet into the password that the data is encrypted with,
+/// ignoring dashes, whitespace and case
+fn recovery_password(recovery_key: &str) -> Result<String, EmergencySheetError> {
+    let password: String = recovery_key
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    if password.len() != RECOVERY_KEY_LENGTH * 2 || !password.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(EmergencySheetError::InvalidRecoveryKey);
+    }
+    Ok(password)
+}
+
+fn checksum(data: &[u8]) -> String {
+    let hash = Sha256::digest(data);
+    hex::encode_upper(&hash[..CHECKSUM_LENGTH])
+}
+
+/// Decode armored data as created by [`armor`], verifying its checksum
+fn dearmor(armored: &str) -> Result<Vec<u8>, EmergencySheetError> {
+    let mut lines = armored.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some(EMERGENCY_SHEET_ARMOR_BEGIN) {
+        return Err(EmergencySheetError::InvalidArmor);
+    }
+
+    let mut encoded = String::new();
+    let mut expected_checksum = None;
+    let mut complete = false;
+    for line in lines {
+        if line == EMERGENCY_SHEET_ARMOR_END {
+            complete = true;
+            break;
+        }
+        match line.strip_prefix('=') {
+            Some(c) => expected_checksum = Some(c.to_ascii_uppercase()),
+            None if expected_checksum.is_none() => encoded += line,
+            None => return Err(EmergencySheetError::InvalidArmor),
+        }
+    }
+
+    if !complete {
+        return Err(EmergencySheetError::InvalidArmor);
+    }
+
+    let data = base64_engine::STANDARD
+        .decode(&encoded)
+        .map_err(|_| EmergencySheetError::InvalidArmor)?;
+    if expected_checksum != Some(checksum(&data)) {
+        return Err(EmergencySheetError::InvalidArmor);
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "save_kdbx4")]
+fn armor(data: &[u8], line_width: usize) -> String {
+    let encoded = base64_engine::STANDARD.encode(data);
+
+    let mut armored = format!("{}\n", EMERGENCY_SHEET_ARMOR_BEGIN);
+    // Base64 is ASCII, so chunking the bytes never splits a character
+    for line in encoded.as_bytes().chunks(line_width.max(4)) {
+        armored += &String::from_utf8_lossy(line);
+        armored += "\n";
+    }
+    armored += &format!("={}\n{}", checksum(data), EMERGENCY_SHEET_ARMOR_END);
+    armored
+}
+
+impl Database {
+    /// Create a printable emergency sheet for offline access to the given entries, or to all
+    /// entries outside of the recycle bin if `entries` is `None`.
+    ///
+    /// The entries are saved as a KDBX4 database with the encryption settings of this database,
+    /// encrypted with a new random recovery key. Selected entries are placed directly in the root
+    /// group of that database, while a whole database keeps its group structure.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn export_emergency_sheet(
+        &self,
+        entries: Option<&[crate::db::EntryId]>,
+        options: EmergencySheetOptions,
+    ) -> Result<EmergencySheet, EmergencySheetError> {
+        use crate::{
+            config::DatabaseVersion,
+            db::{subtree, Group, Times},
+            format::KDBX4_CURRENT_MINOR_VERSION,
+        };
+
+        let title = options
+            .title
+            .clone()
+            .or_else(|| self.meta.database_name.clone())
+            .unwrap_or_default();
+
+        let mut root = match entries {
+            Some(ids) => {
+                let mut root = Group::new(&title);
+                for id in ids {
+                    let entry = self
+                        .entry_by_id(*id)
+                        .ok_or(EmergencySheetError::EntryNotFound(*id))?;
+                    root.add_child(entry.clone());
+                }
+                root
+            }
+            None => {
+                let mut root = self.root.clone();
+                if let Some(recycle_bin) = self.meta.recyclebin_uuid.filter(|uuid| !uuid.is_nil()) {
+                    crate::db::tracked::take_node(&mut root, &recycle_bin);
+                }
+                root
+            }
+        };
+
+        root.for_each_entry_mut(&mut |entry| {
+            if !options.include_history {
+                entry.history = None;
+            }
+            if !options.include_attachments {
+                entry.attachments.clear();
+            }
+        });
+
+        let mut config = self.config.clone();
+        config.public_custom_data = Default::default();
+        // only KDBX4 can be saved
+        config.version = DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION);
+
+        let mut export = Database::new(config);
+        export.meta.database_name = Some(title.clone());
+        export.meta.memory_protection = self.meta.memory_protection.clone();
+        subtree::copy_attachments(&mut root, self, &mut export);
+        subtree::copy_custom_icons(&root, self, &mut export);
+
+        let entry_titles = root
+            .iter()
+            .filter_map(|node| match node {
+                crate::db::NodeRef::Entry(e) => Some(e.get_title().unwrap_or("(untitled)").to_string()),
+                crate::db::NodeRef::Group(_) => None,
+            })
+            .collect();
+        export.root = root;
+
+        let mut key_bytes = [0u8; RECOVERY_KEY_LENGTH];
+        getrandom::fill(&mut key_bytes).map_err(crate::error::DatabaseSaveError::from)?;
+        let mut password = hex::encode_upper(key_bytes);
+        key_bytes.zeroize();
+
+        let mut data = Vec::new();
+        let saved = export.save(&mut data, DatabaseKey::new().with_password(&password));
+        let recovery_key = password
+            .as_bytes()
+            .chunks(4)
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join("-");
+        password.zeroize();
+        saved?;
+
+        Ok(EmergencySheet {
+            title,
+            created: Times::now(),
+            entry_titles,
+            recovery_key,
+            armored_data: armor(&data, options.line_width),
+        })
+    }
+
+    /// Restore the entries of an emergency sheet from its armored data, as typed in from the
+    /// printed sheet, and its recovery key. See [`Database::export_emergency_sheet`].
+    pub fn import_emergency_sheet(
+        armored_data: &str,
+        recovery_key: &str,
+    ) -> Result<Database, EmergencySheetError> {
+        let mut password = recovery_password(recovery_key)?;
+        let data = dearmor(armored_data)?;
+
+        let db = Database::parse(&data, DatabaseKey::new().with_password(&password));
+        password.zeroize();
+        Ok(db?)
+    }
+}
+
+#[cfg(all(test, feature = "save_kdbx4"))]
+mod emergency_tests {
+    use super::{EmergencySheetOptions, EMERGENCY_SHEET_ARMOR_BEGIN};
+    use crate::{
+        db::{Database, Entry, EntryId, Group},
+        error::{EmergencySheetError, ErrorCode},
+        DatabaseKey,
+    };
+
+    fn database() -> (Database, EntryId) {
+        let mut db = Database::new(Default::default());
+        db.meta.database_name = Some("Family".to_string());
+
+        let mut bank = Entry::new();
+        bank.set_title("Bank");
+        bank.set_password("s3cret");
+        let bank_id = EntryId::from(bank.uuid);
+        db.root.add_child(bank);
+
+        let mut group = Group::new("Mail");
+        let mut mail = Entry::new();
+        mail.set_title("Mail");
+        group.add_child(mail);
+        db.root.add_child(group);
+
+        (db, bank_id)
+    }
+
+    #[test]
+    fn emergency_sheet_roundtrip() {
+        let (db, bank_id) = database();
+
+        let sheet = db
+            .export_emergency_sheet(Some(&[bank_id]), EmergencySheetOptions::default())
+            .unwrap();
+        assert_eq!(sheet.title, "Family");
+        assert_eq!(sheet.entry_titles, vec!["Bank".to_string()]);
+        assert_eq!(sheet.recovery_key.split('-').count(), 10);
+        assert!(sheet.armored_data.starts_with(EMERGENCY_SHEET_ARMOR_BEGIN));
+        assert!(sheet.armored_data.lines().all(|line| line.len() <= 64));
+        assert!(!format!("{:?}", sheet).contains(&sheet.recovery_key));
+
+        let rendered = sheet.render();
+        assert!(rendered.contains(&sheet.recovery_key));
+        assert!(rendered.contains(&sheet.armored_data));
+        assert!(!rendered.contains("s3cret"));
+
+        // the recovery key may be typed in without dashes and in lower case
+        let typed_key = sheet.recovery_key.replace('-', "").to_lowercase();
+        let restored = Database::import_emergency_sheet(&sheet.armored_data, &typed_key).unwrap();
+        let entries = restored.root.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_password(), Some("s3cret"));
+
+        // the armored data is a regular database
+        let data = super::dearmor(&sheet.armored_data).unwrap();
+        let password = sheet.recovery_key.replace('-', "");
+        Database::parse(&data, DatabaseKey::new().with_password(&password)).unwrap();
+
+        // the whole database keeps its structure
+        let sheet = db
+            .export_emergency_sheet(None, EmergencySheetOptions::default())
+            .unwrap();
+        assert_eq!(sheet.entry_titles, vec!["Bank".to_string(), "Mail".to_string()]);
+        let restored = Database::import_emergency_sheet(&sheet.armored_data, &sheet.recovery_key).unwrap();
+        assert!(restored.root.get(&["Mail", "Mail"]).is_some());
+    }
+
+    #[test]
+    fn emergency_sheet_errors() {
+        let (db, _) = database();
+        let sheet = db
+            .export_emergency_sheet(None, EmergencySheetOptions::default())
+            .unwrap();
+
+        let missing = EntryId::from(uuid::Uuid::new_v4());
+        assert!(matches!(
+            db.export_emergency_sheet(Some(&[missing]), EmergencySheetOptions::default()),
+            Err(EmergencySheetError::EntryNotFound(id)) if id == missing
+        ));
+
+        // a typo in the data is caught by the checksum
+        let mut lines: Vec<String> = sheet.armored_data.lines().map(str::to_string).collect();
+        let typo = lines[1].replacen(|c: char| c.is_ascii_uppercase(), "x", 1);
+        lines[1] = typo;
+        let err = Database::import_emergency_sheet(&lines.join("\n"), &sheet.recovery_key).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidEmergencySheet);
+
+        let err = Database::import_emergency_sheet(&sheet.armored_data, "1234-5678").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidRecoveryKey);
+
+        let wrong_key = "0".repeat(40);
+        let err = Database::import_emergency_sheet(&sheet.armored_data, &wrong_key).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IncorrectKey);
+    }
+}
@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
 
 use secstr::SecStr;
 use uuid::Uuid;
@@ -8,7 +11,11 @@ use crate::db::merge::{MergeError, MergeLog};
 #[cfg(all(test, feature = "_merge"))]
 use std::{thread, time};
 
-use crate::db::{Color, CustomData, Times};
+use crate::{
+    crypt::ciphers::InnerStream,
+    db::{Color, CustomData, InternedStr, Times, XmlElement},
+    error::CryptographyError,
+};
 
 #[cfg(feature = "totp")]
 use crate::db::otp::{TOTPError, TOTP};
@@ -18,9 +25,14 @@ use crate::db::otp::{TOTPError, TOTP};
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub struct Entry {
     pub uuid: Uuid,
-    pub fields: HashMap<String, Value>,
+    pub fields: HashMap<InternedStr, Value>,
+
+    /// References to binary attachments, stored in the header attachments (KDBX4) or the
+    /// metadata binaries (KDBX3) of the database
+    pub attachments: Vec<AttachmentRef>,
+
     pub autotype: Option<AutoType>,
-    pub tags: Vec<String>,
+    pub tags: Vec<InternedStr>,
 
     pub times: Times,
 
@@ -36,6 +48,9 @@ pub struct Entry {
     pub quality_check: Option<bool>,
 
     pub history: Option<History>,
+
+    /// XML elements of the entry that are not known to this library
+    pub unknown_elements: Vec<XmlElement>,
 }
 impl Entry {
     pub fn new() -> Entry {
@@ -76,9 +91,10 @@ impl Entry {
                 // This should never happen.
                 // This means that an entry was updated without updating the last modification
                 // timestamp.
-                return Err(MergeError::EntryModificationTimeNotUpdated(
-                    other.uuid.to_string(),
-                ));
+                return Err(MergeError::EntryModificationTimeNotUpdated {
+                    uuid: other.uuid.to_string(),
+                    title: other.get_title().unwrap_or_default().to_string(),
+                });
             }
             return Ok((None, log));
         }
@@ -89,6 +105,12 @@ impl Entry {
             false => other.clone().merge_history(&self)?,
         };
 
+        // Keep the changes of the older entry to fields that were not changed in the newer one.
+        match destination_last_modification > source_last_modification {
+            true => merged_entry.merge_fields(self, other),
+            false => merged_entry.merge_fields(other, self),
+        }
+
         // The location changed timestamp is handled separately when merging two databases.
         if let Some(location_changed_timestamp) = self.times.get_location_changed() {
             merged_entry
@@ -142,6 +164,52 @@ impl Entry {
         Ok((response, log.merge_with(&history_merge_log)))
     }
 
+    #[cfg(feature = "_merge")]
+    // Take the values of fields that were changed more recently in the older entry than in the
+    // newer one. If any field is taken, the result is committed as a new version, newer than both.
+    fn merge_fields(&mut self, newer: &Entry, older: &Entry) {
+        let mut keys: Vec<&InternedStr> = newer.fields.keys().chain(older.fields.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut changed = false;
+        for key in keys {
+            if newer.fields.get(key.as_str()) == older.fields.get(key.as_str()) {
+                continue;
+            }
+
+            if older.field_last_changed(key) <= newer.field_last_changed(key) {
+                continue;
+            }
+
+            match older.fields.get(key.as_str()) {
+                Some(value) => self.fields.insert(key.clone(), value.clone()),
+                None => self.fields.remove(key.as_str()),
+            };
+            changed = true;
+        }
+
+        if !changed {
+            return;
+        }
+
+        // History entries are identified by their modification time, so make sure that the
+        // merged version does not share it with the newer entry.
+        let newest = newer
+            .times
+            .get_last_modification()
+            .copied()
+            .unwrap_or_else(Times::epoch);
+        let now = Times::now().max(newest + chrono::Duration::seconds(1));
+        self.times.set_last_modification(now);
+
+        let mut new_history_entry = self.clone();
+        new_history_entry.history.take();
+        self.history
+            .get_or_insert_with(History::default)
+            .add_entry(new_history_entry);
+    }
+
     #[cfg(all(test, feature = "_merge"))]
     // Convenience function used in unit tests, to make sure that:
     // 1. The history gets updated after changing a field
@@ -149,10 +217,8 @@ impl Entry {
     //    as it previously was. This is necessary since the timestamps in the KDBX format
     //    do not preserve the msecs.
     pub(crate) fn set_field_and_commit(&mut self, field_name: &str, field_value: &str) {
-        self.fields.insert(
-            field_name.to_string(),
-            Value::Unprotected(field_value.to_string()),
-        );
+        self.fields
+            .insert(field_name.into(), Value::Unprotected(field_value.to_string()));
         thread::sleep(time::Duration::from_secs(1));
         self.update_history();
     }
@@ -173,11 +239,38 @@ impl Entry {
 }
 
 impl<'a> Entry {
+    /// Get the time at which a field got its current value, or was removed.
+    ///
+    /// This walks the history of the entry back to the last version that had a different value,
+    /// so that changes to other fields are not taken into account. If the value never changed, the
+    /// creation time of the entry is returned.
+    pub fn field_last_changed(&self, key: &str) -> Option<chrono::NaiveDateTime> {
+        let value = self.fields.get(key);
+
+        let mut last_changed = self.times.get_last_modification();
+        let mut oldest = self;
+
+        if let Some(history) = &self.history {
+            // history entries are ordered from newest to oldest
+            for previous in history.get_entries() {
+                if previous.fields.get(key) != value {
+                    return last_changed.copied();
+                }
+
+                last_changed = previous.times.get_last_modification();
+                oldest = previous;
+            }
+        }
+
+        oldest.times.get_creation().or(last_changed).copied()
+    }
+
     /// Get a field by name, taking care of unprotecting Protected values automatically
     pub fn get(&'a self, key: &str) -> Option<&'a str> {
         match self.fields.get(key) {
             Some(&Value::Bytes(_)) => None,
             Some(&Value::Protected(ref pv)) => std::str::from_utf8(pv.unsecure()).ok(),
+            Some(&Value::Encrypted(ref ev)) => std::str::from_utf8(ev.decrypt().ok()?.unsecure()).ok(),
             Some(&Value::Unprotected(ref uv)) => Some(&uv),
             None => None,
         }
@@ -242,6 +335,93 @@ impl<'a> Entry {
         self.get("URL")
     }
 
+    /// Convenience method for getting the value of the 'Notes' field
+    pub fn get_notes(&'a self) -> Option<&'a str> {
+        self.get("Notes")
+    }
+
+    /// Set a string field, keeping it protected if it already was and protecting new values if
+    /// `protect` is set
+    fn set_string_field(&mut self, key: &str, value: &str, protect: bool) {
+        let value = match self.fields.get(key) {
+            Some(Value::Protected(_) | Value::Encrypted(_)) => Value::Protected(value.as_bytes().into()),
+            _ if protect => Value::Protected(value.as_bytes().into()),
+            _ => Value::Unprotected(value.to_string()),
+        };
+        self.fields.insert(key.into(), value);
+        self.times.set_last_modification(Times::now());
+    }
+
+    /// Convenience method for setting the value of the 'Title' field
+    pub fn set_title(&mut self, title: &str) {
+        self.set_string_field("Title", title, false)
+    }
+
+    /// Convenience method for setting the value of the 'UserName' field
+    pub fn set_username(&mut self, username: &str) {
+        self.set_string_field("UserName", username, false)
+    }
+
+    /// Convenience method for setting the value of the 'Password' field, which is always
+    /// protected
+    pub fn set_password(&mut self, password: &str) {
+        self.set_string_field("Password", password, true)
+    }
+
+    /// Convenience method for setting the value of the 'URL' field
+    pub fn set_url(&mut self, url: &str) {
+        self.set_string_field("URL", url, false)
+    }
+
+    /// Convenience method for setting the value of the 'Notes' field
+    pub fn set_notes(&mut self, notes: &str) {
+        self.set_string_field("Notes", notes, false)
+    }
+
+    /// Whether the quality of the password of this entry should be checked, e.g. by
+    /// [`Database::evaluate_policy`](crate::Database::evaluate_policy). This is the case unless
+    /// it was disabled for the entry.
+    pub fn is_quality_check_enabled(&self) -> bool {
+        self.quality_check != Some(false)
+    }
+
+    /// Enable or disable checking the quality of the password of this entry, e.g. for
+    /// passwords that cannot be changed. This is stored in KDBX 4.1 databases.
+    pub fn set_quality_check(&mut self, enabled: bool) {
+        self.quality_check = Some(enabled);
+        self.times.set_last_modification(Times::now());
+    }
+
+    /// Approximate size of the entry in bytes, counting field names and values, tags, URLs,
+    /// custom data and the content of attachments. The history of the entry is not included.
+    ///
+    /// Attachment contents are stored in the database, so their sizes are looked up by identifier
+    /// in `binary_sizes`, as returned by [`Database::binary_sizes`](crate::Database::binary_sizes).
+    pub fn approximate_size(&self, binary_sizes: &HashMap<String, usize>) -> usize {
+        let fields: usize = self
+            .fields
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum();
+
+        let tags: usize = self.tags.iter().map(|t| t.len()).sum();
+
+        let custom_data: usize = self
+            .custom_data
+            .items
+            .iter()
+            .map(|(key, item)| key.len() + item.value.as_ref().map_or(0, |v| v.len()))
+            .sum();
+
+        let attachments: usize = self
+            .attachments
+            .iter()
+            .map(|a| binary_sizes.get(&a.identifier).copied().unwrap_or_default())
+            .sum();
+
+        fields + tags + custom_data + attachments + self.override_url.as_ref().map_or(0, |u| u.len())
+    }
+
     /// Adds the current version of the entry to the entry's history
     /// and updates the last modification timestamp.
     /// The history will only be updated if the entry has
@@ -269,6 +449,70 @@ impl<'a> Entry {
         true
     }
 
+    /// Edit the entry with a closure, the way KeePass does when an entry is edited in its dialog:
+    /// if the closure changed anything, the previous version of the entry is added to the history
+    /// and the modification and access times are updated, once for all changes made in the
+    /// closure. Changes to the times or the history themselves are not considered edits.
+    ///
+    /// Returns the result of the closure.
+    pub fn edit<R, F: FnOnce(&mut Entry) -> R>(&mut self, f: F) -> R {
+        let mut before = self.clone();
+        let result = f(self);
+
+        let unchanged = {
+            let mut after = self.clone();
+            after.times = before.times.clone();
+            after.history = before.history.clone();
+            after == before
+        };
+        if unchanged {
+            return result;
+        }
+
+        before.history = None;
+        self.history
+            .get_or_insert_with(Default::default)
+            .add_entry(before);
+
+        let now = Times::now();
+        self.times.set_last_modification(now);
+        self.times.set_last_access(now);
+
+        result
+    }
+
+    /// Get the passwords that the entry had over time, oldest first, with the last modification
+    /// time of the version that had them. The versions in the history are followed by the
+    /// current version, and consecutive versions with the same password are reported once, with
+    /// the time of the oldest of them. Versions without a password are skipped.
+    ///
+    /// This allows checking that a new password was not used before without going through the
+    /// history manually.
+    pub fn password_history(&self) -> Vec<(chrono::NaiveDateTime, SecStr)> {
+        let history = self.history.iter().flat_map(|h| h.entries.iter().rev());
+
+        let mut passwords: Vec<(chrono::NaiveDateTime, SecStr)> = Vec::new();
+        for version in history.chain(std::iter::once(self)) {
+            let password = match version.get_password() {
+                Some(p) => p,
+                None => continue,
+            };
+            if let Some((_, previous)) = passwords.last() {
+                if previous.unsecure() == password.as_bytes() {
+                    continue;
+                }
+            }
+
+            let time = version
+                .times
+                .get_last_modification()
+                .copied()
+                .unwrap_or_else(Times::epoch);
+            passwords.push((time, SecStr::from(password)));
+        }
+        passwords
+    }
+
     /// Determines if the entry was modified since the last
     /// history update.
     fn has_uncommitted_changes(&self) -> bool {
@@ -296,19 +540,143 @@ impl<'a> Entry {
 }
 
 /// A value that can be a raw string, byte array, or protected memory region
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Eq, Clone)]
 pub enum Value {
     Bytes(Vec<u8>),
     Unprotected(String),
     Protected(SecStr),
+
+    /// A protected value that has not been decrypted yet, see [`EncryptedValue`]
+    Encrypted(EncryptedValue),
+}
+
+/// Protected values are equal if their plaintext is equal, whether they were decrypted already
+/// or not
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Unprotected(a), Value::Unprotected(b)) => a == b,
+            (Value::Protected(a), Value::Protected(b)) => a == b,
+            (a, b) => match (a.protected_bytes(), b.protected_bytes()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bytes(b) => f.debug_tuple("Bytes").field(b).finish(),
+            Value::Unprotected(s) => f.debug_tuple("Unprotected").field(s).finish(),
+            Value::Protected(p) => f
+                .debug_tuple("Protected")
+                .field(crate::redact::secret(&String::from_utf8_lossy(p.unsecure())))
+                .finish(),
+            Value::Encrypted(e) => f.debug_tuple("Encrypted").field(e).finish(),
+        }
+    }
 }
 
 impl Value {
+    /// Length of the value in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Value::Bytes(b) => b.len(),
+            Value::Unprotected(s) => s.len(),
+            Value::Protected(p) => p.unsecure().len(),
+            Value::Encrypted(e) => e.len(),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         match self {
             Value::Bytes(b) => b.is_empty(),
             Value::Unprotected(u) => u.is_empty(),
             Value::Protected(p) => p.unsecure().is_empty(),
+            Value::Encrypted(e) => e.is_empty(),
+        }
+    }
+
+    /// Get the plaintext of a protected value, decrypting it if necessary
+    pub(crate) fn protected_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Protected(p) => Some(p.unsecure()),
+            Value::Encrypted(e) => e.decrypt().ok().map(|p| p.unsecure()),
+            _ => None,
+        }
+    }
+}
+
+/// A protected value of a database that was opened with
+/// [`OpenOptions::lazy_protected_values`](crate::config::OpenOptions::lazy_protected_values).
+///
+/// The value stays encrypted with the inner stream cipher of the database until it is accessed
+/// for the first time, e.g. with [`Entry::get`], so that secrets that are never used are never
+/// decrypted in memory. Since the position of the value in the key stream is known, it can be
+/// decrypted independently of all other values.
+#[derive(Clone)]
+pub struct EncryptedValue {
+    ciphertext: Vec<u8>,
+    position: u64,
+    stream: Arc<InnerStream>,
+    plaintext: OnceLock<SecStr>,
+}
+
+impl EncryptedValue {
+    pub(crate) fn new(ciphertext: Vec<u8>, position: u64, stream: Arc<InnerStream>) -> Self {
+        EncryptedValue {
+            ciphertext,
+            position,
+            stream,
+            plaintext: OnceLock::new(),
+        }
+    }
+
+    /// Decrypt the value, or get it if it was decrypted before
+    pub fn decrypt(&self) -> Result<&SecStr, CryptographyError> {
+        if let Some(plaintext) = self.plaintext.get() {
+            return Ok(plaintext);
+        }
+
+        let decrypted = self.stream.decrypt_at(self.position, &self.ciphertext)?;
+        let value = SecStr::from(String::from_utf8_lossy(&decrypted).to_string());
+        Ok(self.plaintext.get_or_init(|| value))
+    }
+
+    /// Whether the value has been decrypted
+    pub fn is_decrypted(&self) -> bool {
+        self.plaintext.get().is_some()
+    }
+
+    /// Length of the encrypted value in bytes, which is the length of the plaintext
+    pub fn len(&self) -> usize {
+        self.ciphertext.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ciphertext.is_empty()
+    }
+}
+
+impl std::fmt::Debug for EncryptedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedValue")
+            .field("len", &self.len())
+            .field("decrypted", &self.is_decrypted())
+            .finish()
+    }
+}
+
+impl Eq for EncryptedValue {}
+
+impl PartialEq for EncryptedValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.decrypt(), other.decrypt()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
         }
     }
 }
@@ -323,10 +691,30 @@ impl serde::Serialize for Value {
             Value::Bytes(b) => serializer.serialize_bytes(b),
             Value::Unprotected(u) => serializer.serialize_str(u),
             Value::Protected(p) => serializer.serialize_str(String::from_utf8_lossy(p.unsecure()).as_ref()),
+            Value::Encrypted(e) => {
+                let p = e.decrypt().map_err(serde::ser::Error::custom)?;
+                serializer.serialize_str(String::from_utf8_lossy(p.unsecure()).as_ref())
+            }
         }
     }
 }
 
+/// A named reference from an entry to a binary attachment of the database
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct AttachmentRef {
+    /// File name of the attachment
+    pub name: String,
+
+    /// Identifier of the referenced binary. In KDBX4 databases, this is the index into the
+    /// header attachments of the database.
+    ///
+    /// Identifiers are saved as they are, so that they stay the same across saves. Unlike
+    /// KeePass, this library does not renumber the binary pool when saving, and new binaries are
+    /// appended to it.
+    pub identifier: String,
+}
+
 /// An AutoType setting associated with an Entry
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -366,6 +754,65 @@ impl History {
         &self.entries
     }
 
+    /// Remove the oldest history items until at most `max_items` items with a total approximate
+    /// size of at most `max_size` bytes remain. Attachment sizes are looked up in `binary_sizes`,
+    /// see [`Entry::approximate_size`].
+    ///
+    /// Returns the number of removed items and their approximate size in bytes.
+    pub fn prune(
+        &mut self,
+        max_items: Option<usize>,
+        max_size: Option<usize>,
+        binary_sizes: &HashMap<String, usize>,
+    ) -> (usize, usize) {
+        // items added by this library are ordered from newest to oldest, but KeePass writes them
+        // from oldest to newest, so go by their modification times instead of their positions
+        let mut newest_first: Vec<usize> = (0..self.entries.len()).collect();
+        newest_first.sort_by(|a, b| {
+            self.entries[*b]
+                .times
+                .get_last_modification()
+                .cmp(&self.entries[*a].times.get_last_modification())
+        });
+
+        let mut keep = newest_first.len();
+
+        if let Some(max_items) = max_items {
+            keep = keep.min(max_items);
+        }
+
+        if let Some(max_size) = max_size {
+            let mut total_size = 0;
+            for (i, index) in newest_first.iter().take(keep).enumerate() {
+                total_size += self.entries[*index].approximate_size(binary_sizes);
+                if total_size > max_size {
+                    keep = i;
+                    break;
+                }
+            }
+        }
+
+        let mut removed = vec![false; self.entries.len()];
+        for index in &newest_first[keep..] {
+            removed[*index] = true;
+        }
+
+        let mut removed_count = 0;
+        let mut removed_size = 0;
+        let mut index = 0;
+        self.entries.retain(|entry| {
+            let keep = !removed[index];
+            index += 1;
+            if !keep {
+                removed_count += 1;
+                removed_size += entry.approximate_size(binary_sizes);
+            }
+            keep
+        });
+
+        (removed_count, removed_size)
+    }
+
     #[cfg(all(test, feature = "_merge"))]
     // Determines if the entries of the history are
     // ordered by last modification time.
@@ -395,10 +842,11 @@ impl History {
         for history_entry in &self.entries {
             let modification_time = history_entry.times.get_last_modification().unwrap();
             if new_history_entries.contains_key(modification_time) {
-                return Err(MergeError::DuplicateHistoryEntries(
-                    modification_time.to_string(),
-                    history_entry.uuid.to_string(),
-                ));
+                return Err(MergeError::DuplicateHistoryEntries {
+                    timestamp: modification_time.to_string(),
+                    uuid: history_entry.uuid.to_string(),
+                    title: history_entry.get_title().unwrap_or_default().to_string(),
+                });
             }
             new_history_entries.insert(modification_time.clone(), history_entry.clone());
         }
@@ -431,6 +879,20 @@ impl History {
     }
 }
 
+/// Summary of the history items that were removed by
+/// [`Database::apply_history_limits`](crate::Database::apply_history_limits)
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct HistoryPruneReport {
+    /// Number of entries that had history items removed
+    pub entries_affected: usize,
+
+    /// Total number of history items that were removed
+    pub items_removed: usize,
+
+    /// Approximate total size of the removed history items in bytes
+    pub bytes_removed: usize,
+}
+
 #[cfg(test)]
 mod entry_tests {
     use std::{thread, time};
@@ -442,17 +904,14 @@ mod entry_tests {
     #[test]
     fn byte_values() {
         let mut entry = Entry::new();
+        entry.fields.insert("a-bytes".into(), Value::Bytes(vec![1, 2, 3]));
+
         entry
             .fields
-            .insert("a-bytes".to_string(), Value::Bytes(vec![1, 2, 3]));
-
-        entry.fields.insert(
-            "a-unprotected".to_string(),
-            Value::Unprotected("asdf".to_string()),
-        );
+            .insert("a-unprotected".into(), Value::Unprotected("asdf".to_string()));
 
         entry.fields.insert(
-            "a-protected".to_string(),
+            "a-protected".into(),
             Value::Protected(SecStr::new("asdf".as_bytes().to_vec())),
         );
 
@@ -465,6 +924,32 @@ mod entry_tests {
         assert_eq!(entry.fields["a-bytes"].is_empty(), false);
     }
 
+    #[test]
+    fn standard_field_setters() {
+        let mut entry = Entry::new();
+        entry.set_title("Title");
+        entry.set_username("jdoe");
+        entry.set_password("hunter2");
+        entry.set_url("https://example.com");
+        entry.set_notes("Notes");
+
+        assert_eq!(entry.get_title(), Some("Title"));
+        assert_eq!(entry.get_username(), Some("jdoe"));
+        assert_eq!(entry.get_password(), Some("hunter2"));
+        assert_eq!(entry.get_url(), Some("https://example.com"));
+        assert_eq!(entry.get_notes(), Some("Notes"));
+        assert!(matches!(entry.fields["Password"], Value::Protected(_)));
+        assert!(matches!(entry.fields["Title"], Value::Unprotected(_)));
+
+        // fields stay protected
+        entry
+            .fields
+            .insert("Notes".into(), Value::Protected("secret".as_bytes().into()));
+        entry.set_notes("Other notes");
+        assert!(matches!(entry.fields["Notes"], Value::Protected(_)));
+        assert_eq!(entry.get_notes(), Some("Other notes"));
+    }
+
     #[test]
     fn update_history() {
         let mut entry = Entry::new();
@@ -472,7 +957,7 @@ mod entry_tests {
 
         entry
             .fields
-            .insert("Username".to_string(), Value::Unprotected("user".to_string()));
+            .insert("Username".into(), Value::Unprotected("user".to_string()));
         // Making sure to wait 1 sec before update the history, to make
         // sure that we get a different modification timestamp.
         thread::sleep(time::Duration::from_secs(1));
@@ -499,7 +984,7 @@ mod entry_tests {
 
         entry
             .fields
-            .insert("Title".to_string(), Value::Unprotected("first title".to_string()));
+            .insert("Title".into(), Value::Unprotected("first title".to_string()));
 
         assert!(entry.update_history());
         assert!(entry.history.is_some());
@@ -519,10 +1004,9 @@ mod entry_tests {
             &last_modification_time
         );
 
-        entry.fields.insert(
-            "Title".to_string(),
-            Value::Unprotected("second title".to_string()),
-        );
+        entry
+            .fields
+            .insert("Title".into(), Value::Unprotected("second title".to_string()));
 
         assert!(entry.update_history());
         assert!(entry.history.is_some());
@@ -550,11 +1034,62 @@ mod entry_tests {
         }
     }
 
+    #[test]
+    fn edit() {
+        let mut entry = Entry::new();
+        entry.set_title("first title");
+        let long_ago = chrono::NaiveDateTime::default();
+        entry.times.set_last_modification(long_ago);
+
+        // edits that change nothing leave the entry alone
+        assert_eq!(entry.edit(|e| e.get_title().map(str::len)), Some(11));
+        assert!(entry.history.is_none());
+        assert_eq!(entry.times.get_last_modification(), Some(&long_ago));
+
+        entry.edit(|e| {
+            e.set_title("second title");
+            e.set_username("user");
+        });
+        let history = entry.history.as_ref().unwrap().get_entries();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].get_title(), Some("first title"));
+        assert_eq!(history[0].get_username(), None);
+        assert_eq!(history[0].times.get_last_modification(), Some(&long_ago));
+        assert_ne!(entry.times.get_last_modification(), Some(&long_ago));
+
+        entry.edit(|e| e.set_title("third title"));
+        let history = entry.history.as_ref().unwrap().get_entries();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].get_title(), Some("second title"));
+        assert!(history[0].history.is_none());
+    }
+
+    #[test]
+    fn password_history() {
+        let mut entry = Entry::new();
+        assert!(entry.password_history().is_empty());
+
+        for (i, password) in ["first", "second", "second", "first"].iter().enumerate() {
+            entry.edit(|e| {
+                e.set_password(password);
+                e.set_notes(&i.to_string());
+            });
+        }
+        entry.edit(|e| e.set_notes("no new password"));
+
+        let history = entry.password_history();
+        let passwords: Vec<&str> = history
+            .iter()
+            .map(|(_, p)| std::str::from_utf8(p.unsecure()).unwrap())
+            .collect();
+        assert_eq!(passwords, vec!["first", "second", "first"]);
+    }
+
     #[cfg(feature = "totp")]
     #[test]
     fn totp() {
         let mut entry = Entry::new();
-        entry.fields.insert("otp".to_string(), Value::Unprotected("otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30".to_string()));
+        entry.fields.insert("otp".into(), Value::Unprotected("otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30".to_string()));
 
         assert!(entry.get_otp().is_ok());
     }
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use crate::db::{CustomDataItem, Entry, Times, Value};
+
+/// Prefix of the custom data keys that store per-field metadata
+pub const FIELD_METADATA_PREFIX: &str = "FieldMetadata:";
+
+const SEPARATOR: char = ':';
+const ESCAPE: char = '\\';
+
+fn metadata_key(field: &str, attribute: &str) -> String {
+    let mut key = String::from(FIELD_METADATA_PREFIX);
+    for c in field.chars() {
+        if c == SEPARATOR || c == ESCAPE {
+            key.push(ESCAPE);
+        }
+        key.push(c);
+    }
+    key.push(SEPARATOR);
+    key.push_str(attribute);
+    key
+}
+
+/// Split a custom data key into the field name and the metadata attribute, if it is a field
+/// metadata key
+fn parse_metadata_key(key: &str) -> Option<(String, &str)> {
+    let rest = key.strip_prefix(FIELD_METADATA_PREFIX)?;
+
+    let mut field = String::new();
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        if escaped {
+            field.push(c);
+            escaped = false;
+        } else if c == ESCAPE {
+            escaped = true;
+        } else if c == SEPARATOR {
+            return Some((field, &rest[i + c.len_utf8()..]));
+        } else {
+            field.push(c);
+        }
+    }
+
+    None
+}
+
+impl Entry {
+    /// Get all metadata attributes that have been attached to the string field `field`.
+    ///
+    /// Field metadata is stored in the custom data of the entry, so that applications can attach
+    /// their own attributes to fields (e.g. whether a field should be used for auto-type) that
+    /// survive saving and loading the database.
+    pub fn field_metadata(&self, field: &str) -> HashMap<String, String> {
+        self.custom_data
+            .items
+            .iter()
+            .filter_map(|(key, item)| {
+                let (f, attribute) = parse_metadata_key(key)?;
+                if f != field {
+                    return None;
+                }
+
+                match item.value.as_ref()? {
+                    Value::Unprotected(v) => Some((attribute.to_string(), v.clone())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Get a single metadata attribute of the string field `field`
+    pub fn get_field_metadata(&self, field: &str, attribute: &str) -> Option<&str> {
+        match self
+            .custom_data
+            .items
+            .get(&metadata_key(field, attribute))?
+            .value
+            .as_ref()?
+        {
+            Value::Unprotected(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Set a metadata attribute of the string field `field`
+    pub fn set_field_metadata(&mut self, field: &str, attribute: &str, value: &str) {
+        self.custom_data.items.insert(
+            metadata_key(field, attribute),
+            CustomDataItem {
+                value: Some(Value::Unprotected(value.to_string())),
+                last_modification_time: Some(Times::now()),
+            },
+        );
+    }
+
+    /// Remove a metadata attribute of the string field `field`, returning its previous value
+    pub fn remove_field_metadata(&mut self, field: &str, attribute: &str) -> Option<String> {
+        match self
+            .custom_data
+            .items
+            .remove(&metadata_key(field, attribute))?
+            .value?
+        {
+            Value::Unprotected(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Remove all metadata attributes of the string field `field`
+    pub fn clear_field_metadata(&mut self, field: &str) {
+        self.custom_data
+            .items
+            .retain(|key, _| !matches!(parse_metadata_key(key), Some((f, _)) if f == field));
+    }
+}
+
+#[cfg(test)]
+mod field_metadata_tests {
+    use super::{metadata_key, parse_metadata_key};
+    use crate::db::Entry;
+
+    #[test]
+    fn metadata_keys() {
+        assert_eq!(
+            metadata_key("Password", "AutoType"),
+            "FieldMetadata:Password:AutoType"
+        );
+        assert_eq!(metadata_key("a:b\\c", "x:y"), "FieldMetadata:a\\:b\\\\c:x:y");
+
+        assert_eq!(
+            parse_metadata_key("FieldMetadata:a\\:b\\\\c:x:y"),
+            Some(("a:b\\c".to_string(), "x:y"))
+        );
+        assert_eq!(parse_metadata_key("FieldMetadata:incomplete"), None);
+        assert_eq!(parse_metadata_key("OtherKey"), None);
+    }
+
+    #[test]
+    fn field_metadata() {
+        let mut entry = Entry::new();
+        entry.set_field_metadata("Password", "AutoType", "false");
+        entry.set_field_metadata("Password", "Spelling", "true");
+        entry.set_field_metadata("PIN", "AutoType", "true");
+
+        assert_eq!(entry.get_field_metadata("Password", "AutoType"), Some("false"));
+        assert_eq!(entry.field_metadata("Password").len(), 2);
+        assert_eq!(entry.field_metadata("PIN").len(), 1);
+
+        assert_eq!(
+            entry.remove_field_metadata("Password", "Spelling"),
+            Some("true".to_string())
+        );
+        assert_eq!(entry.field_metadata("Password").len(), 1);
+
+        entry.clear_field_metadata("Password");
+        assert!(entry.field_metadata("Password").is_empty());
+        assert_eq!(entry.get_field_metadata("PIN", "AutoType"), Some("true"));
+    }
+}
@@ -1,23 +1,31 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use uuid::Uuid;
 
 use crate::db::{
     entry::Entry,
-    node::{Node, NodeIter, NodeRef, NodeRefMut},
-    CustomData, Times,
+    node::{Descendants, Node, NodeIter, NodeRef, NodeRefMut},
+    CustomData, EntryId, InternedStr, TextMatching, Times, Value, XmlElement,
 };
 
+/// Number of UUIDs whose random bytes are requested from the operating system at once by
+/// [`Group::add_entries_bulk`]
+const BULK_UUID_BATCH: usize = 256;
+
 #[cfg(feature = "_merge")]
 use crate::db::merge::{MergeError, MergeEvent, MergeEventType, MergeLog};
 
-#[cfg(feature = "_merge")]
+/// UUIDs of the groups leading from the root group (excluded) to the parent of a node
 pub(crate) type NodeLocation = Vec<Uuid>;
 
+/// Locations of all nodes below a group, indexed by their UUIDs
+#[cfg(feature = "_merge")]
+pub(crate) type LocationIndex = HashMap<Uuid, NodeLocation>;
+
 pub enum SearchField {
     #[cfg(any(test, feature = "_merge"))]
     UUID,
-    Title,
+    Title(TextMatching),
 }
 
 impl SearchField {
@@ -31,13 +39,13 @@ impl SearchField {
                 };
                 uuid.to_string() == field_value
             }
-            SearchField::Title => {
+            SearchField::Title(matching) => {
                 let title = match node {
                     Node::Entry(e) => e.get_title(),
                     Node::Group(g) => Some(g.get_name()),
                 };
                 match title {
-                    Some(t) => t == field_value,
+                    Some(t) => matching.matches(t, field_value),
                     None => false,
                 }
             }
@@ -87,10 +95,16 @@ pub struct Group {
     // TODO: in example XML files, this is "null" - what should the type be?
     pub enable_searching: Option<String>,
 
-    /// UUID for the last top visible entry
-    // TODO figure out what that is supposed to mean. According to the KeePass sourcecode, it has
-    // something to do with restoring selected items when re-opening a database.
+    /// UUID of the entry shown at the top of the entry list when the group was last viewed, used
+    /// by KeePass to restore the scroll position when re-opening the database
     pub last_top_visible_entry: Option<Uuid>,
+
+    /// XML elements of the group that are not known to this library
+    pub unknown_elements: Vec<XmlElement>,
+
+    /// Where unknown elements that came after some of the children were found in the XML, as
+    /// pairs of an index into `unknown_elements` and the number of children preceding it
+    pub(crate) unknown_element_positions: Vec<(usize, usize)>,
 }
 
 impl Group {
@@ -108,6 +122,54 @@ impl Group {
         self.children.push(node.into());
     }
 
+    /// Add many new entries with the given fields at once, e.g. when importing from another
+    /// password manager, returning their identifiers.
+    ///
+    /// This is faster than creating the entries one by one with [`Entry::new`], since the random
+    /// bytes for the UUIDs are requested in batches and all entries share the same timestamps.
+    pub fn add_entries_bulk<I>(&mut self, entries: I) -> Vec<EntryId>
+    where
+        I: IntoIterator<Item = HashMap<InternedStr, Value>>,
+    {
+        let entries = entries.into_iter();
+        let (capacity, _) = entries.size_hint();
+        self.children.reserve(capacity);
+        let mut ids = Vec::with_capacity(capacity);
+
+        let times = Times::new();
+        let mut random = [0u8; 16 * BULK_UUID_BATCH];
+        let mut available = 0;
+
+        for fields in entries {
+            if available == 0 {
+                available = match getrandom::fill(&mut random) {
+                    Ok(()) => BULK_UUID_BATCH,
+                    Err(_) => 0,
+                };
+            }
+
+            let uuid = match available {
+                0 => Uuid::new_v4(),
+                _ => {
+                    available -= 1;
+                    let mut bytes = [0u8; 16];
+                    bytes.copy_from_slice(&random[16 * available..16 * (available + 1)]);
+                    uuid::Builder::from_random_bytes(bytes).into_uuid()
+                }
+            };
+
+            ids.push(EntryId::from(uuid));
+            self.children.push(Node::Entry(Entry {
+                uuid,
+                fields,
+                times: times.clone(),
+                ..Default::default()
+            }));
+        }
+
+        ids
+    }
+
     /// Recursively get a Group or Entry reference by specifying a path relative to the current Group
     /// ```
     /// use keepass::{Database, DatabaseKey, db::NodeRef};
@@ -128,7 +190,13 @@ impl Group {
     /// }
     /// ```
     pub fn get<'a>(&'a self, path: &[&str]) -> Option<NodeRef<'a>> {
-        self.get_internal(&path, SearchField::Title)
+        self.get_internal(&path, SearchField::Title(TextMatching::default()))
+    }
+
+    /// Like [`Group::get`], but compare the names of groups and the titles of entries as
+    /// specified by `matching`, e.g. ignoring differences in Unicode normalization or case
+    pub fn get_with<'a>(&'a self, path: &[&str], matching: TextMatching) -> Option<NodeRef<'a>> {
+        self.get_internal(path, SearchField::Title(matching))
     }
 
     #[cfg(any(test, feature = "_merge"))]
@@ -165,7 +233,12 @@ impl Group {
     /// Recursively get a mutable reference to a Group or Entry by specifying a path relative to
     /// the current Group
     pub fn get_mut<'a>(&'a mut self, path: &[&str]) -> Option<NodeRefMut<'a>> {
-        self.get_mut_internal(path, SearchField::Title)
+        self.get_mut_internal(path, SearchField::Title(TextMatching::default()))
+    }
+
+    /// Like [`Group::get_mut`], but compare names and titles as specified by `matching`
+    pub fn get_mut_with<'a>(&'a mut self, path: &[&str], matching: TextMatching) -> Option<NodeRefMut<'a>> {
+        self.get_mut_internal(path, SearchField::Title(matching))
     }
 
     #[cfg(any(test, feature = "_merge"))]
@@ -277,6 +350,28 @@ impl Group {
         self.times.get_expiry()
     }
 
+    /// Whether the group is expanded in the tree view of KeePass and other clients
+    pub fn is_expanded(&self) -> bool {
+        self.is_expanded
+    }
+
+    /// Expand or collapse the group in the tree view. This is user interface state, so the
+    /// modification time of the group is left as is.
+    pub fn set_expanded(&mut self, expanded: bool) {
+        self.is_expanded = expanded;
+    }
+
+    /// Get the entry that was shown at the top of the entry list when the group was last viewed
+    pub fn last_top_visible_entry(&self) -> Option<EntryId> {
+        self.last_top_visible_entry.map(EntryId::from)
+    }
+
+    /// Set the entry that is scrolled to the top of the entry list when the group is opened. Like
+    /// [`Group::set_expanded`], this does not touch the modification time.
+    pub fn set_last_top_visible_entry(&mut self, entry: Option<EntryId>) {
+        self.last_top_visible_entry = entry.map(Uuid::from);
+    }
+
     pub fn entries(&self) -> Vec<&Entry> {
         let mut response: Vec<&Entry> = vec![];
         for node in &self.children {
@@ -297,6 +392,16 @@ impl Group {
         response
     }
 
+    /// Call `f` on every entry contained in this group or any of its subgroups
+    pub(crate) fn for_each_entry_mut<F: FnMut(&mut Entry)>(&mut self, f: &mut F) {
+        for node in &mut self.children {
+            match node {
+                Node::Entry(e) => f(e),
+                Node::Group(g) => g.for_each_entry_mut(f),
+            }
+        }
+    }
+
     pub fn groups(&self) -> Vec<&Group> {
         let mut response: Vec<&Group> = vec![];
         for node in &self.children {
@@ -351,7 +456,6 @@ impl Group {
         )));
     }
 
-    #[cfg(feature = "_merge")]
     pub(crate) fn find_node_location(&self, id: Uuid) -> Option<NodeLocation> {
         let mut current_location = vec![self.uuid];
         for node in &self.children {
@@ -375,6 +479,25 @@ impl Group {
         None
     }
 
+    /// Add the locations of all nodes below this group to `index`, given the location of the
+    /// children of this group.
+    #[cfg(feature = "_merge")]
+    pub(crate) fn index_node_locations(&self, location: &NodeLocation, index: &mut LocationIndex) {
+        for node in &self.children {
+            match node {
+                Node::Entry(e) => {
+                    index.insert(e.uuid, location.clone());
+                }
+                Node::Group(g) => {
+                    index.insert(g.uuid, location.clone());
+                    let mut child_location = location.clone();
+                    child_location.push(g.uuid);
+                    g.index_node_locations(&child_location, index);
+                }
+            }
+        }
+    }
+
     #[cfg(feature = "_merge")]
     pub(crate) fn merge_with(&mut self, other: &Group) -> Result<MergeLog, MergeError> {
         let mut log = MergeLog::default();
@@ -405,9 +528,10 @@ impl Group {
                 // This should never happen.
                 // This means that a group was updated without updating the last modification
                 // timestamp.
-                return Err(MergeError::GroupModificationTimeNotUpdated(
-                    other.uuid.to_string(),
-                ));
+                return Err(MergeError::GroupModificationTimeNotUpdated {
+                    uuid: other.uuid.to_string(),
+                    name: other.name.clone(),
+                });
             }
             return Ok(log);
         }
@@ -435,10 +559,8 @@ impl Group {
         self.enable_searching = other.enable_searching.clone();
         self.last_top_visible_entry = other.last_top_visible_entry.clone();
 
-        log.events.push(MergeEvent {
-            event_type: MergeEventType::GroupUpdated,
-            node_uuid: self.uuid,
-        });
+        log.events
+            .push(MergeEvent::new(MergeEventType::GroupUpdated, self.uuid));
 
         Ok(log)
     }
@@ -458,9 +580,33 @@ impl Group {
 }
 
 impl<'a> Group {
+    /// Iterate over this group and all nodes below it, breadth-first
     pub fn iter(&'a self) -> NodeIter<'a> {
         (&self).into_iter()
     }
+
+    /// Iterate over all nodes below this group, depth-first and in the order in which they are
+    /// stored, i.e. in the order in which a tree view shows them when it is fully expanded. The
+    /// group itself is not included.
+    pub fn descendants(&'a self) -> Descendants<'a> {
+        Descendants::new(self)
+    }
+
+    /// Iterate over all nodes below this group, breadth-first and in the order in which they are
+    /// stored. Unlike [`Group::iter`], the group itself is not included.
+    pub fn descendants_breadth_first(&'a self) -> NodeIter<'a> {
+        NodeIter::new(self.children.iter().map(NodeRef::from).collect())
+    }
+
+    /// Iterate over all nodes below this group in parallel, e.g. to analyze the entries of a
+    /// large database on all CPUs. The nodes are produced in the same order as by
+    /// [`Group::descendants`] when the iterator is collected.
+    #[cfg(feature = "rayon")]
+    pub fn par_descendants(&'a self) -> rayon::vec::IntoIter<NodeRef<'a>> {
+        use rayon::iter::IntoParallelIterator;
+
+        self.descendants().collect::<Vec<_>>().into_par_iter()
+    }
 }
 
 impl<'a> IntoIterator for &'a Group {
@@ -477,8 +623,10 @@ impl<'a> IntoIterator for &'a Group {
 
 #[cfg(test)]
 mod group_tests {
+    use std::collections::{HashMap, HashSet};
+
     use super::Group;
-    use crate::db::Entry;
+    use crate::db::{Entry, Value};
     use crate::Database;
 
     #[test]
@@ -488,7 +636,7 @@ mod group_tests {
         let mut general_group = Group::new("General");
         let mut sample_entry = Entry::new();
         sample_entry.fields.insert(
-            "Title".to_string(),
+            "Title".into(),
             crate::db::Value::Unprotected("Sample Entry #2".to_string()),
         );
         general_group.add_child(sample_entry);
@@ -507,7 +655,7 @@ mod group_tests {
         let mut general_group = Group::new("General");
         let mut sample_entry = Entry::new();
         sample_entry.fields.insert(
-            "Title".to_string(),
+            "Title".into(),
             crate::db::Value::Unprotected("Sample Entry #2".to_string()),
         );
         general_group.add_child(sample_entry);
@@ -526,7 +674,7 @@ mod group_tests {
         let mut general_group = Group::new("General");
         let mut sample_entry = Entry::new();
         sample_entry.fields.insert(
-            "Title".to_string(),
+            "Title".into(),
             crate::db::Value::Unprotected("Sample Entry #2".to_string()),
         );
         general_group.add_child(sample_entry.clone());
@@ -566,7 +714,7 @@ mod group_tests {
         let mut general_group = Group::new("General");
         let mut sample_entry = Entry::new();
         sample_entry.fields.insert(
-            "Title".to_string(),
+            "Title".into(),
             crate::db::Value::Unprotected("Sample Entry #2".to_string()),
         );
         general_group.add_child(sample_entry.clone());
@@ -598,4 +746,99 @@ mod group_tests {
         assert!(db.root.get_by_uuid_mut(&invalid_path).is_none());
         assert!(db.root.get_by_uuid_mut(&empty_path).is_some());
     }
+
+    #[test]
+    fn add_entries_bulk() {
+        let mut group = Group::new("Imported");
+        group.add_child(Entry::new());
+
+        let ids = group.add_entries_bulk((0..1000).map(|i| {
+            let mut fields = HashMap::new();
+            fields.insert("Title".into(), Value::Unprotected(format!("Entry {}", i)));
+            fields
+        }));
+        assert_eq!(ids.len(), 1000);
+        assert_eq!(group.children.len(), 1001);
+
+        let entries = group.entries();
+        assert_eq!(entries[1].id(), ids[0]);
+        assert_eq!(entries[1000].get_title(), Some("Entry 999"));
+        assert_eq!(entries[1].uuid.get_version_num(), 4);
+        assert!(entries[1].times.get_creation().is_some());
+
+        let unique: HashSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len());
+    }
+
+    #[test]
+    fn ui_state() {
+        let mut group = Group::new("General");
+        let entry = Entry::new();
+        let entry_id = entry.id();
+        group.add_child(entry);
+        let modified = group.times.get_last_modification().cloned();
+
+        assert!(!group.is_expanded());
+        group.set_expanded(true);
+        assert!(group.is_expanded());
+
+        assert_eq!(group.last_top_visible_entry(), None);
+        group.set_last_top_visible_entry(Some(entry_id));
+        assert_eq!(group.last_top_visible_entry(), Some(entry_id));
+        assert_eq!(group.last_top_visible_entry, Some(*entry_id.as_uuid()));
+
+        assert_eq!(group.times.get_last_modification().cloned(), modified);
+    }
+
+    #[test]
+    fn traversal_order() {
+        use crate::db::NodeRef;
+
+        fn names<'a>(nodes: impl Iterator<Item = NodeRef<'a>>) -> Vec<String> {
+            nodes
+                .map(|node| match node {
+                    NodeRef::Group(g) => g.name.clone(),
+                    NodeRef::Entry(e) => e.get_title().unwrap_or_default().to_string(),
+                })
+                .collect()
+        }
+
+        let mut root = Group::new("Root");
+        let mut a = Group::new("a");
+        let mut a1 = Group::new("a1");
+        let mut entry = Entry::new();
+        entry.set_title("a1-entry");
+        a1.add_child(entry);
+        a.add_child(a1);
+        let mut entry = Entry::new();
+        entry.set_title("a-entry");
+        a.add_child(entry);
+        root.add_child(a);
+        root.add_child(Group::new("b"));
+
+        assert_eq!(
+            names(root.descendants()),
+            vec!["a", "a1", "a1-entry", "a-entry", "b"]
+        );
+        assert_eq!(
+            names(root.descendants_breadth_first()),
+            vec!["a", "b", "a1", "a-entry", "a1-entry"]
+        );
+        assert_eq!(root.iter().count(), root.descendants().count() + 1);
+        assert_eq!(Group::new("Empty").descendants().count(), 0);
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::iter::ParallelIterator;
+
+            let titles: Vec<&str> = root
+                .par_descendants()
+                .filter_map(|node| match node {
+                    NodeRef::Entry(e) => e.get_title(),
+                    NodeRef::Group(_) => None,
+                })
+                .collect();
+            assert_eq!(titles, vec!["a1-entry", "a-entry"]);
+        }
+    }
 }
@@ -0,0 +1,335 @@
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    db::{sniff_mime_type, tracked::find_with_ancestors, Database, Entry, Group, Meta, NodeRef, Times},
+    error::InvalidIconIdError,
+};
+
+/// Standard icon of groups that neither have an icon of their own nor inherit one (a folder)
+pub const DEFAULT_GROUP_ICON_ID: usize = 48;
+
+macro_rules! standard_icons {
+    ($($variant:ident = $id:literal => $name:literal,)*) => {
+        /// The standard icons that are built into KeePass, with their IDs as stored in
+        /// [`Entry::icon_id`] and [`Group::icon_id`]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+        pub enum StandardIcon {
+            $($variant = $id,)*
+        }
+
+        impl StandardIcon {
+            /// All standard icons, ordered by their IDs
+            pub const ALL: [StandardIcon; 69] = [$(StandardIcon::$variant,)*];
+
+            /// English display name of the icon, e.g. for an icon picker
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(StandardIcon::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+standard_icons! {
+    Key = 0 => "Key",
+    World = 1 => "World",
+    Warning = 2 => "Warning",
+    NetworkServer = 3 => "Network server",
+    MarkedDirectory = 4 => "Marked directory",
+    UserCommunication = 5 => "User communication",
+    Parts = 6 => "Parts",
+    Notepad = 7 => "Notepad",
+    WorldSocket = 8 => "World socket",
+    Identity = 9 => "Identity",
+    PaperReady = 10 => "Paper ready",
+    Digicam = 11 => "Digital camera",
+    IRCommunication = 12 => "IR communication",
+    MultiKeys = 13 => "Multiple keys",
+    Energy = 14 => "Energy",
+    Scanner = 15 => "Scanner",
+    WorldStar = 16 => "World star",
+    CDRom = 17 => "CD-ROM",
+    Monitor = 18 => "Monitor",
+    EMail = 19 => "E-mail",
+    Configuration = 20 => "Configuration",
+    ClipboardReady = 21 => "Clipboard ready",
+    PaperNew = 22 => "New paper",
+    Screen = 23 => "Screen",
+    EnergyCareful = 24 => "Energy careful",
+    EMailBox = 25 => "E-mail box",
+    Disk = 26 => "Disk",
+    Drive = 27 => "Drive",
+    PaperQ = 28 => "Paper question",
+    TerminalEncrypted = 29 => "Encrypted terminal",
+    Console = 30 => "Console",
+    Printer = 31 => "Printer",
+    ProgramIcons = 32 => "Program icons",
+    Run = 33 => "Run",
+    Settings = 34 => "Settings",
+    WorldComputer = 35 => "World computer",
+    Archive = 36 => "Archive",
+    Homebanking = 37 => "Home banking",
+    DriveWindows = 38 => "Windows drive",
+    Clock = 39 => "Clock",
+    EMailSearch = 40 => "E-mail search",
+    PaperFlag = 41 => "Paper flag",
+    Memory = 42 => "Memory",
+    TrashBin = 43 => "Trash bin",
+    Note = 44 => "Note",
+    Expired = 45 => "Expired",
+    Info = 46 => "Info",
+    Package = 47 => "Package",
+    Folder = 48 => "Folder",
+    FolderOpen = 49 => "Open folder",
+    FolderPackage = 50 => "Folder package",
+    LockOpen = 51 => "Open lock",
+    PaperLocked = 52 => "Locked paper",
+    Checked = 53 => "Checked",
+    Pen = 54 => "Pen",
+    Thumbnail = 55 => "Thumbnail",
+    Book = 56 => "Book",
+    List = 57 => "List",
+    UserKey = 58 => "User key",
+    Tool = 59 => "Tool",
+    Home = 60 => "Home",
+    Star = 61 => "Star",
+    Tux = 62 => "Tux",
+    Feather = 63 => "Feather",
+    Apple = 64 => "Apple",
+    Wiki = 65 => "Wiki",
+    Money = 66 => "Money",
+    Certificate = 67 => "Certificate",
+    BlackBerry = 68 => "BlackBerry",
+}
+
+impl StandardIcon {
+    /// The ID of the icon as stored in the database
+    pub fn id(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl TryFrom<usize> for StandardIcon {
+    type Error = InvalidIconIdError;
+
+    fn try_from(id: usize) -> Result<Self, Self::Error> {
+        StandardIcon::ALL.get(id).copied().ok_or(InvalidIconIdError(id))
+    }
+}
+
+impl From<StandardIcon> for usize {
+    fn from(icon: StandardIcon) -> Self {
+        icon.id()
+    }
+}
+
+impl std::fmt::Display for StandardIcon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The icon that is displayed for an entry or group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum IconSource {
+    /// A custom icon stored in [`Meta::custom_icons`]
+    Custom(Uuid),
+
+    /// One of the standard icons of KeePass
+    Standard(usize),
+}
+
+fn own_icon(meta: &Meta, icon_id: Option<usize>, custom_icon_uuid: Option<Uuid>) -> Option<IconSource> {
+    // custom icons take precedence, but only if they actually exist
+    if let Some(uuid) = custom_icon_uuid {
+        if meta.custom_icons.icons.iter().any(|i| i.uuid == uuid) {
+            return Some(IconSource::Custom(uuid));
+        }
+    }
+
+    icon_id.map(IconSource::Standard)
+}
+
+impl Group {
+    /// Get the icon set on this group, if any. A custom icon that is missing from `meta` is
+    /// ignored.
+    pub fn icon(&self, meta: &Meta) -> Option<IconSource> {
+        own_icon(meta, self.icon_id, self.custom_icon_uuid)
+    }
+
+    /// Get the standard icon of this group, if it has a known one
+    pub fn standard_icon(&self) -> Option<StandardIcon> {
+        self.icon_id.and_then(|id| StandardIcon::try_from(id).ok())
+    }
+
+    /// Set a standard icon for this group, removing its custom icon, which would take precedence
+    pub fn set_standard_icon(&mut self, icon: StandardIcon) {
+        self.icon_id = Some(icon.id());
+        self.custom_icon_uuid = None;
+        self.times.set_last_modification(Times::now());
+    }
+}
+
+impl Entry {
+    /// Get the icon set on this entry, if any. A custom icon that is missing from `meta` is
+    /// ignored.
+    pub fn icon(&self, meta: &Meta) -> Option<IconSource> {
+        own_icon(meta, self.icon_id, self.custom_icon_uuid)
+    }
+
+    /// Get the standard icon of this entry, if it has a known one
+    pub fn standard_icon(&self) -> Option<StandardIcon> {
+        self.icon_id.and_then(|id| StandardIcon::try_from(id).ok())
+    }
+
+    /// Set a standard icon for this entry, removing its custom icon, which would take precedence
+    pub fn set_standard_icon(&mut self, icon: StandardIcon) {
+        self.icon_id = Some(icon.id());
+        self.custom_icon_uuid = None;
+        self.times.set_last_modification(Times::now());
+    }
+}
+
+impl Database {
+    /// Get the icon to display for the entry or group with the given UUID.
+    ///
+    /// Nodes without an icon of their own inherit the icon of the closest ancestor group that has
+    /// one. If no group has an icon, the [`DEFAULT_GROUP_ICON_ID`] is used. Returns `None` if
+    /// there is no node with the UUID.
+    pub fn effective_icon(&self, uuid: Uuid) -> Option<IconSource> {
+        if self.root.uuid == uuid {
+            return Some(
+                self.root
+                    .icon(&self.meta)
+                    .unwrap_or(IconSource::Standard(DEFAULT_GROUP_ICON_ID)),
+            );
+        }
+
+        let (node, ancestors) = find_with_ancestors(&self.root, &uuid)?;
+        let icon = match node {
+            NodeRef::Entry(e) => e.icon(&self.meta),
+            NodeRef::Group(g) => g.icon(&self.meta),
+        };
+
+        Some(
+            icon.or_else(|| ancestors.iter().rev().find_map(|g| g.icon(&self.meta)))
+                .unwrap_or(IconSource::Standard(DEFAULT_GROUP_ICON_ID)),
+        )
+    }
+}
+
+impl Meta {
+    /// Write every custom icon to a file named after its UUID in the directory `dir`, with a file
+    /// extension matching the image format. Returns the paths of the written files.
+    pub fn export_custom_icons(&self, dir: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+        self.custom_icons
+            .icons
+            .iter()
+            .map(|icon| {
+                let extension = match sniff_mime_type(&icon.data) {
+                    "image/png" => "png",
+                    "image/jpeg" => "jpg",
+                    "image/gif" => "gif",
+                    _ => "bin",
+                };
+
+                let path = dir.as_ref().join(format!("{}.{}", icon.uuid, extension));
+                std::fs::write(&path, &icon.data)?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod icon_tests {
+    use uuid::Uuid;
+
+    use std::convert::TryFrom;
+
+    use super::{IconSource, StandardIcon, DEFAULT_GROUP_ICON_ID};
+    use crate::db::{Database, Entry, Group, Icon};
+
+    #[test]
+    fn standard_icons() {
+        for (id, icon) in StandardIcon::ALL.iter().enumerate() {
+            assert_eq!(icon.id(), id);
+            assert_eq!(StandardIcon::try_from(id).unwrap(), *icon);
+        }
+        assert_eq!(
+            StandardIcon::try_from(DEFAULT_GROUP_ICON_ID).unwrap(),
+            StandardIcon::Folder
+        );
+        assert_eq!(StandardIcon::BlackBerry.id(), 68);
+        assert_eq!(StandardIcon::NetworkServer.to_string(), "Network server");
+        assert!(StandardIcon::try_from(69).is_err());
+
+        let mut entry = Entry::new();
+        entry.custom_icon_uuid = Some(Uuid::new_v4());
+        entry.set_standard_icon(StandardIcon::Key);
+        assert_eq!(entry.icon_id, Some(0));
+        assert_eq!(entry.custom_icon_uuid, None);
+        assert_eq!(entry.standard_icon(), Some(StandardIcon::Key));
+
+        let mut group = Group::new("Trash");
+        group.set_standard_icon(StandardIcon::TrashBin);
+        assert_eq!(group.icon_id, Some(43));
+        group.icon_id = Some(123);
+        assert_eq!(group.standard_icon(), None);
+    }
+
+    #[test]
+    fn effective_icon() {
+        let mut db = Database::new(Default::default());
+        let custom = Uuid::new_v4();
+        db.meta.custom_icons.icons.push(Icon {
+            uuid: custom,
+            data: b"\x89PNG\r\n\x1a\n".to_vec(),
+        });
+
+        let mut group = Group::new("Inherits");
+        group.custom_icon_uuid = Some(custom);
+        group.icon_id = Some(3);
+
+        let plain = Entry::new();
+        let mut own_icon = Entry::new();
+        own_icon.icon_id = Some(12);
+        let mut missing_custom = Entry::new();
+        missing_custom.custom_icon_uuid = Some(Uuid::new_v4());
+        let (plain_uuid, own_uuid, missing_uuid) = (plain.uuid, own_icon.uuid, missing_custom.uuid);
+
+        group.add_child(plain);
+        group.add_child(own_icon);
+        group.add_child(missing_custom);
+        let group_uuid = group.uuid;
+        db.root.add_child(group);
+
+        let root_entry = Entry::new();
+        let root_entry_uuid = root_entry.uuid;
+        db.root.add_child(root_entry);
+
+        assert_eq!(db.effective_icon(group_uuid), Some(IconSource::Custom(custom)));
+        assert_eq!(db.effective_icon(plain_uuid), Some(IconSource::Custom(custom)));
+        assert_eq!(db.effective_icon(own_uuid), Some(IconSource::Standard(12)));
+        assert_eq!(db.effective_icon(missing_uuid), Some(IconSource::Custom(custom)));
+        assert_eq!(
+            db.effective_icon(root_entry_uuid),
+            Some(IconSource::Standard(DEFAULT_GROUP_ICON_ID))
+        );
+        assert_eq!(db.effective_icon(Uuid::new_v4()), None);
+
+        let dir = std::env::temp_dir().join(format!("keepass-icons-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let paths = db.meta.export_custom_icons(&dir).unwrap();
+        assert_eq!(paths, vec![dir.join(format!("{}.png", custom))]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
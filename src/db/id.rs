@@ -0,0 +1,165 @@
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, Group, NodeRef};
+
+macro_rules! node_id {
+    ($name:ident, $kind:literal) => {
+        #[doc = concat!("Stable identifier of ", $kind, ".")]
+        ///
+        /// The identifier is the UUID stored in the database file, so it stays the same when the
+        /// database is saved and loaded again and can be persisted or exchanged with other
+        /// applications. Its string form is the canonical hyphenated UUID.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(Uuid);
+
+        impl $name {
+            pub fn as_uuid(&self) -> &Uuid {
+                &self.0
+            }
+        }
+
+        impl From<Uuid> for $name {
+            fn from(uuid: Uuid) -> Self {
+                $name(uuid)
+            }
+        }
+
+        impl From<$name> for Uuid {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.hyphenated().fmt(f)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = uuid::Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok($name(Uuid::parse_str(s)?))
+            }
+        }
+
+        #[cfg(feature = "serialization")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        #[cfg(feature = "serialization")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+node_id!(EntryId, "an entry");
+node_id!(GroupId, "a group");
+
+impl Entry {
+    pub fn id(&self) -> EntryId {
+        EntryId(self.uuid)
+    }
+}
+
+impl Group {
+    pub fn id(&self) -> GroupId {
+        GroupId(self.uuid)
+    }
+}
+
+impl Database {
+    /// Find an entry anywhere in the database by its identifier
+    pub fn entry_by_id(&self, id: EntryId) -> Option<&Entry> {
+        self.root.iter().find_map(|node| match node {
+            NodeRef::Entry(e) if e.uuid == id.0 => Some(e),
+            _ => None,
+        })
+    }
+
+    /// Find a group anywhere in the database by its identifier
+    pub fn group_by_id(&self, id: GroupId) -> Option<&Group> {
+        self.root.iter().find_map(|node| match node {
+            NodeRef::Group(g) if g.uuid == id.0 => Some(g),
+            _ => None,
+        })
+    }
+
+    /// Find the location of an entry or group in the database by its UUID, i.e. the groups
+    /// leading from the root group (excluded) to its parent.
+    ///
+    /// Nodes that are direct children of the root group have an empty location. Returns `None`
+    /// for the root group itself and for UUIDs that are not in the database.
+    pub fn locate(&self, uuid: Uuid) -> Option<Vec<GroupId>> {
+        let location = self.root.find_node_location(uuid)?;
+        Some(location.into_iter().skip(1).map(GroupId).collect())
+    }
+}
+
+#[cfg(test)]
+mod id_tests {
+    use uuid::Uuid;
+
+    use super::{EntryId, GroupId};
+    use crate::db::{Database, Entry, Group};
+
+    #[test]
+    fn string_round_trip() {
+        let uuid = Uuid::new_v4();
+        let id = EntryId::from(uuid);
+
+        assert_eq!(id.to_string(), uuid.hyphenated().to_string());
+        assert_eq!(id.to_string().parse::<EntryId>().unwrap(), id);
+        assert_eq!(Uuid::from(id), uuid);
+        assert!("not a uuid".parse::<GroupId>().is_err());
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn serde_round_trip() {
+        let id = GroupId::from(Uuid::new_v4());
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", id));
+        assert_eq!(serde_json::from_str::<GroupId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn lookup() {
+        let mut db = Database::new(Default::default());
+        let entry = Entry::new();
+        let id = entry.id();
+        db.root.add_child(entry);
+
+        assert_eq!(db.entry_by_id(id).map(|e| e.id()), Some(id));
+        assert_eq!(db.group_by_id(db.root.id()).map(|g| g.uuid), Some(db.root.uuid));
+        assert!(db.entry_by_id(EntryId::from(Uuid::new_v4())).is_none());
+    }
+
+    #[test]
+    fn locate() {
+        let mut db = Database::new(Default::default());
+        let mut outer = Group::new("Outer");
+        let mut inner = Group::new("Inner");
+        let nested = Entry::new();
+        let top = Entry::new();
+        let (outer_id, inner_id, nested_uuid, top_uuid) = (outer.id(), inner.id(), nested.uuid, top.uuid);
+        inner.add_child(nested);
+        outer.add_child(inner);
+        db.root.add_child(outer);
+        db.root.add_child(top);
+
+        assert_eq!(db.locate(nested_uuid), Some(vec![outer_id, inner_id]));
+        assert_eq!(db.locate(*inner_id.as_uuid()), Some(vec![outer_id]));
+        assert_eq!(db.locate(top_uuid), Some(vec![]));
+        assert_eq!(db.locate(db.root.uuid), None);
+        assert_eq!(db.locate(Uuid::new_v4()), None);
+    }
+}
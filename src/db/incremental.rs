@@ -0,0 +1,176 @@
+use crate::{
+    config::DatabaseVersion,
+    db::Database,
+    error::DatabaseSaveError,
+    format::kdbx4::{dump_kdbx4_incremental, SavedKdbx4},
+    key::DatabaseKey,
+    trace::phase,
+};
+
+/// State kept between calls to [`Database::save_incremental`], holding the keys and encrypted
+/// blocks of the previous save.
+///
+/// The state contains the master key of the database, so it should only live as long as the
+/// database is open.
+#[derive(Default)]
+pub struct IncrementalSaveState {
+    saved: Option<SavedKdbx4>,
+}
+
+impl IncrementalSaveState {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Forget the previous save, so that the next save is a full save with fresh seeds
+    pub fn reset(&mut self) {
+        self.saved = None;
+    }
+}
+
+impl std::fmt::Debug for IncrementalSaveState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IncrementalSaveState")
+            .field("saved", &self.saved.is_some())
+            .finish()
+    }
+}
+
+/// Result of [`Database::save_incremental`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncrementalSaveReport {
+    /// Whether the whole file was encrypted with fresh seeds
+    pub full_save: bool,
+
+    /// Number of bytes of encrypted payload that were taken over from the previous save
+    pub reused_bytes: usize,
+
+    /// Total number of bytes of encrypted payload
+    pub total_bytes: usize,
+}
+
+impl Database {
+    /// Save the database as KDBX4, re-using the encrypted blocks of the previous save through
+    /// `state` where the payload did not change. This avoids running the key derivation and
+    /// re-encrypting the whole payload, which helps for large databases with many attachments.
+    ///
+    /// Only the payload after the first change is encrypted again, so changes near the end of
+    /// the database benefit most. Unless the compression is disabled, a small change usually
+    /// alters most of the compressed payload.
+    ///
+    /// A full save is performed the first time, if the configuration or the key changed, and for
+    /// the ChaCha20 outer cipher, whose key stream must not be re-used.
+    ///
+    /// **Unlike [`Database::save`], an incremental save keeps the master seed, the encryption IV
+    /// and the key of the inner stream cipher of the previous save.** Saved files thus share
+    /// their keys, and an observer of several versions of the file can tell which part of the
+    /// payload changed. Call [`IncrementalSaveState::reset`] to force fresh seeds.
+    pub fn save_incremental(
+        &self,
+        destination: &mut dyn std::io::Write,
+        key: DatabaseKey,
+        state: &mut IncrementalSaveState,
+    ) -> Result<IncrementalSaveReport, DatabaseSaveError> {
+        if !matches!(self.config.version, DatabaseVersion::KDB4(_)) {
+            return Err(DatabaseSaveError::UnsupportedVersion);
+        }
+
+        phase!(
+            "save",
+            dump_kdbx4_incremental(self, &key, &mut state.saved, destination)
+        )
+    }
+}
+
+#[cfg(test)]
+mod incremental_tests {
+    use super::IncrementalSaveState;
+    use crate::hmac_block_stream::HMAC_BLOCK_SIZE;
+    use crate::{
+        config::{CompressionConfig, DatabaseConfig, KdfConfig, OuterCipherConfig},
+        db::{Database, Entry, HeaderAttachment, Value},
+        key::DatabaseKey,
+    };
+
+    fn database(outer_cipher_config: OuterCipherConfig) -> Database {
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            compression_config: CompressionConfig::None,
+            outer_cipher_config,
+            ..Default::default()
+        });
+
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: vec![42; 3 * HMAC_BLOCK_SIZE / 2],
+        });
+        db.root.add_child(Entry::new());
+        db
+    }
+
+    fn key() -> DatabaseKey {
+        DatabaseKey::new().with_password("incremental")
+    }
+
+    #[test]
+    fn reuses_unchanged_blocks() {
+        let mut db = database(OuterCipherConfig::AES256);
+        let mut state = IncrementalSaveState::new();
+
+        let mut first = Vec::new();
+        let report = db.save_incremental(&mut first, key(), &mut state).unwrap();
+        assert!(report.full_save);
+
+        let mut second = Vec::new();
+        let report = db.save_incremental(&mut second, key(), &mut state).unwrap();
+        assert!(!report.full_save);
+        assert_eq!(report.reused_bytes, report.total_bytes);
+        assert_eq!(first, second);
+
+        // the attachment comes before the XML document, so it is neither encrypted nor
+        // authenticated again
+        db.root.entries_mut()[0]
+            .fields
+            .insert("Title".into(), Value::Unprotected("Changed".to_string()));
+        let mut third = Vec::new();
+        let report = db.save_incremental(&mut third, key(), &mut state).unwrap();
+        assert!(!report.full_save);
+        assert!(report.reused_bytes > HMAC_BLOCK_SIZE);
+        assert!(report.reused_bytes < report.total_bytes);
+
+        let opened = Database::open(&mut third.as_slice(), key()).unwrap();
+        assert_eq!(opened.root.entries()[0].get_title(), Some("Changed"));
+        assert_eq!(opened.header_attachments, db.header_attachments);
+
+        // changes after a partial save are picked up as well
+        db.root.entries_mut()[0]
+            .fields
+            .insert("Notes".into(), Value::Unprotected("Notes".to_string()));
+        let mut fourth = Vec::new();
+        db.save_incremental(&mut fourth, key(), &mut state).unwrap();
+        let opened = Database::open(&mut fourth.as_slice(), key()).unwrap();
+        assert_eq!(opened.root.entries()[0].get("Notes"), Some("Notes"));
+
+        // a different key requires a full save
+        let other_key = DatabaseKey::new().with_password("other");
+        let mut fifth = Vec::new();
+        let report = db
+            .save_incremental(&mut fifth, other_key.clone(), &mut state)
+            .unwrap();
+        assert!(report.full_save);
+        Database::open(&mut fifth.as_slice(), other_key).unwrap();
+    }
+
+    #[test]
+    fn stream_cipher_full_save() {
+        let db = database(OuterCipherConfig::ChaCha20);
+        let mut state = IncrementalSaveState::new();
+
+        for _ in 0..2 {
+            let mut buffer = Vec::new();
+            let report = db.save_incremental(&mut buffer, key(), &mut state).unwrap();
+            assert!(report.full_save);
+            Database::open(&mut buffer.as_slice(), key()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::db::{
+    tracked::find_with_ancestors, ChangeEvent, Database, Entry, EntryId, Group, GroupId, Node, NodeRef,
+    TrackedDatabase,
+};
+
+/// Split a value into lowercase words for indexing and querying
+fn tokenize(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+fn entry_tokens(entry: &Entry) -> HashSet<String> {
+    [entry.get_title(), entry.get_username(), entry.get_url()]
+        .iter()
+        .flatten()
+        .copied()
+        .chain(entry.tags.iter().map(|tag| &**tag))
+        .flat_map(tokenize)
+        .collect()
+}
+
+/// Parent group of each group except for the root group, for walking up the group tree in the
+/// caches that are kept up to date with [`ChangeEvent`]s
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParentMap(HashMap<Uuid, Uuid>);
+
+impl ParentMap {
+    pub(crate) fn insert(&mut self, group: Uuid, parent: Uuid) {
+        self.0.insert(group, parent);
+    }
+
+    /// The group `group` followed by its ancestors, up to the root group
+    pub(crate) fn ancestors(&self, group: Uuid) -> impl Iterator<Item = Uuid> + '_ {
+        std::iter::successors(Some(group), move |g| self.0.get(g).copied())
+    }
+
+    /// Whether the group `group` is `ancestor` or below it
+    pub(crate) fn is_in_group(&self, group: Uuid, ancestor: Uuid) -> bool {
+        self.ancestors(group).any(|g| g == ancestor)
+    }
+
+    /// Forget the group `group` and all groups below it, returning the forgotten groups
+    pub(crate) fn remove_group(&mut self, group: Uuid) -> HashSet<Uuid> {
+        let removed: HashSet<Uuid> = self
+            .0
+            .keys()
+            .copied()
+            .filter(|g| self.is_in_group(*g, group))
+            .collect();
+
+        self.0.retain(|g, _| !removed.contains(g));
+        removed
+    }
+}
+
+/// An in-memory inverted index over the titles, usernames, URLs and tags of the entries of a
+/// database, for fast searches in large databases.
+///
+/// The index is built once with [`SearchIndex::build`] and can then be kept up to date by
+/// applying the [`ChangeEvent`]s of a [`TrackedDatabase`], which
+/// [`TrackedDatabase::enable_search_index`] does automatically. Changes that are made to the
+/// database in other ways require the index to be built again.
+///
+/// History items are not indexed.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    /// Entries by the words of their indexed fields
+    words: BTreeMap<String, HashSet<Uuid>>,
+
+    /// Indexed words of each entry, so that they can be removed again
+    entry_words: HashMap<Uuid, HashSet<String>>,
+
+    /// Parent group of each entry
+    entry_parents: HashMap<Uuid, Uuid>,
+
+    /// Parent group of each group, except for the root group
+    group_parents: ParentMap,
+}
+
+impl SearchIndex {
+    /// Index all entries of a database
+    pub fn build(db: &Database) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        index.add_group(&db.root);
+        index
+    }
+
+    /// Number of indexed entries
+    pub fn len(&self) -> usize {
+        self.entry_words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entry_words.is_empty()
+    }
+
+    /// Index the entries of `group` and its subgroups
+    fn add_group(&mut self, group: &Group) {
+        for node in &group.children {
+            match node {
+                Node::Entry(e) => self.add_entry(e, group.uuid),
+                Node::Group(g) => {
+                    self.group_parents.insert(g.uuid, group.uuid);
+                    self.add_group(g);
+                }
+            }
+        }
+    }
+
+    fn add_entry(&mut self, entry: &Entry, parent: Uuid) {
+        self.remove_entry(entry.uuid);
+
+        let words = entry_tokens(entry);
+        for word in &words {
+            self.words.entry(word.clone()).or_default().insert(entry.uuid);
+        }
+        self.entry_words.insert(entry.uuid, words);
+        self.entry_parents.insert(entry.uuid, parent);
+    }
+
+    fn remove_entry(&mut self, uuid: Uuid) {
+        for word in self.entry_words.remove(&uuid).unwrap_or_default() {
+            if let Some(entries) = self.words.get_mut(&word) {
+                entries.remove(&uuid);
+                if entries.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+        self.entry_parents.remove(&uuid);
+    }
+
+    /// Remove a group and everything below it from the index
+    fn remove_group(&mut self, uuid: Uuid) {
+        let groups = self.group_parents.remove_group(uuid);
+
+        let entries: Vec<Uuid> = self
+            .entry_parents
+            .iter()
+            .filter(|(_, parent)| groups.contains(*parent))
+            .map(|(entry, _)| *entry)
+            .collect();
+
+        for entry in entries {
+            self.remove_entry(entry);
+        }
+    }
+
+    /// Update the index for a change that was made to `db`
+    pub fn apply(&mut self, db: &Database, event: &ChangeEvent) {
+        match event {
+            ChangeEvent::EntryAdded { uuid, .. }
+            | ChangeEvent::FieldChanged { entry: uuid, .. }
+            | ChangeEvent::EntryModified { uuid } => {
+                if let Some((NodeRef::Entry(e), ancestors)) = find_with_ancestors(&db.root, uuid) {
+                    if let Some(parent) = ancestors.last() {
+                        self.add_entry(e, parent.uuid);
+                    }
+                }
+            }
+            ChangeEvent::GroupAdded { uuid, parent } => {
+                if let Some((NodeRef::Group(g), _)) = find_with_ancestors(&db.root, uuid) {
+                    self.group_parents.insert(*uuid, *parent);
+                    self.add_group(g);
+                }
+            }
+            ChangeEvent::EntryRemoved { uuid, .. } => self.remove_entry(*uuid),
+            ChangeEvent::GroupRemoved { uuid, .. } => self.remove_group(*uuid),
+            ChangeEvent::EntryMoved { uuid, to, .. } => {
+                if let Some(parent) = self.entry_parents.get_mut(uuid) {
+                    *parent = *to;
+                }
+            }
+            ChangeEvent::GroupMoved { uuid, to, .. } => {
+                self.group_parents.insert(*uuid, *to);
+            }
+            ChangeEvent::GroupModified { .. } => {}
+        }
+    }
+
+    /// Find the entries that contain all words of `query` as prefixes of the words of their
+    /// title, username, URL or tags, ignoring case. Returns nothing for an empty query.
+    pub fn search(&self, query: &str) -> Vec<EntryId> {
+        self.search_uuids(query, None)
+    }
+
+    /// Like [`SearchIndex::search`], but only find the entries in `group` and its subgroups
+    pub fn search_in(&self, query: &str, group: GroupId) -> Vec<EntryId> {
+        self.search_uuids(query, Some(*group.as_uuid()))
+    }
+
+    fn search_uuids(&self, query: &str, group: Option<Uuid>) -> Vec<EntryId> {
+        let mut matches: Option<HashSet<Uuid>> = None;
+
+        for word in tokenize(query) {
+            let found: HashSet<Uuid> = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(&word))
+                .flat_map(|(_, entries)| entries.iter().copied())
+                .collect();
+
+            matches = Some(match matches {
+                Some(previous) => previous.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+
+        let mut found: Vec<EntryId> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|uuid| match (group, self.entry_parents.get(uuid)) {
+                (Some(group), Some(parent)) => self.group_parents.is_in_group(*parent, group),
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+            .map(EntryId::from)
+            .collect();
+        found.sort();
+        found
+    }
+}
+
+impl Database {
+    /// Find the entries that match `query` using a [`SearchIndex`] of this database, in the order
+    /// in which they appear in the database. See [`SearchIndex::search`] for how entries match.
+    pub fn search_indexed(&self, index: &SearchIndex, query: &str) -> Vec<&Entry> {
+        let found: HashSet<EntryId> = index.search(query).into_iter().collect();
+        if found.is_empty() {
+            return Vec::new();
+        }
+
+        self.root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Entry(e) if found.contains(&e.id()) => Some(e),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl TrackedDatabase {
+    /// Build a [`SearchIndex`] of the database that is updated with every change made through
+    /// the `TrackedDatabase`
+    pub fn enable_search_index(&mut self) {
+        self.index = Some(SearchIndex::build(&self.db));
+    }
+
+    /// Stop maintaining the search index
+    pub fn disable_search_index(&mut self) {
+        self.index = None;
+    }
+
+    /// The search index, if it was enabled with [`TrackedDatabase::enable_search_index`]
+    pub fn search_index(&self) -> Option<&SearchIndex> {
+        self.index.as_ref()
+    }
+
+    /// Find the entries that match `query` using the search index, see
+    /// [`Database::search_indexed`]. Returns `None` if the index is not enabled.
+    pub fn search_indexed(&self, query: &str) -> Option<Vec<&Entry>> {
+        Some(self.db.search_indexed(self.index.as_ref()?, query))
+    }
+}
+
+#[cfg(test)]
+mod index_tests {
+    use super::SearchIndex;
+    use crate::db::{Database, Entry, EntryId, Group, TrackedDatabase, Value};
+
+    fn entry(title: &str, username: &str, url: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry.set_title(title);
+        entry.set_username(username);
+        entry.set_url(url);
+        entry
+    }
+
+    fn titles(entries: Vec<&Entry>) -> Vec<&str> {
+        entries.into_iter().filter_map(|e| e.get_title()).collect()
+    }
+
+    #[test]
+    fn search() {
+        let mut db = Database::new(Default::default());
+        db.root
+            .add_child(entry("Example Mail", "jdoe", "https://mail.example.com"));
+        let mut work = Group::new("Work");
+        let mut vpn = entry("VPN", "john.doe", "https://vpn.corp.example");
+        vpn.tags.push("Remote-Access".into());
+        work.add_child(vpn);
+        let work_id = work.id();
+        db.root.add_child(work);
+
+        let index = SearchIndex::build(&db);
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            titles(db.search_indexed(&index, "EXAMPLE")),
+            vec!["Example Mail", "VPN"]
+        );
+        assert_eq!(titles(db.search_indexed(&index, "exa com")), vec!["Example Mail"]);
+        assert_eq!(titles(db.search_indexed(&index, "john remote")), vec!["VPN"]);
+        assert_eq!(titles(db.search_indexed(&index, "mail vpn")), Vec::<&str>::new());
+        assert!(db.search_indexed(&index, "  ").is_empty());
+        assert_eq!(index.search_in("example", work_id).len(), 1);
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let root = db.database().root.uuid;
+        let group = db.add_group(root, Group::new("Group")).unwrap();
+        let first = db.add_entry(group, entry("First", "alice", "")).unwrap();
+        db.enable_search_index();
+
+        let second = db.add_entry(root, entry("Second", "bob", "")).unwrap();
+        assert_eq!(titles(db.search_indexed("second").unwrap()), vec!["Second"]);
+
+        db.set_field(first, "UserName", Value::Unprotected("carol".to_string()));
+        assert!(db.search_indexed("alice").unwrap().is_empty());
+        assert_eq!(titles(db.search_indexed("carol").unwrap()), vec!["First"]);
+
+        let index = db.search_index().unwrap();
+        assert_eq!(index.search_in("carol", group.into()), vec![EntryId::from(first)]);
+        db.move_node(first, root).unwrap();
+        let index = db.search_index().unwrap();
+        assert!(index.search_in("carol", group.into()).is_empty());
+
+        db.move_node(second, group).unwrap();
+        db.remove(group).unwrap();
+        assert!(db.search_indexed("second").unwrap().is_empty());
+        assert_eq!(db.search_index().unwrap().len(), 1);
+
+        // undoing changes updates the index as well
+        db.enable_undo(10);
+        db.remove(first).unwrap();
+        assert!(db.search_indexed("first").unwrap().is_empty());
+        db.undo();
+        assert_eq!(titles(db.search_indexed("first").unwrap()), vec!["First"]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{HashSet, VecDeque};
+
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, Node, NodeRef};
+
+/// A reference inside a database that does not point to anything valid
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum IntegrityIssue {
+    /// An attachment of an entry, or of one of its history items, refers to a binary that is not
+    /// in the binary pool of the database
+    MissingAttachment {
+        entry: Uuid,
+        name: String,
+        identifier: String,
+    },
+
+    /// An entry or group refers to a custom icon that is not in the metadata
+    MissingCustomIcon { node: Uuid, icon: Uuid },
+
+    /// The recycle bin set in the metadata is not a group of the database
+    MissingRecycleBin(Uuid),
+
+    /// A node is listed as deleted, but is still part of the database
+    DeletedNodeAlive(Uuid),
+
+    /// A history item of an entry has a different UUID than the entry itself
+    HistoryUuidMismatch { entry: Uuid, history_uuid: Uuid },
+
+    /// Several nodes have the same UUID, so that only the first of them can be found by it. See
+    /// [`Database::repair_duplicate_uuids`].
+    DuplicateUuid(Uuid),
+}
+
+/// Result of [`Database::verify_integrity`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct IntegrityReport {
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether no issues were found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Database {
+    /// Check that the references within the database are consistent, e.g. before and after
+    /// merging or importing other databases.
+    ///
+    /// This checks that attachments refer to existing binaries, custom icons exist, the recycle
+    /// bin is a group of the database, no live node is listed as deleted, no two nodes share a
+    /// UUID and history items have the UUID of their entry. Nothing is changed.
+    pub fn verify_integrity(&self) -> IntegrityReport {
+        let mut issues = Vec::new();
+
+        let icons: HashSet<Uuid> = self.meta.custom_icons.icons.iter().map(|i| i.uuid).collect();
+        let deleted: HashSet<Uuid> = self.deleted_objects.objects.iter().map(|d| d.uuid).collect();
+        let mut groups = HashSet::from([self.root.uuid]);
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+
+        let check_icon = |node: Uuid, icon: Option<Uuid>, issues: &mut Vec<IntegrityIssue>| {
+            if let Some(icon) = icon.filter(|icon| !icons.contains(icon)) {
+                issues.push(IntegrityIssue::MissingCustomIcon { node, icon });
+            }
+            if deleted.contains(&node) {
+                issues.push(IntegrityIssue::DeletedNodeAlive(node));
+            }
+        };
+
+        check_icon(self.root.uuid, self.root.custom_icon_uuid, &mut issues);
+        for node in self.root.iter() {
+            let uuid = match node {
+                NodeRef::Group(g) => g.uuid,
+                NodeRef::Entry(e) => e.uuid,
+            };
+            if !seen.insert(uuid) && duplicates.insert(uuid) {
+                issues.push(IntegrityIssue::DuplicateUuid(uuid));
+            }
+
+            match node {
+                NodeRef::Group(g) => {
+                    groups.insert(g.uuid);
+                    check_icon(g.uuid, g.custom_icon_uuid, &mut issues);
+                }
+                NodeRef::Entry(e) => {
+                    check_icon(e.uuid, e.custom_icon_uuid, &mut issues);
+                    self.check_attachments(e, e.uuid, &mut issues);
+
+                    for item in e.history.iter().flat_map(|h| h.get_entries()) {
+                        if item.uuid != e.uuid {
+                            issues.push(IntegrityIssue::HistoryUuidMismatch {
+                                entry: e.uuid,
+                                history_uuid: item.uuid,
+                            });
+                        }
+                        self.check_attachments(item, e.uuid, &mut issues);
+                    }
+                }
+            }
+        }
+
+        // KeePass stores the nil UUID if there is no recycle bin
+        if let Some(uuid) = self.meta.recyclebin_uuid.filter(|uuid| !uuid.is_nil()) {
+            if !groups.contains(&uuid) {
+                issues.push(IntegrityIssue::MissingRecycleBin(uuid));
+            }
+        }
+
+        IntegrityReport { issues }
+    }
+
+    /// Give new UUIDs to nodes whose UUID is already used by another node, e.g. in malformed
+    /// exports of other password managers, so that all nodes can be found by their UUIDs again.
+    ///
+    /// The first node with a UUID, in the order of [`Group::iter`](crate::db::Group::iter),
+    /// keeps it. The history items of re-keyed entries are re-keyed along with them. Returns the
+    /// old and new UUIDs of the re-keyed nodes.
+    ///
+    /// Other copies of the database will see the re-keyed nodes as new nodes when merging.
+    pub fn repair_duplicate_uuids(&mut self) -> Vec<(Uuid, Uuid)> {
+        let mut seen = HashSet::from([self.root.uuid]);
+        let mut rekeyed = Vec::new();
+        let mut rekey = |uuid: &mut Uuid| {
+            if !seen.insert(*uuid) {
+                let new_uuid = Uuid::new_v4();
+                rekeyed.push((*uuid, new_uuid));
+                *uuid = new_uuid;
+                return true;
+            }
+            false
+        };
+
+        // nodes are visited in the same order as by Group::iter
+        let mut groups = VecDeque::from([&mut self.root]);
+        while let Some(group) = groups.pop_front() {
+            for node in group.children.iter_mut() {
+                match node {
+                    Node::Entry(e) => {
+                        if rekey(&mut e.uuid) {
+                            for item in e.history.iter_mut().flat_map(|h| h.entries.iter_mut()) {
+                                item.uuid = e.uuid;
+                            }
+                        }
+                    }
+                    Node::Group(g) => {
+                        rekey(&mut g.uuid);
+                        groups.push_back(g);
+                    }
+                }
+            }
+        }
+
+        rekeyed
+    }
+
+    fn check_attachments(&self, entry: &Entry, uuid: Uuid, issues: &mut Vec<IntegrityIssue>) {
+        for attachment in &entry.attachments {
+            if self.binary_content(&attachment.identifier).is_none() {
+                issues.push(IntegrityIssue::MissingAttachment {
+                    entry: uuid,
+                    name: attachment.name.clone(),
+                    identifier: attachment.identifier.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod integrity_tests {
+    use uuid::Uuid;
+
+    use super::IntegrityIssue;
+    use crate::db::{AttachmentRef, Database, DeletedObject, Entry, Group, HeaderAttachment, History};
+
+    #[test]
+    fn verify_integrity() {
+        let mut db = Database::new(Default::default());
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: b"content".to_vec(),
+        });
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: b"old content".to_vec(),
+        });
+
+        let mut entry = Entry::new();
+        entry.attachments.push(AttachmentRef {
+            name: "valid.txt".to_string(),
+            identifier: "0".to_string(),
+        });
+        let entry_uuid = entry.uuid;
+
+        let mut old = entry.clone();
+        old.attachments.push(AttachmentRef {
+            name: "old.txt".to_string(),
+            identifier: "1".to_string(),
+        });
+        let mut history = History::default();
+        history.add_entry(old);
+        entry.history = Some(history);
+
+        let recycle_bin = Group::new("Recycle Bin");
+        db.meta.recyclebin_uuid = Some(recycle_bin.uuid);
+        db.root.add_child(recycle_bin);
+        db.root.add_child(entry);
+        assert!(db.verify_integrity().is_ok());
+
+        // KeePass writes the nil UUID when there is no recycle bin
+        db.meta.recyclebin_uuid = Some(Uuid::nil());
+        assert!(db.verify_integrity().is_ok());
+
+        let icon = Uuid::new_v4();
+        let mut group = Group::new("Broken");
+        group.custom_icon_uuid = Some(icon);
+        let group_uuid = group.uuid;
+        db.root.add_child(group);
+
+        let mut stray = Entry::new();
+        stray.history = Some(History::default());
+        stray.history.as_mut().unwrap().add_entry(Entry::new());
+        let stray_uuid = stray.uuid;
+        let history_uuid = stray.history.as_ref().unwrap().get_entries()[0].uuid;
+        db.root.add_child(stray);
+
+        let missing_bin = Uuid::new_v4();
+        db.meta.recyclebin_uuid = Some(missing_bin);
+        db.deleted_objects.objects.push(DeletedObject {
+            uuid: group_uuid,
+            deletion_time: Default::default(),
+        });
+        db.header_attachments.clear();
+
+        let issues = db.verify_integrity().issues;
+        // the valid attachment is reported for the entry and its history item
+        assert_eq!(issues.len(), 7);
+        for issue in [
+            IntegrityIssue::MissingCustomIcon {
+                node: group_uuid,
+                icon,
+            },
+            IntegrityIssue::DeletedNodeAlive(group_uuid),
+            IntegrityIssue::HistoryUuidMismatch {
+                entry: stray_uuid,
+                history_uuid,
+            },
+            IntegrityIssue::MissingRecycleBin(missing_bin),
+            IntegrityIssue::MissingAttachment {
+                entry: entry_uuid,
+                name: "valid.txt".to_string(),
+                identifier: "0".to_string(),
+            },
+            IntegrityIssue::MissingAttachment {
+                entry: entry_uuid,
+                name: "old.txt".to_string(),
+                identifier: "1".to_string(),
+            },
+        ] {
+            assert!(issues.contains(&issue), "{:?} not in {:?}", issue, issues);
+        }
+    }
+
+    #[test]
+    fn repair_duplicate_uuids() {
+        let mut db = Database::new(Default::default());
+        let mut entry = Entry::new();
+        entry.set_title("first");
+        let uuid = entry.uuid;
+
+        let mut duplicate = entry.clone();
+        duplicate.set_title("second");
+        let mut history = History::default();
+        history.add_entry(duplicate.clone());
+        duplicate.history = Some(history);
+
+        let mut group = Group::new("Group");
+        group.add_child(duplicate);
+        db.root.add_child(group);
+        db.root.add_child(entry);
+
+        assert_eq!(
+            db.verify_integrity().issues,
+            vec![IntegrityIssue::DuplicateUuid(uuid)]
+        );
+
+        let rekeyed = db.repair_duplicate_uuids();
+        assert_eq!(rekeyed.len(), 1);
+        assert_eq!(rekeyed[0].0, uuid);
+        assert!(db.verify_integrity().is_ok());
+
+        // the first entry in iteration order keeps its UUID, and both entries are preserved
+        assert_eq!(db.entry_by_id(uuid.into()).unwrap().get_title(), Some("first"));
+        let second = db.entry_by_id(rekeyed[0].1.into()).unwrap();
+        assert_eq!(second.get_title(), Some("second"));
+        assert!(db.repair_duplicate_uuids().is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    borrow::Borrow,
+    collections::HashSet,
+    sync::{Arc, OnceLock},
+};
+
+use crate::db::{Database, Entry, Node};
+
+/// Names of the standard fields, which are shared by all entries of all databases
+const STANDARD_FIELD_NAMES: [&str; 5] = ["Title", "UserName", "Password", "URL", "Notes"];
+
+fn standard_field_names() -> &'static [Arc<str>; 5] {
+    static NAMES: OnceLock<[Arc<str>; 5]> = OnceLock::new();
+    NAMES.get_or_init(|| STANDARD_FIELD_NAMES.map(Arc::from))
+}
+
+/// An immutable string that can be shared between entries, used for the names of fields and
+/// for tags.
+///
+/// Cloning an `InternedStr` only increments a reference count. The names of the standard fields
+/// are always shared, and [`Database::intern_strings`] makes all entries of a database share
+/// equal names and tags, which saves a lot of memory in databases with many entries.
+///
+/// An `InternedStr` dereferences to `str` and compares equal to strings with the same contents,
+/// and maps keyed by it can be queried with a `&str`, e.g. `entry.fields.get("Title")`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InternedStr(Arc<str>);
+
+impl InternedStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this string shares its memory with `other`
+    pub fn ptr_eq(&self, other: &InternedStr) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl From<&str> for InternedStr {
+    fn from(s: &str) -> Self {
+        match standard_field_names().iter().find(|name| &***name == s) {
+            Some(name) => InternedStr(name.clone()),
+            None => InternedStr(Arc::from(s)),
+        }
+    }
+}
+
+impl From<String> for InternedStr {
+    fn from(s: String) -> Self {
+        InternedStr::from(s.as_str())
+    }
+}
+
+impl From<&String> for InternedStr {
+    fn from(s: &String) -> Self {
+        InternedStr::from(s.as_str())
+    }
+}
+
+impl From<InternedStr> for String {
+    fn from(s: InternedStr) -> Self {
+        s.0.to_string()
+    }
+}
+
+impl std::ops::Deref for InternedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for InternedStr {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for InternedStr {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for InternedStr {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for InternedStr {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for InternedStr {
+    fn eq(&self, other: &String) -> bool {
+        &*self.0 == other.as_str()
+    }
+}
+
+impl std::fmt::Debug for InternedStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::fmt::Display for InternedStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl serde::Serialize for InternedStr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+/// Pool of the strings of a database, handing out a shared copy of each distinct string
+#[derive(Default)]
+struct StringPool(HashSet<Arc<str>>);
+
+impl StringPool {
+    fn intern(&mut self, s: &InternedStr) -> InternedStr {
+        match self.0.get(s.as_str()) {
+            Some(shared) => InternedStr(shared.clone()),
+            None => {
+                self.0.insert(s.0.clone());
+                s.clone()
+            }
+        }
+    }
+
+    fn intern_entry(&mut self, entry: &mut Entry) {
+        entry.fields = entry
+            .fields
+            .drain()
+            .map(|(name, value)| (self.intern(&name), value))
+            .collect();
+        for tag in entry.tags.iter_mut() {
+            *tag = self.intern(tag);
+        }
+
+        if let Some(history) = entry.history.as_mut() {
+            for item in history.entries.iter_mut() {
+                self.intern_entry(item);
+            }
+        }
+    }
+}
+
+impl Database {
+    /// Make all entries of the database, including their history, share the memory of equal
+    /// field names and tags.
+    ///
+    /// This is done when a database is opened, and can be called again after adding many
+    /// entries, e.g. when importing from another password manager.
+    pub fn intern_strings(&mut self) {
+        let mut pool = StringPool::default();
+        for name in standard_field_names() {
+            pool.0.insert(name.clone());
+        }
+
+        let mut groups = vec![&mut self.root];
+        while let Some(group) = groups.pop() {
+            for node in group.children.iter_mut() {
+                match node {
+                    Node::Group(g) => groups.push(g),
+                    Node::Entry(e) => pool.intern_entry(e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod intern_tests {
+    use std::collections::HashMap;
+
+    use super::InternedStr;
+    use crate::db::{Database, Entry, Value};
+
+    #[test]
+    fn compare_and_lookup() {
+        let tag = InternedStr::from("work");
+        assert_eq!(tag, "work");
+        assert_eq!(tag, "work".to_string());
+        assert_eq!(tag.len(), 4);
+        assert_eq!(format!("{} {:?}", tag, tag), "work \"work\"");
+
+        let mut fields = HashMap::new();
+        fields.insert(
+            InternedStr::from("Title"),
+            Value::Unprotected("Example".to_string()),
+        );
+        assert!(fields.contains_key("Title"));
+
+        // the names of standard fields are always shared
+        assert!(InternedStr::from("Title").ptr_eq(&InternedStr::from("Title".to_string())));
+        assert!(!tag.ptr_eq(&InternedStr::from("work")));
+    }
+
+    #[test]
+    fn intern_strings() {
+        let mut db = Database::new(Default::default());
+        for _ in 0..2 {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Custom".into(), Value::Unprotected("value".to_string()));
+            entry.tags.push("work".into());
+            db.root.add_child(entry);
+        }
+
+        db.intern_strings();
+
+        let entries = db.root.entries();
+        let names: Vec<&InternedStr> = entries.iter().flat_map(|e| e.fields.keys()).collect();
+        assert!(names[0].ptr_eq(names[1]));
+        assert!(entries[0].tags[0].ptr_eq(&entries[1].tags[0]));
+    }
+}
@@ -0,0 +1,85 @@
+use chrono::{Duration, NaiveDateTime};
+
+use crate::db::Database;
+
+/// Whether the database policy asks for the master key to be changed, as returned by
+/// [`Database::key_change_status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum KeyChangeStatus {
+    /// The master key does not need to be changed
+    None,
+
+    /// The master key is older than `MasterKeyChangeRec`, and the user should be asked to change
+    /// it
+    Recommended,
+
+    /// The master key is older than `MasterKeyChangeForce`, and the user must change it before
+    /// continuing
+    Required,
+}
+
+/// Whether a key of age `age` is older than a policy of `days` days. Negative values disable the
+/// policy, as in KeePass.
+fn is_due(days: Option<isize>, age: Option<Duration>) -> bool {
+    match (days, age) {
+        (Some(days), _) if days < 0 => false,
+        (None, _) => false,
+        (Some(days), Some(age)) => age >= Duration::days(days as i64),
+        (Some(_), None) => true,
+    }
+}
+
+impl Database {
+    /// Check whether the master key should be changed at the time `now`, according to the
+    /// `MasterKeyChangeRec` and `MasterKeyChangeForce` settings of the database, see
+    /// [`Meta::master_key_change_rec`](crate::db::Meta::master_key_change_rec).
+    ///
+    /// The age of the key is measured from
+    /// [`Meta::master_key_changed`](crate::db::Meta::master_key_changed). If that time is
+    /// missing, the key is considered to be due for a change whenever a policy is set.
+    pub fn key_change_status(&self, now: NaiveDateTime) -> KeyChangeStatus {
+        let age = self.meta.master_key_changed.map(|changed| now - changed);
+
+        if is_due(self.meta.master_key_change_force, age) {
+            KeyChangeStatus::Required
+        } else if is_due(self.meta.master_key_change_rec, age) {
+            KeyChangeStatus::Recommended
+        } else {
+            KeyChangeStatus::None
+        }
+    }
+}
+
+#[cfg(test)]
+mod key_change_tests {
+    use chrono::Duration;
+
+    use super::KeyChangeStatus;
+    use crate::db::{Database, Times};
+
+    #[test]
+    fn key_change_status() {
+        let mut db = Database::new(Default::default());
+        let now = Times::now();
+        db.meta.master_key_changed = Some(now - Duration::days(100));
+        assert_eq!(db.key_change_status(now), KeyChangeStatus::None);
+
+        db.meta.master_key_change_rec = Some(90);
+        db.meta.master_key_change_force = Some(-1);
+        assert_eq!(db.key_change_status(now), KeyChangeStatus::Recommended);
+        assert_eq!(
+            db.key_change_status(now - Duration::days(20)),
+            KeyChangeStatus::None
+        );
+
+        db.meta.master_key_change_force = Some(100);
+        assert_eq!(db.key_change_status(now), KeyChangeStatus::Required);
+
+        db.meta.master_key_changed = None;
+        assert_eq!(db.key_change_status(now), KeyChangeStatus::Required);
+        db.meta.master_key_change_rec = None;
+        db.meta.master_key_change_force = None;
+        assert_eq!(db.key_change_status(now), KeyChangeStatus::None);
+    }
+}
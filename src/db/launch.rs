@@ -0,0 +1,504 @@
+use crate::db::Entry;
+
+/// What should be opened when the URL of an entry is launched
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum LaunchTarget {
+    /// A URL that should be opened with the default handler of the system
+    Url(String),
+
+    /// A command line that should be executed, as given by a `cmd://` URL
+    Command(String),
+}
+
+const CMD_SCHEME: &str = "cmd://";
+const FILE_SCHEME: &str = "file://";
+
+/// Placeholder for the directory separator of the platform, so that `cmd://` URLs can be
+/// written once for all platforms
+pub const ENV_DIRSEP_PLACEHOLDER: &str = "{ENV_DIRSEP}";
+
+/// Conventions for file system paths, used to write `cmd://` and `file://` URLs for a platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathStyle {
+    /// Backslash separators, drive letters and UNC paths
+    Windows,
+
+    /// Slash separators
+    Unix,
+}
+
+impl PathStyle {
+    /// The style of the platform that the program runs on
+    pub fn native() -> PathStyle {
+        if cfg!(windows) {
+            PathStyle::Windows
+        } else {
+            PathStyle::Unix
+        }
+    }
+
+    pub fn separator(self) -> char {
+        match self {
+            PathStyle::Windows => '\\',
+            PathStyle::Unix => '/',
+        }
+    }
+
+    /// Replace [`ENV_DIRSEP_PLACEHOLDER`] with the separator of this style, ignoring the case of
+    /// the placeholder like KeePass does
+    pub fn expand_dirsep(self, value: &str) -> String {
+        replace_ignore_ascii_case(value, ENV_DIRSEP_PLACEHOLDER, &self.separator().to_string())
+    }
+
+    /// Convert a path to this style by expanding [`ENV_DIRSEP_PLACEHOLDER`] and replacing the
+    /// separators of the other style. Unix paths are not made absolute or relative on Windows and
+    /// vice versa, only the separators change.
+    pub fn convert_path(self, path: &str) -> String {
+        let path = self.expand_dirsep(path);
+        match self {
+            PathStyle::Windows => path.replace('/', "\\"),
+            PathStyle::Unix => path.replace('\\', "/"),
+        }
+    }
+
+    /// Convert a `file://` URL to a path in this style, e.g. `file:///C:/My%20Files/a.txt` to
+    /// `C:\My Files\a.txt` on Windows. On Windows, URLs with a host are converted to UNC paths.
+    ///
+    /// Returns `None` if `url` is not a `file://` URL.
+    pub fn file_url_to_path(self, url: &str) -> Option<String> {
+        let rest = strip_prefix_ignore_ascii_case(url.trim(), FILE_SCHEME)?;
+        let rest = percent_decode(rest);
+
+        let (host, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest.as_str(), ""),
+        };
+        let host = if host.eq_ignore_ascii_case("localhost") {
+            ""
+        } else {
+            host
+        };
+
+        let path = match self {
+            PathStyle::Windows => {
+                // file:///C:/dir -> C:/dir
+                let path = match path.strip_prefix('/') {
+                    Some(p) if host.is_empty() && is_drive_path(p) => p,
+                    _ => path,
+                };
+                if host.is_empty() {
+                    path.to_string()
+                } else {
+                    format!("//{}{}", host, path)
+                }
+            }
+            PathStyle::Unix if host.is_empty() => path.to_string(),
+            PathStyle::Unix => format!("//{}{}", host, path),
+        };
+
+        Some(self.convert_path(&path))
+    }
+
+    /// Convert a path in this style to a `file://` URL, percent-encoding characters that are not
+    /// allowed in URLs
+    pub fn path_to_file_url(self, path: &str) -> String {
+        let path = self.convert_path(path).replace('\\', "/");
+        let path = if let Some(unc) = path.strip_prefix("//") {
+            unc.to_string()
+        } else if path.starts_with('/') {
+            path
+        } else {
+            format!("/{}", path)
+        };
+
+        let mut url = String::from(FILE_SCHEME);
+        for c in path.chars() {
+            if c.is_ascii_alphanumeric() || "/:-._~!$&'()*+,;=@".contains(c) {
+                url.push(c);
+            } else {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    url.push_str(&format!("%{:02X}", b));
+                }
+            }
+        }
+        url
+    }
+}
+
+/// A command line of a `cmd://` URL, split into the program and its arguments.
+///
+/// Arguments are separated by whitespace and can be enclosed in double quotes to contain
+/// whitespace. Inside quotes, two double quotes stand for one. Backslashes have no special
+/// meaning, so that Windows paths can be written as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl CommandLine {
+    /// Split a command line, with or without the `cmd://` scheme. A quote that is not closed
+    /// extends to the end of the command line. Returns `None` if there is no program.
+    pub fn parse(command: &str) -> Option<CommandLine> {
+        let command = strip_prefix_ignore_ascii_case(command, CMD_SCHEME).unwrap_or(command);
+
+        let mut words: Vec<String> = Vec::new();
+        let mut chars = command.chars().peekable();
+        loop {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let mut word = String::new();
+            let mut quoted = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        word.push('"');
+                    }
+                    '"' => quoted = !quoted,
+                    c if c.is_whitespace() && !quoted => break,
+                    c => word.push(c),
+                }
+            }
+            words.push(word);
+        }
+
+        let mut words = words.into_iter();
+        let program = words.next()?;
+        Some(CommandLine {
+            program,
+            args: words.collect(),
+        })
+    }
+
+    /// Convert the program path to the given style and expand [`ENV_DIRSEP_PLACEHOLDER`] in the
+    /// arguments. Other separators in the arguments are left alone, since they may not be paths.
+    pub fn canonicalize(&self, style: PathStyle) -> CommandLine {
+        let program = match style.file_url_to_path(&self.program) {
+            Some(path) => path,
+            None => style.convert_path(&self.program),
+        };
+
+        CommandLine {
+            program,
+            args: self.args.iter().map(|arg| style.expand_dirsep(arg)).collect(),
+        }
+    }
+
+    /// Join the program and arguments into a command line that [`CommandLine::parse`] splits the
+    /// same way again, quoting where necessary
+    pub fn to_command_string(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|word| quote_word(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Format the command line as `cmd://` URL
+    pub fn to_cmd_url(&self) -> String {
+        format!("{}{}", CMD_SCHEME, self.to_command_string())
+    }
+}
+
+impl std::fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_command_string())
+    }
+}
+
+/// Normalize a `cmd://` or `file://` URL for the given platform, e.g. when importing entries
+/// from another platform. `cmd://` URLs are rewritten with [`CommandLine::canonicalize`], and
+/// `file://` URLs are re-encoded from their path. Other URLs are returned unchanged.
+pub fn canonicalize_launch_url(url: &str, style: PathStyle) -> String {
+    if strip_prefix_ignore_ascii_case(url, CMD_SCHEME).is_some() {
+        match CommandLine::parse(url) {
+            Some(command) => command.canonicalize(style).to_cmd_url(),
+            None => CMD_SCHEME.to_string(),
+        }
+    } else if let Some(path) = style.file_url_to_path(url) {
+        style.path_to_file_url(&path)
+    } else {
+        url.to_string()
+    }
+}
+
+fn quote_word(word: &str) -> String {
+    if !word.is_empty() && !word.chars().any(|c| c.is_whitespace() || c == '"') {
+        return word.to_string();
+    }
+    format!("\"{}\"", word.replace('"', "\"\""))
+}
+
+fn is_drive_path(path: &str) -> bool {
+    let mut chars = path.chars();
+    matches!(
+        (chars.next(), chars.next(), chars.next()),
+        (Some(d), Some(':'), None | Some('/') | Some('\\')) if d.is_ascii_alphabetic()
+    )
+}
+
+fn strip_prefix_ignore_ascii_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    match value.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => value.get(prefix.len()..),
+        _ => None,
+    }
+}
+
+fn replace_ignore_ascii_case(value: &str, pattern: &str, replacement: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.to_ascii_uppercase().find(&pattern.to_ascii_uppercase()) {
+        out.push_str(&rest[..i]);
+        out.push_str(replacement);
+        rest = &rest[i + pattern.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decode `%XX` escapes, leaving invalid escapes as they are
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Browser placeholders supported by KeePass, along with the executable they resolve to
+const BROWSER_PLACEHOLDERS: [(&str, &str); 5] = [
+    ("{INTERNETEXPLORER}", "iexplore"),
+    ("{FIREFOX}", "firefox"),
+    ("{OPERA}", "opera"),
+    ("{GOOGLECHROME}", "chrome"),
+    ("{SAFARI}", "safari"),
+];
+
+/// Replace the entry field placeholders (e.g. `{USERNAME}` or `{S:Custom Field}`) in `value`
+/// with the values of `entry`. Unknown placeholders are left untouched.
+pub(crate) fn expand_entry_placeholders(entry: &Entry, value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let placeholder = &rest[1..end];
+        match resolve_placeholder(entry, placeholder) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn resolve_placeholder(entry: &Entry, placeholder: &str) -> Option<String> {
+    if let Some(field) = placeholder.strip_prefix("S:") {
+        return entry.get(field).map(|v| v.to_string());
+    }
+
+    let url = entry.get_url().unwrap_or("");
+
+    let value = match placeholder.to_uppercase().as_str() {
+        "TITLE" => entry.get_title().unwrap_or(""),
+        "USERNAME" => entry.get_username().unwrap_or(""),
+        "PASSWORD" => entry.get_password().unwrap_or(""),
+        "NOTES" => entry.get("Notes").unwrap_or(""),
+        "URL" => url,
+        "URL:RMVSCM" => url.split_once("://").map(|(_, rest)| rest).unwrap_or(url),
+        "URL:SCM" => url.split_once("://").map(|(scheme, _)| scheme).unwrap_or(""),
+        "ENV_DIRSEP" => return Some(PathStyle::native().separator().to_string()),
+        _ => return None,
+    };
+
+    Some(value.to_string())
+}
+
+impl Entry {
+    /// Determine what should be opened when the URL of this entry is launched, taking into
+    /// account the override URL of the entry and expanding placeholders like KeePass does.
+    ///
+    /// Returns `None` if neither an override URL nor a URL is set.
+    pub fn resolved_launch_target(&self) -> Option<LaunchTarget> {
+        let template = match self.override_url.as_deref().filter(|u| !u.is_empty()) {
+            Some(override_url) => override_url,
+            None => self.get_url().filter(|u| !u.is_empty())?,
+        };
+
+        let mut resolved = template.to_string();
+        for (placeholder, executable) in BROWSER_PLACEHOLDERS.iter() {
+            resolved = resolved.replace(placeholder, executable);
+        }
+        let resolved = expand_entry_placeholders(self, &resolved);
+
+        let is_command = matches!(
+            resolved.get(..CMD_SCHEME.len()),
+            Some(scheme) if scheme.eq_ignore_ascii_case(CMD_SCHEME)
+        );
+
+        if is_command {
+            Some(LaunchTarget::Command(resolved[CMD_SCHEME.len()..].to_string()))
+        } else {
+            Some(LaunchTarget::Url(resolved))
+        }
+    }
+}
+
+#[cfg(test)]
+mod launch_tests {
+    use super::{canonicalize_launch_url, CommandLine, LaunchTarget, PathStyle};
+    use crate::db::{Entry, Value};
+
+    fn entry_with_url(url: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("URL".into(), Value::Unprotected(url.to_string()));
+        entry
+            .fields
+            .insert("UserName".into(), Value::Unprotected("jdoe".to_string()));
+        entry
+    }
+
+    #[test]
+    fn plain_url() {
+        let entry = entry_with_url("https://example.com");
+        assert_eq!(
+            entry.resolved_launch_target(),
+            Some(LaunchTarget::Url("https://example.com".to_string()))
+        );
+
+        assert_eq!(Entry::new().resolved_launch_target(), None);
+    }
+
+    #[test]
+    fn override_url() {
+        let mut entry = entry_with_url("https://example.com/login");
+        entry.override_url = Some("cmd://{FIREFOX} -private \"{URL}\"".to_string());
+        assert_eq!(
+            entry.resolved_launch_target(),
+            Some(LaunchTarget::Command(
+                "firefox -private \"https://example.com/login\"".to_string()
+            ))
+        );
+
+        entry.override_url = Some("ssh://{USERNAME}@{URL:RMVSCM}".to_string());
+        assert_eq!(
+            entry.resolved_launch_target(),
+            Some(LaunchTarget::Url("ssh://jdoe@example.com/login".to_string()))
+        );
+
+        entry.override_url = Some("{UNKNOWN}".to_string());
+        assert_eq!(
+            entry.resolved_launch_target(),
+            Some(LaunchTarget::Url("{UNKNOWN}".to_string()))
+        );
+    }
+
+    #[test]
+    fn command_lines() {
+        let command =
+            CommandLine::parse(r#"cmd://"C:\Program Files\PuTTY\putty.exe" -ssh  "say ""hi""" x"#).unwrap();
+        assert_eq!(command.program, r"C:\Program Files\PuTTY\putty.exe");
+        assert_eq!(command.args, vec!["-ssh", r#"say "hi""#, "x"]);
+        assert_eq!(CommandLine::parse(&command.to_cmd_url()), Some(command));
+
+        let command = CommandLine::parse(r#"app "" "unterminated arg"#).unwrap();
+        assert_eq!(command.args, vec!["", "unterminated arg"]);
+        assert_eq!(CommandLine::parse("cmd://  "), None);
+    }
+
+    #[test]
+    fn canonicalize_urls() {
+        let url = "cmd://{ENV_DIRSEP}usr/bin{env_dirsep}ssh -i ~{ENV_DIRSEP}.ssh/id a\\b";
+        assert_eq!(
+            canonicalize_launch_url(url, PathStyle::Unix),
+            "cmd:///usr/bin/ssh -i ~/.ssh/id a\\b"
+        );
+        assert_eq!(
+            canonicalize_launch_url(url, PathStyle::Windows),
+            r"cmd://\usr\bin\ssh -i ~\.ssh/id a\b"
+        );
+        assert_eq!(
+            canonicalize_launch_url(r#"cmd://"file:///C:/My%20Apps/a.exe" x"#, PathStyle::Windows),
+            r#"cmd://"C:\My Apps\a.exe" x"#
+        );
+
+        assert_eq!(
+            PathStyle::Windows
+                .file_url_to_path("file:///C:/My%20Files/a.txt")
+                .as_deref(),
+            Some(r"C:\My Files\a.txt")
+        );
+        assert_eq!(
+            PathStyle::Windows
+                .file_url_to_path("FILE://server/share/a.txt")
+                .as_deref(),
+            Some(r"\\server\share\a.txt")
+        );
+        assert_eq!(
+            PathStyle::Unix
+                .file_url_to_path("file://localhost/home/me/a%C3%A4.txt")
+                .as_deref(),
+            Some("/home/me/aä.txt")
+        );
+        assert_eq!(PathStyle::Unix.file_url_to_path("https://example.com"), None);
+
+        assert_eq!(
+            PathStyle::Windows.path_to_file_url(r"C:\My Files\a.txt"),
+            "file:///C:/My%20Files/a.txt"
+        );
+        assert_eq!(
+            PathStyle::Windows.path_to_file_url(r"\\server\share\a.txt"),
+            "file://server/share/a.txt"
+        );
+        assert_eq!(
+            PathStyle::Unix.path_to_file_url("/home/me/aä.txt"),
+            "file:///home/me/a%C3%A4.txt"
+        );
+        assert_eq!(
+            canonicalize_launch_url("file:///home/me/a b.txt", PathStyle::Unix),
+            "file:///home/me/a%20b.txt"
+        );
+        assert_eq!(
+            canonicalize_launch_url("https://example.com", PathStyle::Unix),
+            "https://example.com"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use secstr::SecStr;
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, EntryId, NodeRef, Times, Value};
+
+const REF_PREFIX: &str = "{REF:";
+
+/// A standard field of an entry that can be referenced by an [`EntryLink`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum LinkField {
+    Title,
+    UserName,
+    Password,
+    Url,
+    Notes,
+    Uuid,
+}
+
+impl LinkField {
+    /// Name of the field in [`Entry::fields`]
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            LinkField::Title => "Title",
+            LinkField::UserName => "UserName",
+            LinkField::Password => "Password",
+            LinkField::Url => "URL",
+            LinkField::Notes => "Notes",
+            LinkField::Uuid => "Uuid",
+        }
+    }
+
+    /// Letter identifying the field in a reference
+    fn code(&self) -> char {
+        match self {
+            LinkField::Title => 'T',
+            LinkField::UserName => 'U',
+            LinkField::Password => 'P',
+            LinkField::Url => 'A',
+            LinkField::Notes => 'N',
+            LinkField::Uuid => 'I',
+        }
+    }
+
+    fn from_code(code: char) -> Option<LinkField> {
+        match code.to_ascii_uppercase() {
+            'T' => Some(LinkField::Title),
+            'U' => Some(LinkField::UserName),
+            'P' => Some(LinkField::Password),
+            'A' => Some(LinkField::Url),
+            'N' => Some(LinkField::Notes),
+            'I' => Some(LinkField::Uuid),
+            _ => None,
+        }
+    }
+}
+
+/// A reference from a field value to a field of another entry, written as
+/// `{REF:<field>@I:<uuid>}` so that KeePass and other clients show the value of the referenced
+/// field instead.
+///
+/// Only references that identify the referenced entry by its UUID are links in this sense.
+/// References that search for an entry by another field, e.g. `{REF:P@T:Title}`, are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct EntryLink {
+    /// The referenced entry
+    pub target: EntryId,
+
+    /// The referenced field of the target entry
+    pub field: LinkField,
+}
+
+impl EntryLink {
+    pub fn new(target: EntryId, field: LinkField) -> Self {
+        EntryLink { target, field }
+    }
+
+    /// Find all links in a field value
+    pub fn find_all(text: &str) -> Vec<EntryLink> {
+        let mut links = Vec::new();
+
+        let mut rest = text;
+        while let Some(start) = find_ignore_case(rest, REF_PREFIX) {
+            rest = &rest[start + REF_PREFIX.len()..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+
+            if let Some(link) = EntryLink::parse_reference(&rest[..end]) {
+                links.push(link);
+            }
+            rest = &rest[end + 1..];
+        }
+
+        links
+    }
+
+    /// Parse the part of a reference between `{REF:` and `}`
+    fn parse_reference(reference: &str) -> Option<EntryLink> {
+        let (wanted, search) = reference.split_once('@')?;
+        let (search_in, uuid) = search.split_once(':')?;
+
+        let mut wanted = wanted.chars();
+        let field = LinkField::from_code(wanted.next()?)?;
+        if wanted.next().is_some() || !search_in.eq_ignore_ascii_case("I") {
+            return None;
+        }
+
+        let target = Uuid::parse_str(uuid).ok()?;
+        Some(EntryLink {
+            target: target.into(),
+            field,
+        })
+    }
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.char_indices().map(|(i, _)| i).find(|&i| {
+        haystack
+            .get(i..i + needle.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(needle))
+    })
+}
+
+impl std::fmt::Display for EntryLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}@I:{}}}",
+            REF_PREFIX,
+            self.field.code(),
+            self.target.as_uuid().simple().to_string().to_uppercase()
+        )
+    }
+}
+
+impl Entry {
+    /// Replace a standard field of this entry by a link to the same field of the entry `target`.
+    ///
+    /// The password stays protected.
+    pub fn link_field_to(&mut self, target: EntryId, field: LinkField) {
+        let link = EntryLink::new(target, field).to_string();
+        let name = field.field_name();
+
+        let value = match self.fields.get(name) {
+            Some(Value::Protected(_) | Value::Encrypted(_)) => Value::Protected(SecStr::new(link.into_bytes())),
+            _ if field == LinkField::Password => Value::Protected(SecStr::new(link.into_bytes())),
+            _ => Value::Unprotected(link),
+        };
+        self.fields.insert(name.into(), value);
+        self.times.set_last_modification(Times::now());
+    }
+
+    /// Get the links in the fields of this entry, along with the names of the fields
+    pub fn links(&self) -> Vec<(&str, EntryLink)> {
+        let mut links: Vec<(&str, EntryLink)> = self
+            .fields
+            .keys()
+            .filter_map(|name| Some((name.as_str(), self.get(name)?)))
+            .flat_map(|(name, value)| {
+                EntryLink::find_all(value)
+                    .into_iter()
+                    .map(move |link| (name, link))
+            })
+            .collect();
+
+        links.sort_by_key(|(name, _)| *name);
+        links
+    }
+}
+
+impl Database {
+    /// Get the entries that link to a field of the entry `target`, e.g. to warn before the
+    /// entry is deleted. History items are not taken into account.
+    pub fn references_to(&self, target: EntryId) -> Vec<EntryId> {
+        self.root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Entry(e) if e.links().iter().any(|(_, link)| link.target == target) => Some(e.id()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod link_tests {
+    use super::{EntryLink, LinkField};
+    use crate::db::{Database, Entry, Value};
+
+    #[test]
+    fn parse_and_format() {
+        let target = Entry::new().id();
+        let link = EntryLink::new(target, LinkField::UserName);
+        let text = link.to_string();
+        assert!(text.starts_with("{REF:U@I:"));
+        assert_eq!(text.len(), "{REF:U@I:}".len() + 32);
+
+        let value = format!("{} / {{ref:p@i:{}}} {{REF:P@T:Title}} {{REF:X@I:", text, target);
+        assert_eq!(
+            EntryLink::find_all(&value),
+            vec![link, EntryLink::new(target, LinkField::Password)]
+        );
+    }
+
+    #[test]
+    fn references_to() {
+        let mut db = Database::new(Default::default());
+        let mut target = Entry::new();
+        target
+            .fields
+            .insert("Password".into(), Value::Unprotected("secret".to_string()));
+        let target_id = target.id();
+
+        let mut linked = Entry::new();
+        linked.link_field_to(target_id, LinkField::Password);
+        linked.link_field_to(target_id, LinkField::UserName);
+        assert!(matches!(linked.fields["Password"], Value::Protected(_)));
+        assert_eq!(
+            linked.links(),
+            vec![
+                ("Password", EntryLink::new(target_id, LinkField::Password)),
+                ("UserName", EntryLink::new(target_id, LinkField::UserName)),
+            ]
+        );
+        let linked_id = linked.id();
+
+        db.root.add_child(target);
+        db.root.add_child(linked);
+        db.root.add_child(Entry::new());
+
+        assert_eq!(db.references_to(target_id), vec![linked_id]);
+        assert!(db.references_to(linked_id).is_empty());
+    }
+}
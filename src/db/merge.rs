@@ -1,4 +1,6 @@
-use crate::db::NodeLocation;
+use std::collections::HashMap;
+
+use crate::db::{join_path, Group, Node, NodeLocation};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -22,6 +24,25 @@ pub struct MergeEvent {
     pub node_uuid: Uuid,
 
     pub event_type: MergeEventType,
+
+    /// Title of the entry or name of the group, as it is after the merge, or as it was before a
+    /// deletion
+    pub title: Option<String>,
+
+    /// Path of the node in the merged database, or where it was before a deletion, see
+    /// [`Database::path_of`](crate::Database::path_of)
+    pub path: Option<String>,
+}
+
+impl MergeEvent {
+    pub(crate) fn new(event_type: MergeEventType, node_uuid: Uuid) -> Self {
+        MergeEvent {
+            node_uuid,
+            event_type,
+            title: None,
+            path: None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -37,20 +58,74 @@ pub enum MergeError {
     #[error("{0}")]
     GenericError(String),
 
-    #[error("Could not find group at {0:?}")]
-    FindGroupError(NodeLocation),
+    /// A group was expected at `location` but not found. `path` describes the location with the
+    /// names of the groups along it, as far as they exist.
+    #[error("Could not find group at {path}")]
+    FindGroupError { location: NodeLocation, path: String },
 
-    #[error("Could not find entry at {0:?}")]
-    FindEntryError(NodeLocation),
+    /// An entry was expected at `location` but not found
+    #[error("Could not find entry at {path}")]
+    FindEntryError { location: NodeLocation, path: String },
 
-    #[error("Entries with UUID {0} have the same modification time but have diverged.")]
-    EntryModificationTimeNotUpdated(String),
+    #[error("Entries with UUID {uuid} (\"{title}\") have the same modification time but have diverged.")]
+    EntryModificationTimeNotUpdated { uuid: String, title: String },
+
+    #[error("Groups with UUID {uuid} (\"{name}\") have the same modification time but have diverged.")]
+    GroupModificationTimeNotUpdated { uuid: String, name: String },
+
+    #[error("Found history entries with the same timestamp ({timestamp}) for entry {uuid} (\"{title}\").")]
+    DuplicateHistoryEntries {
+        timestamp: String,
+        uuid: String,
+        title: String,
+    },
+}
+
+/// Describe a location below `root` with the names of the groups along it, as path components.
+/// Groups that do not exist are described by their UUID.
+pub(crate) fn describe_location(root: &Group, location: &[Uuid]) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut current = Some(root);
+    for uuid in location {
+        let group = current.and_then(|g| {
+            g.children.iter().find_map(|node| match node {
+                Node::Group(child) if child.uuid == *uuid => Some(child),
+                _ => None,
+            })
+        });
+        match group {
+            Some(g) => components.push(g.name.clone()),
+            None => components.push(uuid.to_string()),
+        }
+        current = group;
+    }
+    components
+}
 
-    #[error("Groups with UUID {0} have the same modification time but have diverged.")]
-    GroupModificationTimeNotUpdated(String),
+/// Collect the titles and paths of all nodes below `root`
+pub(crate) fn describe_nodes(root: &Group) -> HashMap<Uuid, (String, String)> {
+    fn collect(group: &Group, path: &mut Vec<String>, out: &mut HashMap<Uuid, (String, String)>) {
+        for node in &group.children {
+            match node {
+                Node::Entry(e) => {
+                    let title = e.get_title().unwrap_or_default().to_string();
+                    path.push(title.clone());
+                    out.insert(e.uuid, (title, join_path(path)));
+                    path.pop();
+                }
+                Node::Group(g) => {
+                    path.push(g.name.clone());
+                    out.insert(g.uuid, (g.name.clone(), join_path(path)));
+                    collect(g, path, out);
+                    path.pop();
+                }
+            }
+        }
+    }
 
-    #[error("Found history entries with the same timestamp ({0}) for entry {1}.")]
-    DuplicateHistoryEntries(String, String),
+    let mut out = HashMap::new();
+    collect(root, &mut Vec::new(), &mut out);
+    out
 }
 
 impl MergeLog {
@@ -400,6 +475,102 @@ mod merge_tests {
         assert!(destination_db.deleted_objects.contains(deleted_entry_uuid));
     }
 
+    #[test]
+    fn test_deleted_objects_retention() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+
+        let old_tombstone = Uuid::new_v4();
+        destination_db
+            .deleted_objects
+            .objects
+            .push(crate::db::DeletedObject {
+                uuid: old_tombstone,
+                deletion_time: Times::now() - chrono::Duration::days(100),
+            });
+
+        let mut deleted_entry = Entry::new();
+        let deleted_entry_uuid = deleted_entry.uuid;
+        deleted_entry.set_field_and_commit("Title", "deleted_entry");
+        destination_db.root.add_child(deleted_entry);
+
+        thread::sleep(time::Duration::from_secs(1));
+        source_db.deleted_objects.objects.push(crate::db::DeletedObject {
+            uuid: deleted_entry_uuid,
+            deletion_time: Times::now(),
+        });
+
+        // records are kept by default
+        let mut kept_db = destination_db.clone();
+        kept_db.merge(&source_db).unwrap();
+        assert!(kept_db.deleted_objects.contains(old_tombstone));
+
+        destination_db.config.deleted_objects_retention = Some(chrono::Duration::days(30));
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert!(destination_db
+            .root
+            .find_node_location(deleted_entry_uuid)
+            .is_none());
+        assert!(destination_db.deleted_objects.contains(deleted_entry_uuid));
+        assert!(!destination_db.deleted_objects.contains(old_tombstone));
+    }
+
+    #[test]
+    fn test_merge_descriptions() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+
+        let mut deleted_entry = Entry::new();
+        let deleted_entry_uuid = deleted_entry.uuid;
+        deleted_entry.set_field_and_commit("Title", "deleted_entry");
+        get_group_mut(&mut destination_db, &["group2"]).add_child(deleted_entry);
+
+        let mut new_entry = Entry::new();
+        new_entry.set_field_and_commit("Title", "new_entry");
+        get_group_mut(&mut source_db, &["group1", "subgroup1"]).add_child(new_entry);
+
+        thread::sleep(time::Duration::from_secs(1));
+        source_db.deleted_objects.objects.push(crate::db::DeletedObject {
+            uuid: deleted_entry_uuid,
+            deletion_time: Times::now(),
+        });
+
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        let descriptions: Vec<(Option<&str>, Option<&str>)> = merge_result
+            .events
+            .iter()
+            .map(|e| (e.title.as_deref(), e.path.as_deref()))
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                (Some("new_entry"), Some("/group1/subgroup1/new_entry")),
+                (Some("deleted_entry"), Some("/group2/deleted_entry")),
+            ]
+        );
+
+        // errors describe the nodes with their names
+        let missing = Uuid::new_v4();
+        let err = destination_db
+            .relocate_node(
+                &Uuid::parse_str(ENTRY2_ID).unwrap(),
+                &vec![Uuid::parse_str(GROUP1_ID).unwrap(), missing],
+                &vec![],
+                Times::now(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Could not find group at /group1/{}", missing)
+        );
+
+        let mut diverged_db = destination_db.clone();
+        get_group_mut(&mut diverged_db, &["group2"]).notes = Some("diverged".to_string());
+        let err = destination_db.merge(&diverged_db).unwrap_err();
+        assert!(err.to_string().contains("(\"group2\")"));
+    }
+
     #[test]
     fn test_deleted_group_in_source() {
         let mut destination_db = create_test_database();
@@ -1098,6 +1269,41 @@ mod merge_tests {
         assert_eq!(merge_result.events.len(), 0);
     }
 
+    #[test]
+    fn test_update_different_fields() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+
+        let entry = &mut destination_db.root.entries_mut()[0];
+        entry.set_field_and_commit("Title", "entry1_updated_from_destination");
+
+        let entry = &mut source_db.root.entries_mut()[0];
+        entry.set_field_and_commit("UserName", "user_updated_from_source");
+
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.warnings.len(), 0);
+        assert_eq!(merge_result.events.len(), 1);
+
+        let entry = destination_db.root.entries()[0];
+        assert_eq!(entry.get_title(), Some("entry1_updated_from_destination"));
+        assert_eq!(entry.get_username(), Some("user_updated_from_source"));
+
+        let merged_history = entry.history.clone().unwrap();
+        assert!(merged_history.is_ordered());
+        assert_eq!(merged_history.entries[0].get_title(), entry.get_title());
+        assert_eq!(merged_history.entries[0].get_username(), entry.get_username());
+
+        // The merged version is newer than both, so it is also taken by the source.
+        let merge_result = source_db.merge(&destination_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert_eq!(source_db.root.entries()[0], destination_db.root.entries()[0]);
+
+        // Merging again should not result in any additional change.
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.warnings.len(), 0);
+        assert_eq!(merge_result.events.len(), 0);
+    }
+
     #[test]
     fn test_group_update_in_source() {
         let mut destination_db = create_test_database();
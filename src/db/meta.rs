@@ -1,7 +1,7 @@
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
-use crate::db::{Color, CustomData};
+use crate::db::{Color, CustomData, XmlElement};
 
 /// Database metadata
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
@@ -37,8 +37,10 @@ pub struct Meta {
     /// time the master key was last changed
     pub master_key_changed: Option<NaiveDateTime>,
 
+    /// number of days after which changing the master key is recommended, or -1 if disabled
     pub master_key_change_rec: Option<isize>,
 
+    /// number of days after which changing the master key is required, or -1 if disabled
     pub master_key_change_force: Option<isize>,
 
     /// memory protection settings
@@ -82,6 +84,9 @@ pub struct Meta {
 
     /// Additional custom data fields
     pub custom_data: CustomData,
+
+    /// XML elements of the metadata that are not known to this library
+    pub unknown_elements: Vec<XmlElement>,
 }
 
 /// Database memory protection settings
@@ -142,10 +147,27 @@ pub struct BinaryAttachments {
 }
 
 /// Binary attachment in the metadata of a XML database
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub struct BinaryAttachment {
     pub identifier: Option<String>,
     pub compressed: bool,
+
+    /// Whether the content is protected. In the XML document of a database file, protected
+    /// content is encrypted with the inner stream cipher. Standalone XML documents mark it with
+    /// `ProtectInMemory` and store it unencrypted.
+    pub protected: bool,
+
     pub content: Vec<u8>,
 }
+
+impl std::fmt::Debug for BinaryAttachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BinaryAttachment")
+            .field("identifier", &self.identifier)
+            .field("compressed", &self.compressed)
+            .field("protected", &self.protected)
+            .field("content", crate::redact::secret(&self.content))
+            .finish()
+    }
+}
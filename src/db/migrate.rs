@@ -0,0 +1,166 @@
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, Times};
+
+/// What to do when renaming a field of an entry that already has a field with the new name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave both fields of the entry unchanged
+    Skip,
+
+    /// Replace the existing field with the renamed one
+    Overwrite,
+
+    /// Keep the existing field and remove the one that would have been renamed
+    KeepExisting,
+}
+
+/// Summary of the changes made by [`Database::migrate_fields`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Number of renamed fields, including those in history entries
+    pub renamed: usize,
+
+    /// Entries that already had a field with the new name, with the name of the field that was
+    /// to be renamed. History entries are not listed.
+    pub conflicts: Vec<(Uuid, String)>,
+}
+
+/// Rename the fields of a single entry version. Returns whether anything changed.
+fn migrate_entry(
+    entry: &mut Entry,
+    mapping: &[(&str, &str)],
+    conflict: ConflictPolicy,
+    report: &mut MigrationReport,
+    is_history: bool,
+) -> bool {
+    let mut changed = false;
+
+    for (from, to) in mapping {
+        if from == to || !entry.fields.contains_key(*from) {
+            continue;
+        }
+
+        if entry.fields.contains_key(*to) {
+            if !is_history {
+                report.conflicts.push((entry.uuid, from.to_string()));
+            }
+
+            match conflict {
+                ConflictPolicy::Skip => continue,
+                ConflictPolicy::Overwrite => {}
+                ConflictPolicy::KeepExisting => {
+                    entry.fields.remove(*from);
+                    entry.clear_field_metadata(from);
+                    changed = true;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(value) = entry.fields.remove(*from) {
+            entry.fields.insert((*to).into(), value);
+            report.renamed += 1;
+            changed = true;
+        }
+
+        let metadata = entry.field_metadata(from);
+        if !metadata.is_empty() {
+            entry.clear_field_metadata(from);
+            entry.clear_field_metadata(to);
+            for (attribute, value) in metadata {
+                entry.set_field_metadata(to, &attribute, &value);
+            }
+        }
+    }
+
+    changed
+}
+
+impl Database {
+    /// Rename fields across all entries, e.g. `&[("TOTP Seed", "otp")]`, including their
+    /// history entries and field metadata.
+    ///
+    /// The mappings are applied in order. `conflict` decides what happens if an entry already
+    /// has a field with the new name. The last modification time of every changed entry is
+    /// updated, but no history entries are added, since the history is migrated as well.
+    pub fn migrate_fields(&mut self, mapping: &[(&str, &str)], conflict: ConflictPolicy) -> MigrationReport {
+        let mut report = MigrationReport::default();
+
+        self.root.for_each_entry_mut(&mut |entry| {
+            if let Some(history) = entry.history.as_mut() {
+                for old in history.entries.iter_mut() {
+                    migrate_entry(old, mapping, conflict, &mut report, true);
+                }
+            }
+
+            if migrate_entry(entry, mapping, conflict, &mut report, false) {
+                entry.times.set_last_modification(Times::now());
+            }
+        });
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod migrate_tests {
+    use super::ConflictPolicy;
+    use crate::db::{Database, Entry, History, Value};
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry::new();
+        for (key, value) in fields {
+            entry
+                .fields
+                .insert((*key).into(), Value::Unprotected(value.to_string()));
+        }
+        entry
+    }
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+
+        let mut with_history = entry(&[("TOTP Seed", "new")]);
+        with_history.set_field_metadata("TOTP Seed", "Label", "Seed");
+        let mut history = History::default();
+        history.add_entry(entry(&[("TOTP Seed", "old")]));
+        with_history.history = Some(history);
+        db.root.add_child(with_history);
+
+        db.root.add_child(entry(&[("TOTP Seed", "a"), ("otp", "b")]));
+        db
+    }
+
+    #[test]
+    fn migrate_fields() {
+        let mut db = database();
+        let report = db.migrate_fields(&[("TOTP Seed", "otp")], ConflictPolicy::Skip);
+        assert_eq!(report.renamed, 2);
+        assert_eq!(report.conflicts.len(), 1);
+
+        let entries = db.root.entries();
+        assert_eq!(entries[0].get("otp"), Some("new"));
+        assert_eq!(entries[0].get("TOTP Seed"), None);
+        assert_eq!(entries[0].get_field_metadata("otp", "Label"), Some("Seed"));
+        assert!(entries[0].field_metadata("TOTP Seed").is_empty());
+        let history = entries[0].history.as_ref().unwrap().get_entries();
+        assert_eq!(history[0].get("otp"), Some("old"));
+
+        assert_eq!(entries[1].get("TOTP Seed"), Some("a"));
+        assert_eq!(entries[1].get("otp"), Some("b"));
+    }
+
+    #[test]
+    fn conflict_policies() {
+        let mut db = database();
+        db.migrate_fields(&[("TOTP Seed", "otp")], ConflictPolicy::Overwrite);
+        assert_eq!(db.root.entries()[1].get("otp"), Some("a"));
+        assert_eq!(db.root.entries()[1].get("TOTP Seed"), None);
+
+        let mut db = database();
+        db.migrate_fields(&[("TOTP Seed", "otp")], ConflictPolicy::KeepExisting);
+        assert_eq!(db.root.entries()[1].get("otp"), Some("b"));
+        assert_eq!(db.root.entries()[1].get("TOTP Seed"), None);
+    }
+}
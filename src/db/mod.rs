@@ -1,9 +1,50 @@
 //! Types for representing data contained in a KeePass database
 
+pub(crate) mod android;
+pub(crate) mod as_of;
+pub(crate) mod attachment;
+pub(crate) mod audit;
+pub(crate) mod auto_open;
+pub(crate) mod autotype;
+pub(crate) mod color;
+pub(crate) mod compact;
+pub(crate) mod counts;
+pub(crate) mod duplicates;
+pub(crate) mod emergency;
 pub(crate) mod entry;
+pub(crate) mod field_metadata;
 pub(crate) mod group;
+pub(crate) mod icon;
+pub(crate) mod id;
+#[cfg(feature = "save_kdbx4")]
+pub(crate) mod incremental;
+pub(crate) mod index;
+pub(crate) mod integrity;
+pub(crate) mod intern;
+pub(crate) mod key_change;
+pub(crate) mod launch;
+pub(crate) mod link;
 pub(crate) mod meta;
+pub(crate) mod migrate;
 pub(crate) mod node;
+pub(crate) mod normalize;
+pub(crate) mod notes;
+pub(crate) mod parse_report;
+pub(crate) mod path;
+pub(crate) mod policy;
+pub(crate) mod removal;
+pub(crate) mod save_hook;
+pub(crate) mod search;
+pub(crate) mod settings;
+pub(crate) mod subtree;
+pub(crate) mod template;
+pub(crate) mod timestamp;
+pub(crate) mod tracked;
+pub(crate) mod typed_field;
+pub(crate) mod undo;
+pub(crate) mod usage;
+pub(crate) mod version;
+pub(crate) mod xml_element;
 
 #[cfg(feature = "_merge")]
 pub(crate) mod merge;
@@ -11,31 +52,75 @@ pub(crate) mod merge;
 #[cfg(feature = "totp")]
 pub(crate) mod otp;
 
+use std::collections::HashMap;
 #[cfg(feature = "_merge")]
 use std::collections::VecDeque;
-use std::{collections::HashMap, str::FromStr};
 
+use crate::trace::phase;
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
 pub use crate::db::{
-    entry::{AutoType, AutoTypeAssociation, Entry, History, Value},
+    android::{ANDROID_APP_FIELD, ANDROID_APP_URL_SCHEME, KP2A_URL_FIELD},
+    attachment::{sniff_mime_type, AttachOptions, ATTACHMENT_MIME_TYPE_PREFIX, DEFAULT_MIME_TYPE},
+    audit::{ChangeSummary, EntryChange, GroupChange},
+    auto_open::{AutoOpenSpec, KeyFileSource, AUTO_OPEN_GROUP_NAME, IF_DEVICE_FIELD_NAME},
+    autotype::{window_matches, DEFAULT_AUTOTYPE_SEQUENCE},
+    color::Color,
+    compact::{CompactOptions, CompactReport},
+    counts::GroupCounts,
+    duplicates::{DuplicateKey, DuplicateMergeStrategy},
+    emergency::{
+        EmergencySheet, EmergencySheetOptions, EMERGENCY_SHEET_ARMOR_BEGIN, EMERGENCY_SHEET_ARMOR_END,
+    },
+    entry::{
+        AttachmentRef, AutoType, AutoTypeAssociation, EncryptedValue, Entry, History, HistoryPruneReport, Value,
+    },
+    field_metadata::FIELD_METADATA_PREFIX,
     group::Group,
+    icon::{IconSource, StandardIcon, DEFAULT_GROUP_ICON_ID},
+    id::{EntryId, GroupId},
+    index::SearchIndex,
+    integrity::{IntegrityIssue, IntegrityReport},
+    intern::InternedStr,
+    key_change::KeyChangeStatus,
+    launch::{canonicalize_launch_url, CommandLine, LaunchTarget, PathStyle, ENV_DIRSEP_PLACEHOLDER},
+    link::{EntryLink, LinkField},
     meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-    node::{Node, NodeIter, NodeRef, NodeRefMut},
+    migrate::{ConflictPolicy, MigrationReport},
+    node::{Descendants, Node, NodeIter, NodeRef, NodeRefMut},
+    normalize::TextMatching,
+    notes::{parse_key_values, NotesFormat},
+    parse_report::ParseReport,
+    path::{join_path, split_path, PATH_SEPARATOR},
+    policy::{CharacterClass, Policy, PolicyReport, PolicyViolation},
+    removal::EntryDependency,
+    save_hook::SaveHook,
+    search::{AutofillContext, AutofillMatch, RankedEntry},
+    settings::{DatabaseSettings, FDO_SECRETS_EXPOSED_GROUP},
+    subtree::{CollisionPolicy, CopyUuids},
+    template::TEMPLATE_FIELD_PREFIX,
+    timestamp::{Timestamp, TimestampFormat},
+    tracked::{ChangeEvent, ObserverId, TrackedDatabase},
+    typed_field::{FieldType, FIELD_TYPE_ATTRIBUTE},
+    version::Kdbx41Feature,
+    xml_element::{XmlElement, XmlNode},
 };
 
 #[cfg(feature = "_merge")]
-use crate::db::merge::{MergeError, MergeEvent, MergeEventType, MergeLog};
+use crate::db::merge::{describe_location, describe_nodes, MergeError, MergeEvent, MergeEventType, MergeLog};
+
+#[cfg(feature = "save_kdbx4")]
+pub use crate::db::incremental::{IncrementalSaveReport, IncrementalSaveState};
 
 #[cfg(feature = "totp")]
 pub use crate::db::otp::{TOTPAlgorithm, TOTP};
 
 #[cfg(feature = "_merge")]
-use crate::db::group::NodeLocation;
+use crate::db::group::{LocationIndex, NodeLocation};
 use crate::{
-    config::DatabaseConfig,
-    error::{DatabaseIntegrityError, DatabaseOpenError, ParseColorError},
+    config::{DatabaseConfig, KdfConfig, OpenOptions},
+    error::{DatabaseIntegrityError, DatabaseOpenError},
     format::{
         kdb::parse_kdb,
         kdbx3::{decrypt_kdbx3, parse_kdbx3},
@@ -63,44 +148,143 @@ pub struct Database {
 
     /// Metadata of the KeePass database
     pub meta: Meta,
+
+    /// Transformations that are applied when saving, see [`Database::add_save_hook`]
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) save_hooks: save_hook::SaveHooks,
 }
 
 impl Database {
     /// Parse a database from a std::io::Read
+    ///
+    /// The decompressed payload may be at most [`DEFAULT_MAX_DECOMPRESSED_SIZE`] bytes large, use
+    /// [`Database::open_with_options`] to open larger databases.
+    ///
+    /// [`DEFAULT_MAX_DECOMPRESSED_SIZE`]: crate::config::DEFAULT_MAX_DECOMPRESSED_SIZE
     pub fn open(source: &mut dyn std::io::Read, key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
+        Database::open_with_options(source, key, &OpenOptions::default())
+    }
+
+    /// Parse a database from a std::io::Read with custom options, e.g. a different limit for the
+    /// size of the decompressed payload
+    pub fn open_with_options(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        options: &OpenOptions,
+    ) -> Result<Database, DatabaseOpenError> {
         let mut data = Vec::new();
         source.read_to_end(&mut data)?;
 
-        Database::parse(data.as_ref(), key)
+        Database::parse_with_options(data.as_ref(), key, options)
     }
 
+    /// Parse a database from a memory-mapped file.
+    ///
+    /// The headers are parsed directly from the mapped file, so that only the decrypted payload
+    /// needs to be allocated.
+    ///
+    /// # Safety
+    ///
+    /// The file is parsed through a byte slice that is backed by the mapping, so it must not be
+    /// modified or truncated, by this or any other process, until this function returns. Use
+    /// [`Database::open`] if that cannot be guaranteed, e.g. for files on network shares or files
+    /// that another application may save at the same time.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap(path: &std::path::Path, key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
+        let file = std::fs::File::open(path)?;
+
+        // Safety: the caller guarantees that the file is not modified while it is mapped, and
+        // the mapping is dropped before returning
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+
+        Database::parse(&mmap, key)
+    }
+
+    /// Parse a database from a byte slice.
+    ///
+    /// With the `tracing` feature, the phases of opening the database (`header`, `kdf`, `decrypt`,
+    /// `decompress` and `xml_parse`) are recorded as spans inside an `open` span.
     pub fn parse(data: &[u8], key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
-        let database_version = DatabaseVersion::parse(data)?;
+        Database::parse_with_options(data, key, &OpenOptions::default())
+    }
 
-        match database_version {
-            DatabaseVersion::KDB(_) => parse_kdb(data, &key),
-            DatabaseVersion::KDB2(_) => Err(DatabaseOpenError::UnsupportedVersion.into()),
-            DatabaseVersion::KDB3(_) => parse_kdbx3(data, &key),
-            DatabaseVersion::KDB4(_) => parse_kdbx4(data, &key),
+    /// Parse a database from a byte slice with custom options, see [`Database::parse`].
+    ///
+    /// The payload is decompressed while the XML document is parsed, so that it is never held in
+    /// memory as a whole. Opening fails with [`DatabaseOpenError::PayloadTooLarge`] once more
+    /// than [`OpenOptions::max_decompressed_size`] bytes have been decompressed.
+    pub fn parse_with_options(
+        data: &[u8],
+        key: DatabaseKey,
+        options: &OpenOptions,
+    ) -> Result<Database, DatabaseOpenError> {
+        let mut db = phase!("open", {
+            let database_version = DatabaseVersion::parse(data)?;
+
+            match database_version {
+                DatabaseVersion::KDB(_) => parse_kdb(data, &key, options),
+                DatabaseVersion::KDB2(_) => Err(DatabaseOpenError::UnsupportedVersion.into()),
+                DatabaseVersion::KDB3(_) => parse_kdbx3(data, &key, options),
+                DatabaseVersion::KDB4(_) => parse_kdbx4(data, &key, options),
+            }
+        })?;
+
+        db.intern_strings();
+        if options.repair_duplicate_uuids {
+            db.repair_duplicate_uuids();
         }
+        Ok(db)
     }
 
     /// Save a database to a std::io::Write
+    ///
+    /// A new master seed, KDF seed, outer encryption IV and inner stream key are generated every
+    /// time the database is saved, so that no key material is shared between saved versions of
+    /// the same database.
+    ///
+    /// Returns the weaknesses of the key derivation settings that the database was saved with, so
+    /// that applications can suggest an upgrade with [`Database::upgrade_kdf`].
+    ///
+    /// With the `tracing` feature, the phases of saving the database (`kdf`, `xml_dump`,
+    /// `compress` and `encrypt`) are recorded as spans inside a `save` span.
     #[cfg(feature = "save_kdbx4")]
     pub fn save(
         &self,
         destination: &mut dyn std::io::Write,
         key: DatabaseKey,
-    ) -> Result<(), crate::error::DatabaseSaveError> {
+    ) -> Result<Vec<crate::config::KdfAdvisory>, crate::error::DatabaseSaveError> {
         use crate::error::DatabaseSaveError;
         use crate::format::kdbx4::dump_kdbx4;
 
         match self.config.version {
-            DatabaseVersion::KDB(_) => Err(DatabaseSaveError::UnsupportedVersion.into()),
-            DatabaseVersion::KDB2(_) => Err(DatabaseSaveError::UnsupportedVersion.into()),
-            DatabaseVersion::KDB3(_) => Err(DatabaseSaveError::UnsupportedVersion.into()),
-            DatabaseVersion::KDB4(_) => dump_kdbx4(self, &key, destination),
+            DatabaseVersion::KDB(_) => return Err(DatabaseSaveError::UnsupportedVersion.into()),
+            DatabaseVersion::KDB2(_) => return Err(DatabaseSaveError::UnsupportedVersion.into()),
+            DatabaseVersion::KDB3(_) => return Err(DatabaseSaveError::UnsupportedVersion.into()),
+            DatabaseVersion::KDB4(_) => phase!("save", dump_kdbx4(self, &key, destination))?,
         }
+
+        Ok(self.config.kdf_config.advisories())
+    }
+
+    /// Format the database for debugging, including protected values and attachment contents that
+    /// are redacted in the `Debug` output.
+    ///
+    /// **The result contains secrets in plaintext.**
+    #[cfg(feature = "debug_secrets")]
+    pub fn debug_full(&self) -> String {
+        crate::redact::reveal(|| format!("{:#?}", self))
+    }
+
+    /// Switch to different key derivation settings, e.g. [`KdfConfig::recommended`], which will
+    /// be used the next time the database is saved. Returns the previous settings.
+    ///
+    /// Since KDBX3 only supports AES-KDF, KDBX3 databases are upgraded to KDBX4.
+    pub fn upgrade_kdf(&mut self, kdf_config: KdfConfig) -> KdfConfig {
+        if let DatabaseVersion::KDB3(_) = self.config.version {
+            self.config.version = DatabaseVersion::KDB4(crate::format::KDBX4_CURRENT_MINOR_VERSION);
+        }
+
+        std::mem::replace(&mut self.config.kdf_config, kdf_config)
     }
 
     /// Helper function to load a database into its internal XML chunks
@@ -109,17 +293,99 @@ impl Database {
         source.read_to_end(&mut data)?;
 
         let database_version = DatabaseVersion::parse(data.as_ref())?;
+        let options = OpenOptions::default();
 
         let data = match database_version {
             DatabaseVersion::KDB(_) => return Err(DatabaseOpenError::UnsupportedVersion),
             DatabaseVersion::KDB2(_) => return Err(DatabaseOpenError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => decrypt_kdbx3(data.as_ref(), &key)?.2,
-            DatabaseVersion::KDB4(_) => decrypt_kdbx4(data.as_ref(), &key)?.3,
+            DatabaseVersion::KDB3(_) => decrypt_kdbx3(data.as_ref(), &key, &options)?.2,
+            DatabaseVersion::KDB4(_) => decrypt_kdbx4(data.as_ref(), &key, &options)?.3,
         };
 
         Ok(data)
     }
 
+    /// Export the database as a standalone, unencrypted XML document, similar to the XML export
+    /// of KeePass.
+    ///
+    /// Protected values (e.g. passwords) are only included if `reveal_protected` is set, in which
+    /// case they are written to the document **in plaintext** and marked with `ProtectInMemory`.
+    /// Otherwise, they are left empty. The binaries of the inner header of KDBX4 databases are
+    /// written to the metadata, so that the attachments of the entries can be restored on import.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn export_xml(
+        &self,
+        destination: &mut dyn std::io::Write,
+        reveal_protected: bool,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        use crate::crypt::ciphers::{Cipher, PlainCipher, RedactingCipher};
+
+        let mut inner_cipher: Box<dyn Cipher> = if reveal_protected {
+            Box::new(PlainCipher)
+        } else {
+            Box::new(RedactingCipher)
+        };
+
+        crate::xml_db::dump::dump_plain_xml(self, &mut *inner_cipher, destination)?;
+
+        Ok(())
+    }
+
+    /// Import a database from a standalone, unencrypted XML document as written by
+    /// [`Database::export_xml`].
+    ///
+    /// Since the XML document does not contain any encryption settings, the database will use the
+    /// default [`DatabaseConfig`]. The document is parsed while it is being read, without reading
+    /// all of it into memory first. Reads from `source` are buffered, so it does not need to be
+    /// wrapped in a [`std::io::BufReader`].
+    pub fn import_xml(source: &mut dyn std::io::Read) -> Result<Database, DatabaseOpenError> {
+        let mut inner_cipher = crate::crypt::ciphers::PlainCipher;
+        let database_content = crate::xml_db::parse::parse_reader(source, &mut inner_cipher)?;
+
+        let mut db = Database {
+            config: DatabaseConfig::default(),
+            header_attachments: Vec::new(),
+            root: database_content.root.group,
+            deleted_objects: database_content.root.deleted_objects,
+            meta: database_content.meta,
+            save_hooks: Default::default(),
+        };
+
+        // the default configuration is KDBX4, which keeps binaries in the inner header
+        let mut identifiers = HashMap::new();
+        for binary in std::mem::take(&mut db.meta.binaries.binaries) {
+            if let Some(identifier) = binary.identifier {
+                identifiers.insert(identifier, db.header_attachments.len().to_string());
+            }
+            db.header_attachments.push(HeaderAttachment {
+                flags: if binary.protected {
+                    HEADER_ATTACHMENT_PROTECTED
+                } else {
+                    0
+                },
+                content: binary.content,
+            });
+        }
+
+        if !identifiers.is_empty() {
+            db.root.for_each_entry_mut(&mut |entry| {
+                let history = entry.history.iter_mut().flat_map(|h| h.entries.iter_mut());
+                let attachments = entry
+                    .attachments
+                    .iter_mut()
+                    .chain(history.flat_map(|e| e.attachments.iter_mut()));
+
+                for attachment in attachments {
+                    if let Some(identifier) = identifiers.get(&attachment.identifier) {
+                        attachment.identifier = identifier.clone();
+                    }
+                }
+            });
+        }
+
+        Ok(db)
+    }
+
     /// Get the version of a database without decrypting it
     pub fn get_version(source: &mut dyn std::io::Read) -> Result<DatabaseVersion, DatabaseIntegrityError> {
         let mut data = Vec::new();
@@ -128,6 +394,29 @@ impl Database {
         DatabaseVersion::parse(data.as_ref())
     }
 
+    /// Prune the history of every entry in the database according to the limits in
+    /// [`Meta::history_max_items`] and [`Meta::history_max_size`], like KeePass does on save.
+    pub fn apply_history_limits(&mut self) -> HistoryPruneReport {
+        let max_items = self.meta.history_max_items;
+        let max_size = self.meta.history_max_size;
+        let binary_sizes = self.binary_sizes();
+
+        let mut report = HistoryPruneReport::default();
+
+        self.root.for_each_entry_mut(&mut |entry| {
+            if let Some(history) = entry.history.as_mut() {
+                let (items_removed, bytes_removed) = history.prune(max_items, max_size, &binary_sizes);
+                if items_removed > 0 {
+                    report.entries_affected += 1;
+                    report.items_removed += items_removed;
+                    report.bytes_removed += bytes_removed;
+                }
+            }
+        });
+
+        report
+    }
+
     /// Create a new, empty database
     pub fn new(config: DatabaseConfig) -> Database {
         Self {
@@ -136,6 +425,7 @@ impl Database {
             root: Group::new("Root"),
             deleted_objects: Default::default(),
             meta: Default::default(),
+            save_hooks: Default::default(),
         }
     }
 
@@ -144,14 +434,37 @@ impl Database {
     /// the same.
     #[cfg(feature = "_merge")]
     pub fn merge(&mut self, other: &Database) -> Result<MergeLog, MergeError> {
+        // index the locations of all nodes once, instead of searching the tree for every node
+        let mut index = LocationIndex::new();
+        self.root.index_node_locations(&vec![], &mut index);
+
+        // deleted nodes are described as they were before the merge
+        let previous_nodes = describe_nodes(&self.root);
+
         let mut log = MergeLog::default();
-        log.append(&self.merge_group(vec![], &other.root, false)?);
-        log.append(&self.merge_deletions(&other)?);
+        log.append(&self.merge_group(&mut index, vec![], &other.root, false)?);
+        log.append(&self.merge_deletions(&mut index, &other)?);
+
+        // prune after merging, so that old records of the other database still delete objects
+        if let Some(retention) = self.config.deleted_objects_retention {
+            self.prune_deleted_objects(Times::now() - retention);
+        }
+
+        let merged_nodes = describe_nodes(&self.root);
+        for event in &mut log.events {
+            let description = merged_nodes
+                .get(&event.node_uuid)
+                .or_else(|| previous_nodes.get(&event.node_uuid));
+            if let Some((title, path)) = description {
+                event.title = Some(title.clone());
+                event.path = Some(path.clone());
+            }
+        }
         Ok(log)
     }
 
     #[cfg(feature = "_merge")]
-    fn merge_deletions(&mut self, other: &Database) -> Result<MergeLog, MergeError> {
+    fn merge_deletions(&mut self, index: &mut LocationIndex, other: &Database) -> Result<MergeLog, MergeError> {
         // Utility function to search for a UUID in the VecDeque of deleted objects.
         let is_in_deleted_queue = |uuid: Uuid, deleted_groups_queue: &VecDeque<DeletedObject>| -> bool {
             for deleted_object in deleted_groups_queue {
@@ -172,14 +485,14 @@ impl Database {
             if new_deleted_objects.contains(deleted_object.uuid) {
                 continue;
             }
-            let entry_location = match self.find_node_location(deleted_object.uuid) {
-                Some(l) => l,
+            let entry_location = match index.get(&deleted_object.uuid) {
+                Some(l) => l.clone(),
                 None => continue,
             };
 
             let parent_group = match self.root.find_group_mut(&entry_location) {
                 Some(g) => g,
-                None => return Err(MergeError::FindGroupError(entry_location)),
+                None => return Err(self.find_group_error(entry_location)),
             };
 
             let entry = match parent_group.find_entry(&vec![deleted_object.uuid]) {
@@ -201,10 +514,9 @@ impl Database {
 
             if entry_last_modification < deleted_object.deletion_time {
                 parent_group.remove_node(&deleted_object.uuid)?;
-                log.events.push(MergeEvent {
-                    event_type: MergeEventType::EntryDeleted,
-                    node_uuid: deleted_object.uuid,
-                });
+                index.remove(&deleted_object.uuid);
+                log.events
+                    .push(MergeEvent::new(MergeEventType::EntryDeleted, deleted_object.uuid));
 
                 new_deleted_objects.objects.push(deleted_object.clone());
             }
@@ -223,14 +535,14 @@ impl Database {
             if new_deleted_objects.contains(deleted_object.uuid) {
                 continue;
             }
-            let group_location = match self.find_node_location(deleted_object.uuid) {
-                Some(l) => l,
+            let group_location = match index.get(&deleted_object.uuid) {
+                Some(l) => l.clone(),
                 None => continue,
             };
 
             let parent_group = match self.root.find_group_mut(&group_location) {
                 Some(g) => g,
-                None => return Err(MergeError::FindGroupError(group_location)),
+                None => return Err(self.find_group_error(group_location)),
             };
 
             let group = match parent_group.find_group(&vec![deleted_object.uuid]) {
@@ -279,10 +591,9 @@ impl Database {
 
             if group_last_modification < deleted_object.deletion_time {
                 parent_group.remove_node(&deleted_object.uuid)?;
-                log.events.push(MergeEvent {
-                    event_type: MergeEventType::GroupDeleted,
-                    node_uuid: deleted_object.uuid,
-                });
+                index.remove(&deleted_object.uuid);
+                log.events
+                    .push(MergeEvent::new(MergeEventType::GroupDeleted, deleted_object.uuid));
 
                 new_deleted_objects.objects.push(deleted_object.clone());
             }
@@ -292,43 +603,22 @@ impl Database {
         Ok(log)
     }
 
-    #[cfg(feature = "_merge")]
-    pub(crate) fn find_node_location(&self, id: Uuid) -> Option<NodeLocation> {
-        for node in &self.root.children {
-            match node {
-                Node::Entry(e) => {
-                    if e.uuid == id {
-                        return Some(vec![]);
-                    }
-                }
-                Node::Group(g) => {
-                    if g.uuid == id {
-                        return Some(vec![]);
-                    }
-                    if let Some(location) = g.find_node_location(id) {
-                        return Some(location);
-                    }
-                }
-            }
-        }
-        None
-    }
-
     #[cfg(feature = "_merge")]
     fn merge_group(
         &mut self,
+        index: &mut LocationIndex,
         current_group_path: NodeLocation,
         current_group: &Group,
         is_in_deleted_group: bool,
     ) -> Result<MergeLog, MergeError> {
         let mut log = MergeLog::default();
 
-        if let Some(destination_group_location) = self.find_node_location(current_group.uuid) {
+        if let Some(destination_group_location) = index.get(&current_group.uuid).cloned() {
             let mut destination_group_path = destination_group_location.clone();
             destination_group_path.push(current_group.uuid);
             let destination_group = match self.root.find_group_mut(&destination_group_path) {
                 Some(g) => g,
-                None => return Err(MergeError::FindGroupError(destination_group_path)),
+                None => return Err(self.find_group_error(destination_group_path)),
             };
             let group_update_merge_events = destination_group.merge_with(&current_group)?;
             log.append(&group_update_merge_events);
@@ -336,7 +626,7 @@ impl Database {
 
         for other_entry in &current_group.entries() {
             // find the existing location
-            let destination_entry_location = self.find_node_location(other_entry.uuid);
+            let destination_entry_location = index.get(&other_entry.uuid).cloned();
 
             // The group already exists in the destination database.
             if let Some(destination_entry_location) = destination_entry_location {
@@ -371,16 +661,17 @@ impl Database {
                         }
                     };
                     if source_location_changed_time > destination_location_changed {
-                        log.events.push(MergeEvent {
-                            event_type: MergeEventType::EntryLocationUpdated,
-                            node_uuid: other_entry.uuid,
-                        });
+                        log.events.push(MergeEvent::new(
+                            MergeEventType::EntryLocationUpdated,
+                            other_entry.uuid,
+                        ));
                         self.relocate_node(
                             &other_entry.uuid,
                             &destination_entry_location,
                             &current_group_path,
                             source_location_changed_time,
                         )?;
+                        index.insert(other_entry.uuid, current_group_path.clone());
                         // Update the location of the current entry in case we have to update it
                         // after.
                         existing_entry_location = current_group_path.clone();
@@ -409,14 +700,12 @@ impl Database {
 
                 let existing_entry = match self.root.find_entry_mut(&existing_entry_location) {
                     Some(e) => e,
-                    None => return Err(MergeError::FindEntryError(existing_entry_location)),
+                    None => return Err(self.find_entry_error(existing_entry_location)),
                 };
                 *existing_entry = merged_entry.clone();
 
-                log.events.push(MergeEvent {
-                    event_type: MergeEventType::EntryUpdated,
-                    node_uuid: merged_entry.uuid,
-                });
+                log.events
+                    .push(MergeEvent::new(MergeEventType::EntryUpdated, merged_entry.uuid));
                 log.append(&entry_merge_log);
                 continue;
             }
@@ -435,15 +724,14 @@ impl Database {
 
             let new_entry_parent_group = match self.root.find_group_mut(&current_group_path) {
                 Some(g) => g,
-                None => return Err(MergeError::FindGroupError(current_group_path)),
+                None => return Err(self.find_group_error(current_group_path)),
             };
             new_entry_parent_group.add_child(new_entry.clone());
+            index.insert(new_entry.uuid, current_group_path.clone());
 
             // TODO should we update the time info for the entry?
-            log.events.push(MergeEvent {
-                event_type: MergeEventType::EntryCreated,
-                node_uuid: new_entry.uuid,
-            });
+            log.events
+                .push(MergeEvent::new(MergeEventType::EntryCreated, new_entry.uuid));
         }
 
         for other_group in &current_group.groups() {
@@ -452,12 +740,12 @@ impl Database {
             new_group_location.push(other_group_uuid);
 
             if self.deleted_objects.contains(other_group.uuid) || is_in_deleted_group {
-                let new_merge_log = self.merge_group(new_group_location, other_group, true)?;
+                let new_merge_log = self.merge_group(index, new_group_location, other_group, true)?;
                 log.append(&new_merge_log);
                 continue;
             }
 
-            let destination_group_location = self.find_node_location(other_group.uuid);
+            let destination_group_location = index.get(&other_group.uuid).cloned();
 
             // The group already exists in the destination database.
             if let Some(destination_group_location) = destination_group_location {
@@ -497,13 +785,19 @@ impl Database {
                             other_group_location_changed,
                         )?;
 
-                        log.events.push(MergeEvent {
-                            event_type: MergeEventType::GroupLocationUpdated,
-                            node_uuid: other_group.uuid,
-                        });
+                        // the locations of all nodes below the relocated group change as well
+                        index.insert(other_group.uuid, current_group_path.clone());
+                        if let Some(relocated_group) = self.root.find_group(&new_group_location) {
+                            relocated_group.index_node_locations(&new_group_location, index);
+                        }
+
+                        log.events.push(MergeEvent::new(
+                            MergeEventType::GroupLocationUpdated,
+                            other_group.uuid,
+                        ));
 
                         let new_merge_log =
-                            self.merge_group(new_group_location, other_group, is_in_deleted_group)?;
+                            self.merge_group(index, new_group_location, other_group, is_in_deleted_group)?;
                         log.append(&new_merge_log);
                         continue;
                     }
@@ -511,7 +805,8 @@ impl Database {
 
                 // The group already exists and is at the right location, so we can proceed and merge
                 // the two groups.
-                let new_merge_log = self.merge_group(new_group_location, other_group, is_in_deleted_group)?;
+                let new_merge_log =
+                    self.merge_group(index, new_group_location, other_group, is_in_deleted_group)?;
                 log.append(&new_merge_log);
                 continue;
             }
@@ -519,23 +814,41 @@ impl Database {
             // The group doesn't exist in the destination, we create it
             let mut new_group = other_group.to_owned().clone();
             new_group.children = vec![];
-            log.events.push(MergeEvent {
-                event_type: MergeEventType::GroupCreated,
-                node_uuid: new_group.uuid.clone(),
-            });
+            log.events
+                .push(MergeEvent::new(MergeEventType::GroupCreated, new_group.uuid));
             let new_group_parent_group = match self.root.find_group_mut(&current_group_path) {
                 Some(g) => g,
-                None => return Err(MergeError::FindGroupError(current_group_path)),
+                None => return Err(self.find_group_error(current_group_path)),
             };
             new_group_parent_group.add_child(new_group.clone());
+            index.insert(new_group.uuid, current_group_path.clone());
 
-            let new_merge_log = self.merge_group(new_group_location, other_group, is_in_deleted_group)?;
+            let new_merge_log =
+                self.merge_group(index, new_group_location, other_group, is_in_deleted_group)?;
             log.append(&new_merge_log);
         }
 
         Ok(log)
     }
 
+    #[cfg(feature = "_merge")]
+    fn find_group_error(&self, location: NodeLocation) -> MergeError {
+        let path = join_path(&describe_location(&self.root, &location));
+        MergeError::FindGroupError { location, path }
+    }
+
+    #[cfg(feature = "_merge")]
+    fn find_entry_error(&self, location: NodeLocation) -> MergeError {
+        let mut components = Vec::new();
+        if let Some((entry, groups)) = location.split_last() {
+            components = describe_location(&self.root, groups);
+            let title = self.root.find_entry(&location).and_then(|e| e.get_title());
+            components.push(title.map_or_else(|| entry.to_string(), str::to_string));
+        }
+        let path = join_path(&components);
+        MergeError::FindEntryError { location, path }
+    }
+
     #[cfg(feature = "_merge")]
     fn relocate_node(
         &mut self,
@@ -546,7 +859,7 @@ impl Database {
     ) -> Result<(), MergeError> {
         let source_group = match self.root.find_group_mut(&from) {
             Some(g) => g,
-            None => return Err(MergeError::FindGroupError(from.to_vec())),
+            None => return Err(self.find_group_error(from.to_vec())),
         };
 
         let mut relocated_node = source_group.remove_node(&node_uuid)?;
@@ -557,7 +870,7 @@ impl Database {
 
         let destination_group = match self.root.find_group_mut(&to) {
             Some(g) => g,
-            None => return Err(MergeError::FindGroupError(to.to_vec())),
+            None => return Err(self.find_group_error(to.to_vec())),
         };
         destination_group.children.push(relocated_node);
         Ok(())
@@ -680,14 +993,26 @@ pub struct CustomDataItemDenormalized {
     pub custom_data_item: CustomDataItem,
 }
 
+/// Flag of a [`HeaderAttachment`] that marks its content as protected in memory
+pub const HEADER_ATTACHMENT_PROTECTED: u8 = 0x01;
+
 /// Binary attachments stored in a database inner header
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub struct HeaderAttachment {
     pub flags: u8,
     pub content: Vec<u8>,
 }
 
+impl std::fmt::Debug for HeaderAttachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeaderAttachment")
+            .field("flags", &self.flags)
+            .field("content", crate::redact::secret(&self.content))
+            .finish()
+    }
+}
+
 /// Elements that have been previously deleted
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -714,49 +1039,6 @@ pub struct DeletedObject {
     pub deletion_time: NaiveDateTime,
 }
 
-/// A color value for the Database, or Entry
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
-pub struct Color {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-}
-
-#[cfg(feature = "serialization")]
-impl serde::Serialize for Color {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&self.to_string())
-    }
-}
-
-impl FromStr for Color {
-    type Err = ParseColorError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.starts_with('#') || s.len() != 7 {
-            return Err(ParseColorError(s.to_string()));
-        }
-
-        let v =
-            u64::from_str_radix(s.trim_start_matches('#'), 16).map_err(|_e| ParseColorError(s.to_string()))?;
-
-        let r = ((v >> 16) & 0xff) as u8;
-        let g = ((v >> 8) & 0xff) as u8;
-        let b = (v & 0xff) as u8;
-
-        Ok(Self { r, g, b })
-    }
-}
-
-impl Color {
-    pub fn to_string(&self) -> String {
-        format!("#{:0x}{:0x}{:0x}", self.r, self.g, self.b)
-    }
-}
-
 #[cfg(test)]
 mod database_tests {
     use std::fs::File;
@@ -790,6 +1072,228 @@ mod database_tests {
         .is_err());
     }
 
+    #[test]
+    fn test_apply_history_limits() {
+        use crate::db::{AttachmentRef, Entry, Group, HeaderAttachment, Value};
+
+        let mut entry = Entry::new();
+        let mut history = crate::db::History::default();
+        for i in 0..5 {
+            let mut old = Entry::new();
+            old.fields
+                .insert("Title".into(), Value::Unprotected(format!("v{}", i)));
+            history.add_entry(old);
+        }
+        entry.history = Some(history);
+
+        let mut group = Group::new("sub");
+        group.add_child(entry.clone());
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry);
+        db.root.add_child(group);
+
+        db.meta.history_max_items = Some(3);
+        let report = db.apply_history_limits();
+        assert_eq!(report.entries_affected, 2);
+        assert_eq!(report.items_removed, 4);
+        assert_eq!(report.bytes_removed, 4 * "Titlev0".len());
+
+        // each history item is 7 bytes, so only one fits into 10 bytes
+        db.meta.history_max_size = Some(10);
+        let report = db.apply_history_limits();
+        assert_eq!(report.items_removed, 4);
+
+        let entries = db.root.entries();
+        let history = entries[0].history.as_ref().unwrap();
+        assert_eq!(history.get_entries().len(), 1);
+        assert_eq!(history.get_entries()[0].get_title(), Some("v4"));
+
+        assert_eq!(db.apply_history_limits(), Default::default());
+
+        // attachment contents count towards the size of history items
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: vec![0; 100],
+        });
+        db.root.for_each_entry_mut(&mut |entry| {
+            if let Some(history) = entry.history.as_mut() {
+                history.entries[0].attachments.push(AttachmentRef {
+                    name: "file.bin".to_string(),
+                    identifier: "0".to_string(),
+                });
+            }
+        });
+
+        db.meta.history_max_size = Some(100);
+        let report = db.apply_history_limits();
+        assert_eq!(report.items_removed, 2);
+        assert_eq!(report.bytes_removed, 2 * ("Titlev4".len() + 100));
+    }
+
+    #[test]
+    fn test_apply_history_limits_oldest_first() {
+        use crate::db::{Entry, History, Times, Value};
+
+        // KeePass writes history items from oldest to newest
+        let mut entry = Entry::new();
+        let mut history = History::default();
+        for i in 0..5 {
+            let mut old = Entry::new();
+            old.fields
+                .insert("Title".into(), Value::Unprotected(format!("v{}", i)));
+            old.times
+                .set_last_modification(Times::epoch() + chrono::Duration::days(i));
+            history.entries.push(old);
+        }
+        entry.history = Some(history);
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry);
+
+        db.meta.history_max_items = Some(2);
+        let report = db.apply_history_limits();
+        assert_eq!(report.items_removed, 3);
+
+        let entries = db.root.entries();
+        let titles: Vec<_> = entries[0]
+            .history
+            .as_ref()
+            .unwrap()
+            .get_entries()
+            .iter()
+            .map(|e| e.get_title().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["v3", "v4"]);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_export_import_xml_attachments() {
+        use crate::db::{AttachmentRef, Entry, HeaderAttachment, History, HEADER_ATTACHMENT_PROTECTED};
+
+        let mut db = Database::new(Default::default());
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: b"old content".to_vec(),
+        });
+        db.header_attachments.push(HeaderAttachment {
+            flags: HEADER_ATTACHMENT_PROTECTED,
+            content: b"secret content".to_vec(),
+        });
+
+        let attachment = |identifier: &str| AttachmentRef {
+            name: "file.txt".to_string(),
+            identifier: identifier.to_string(),
+        };
+        let mut entry = Entry::new();
+        entry.attachments.push(attachment("1"));
+        let mut previous = Entry::new();
+        previous.attachments.push(attachment("0"));
+        let mut history = History::default();
+        history.add_entry(previous);
+        entry.history = Some(history);
+        db.root.add_child(entry);
+
+        let mut xml = Vec::new();
+        db.export_xml(&mut xml, true).unwrap();
+        let imported = Database::import_xml(&mut xml.as_slice()).unwrap();
+        assert!(imported.meta.binaries.binaries.is_empty());
+        assert_eq!(imported, db);
+
+        // protected binaries are redacted, but still referenced
+        let mut xml = Vec::new();
+        db.export_xml(&mut xml, false).unwrap();
+        let imported = Database::import_xml(&mut xml.as_slice()).unwrap();
+        assert_eq!(imported.header_attachments[0], db.header_attachments[0]);
+        assert_eq!(imported.header_attachments[1].flags, HEADER_ATTACHMENT_PROTECTED);
+        assert!(imported.header_attachments[1].content.is_empty());
+        assert_eq!(imported.root.entries()[0].attachments, vec![attachment("1")]);
+
+        // binaries of KeePass exports are renumbered to the indices of the inner header
+        let xml = r#"<KeePassFile><Meta><Binaries>
+            <Binary ID="7">Y29udGVudA==</Binary>
+            </Binaries></Meta><Root><Group><Name>Root</Name><Entry>
+            <Binary><Key>file.txt</Key><Value Ref="7"/></Binary>
+            </Entry></Group></Root></KeePassFile>"#;
+        let imported = Database::import_xml(&mut xml.as_bytes()).unwrap();
+        assert_eq!(imported.header_attachments[0].content, b"content");
+        assert_eq!(imported.root.entries()[0].attachments, vec![attachment("0")]);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_export_import_xml() {
+        use base64::{engine::general_purpose as base64_engine, Engine as _};
+
+        use crate::db::{CustomDataItem, Entry, Value};
+
+        let mut db = Database::new(Default::default());
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".into(), Value::Unprotected("Demo".to_string()));
+        entry
+            .fields
+            .insert("Password".into(), Value::Protected("secret".into()));
+        entry.custom_data.items.insert(
+            "Plugin.Token".to_string(),
+            CustomDataItem {
+                value: Some(Value::Protected("token".into())),
+                last_modification_time: None,
+            },
+        );
+        db.root.add_child(entry);
+
+        let mut xml = Vec::new();
+        db.export_xml(&mut xml, true).unwrap();
+        let document = String::from_utf8_lossy(&xml);
+        assert!(document.contains(r#"<Value ProtectInMemory="True">secret</Value>"#));
+        let imported = Database::import_xml(&mut xml.as_slice()).unwrap();
+        assert_eq!(imported, db);
+
+        // the document is parsed incrementally from the source
+        let mut chunked = std::io::BufReader::with_capacity(7, xml.as_slice());
+        assert_eq!(Database::import_xml(&mut chunked).unwrap(), db);
+
+        // unbuffered sources such as files are not read byte by byte
+        struct CountingReader<'a> {
+            inner: &'a [u8],
+            reads: usize,
+        }
+
+        impl std::io::Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.reads += 1;
+                self.inner.read(buf)
+            }
+        }
+
+        let mut counting = CountingReader {
+            inner: xml.as_slice(),
+            reads: 0,
+        };
+        assert_eq!(Database::import_xml(&mut counting).unwrap(), db);
+        assert!(
+            counting.reads < 10,
+            "{} reads for {} bytes",
+            counting.reads,
+            xml.len()
+        );
+
+        let mut xml = Vec::new();
+        db.export_xml(&mut xml, false).unwrap();
+        assert!(!String::from_utf8_lossy(&xml).contains(&base64_engine::STANDARD.encode("token")));
+        let imported = Database::import_xml(&mut xml.as_slice()).unwrap();
+        let entries = imported.root.entries();
+        assert_eq!(entries[0].get_title(), Some("Demo"));
+        assert_eq!(entries[0].get_password(), None);
+        assert_eq!(
+            entries[0].custom_data.items["Plugin.Token"].value,
+            Some(Value::Protected("".into()))
+        );
+    }
+
     #[cfg(feature = "save_kdbx4")]
     #[test]
     fn test_save() {
@@ -813,4 +1317,71 @@ mod database_tests {
 
         assert_eq!(db, db_loaded);
     }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_upgrade_kdf() {
+        use crate::config::{DatabaseVersion, KdfAdvisory, KdfConfig};
+
+        let mut db = Database::new(Default::default());
+        db.config.kdf_config = KdfConfig::Aes { rounds: 6000 };
+
+        let mut buffer = Vec::new();
+        let advisories = db
+            .save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+        assert_eq!(
+            advisories,
+            vec![
+                KdfAdvisory::NotMemoryHard,
+                KdfAdvisory::TooFewRounds { rounds: 6000 }
+            ]
+        );
+
+        db.config.version = DatabaseVersion::KDB3(1);
+        let previous = db.upgrade_kdf(KdfConfig::recommended());
+        assert_eq!(previous, KdfConfig::Aes { rounds: 6000 });
+        assert!(matches!(db.config.version, DatabaseVersion::KDB4(_)));
+        assert!(db.config.kdf_config.advisories().is_empty());
+    }
+
+    #[test]
+    fn test_security_assessment() {
+        use crate::config::{
+            CompressionConfig, DatabaseConfig, DatabaseVersion, InnerCipherConfig, KdfAdvisory, KdfConfig,
+            SecurityFinding, Severity,
+        };
+
+        let config = DatabaseConfig {
+            kdf_config: KdfConfig::recommended(),
+            ..Default::default()
+        };
+        assert!(config.security_assessment().is_empty());
+
+        let config = DatabaseConfig {
+            version: DatabaseVersion::KDB3(1),
+            compression_config: CompressionConfig::None,
+            inner_cipher_config: InnerCipherConfig::Salsa20,
+            kdf_config: KdfConfig::Aes { rounds: 6000 },
+            ..Default::default()
+        };
+        let findings = config.security_assessment();
+        assert_eq!(
+            findings,
+            vec![
+                SecurityFinding::Kdf(KdfAdvisory::TooFewRounds { rounds: 6000 }),
+                SecurityFinding::Kdf(KdfAdvisory::NotMemoryHard),
+                SecurityFinding::OutdatedFormat(DatabaseVersion::KDB3(1)),
+                SecurityFinding::Salsa20InnerCipher,
+                SecurityFinding::NoCompression,
+            ]
+        );
+        assert_eq!(findings[0].severity(), Severity::Critical);
+        assert_eq!(findings[4].severity(), Severity::Info);
+        assert!(findings.iter().all(|f| !f.remediation().is_empty()));
+        assert_eq!(
+            findings[2].to_string(),
+            "The database uses the outdated KDBX3.1 format"
+        );
+    }
 }
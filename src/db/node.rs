@@ -75,6 +75,39 @@ impl<'a> NodeIter<'a> {
     }
 }
 
+/// Depth-first iterator over the nodes below a group, see [`Group::descendants`]
+pub struct Descendants<'a> {
+    stack: Vec<std::slice::Iter<'a, Node>>,
+}
+
+impl<'a> Descendants<'a> {
+    pub(crate) fn new(group: &'a Group) -> Self {
+        Self {
+            stack: vec![group.children.iter()],
+        }
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<NodeRef<'a>> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(node) => {
+                    if let Node::Group(g) = node {
+                        self.stack.push(g.children.iter());
+                    }
+                    return Some(node.into());
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
 impl<'a> Iterator for NodeIter<'a> {
     type Item = NodeRef<'a>;
 
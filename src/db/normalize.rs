@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::db::{Database, Entry, Group, Node, Times, Value};
+
+/// How names and titles are compared, e.g. when looking up groups and entries by path.
+///
+/// Different platforms and input methods can produce different Unicode representations of the
+/// same text, e.g. a precomposed "é" or an "e" followed by a combining accent. The default is
+/// to compare texts exactly.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextMatching {
+    /// Compare the texts in Unicode normalization form C (NFC)
+    pub normalize: bool,
+
+    /// Compare the texts ignoring differences in case
+    pub case_insensitive: bool,
+}
+
+impl TextMatching {
+    /// Compare texts exactly
+    pub const EXACT: TextMatching = TextMatching {
+        normalize: false,
+        case_insensitive: false,
+    };
+
+    /// Ignore differences in Unicode normalization and case
+    pub const LENIENT: TextMatching = TextMatching {
+        normalize: true,
+        case_insensitive: true,
+    };
+
+    /// Bring a text into the form in which it is compared
+    pub fn canonicalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+
+        if self.normalize {
+            text = normalize(text);
+        }
+
+        if self.case_insensitive && text.chars().any(char::is_uppercase) {
+            text = Cow::Owned(text.to_lowercase());
+        }
+
+        text
+    }
+
+    /// Whether two texts are considered equal
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        a == b || (*self != TextMatching::EXACT && self.canonicalize(a) == self.canonicalize(b))
+    }
+}
+
+/// Convert a text to Unicode normalization form C, without allocating if it already is
+fn normalize(text: Cow<'_, str>) -> Cow<'_, str> {
+    if is_nfc(&text) {
+        text
+    } else {
+        Cow::Owned(text.nfc().collect())
+    }
+}
+
+/// Normalize a string in place, returning whether it changed
+fn normalize_in_place(text: &mut String) -> bool {
+    if is_nfc(text) {
+        return false;
+    }
+
+    *text = text.nfc().collect();
+    true
+}
+
+fn normalize_group(group: &mut Group) -> usize {
+    let mut changed = normalize_in_place(&mut group.name) as usize;
+    if let Some(notes) = group.notes.as_mut() {
+        changed += normalize_in_place(notes) as usize;
+    }
+
+    // merging keeps the newer version of a group, so the normalized one must be newer
+    if changed > 0 {
+        group.times.set_last_modification(Times::now());
+    }
+
+    for node in group.children.iter_mut() {
+        match node {
+            Node::Group(g) => changed += normalize_group(g),
+            Node::Entry(e) => changed += e.edit(normalize_entry),
+        }
+    }
+
+    changed
+}
+
+fn normalize_entry(entry: &mut Entry) -> usize {
+    let mut changed = 0;
+
+    for value in entry.fields.values_mut() {
+        if let Value::Unprotected(v) = value {
+            changed += normalize_in_place(v) as usize;
+        }
+    }
+    for tag in entry.tags.iter_mut() {
+        if !is_nfc(tag) {
+            *tag = tag.nfc().collect::<String>().into();
+            changed += 1;
+        }
+    }
+
+    changed
+}
+
+impl Database {
+    /// Convert the names and notes of all groups, and the unprotected fields and tags of all
+    /// entries to Unicode normalization form C (NFC), which is what most platforms produce.
+    ///
+    /// Call this after opening a database that was edited on platforms with different input
+    /// methods, so that equal texts are stored equally. Protected fields such as passwords are
+    /// never changed, since they must be typed exactly as stored.
+    ///
+    /// Changed entries are edited like with [`Entry::edit`], so their previous version is kept
+    /// in their history and their modification time is updated, as are the modification times of
+    /// changed groups. This way, merging with an unnormalized copy of the database keeps the
+    /// normalized texts. Returns the number of changed texts.
+    pub fn normalize_unicode(&mut self) -> usize {
+        normalize_group(&mut self.root)
+    }
+}
+
+#[cfg(test)]
+mod normalize_tests {
+    use super::TextMatching;
+    use crate::db::{Database, Entry, Group, NodeRef, Times, Value};
+
+    const COMPOSED: &str = "Caf\u{e9}";
+    const DECOMPOSED: &str = "Cafe\u{301}";
+
+    #[test]
+    fn text_matching() {
+        assert!(!TextMatching::EXACT.matches(COMPOSED, DECOMPOSED));
+        assert!(TextMatching::LENIENT.matches(COMPOSED, DECOMPOSED));
+        assert!(TextMatching::LENIENT.matches("CAF\u{c9}", DECOMPOSED));
+
+        let normalize_only = TextMatching {
+            normalize: true,
+            case_insensitive: false,
+        };
+        assert!(normalize_only.matches(COMPOSED, DECOMPOSED));
+        assert!(!normalize_only.matches("CAF\u{c9}", DECOMPOSED));
+    }
+
+    #[test]
+    fn normalize_database() {
+        let mut db = Database::new(Default::default());
+        let mut group = Group::new(DECOMPOSED);
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".into(), Value::Unprotected(DECOMPOSED.to_string()));
+        entry
+            .fields
+            .insert("Password".into(), Value::Protected(DECOMPOSED.as_bytes().into()));
+        entry.times.set_last_modification(Times::epoch());
+        group.times.set_last_modification(Times::epoch());
+        group.add_child(entry);
+        db.root.add_child(group);
+
+        assert!(db.resolve_path(&format!("/{}", COMPOSED)).is_none());
+        assert!(matches!(
+            db.resolve_path_with(&format!("/{}/{}", COMPOSED, COMPOSED), TextMatching::LENIENT),
+            Some(NodeRef::Entry(_))
+        ));
+
+        assert_eq!(db.normalize_unicode(), 2);
+        assert_eq!(db.normalize_unicode(), 0);
+
+        let entry = match db.resolve_path(&format!("/{}/{}", COMPOSED, COMPOSED)) {
+            Some(NodeRef::Entry(e)) => e,
+            _ => panic!("entry not found after normalization"),
+        };
+        assert_eq!(entry.get_password(), Some(DECOMPOSED));
+
+        // the previous version is kept, and the normalized one is newer
+        let history = entry.history.as_ref().unwrap().get_entries();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].get_title(), Some(DECOMPOSED));
+        assert!(entry.times.get_last_modification() > history[0].times.get_last_modification());
+
+        let group = db.root.groups()[0];
+        assert!(group.times.get_last_modification() > Some(&Times::epoch()));
+    }
+}
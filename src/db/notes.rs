@@ -0,0 +1,110 @@
+use crate::db::Entry;
+
+/// How the notes of an entry are formatted, as guessed by [`NotesFormat::detect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum NotesFormat {
+    Plain,
+    Markdown,
+}
+
+impl NotesFormat {
+    /// Guess whether a text is Markdown, by looking for headings, lists, block quotes, fenced
+    /// code blocks, links and emphasis. Texts without any of these are plain text.
+    pub fn detect(text: &str) -> NotesFormat {
+        let block_syntax = text.lines().map(str::trim_start).any(|line| {
+            let heading = line.trim_start_matches('#');
+            (heading.len() < line.len() && line.len() - heading.len() <= 6 && heading.starts_with(' '))
+                || line.starts_with("- ")
+                || line.starts_with("* ")
+                || line.starts_with("> ")
+                || line.starts_with("```")
+                || is_numbered_list_item(line)
+        });
+
+        let inline_syntax = text.contains("](") || text.contains("**") || text.contains("__");
+
+        if block_syntax || inline_syntax {
+            NotesFormat::Markdown
+        } else {
+            NotesFormat::Plain
+        }
+    }
+}
+
+fn is_numbered_list_item(line: &str) -> bool {
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    rest.len() < line.len() && rest.starts_with(". ")
+}
+
+/// Parse the `key: value` lines of a text. Other lines are skipped, as are lines that look like
+/// URLs, e.g. `https://example.com`, and lines with an empty key.
+pub fn parse_key_values(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let key = key.trim().trim_start_matches(['-', '*']).trim();
+            if key.is_empty() || value.starts_with("//") {
+                return None;
+            }
+            Some((key.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+impl<'a> Entry {
+    /// Parse the `key: value` lines in the notes of this entry, in the order they appear, see
+    /// [`parse_key_values`]. The notes themselves are not changed.
+    pub fn parse_notes_kv(&'a self) -> Vec<(String, String)> {
+        self.get_notes().map(parse_key_values).unwrap_or_default()
+    }
+
+    /// Guess the format of the notes of this entry, see [`NotesFormat::detect`]. Entries without
+    /// notes are plain text.
+    pub fn notes_format(&'a self) -> NotesFormat {
+        self.get_notes().map_or(NotesFormat::Plain, NotesFormat::detect)
+    }
+}
+
+#[cfg(test)]
+mod notes_tests {
+    use super::{parse_key_values, NotesFormat};
+    use crate::db::Entry;
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(NotesFormat::detect("just some notes"), NotesFormat::Plain);
+        assert_eq!(NotesFormat::detect("PIN: 1234\nPUK: 5678"), NotesFormat::Plain);
+        assert_eq!(NotesFormat::detect("#hashtag"), NotesFormat::Plain);
+        assert_eq!(NotesFormat::detect("## Recovery codes"), NotesFormat::Markdown);
+        assert_eq!(NotesFormat::detect("codes:\n- abc\n- def"), NotesFormat::Markdown);
+        assert_eq!(NotesFormat::detect("1. first\n2. second"), NotesFormat::Markdown);
+        assert_eq!(
+            NotesFormat::detect("see [docs](https://example.com)"),
+            NotesFormat::Markdown
+        );
+        assert_eq!(NotesFormat::detect("**important**"), NotesFormat::Markdown);
+    }
+
+    #[test]
+    fn parse_notes_kv() {
+        assert_eq!(
+            parse_key_values("PIN: 1234\nsee https://example.com\n- Account no.: 42 \n: nothing\nfree text"),
+            vec![
+                ("PIN".to_string(), "1234".to_string()),
+                ("Account no.".to_string(), "42".to_string()),
+            ]
+        );
+
+        let mut entry = Entry::new();
+        assert!(entry.parse_notes_kv().is_empty());
+        assert_eq!(entry.notes_format(), NotesFormat::Plain);
+
+        entry.set_notes("# Bank\nIBAN: DE00 1234");
+        assert_eq!(
+            entry.parse_notes_kv(),
+            vec![("IBAN".to_string(), "DE00 1234".to_string())]
+        );
+        assert_eq!(entry.notes_format(), NotesFormat::Markdown);
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+/// What was left out while parsing the XML documents of databases, as collected by
+/// [`ParseReport::collect`].
+///
+/// The parser skips elements that it does not know in some places, instead of failing. Data
+/// that is stored in such elements is lost when the database is saved again, so this helps to
+/// find out why data written by another client went missing. With the `tracing` feature, every
+/// skipped element is also logged as a warning.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    /// Number of skipped elements by their path in the XML document, e.g.
+    /// `KeePassFile/Meta/CustomIcons/Icon/Name`
+    pub ignored_elements: BTreeMap<String, usize>,
+}
+
+impl ParseReport {
+    /// Run `f`, which opens or imports databases on the current thread, and report what was
+    /// skipped while parsing them
+    pub fn collect<T>(f: impl FnOnce() -> T) -> (T, ParseReport) {
+        let (result, ignored_elements) = crate::xml_db::parse::count_ignored_elements(f);
+        (result, ParseReport { ignored_elements })
+    }
+
+    /// Total number of skipped elements
+    pub fn ignored_count(&self) -> usize {
+        self.ignored_elements.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ignored_elements.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod parse_report_tests {
+    use super::ParseReport;
+    use crate::Database;
+
+    #[test]
+    fn collect() {
+        let xml = "<KeePassFile><Meta><CustomIcons>\
+            <Unknown>1</Unknown><Unknown><Nested /></Unknown>\
+            </CustomIcons><MemoryProtection><Other /></MemoryProtection></Meta>\
+            <Root><Group><Name>Root</Name></Group></Root></KeePassFile>";
+
+        let (db, report) = ParseReport::collect(|| Database::import_xml(&mut xml.as_bytes()));
+        assert_eq!(db.unwrap().root.name, "Root");
+        assert_eq!(report.ignored_count(), 3);
+        assert_eq!(
+            report.ignored_elements.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("KeePassFile/Meta/CustomIcons/Unknown".to_string(), 2),
+                ("KeePassFile/Meta/MemoryProtection/Other".to_string(), 1),
+            ]
+        );
+
+        // nothing is counted outside of `collect`
+        Database::import_xml(&mut xml.as_bytes()).unwrap();
+        let ((), report) = ParseReport::collect(|| ());
+        assert!(report.is_empty());
+    }
+}
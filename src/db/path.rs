@@ -0,0 +1,188 @@
+use uuid::Uuid;
+
+use crate::db::{Database, Group, Node, NodeRef, NodeRefMut, TextMatching};
+
+/// Separator between the components of a node path
+pub const PATH_SEPARATOR: char = '/';
+
+const ESCAPE: char = '\\';
+
+/// Split a path like `/Banking/Credit Cards` into its components.
+///
+/// Components are separated by `/`. A literal `/` or `\` inside a group name or entry title can be
+/// written as `\/` or `\\`, respectively. Leading, trailing and repeated separators are ignored.
+pub fn split_path(path: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut current = String::new();
+
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ESCAPE => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            PATH_SEPARATOR => {
+                if !current.is_empty() {
+                    components.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        components.push(current);
+    }
+
+    components
+}
+
+/// Join path components into a canonical path string, escaping separators as needed. This is the
+/// inverse of [`split_path`].
+pub fn join_path<T: AsRef<str>>(components: &[T]) -> String {
+    let mut out = String::new();
+
+    for component in components {
+        out.push(PATH_SEPARATOR);
+        for c in component.as_ref().chars() {
+            if c == PATH_SEPARATOR || c == ESCAPE {
+                out.push(ESCAPE);
+            }
+            out.push(c);
+        }
+    }
+
+    if out.is_empty() {
+        out.push(PATH_SEPARATOR);
+    }
+
+    out
+}
+
+impl Database {
+    /// Get a group or entry by its path relative to the root group, e.g.
+    /// `/Banking/Credit Cards/My Card`. See [`split_path`] for the path syntax.
+    pub fn resolve_path<'a>(&'a self, path: &str) -> Option<NodeRef<'a>> {
+        let components = split_path(path);
+        let components: Vec<&str> = components.iter().map(|c| c.as_str()).collect();
+        self.root.get(&components)
+    }
+
+    /// Like [`Database::resolve_path`], but compare the path components as specified by
+    /// `matching`, e.g. ignoring differences in Unicode normalization or case
+    pub fn resolve_path_with<'a>(&'a self, path: &str, matching: TextMatching) -> Option<NodeRef<'a>> {
+        let components = split_path(path);
+        let components: Vec<&str> = components.iter().map(|c| c.as_str()).collect();
+        self.root.get_with(&components, matching)
+    }
+
+    /// Get a mutable reference to a group or entry by its path relative to the root group
+    pub fn resolve_path_mut<'a>(&'a mut self, path: &str) -> Option<NodeRefMut<'a>> {
+        let components = split_path(path);
+        let components: Vec<&str> = components.iter().map(|c| c.as_str()).collect();
+        self.root.get_mut(&components)
+    }
+
+    /// Get the canonical path of the group or entry with the given UUID, relative to the root
+    /// group. Entries without a title are represented by an empty path component.
+    pub fn path_of(&self, uuid: Uuid) -> Option<String> {
+        if self.root.uuid == uuid {
+            return Some(join_path::<&str>(&[]));
+        }
+
+        let mut components = Vec::new();
+        if collect_path(&self.root, uuid, &mut components) {
+            Some(join_path(&components))
+        } else {
+            None
+        }
+    }
+}
+
+fn collect_path<'a>(group: &'a Group, uuid: Uuid, components: &mut Vec<&'a str>) -> bool {
+    for node in &group.children {
+        match node {
+            Node::Entry(e) => {
+                if e.uuid == uuid {
+                    components.push(e.get_title().unwrap_or(""));
+                    return true;
+                }
+            }
+            Node::Group(g) => {
+                components.push(&g.name);
+                if g.uuid == uuid || collect_path(g, uuid, components) {
+                    return true;
+                }
+                components.pop();
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod path_tests {
+    use super::{join_path, split_path};
+    use crate::db::{Database, Entry, Group, NodeRef, Value};
+
+    #[test]
+    fn split_and_join() {
+        assert_eq!(
+            split_path("/Banking/Credit Cards"),
+            vec!["Banking", "Credit Cards"]
+        );
+        assert_eq!(
+            split_path("Banking//Credit Cards/"),
+            vec!["Banking", "Credit Cards"]
+        );
+        assert_eq!(split_path("/A\\/B/C\\\\D"), vec!["A/B", "C\\D"]);
+        assert!(split_path("/").is_empty());
+
+        assert_eq!(join_path(&["A/B", "C\\D"]), "/A\\/B/C\\\\D");
+        assert_eq!(join_path::<&str>(&[]), "/");
+    }
+
+    #[test]
+    fn resolve_path() {
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".into(), Value::Unprotected("Visa/Debit".to_string()));
+        let entry_uuid = entry.uuid;
+
+        let mut cards = Group::new("Credit Cards");
+        cards.add_child(entry);
+        let cards_uuid = cards.uuid;
+
+        let mut banking = Group::new("Banking");
+        banking.add_child(cards);
+        db.root.add_child(banking);
+
+        match db.resolve_path("/Banking/Credit Cards/Visa\\/Debit") {
+            Some(NodeRef::Entry(e)) => assert_eq!(e.uuid, entry_uuid),
+            _ => panic!("Expected an entry"),
+        }
+
+        match db.resolve_path("/Banking/Credit Cards") {
+            Some(NodeRef::Group(g)) => assert_eq!(g.uuid, cards_uuid),
+            _ => panic!("Expected a group"),
+        }
+
+        assert!(db.resolve_path("/Banking/Missing").is_none());
+
+        assert_eq!(db.path_of(cards_uuid).unwrap(), "/Banking/Credit Cards");
+        assert_eq!(
+            db.path_of(entry_uuid).unwrap(),
+            "/Banking/Credit Cards/Visa\\/Debit"
+        );
+        assert_eq!(db.path_of(db.root.uuid).unwrap(), "/");
+        assert!(db.path_of(uuid::Uuid::new_v4()).is_none());
+
+        let path = db.path_of(entry_uuid).unwrap();
+        assert!(db.resolve_path_mut(&path).is_some());
+    }
+}
@@ -0,0 +1,247 @@
+use chrono::{Duration, NaiveDateTime};
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, EntryId, NodeRef, Times};
+
+/// Classes of characters that a password policy can require
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    /// Any character that is not an ASCII letter or digit
+    Special,
+}
+
+impl CharacterClass {
+    pub fn matches(&self, c: char) -> bool {
+        match self {
+            CharacterClass::Lowercase => c.is_ascii_lowercase(),
+            CharacterClass::Uppercase => c.is_ascii_uppercase(),
+            CharacterClass::Digit => c.is_ascii_digit(),
+            CharacterClass::Special => !c.is_ascii_alphanumeric(),
+        }
+    }
+}
+
+/// Requirements that the passwords of entries should fulfill
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Maximum time since the password was last changed
+    pub max_age: Option<Duration>,
+
+    /// Minimum number of characters in the password
+    pub min_length: Option<usize>,
+
+    /// Character classes that each need to occur at least once in the password
+    pub required_classes: Vec<CharacterClass>,
+}
+
+/// A way in which an entry does not fulfill a [`Policy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum PolicyViolation {
+    /// The entry does not have a password
+    MissingPassword,
+
+    /// The password was changed too long ago
+    TooOld { last_changed: NaiveDateTime },
+
+    /// The password has fewer characters than required
+    TooShort { length: usize },
+
+    /// The password does not contain a character of a required class
+    MissingClass(CharacterClass),
+}
+
+/// The policy violations of a single entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct PolicyReport {
+    pub uuid: Uuid,
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl Entry {
+    /// Get the time at which the password of the entry was last changed.
+    ///
+    /// This is determined by walking the history of the entry back to the last version that had a
+    /// different password, so that changes to other fields do not reset the password age. If the
+    /// password never changed, the creation time of the entry is returned.
+    pub fn password_last_changed(&self) -> Option<NaiveDateTime> {
+        self.field_last_changed("Password")
+    }
+
+    /// Get the time elapsed since the password of the entry was last changed
+    pub fn password_age(&self) -> Option<Duration> {
+        self.password_last_changed().map(|t| Times::now() - t)
+    }
+}
+
+impl Policy {
+    /// Check an entry against the policy, using the current time to determine the password age
+    pub fn evaluate(&self, entry: &Entry) -> Vec<PolicyViolation> {
+        self.evaluate_at(entry, Times::now())
+    }
+
+    /// Check an entry against the policy, determining the password age relative to `now`
+    pub fn evaluate_at(&self, entry: &Entry, now: NaiveDateTime) -> Vec<PolicyViolation> {
+        let password = match entry.get_password() {
+            Some(p) if !p.is_empty() => p,
+            _ => return vec![PolicyViolation::MissingPassword],
+        };
+
+        let mut violations = Vec::new();
+
+        if let (Some(max_age), Some(last_changed)) = (self.max_age, entry.password_last_changed()) {
+            if now - last_changed > max_age {
+                violations.push(PolicyViolation::TooOld { last_changed });
+            }
+        }
+
+        let length = password.chars().count();
+        if matches!(self.min_length, Some(min_length) if length < min_length) {
+            violations.push(PolicyViolation::TooShort { length });
+        }
+
+        for class in &self.required_classes {
+            if !password.chars().any(|c| class.matches(c)) {
+                violations.push(PolicyViolation::MissingClass(*class));
+            }
+        }
+
+        violations
+    }
+}
+
+impl Database {
+    /// Get the entries for which quality checks are disabled, which are excluded from password
+    /// quality reports
+    pub fn quality_check_excluded(&self) -> Vec<EntryId> {
+        self.root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Entry(e) if !e.is_quality_check_enabled() => Some(e.id()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Check all entries of the database against a policy, returning a report for every entry
+    /// that violates it. Entries for which quality checks are disabled are skipped, see
+    /// [`Entry::is_quality_check_enabled`].
+    pub fn evaluate_policy(&self, policy: &Policy) -> Vec<PolicyReport> {
+        let now = Times::now();
+
+        self.root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Entry(e) if e.is_quality_check_enabled() => Some(e),
+                _ => None,
+            })
+            .filter_map(|entry| {
+                let violations = policy.evaluate_at(entry, now);
+                if violations.is_empty() {
+                    return None;
+                }
+
+                Some(PolicyReport {
+                    uuid: entry.uuid,
+                    violations,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod policy_tests {
+    use chrono::{Duration, NaiveDateTime};
+
+    use super::{CharacterClass, Policy, PolicyViolation};
+    use crate::db::{Database, Entry, History, Value};
+
+    fn at(day: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    fn version(password: &str, day: u32) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Password".into(), Value::Protected(password.as_bytes().into()));
+        entry.times.set_creation(at(1));
+        entry.times.set_last_modification(at(day));
+        entry
+    }
+
+    #[test]
+    fn password_last_changed() {
+        let entry = version("secret", 5);
+        assert_eq!(entry.password_last_changed(), Some(at(1)));
+
+        // the password changed on day 10, the username on day 20
+        let mut history = History::default();
+        history.add_entry(version("old", 3));
+        history.add_entry(version("secret", 10));
+
+        let mut entry = version("secret", 20);
+        entry.history = Some(history);
+        assert_eq!(entry.password_last_changed(), Some(at(10)));
+    }
+
+    #[test]
+    fn evaluate_policy() {
+        let policy = Policy {
+            max_age: Some(Duration::days(30)),
+            min_length: Some(8),
+            required_classes: vec![CharacterClass::Digit, CharacterClass::Uppercase],
+        };
+
+        let entry = version("secret1", 5);
+        assert_eq!(
+            policy.evaluate_at(&entry, at(20)),
+            vec![
+                PolicyViolation::TooShort { length: 7 },
+                PolicyViolation::MissingClass(CharacterClass::Uppercase),
+            ]
+        );
+
+        let entry = version("Secret123", 5);
+        assert!(policy.evaluate_at(&entry, at(20)).is_empty());
+        assert_eq!(
+            policy.evaluate_at(&entry, at(1) + Duration::days(40)),
+            vec![PolicyViolation::TooOld { last_changed: at(1) }]
+        );
+
+        assert_eq!(
+            policy.evaluate_at(&Entry::new(), at(20)),
+            vec![PolicyViolation::MissingPassword]
+        );
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(Entry::new());
+        db.root.add_child(version("Secret123", 5));
+        let reports = db.evaluate_policy(&Policy {
+            min_length: Some(8),
+            ..Default::default()
+        });
+        assert_eq!(reports.len(), 1);
+
+        // entries with quality checks disabled are excluded
+        let mut excluded = version("1234", 5);
+        excluded.set_quality_check(false);
+        let excluded_id = excluded.id();
+        db.root.add_child(excluded);
+        let reports = db.evaluate_policy(&Policy {
+            min_length: Some(8),
+            ..Default::default()
+        });
+        assert_eq!(reports.len(), 1);
+        assert_eq!(db.quality_check_excluded(), vec![excluded_id]);
+    }
+}
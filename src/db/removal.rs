@@ -0,0 +1,155 @@
+use crate::{
+    db::{
+        tracked::{find_with_ancestors, take_node},
+        Database, DeletedObject, Entry, EntryId, Node, NodeRef, Times, AUTO_OPEN_GROUP_NAME,
+    },
+    error::RemoveEntryError,
+};
+
+/// A reason why removing an entry may break something else in the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum EntryDependency {
+    /// Another entry links to a field of the entry, see [`Database::references_to`]
+    ReferencedBy(EntryId),
+
+    /// The entry opens another database automatically, see [`Database::auto_open_specs`]
+    AutoOpen,
+
+    /// The entry is in the group of entry templates set in the metadata
+    Template,
+}
+
+impl Database {
+    /// Get what would break if the entry `id` were removed. Returns an empty list if nothing
+    /// depends on the entry, or if there is no such entry.
+    pub fn entry_dependencies(&self, id: EntryId) -> Vec<EntryDependency> {
+        let ancestors = match find_with_ancestors(&self.root, id.as_uuid()) {
+            Some((NodeRef::Entry(_), ancestors)) => ancestors,
+            _ => return Vec::new(),
+        };
+
+        let mut dependencies: Vec<EntryDependency> = self
+            .references_to(id)
+            .into_iter()
+            .filter(|referencing| *referencing != id)
+            .map(EntryDependency::ReferencedBy)
+            .collect();
+
+        // the AutoOpen group is only recognized at the top level
+        if ancestors.len() == 2 && ancestors[1].name == AUTO_OPEN_GROUP_NAME {
+            dependencies.push(EntryDependency::AutoOpen);
+        }
+
+        if let Some(templates) = self.meta.entry_templates_group {
+            if ancestors.iter().any(|g| g.uuid == templates) {
+                dependencies.push(EntryDependency::Template);
+            }
+        }
+
+        dependencies
+    }
+
+    /// Remove the entry `id` from the database and record it as deleted, unless anything
+    /// depends on it as listed by [`Database::entry_dependencies`]. In that case, the database
+    /// is left unchanged and the dependencies are returned in the error, so that they can be
+    /// shown to the user before removing the entry with [`Database::remove_entry`].
+    pub fn remove_entry_checked(&mut self, id: EntryId) -> Result<Entry, RemoveEntryError> {
+        let dependencies = self.entry_dependencies(id);
+        if !dependencies.is_empty() {
+            return Err(RemoveEntryError::HasDependencies(dependencies));
+        }
+
+        self.remove_entry(id).ok_or(RemoveEntryError::EntryNotFound(id))
+    }
+
+    /// Remove the entry `id` from the database and record it as deleted, without checking
+    /// whether anything depends on it. The entry is removed permanently instead of being moved
+    /// to the recycle bin.
+    pub fn remove_entry(&mut self, id: EntryId) -> Option<Entry> {
+        if !matches!(
+            find_with_ancestors(&self.root, id.as_uuid()),
+            Some((NodeRef::Entry(_), _))
+        ) {
+            return None;
+        }
+
+        match take_node(&mut self.root, id.as_uuid())? {
+            (Node::Entry(entry), _, _) => {
+                self.deleted_objects.objects.push(DeletedObject {
+                    uuid: entry.uuid,
+                    deletion_time: Times::now(),
+                });
+                Some(entry)
+            }
+            (Node::Group(_), _, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod removal_tests {
+    use super::EntryDependency;
+    use crate::{
+        db::{Database, Entry, Group, LinkField, AUTO_OPEN_GROUP_NAME},
+        error::RemoveEntryError,
+    };
+
+    #[test]
+    fn remove_entry_checked() {
+        let mut db = Database::new(Default::default());
+
+        let target = Entry::new();
+        let target_id = target.id();
+        let mut linked = Entry::new();
+        linked.link_field_to(target_id, LinkField::Password);
+        let linked_id = linked.id();
+
+        let mut auto_open = Group::new(AUTO_OPEN_GROUP_NAME);
+        let child_database = Entry::new();
+        let child_database_id = child_database.id();
+        auto_open.add_child(child_database);
+
+        let mut templates = Group::new("Templates");
+        let template = Entry::new();
+        let template_id = template.id();
+        templates.add_child(template);
+        db.meta.entry_templates_group = Some(templates.uuid);
+
+        db.root.add_child(target);
+        db.root.add_child(linked);
+        db.root.add_child(auto_open);
+        db.root.add_child(templates);
+
+        assert_eq!(
+            db.entry_dependencies(target_id),
+            vec![EntryDependency::ReferencedBy(linked_id)]
+        );
+        assert_eq!(
+            db.entry_dependencies(child_database_id),
+            vec![EntryDependency::AutoOpen]
+        );
+        assert_eq!(
+            db.entry_dependencies(template_id),
+            vec![EntryDependency::Template]
+        );
+
+        assert!(matches!(
+            db.remove_entry_checked(target_id),
+            Err(RemoveEntryError::HasDependencies(d)) if d.len() == 1
+        ));
+        assert!(db.entry_by_id(target_id).is_some());
+
+        // once the referencing entry is gone, the target can be removed as well
+        assert_eq!(db.remove_entry_checked(linked_id).unwrap().id(), linked_id);
+        assert_eq!(db.remove_entry_checked(target_id).unwrap().id(), target_id);
+        assert!(db.entry_by_id(target_id).is_none());
+        assert!(db.deleted_objects.contains(*target_id.as_uuid()));
+        assert!(matches!(
+            db.remove_entry_checked(target_id),
+            Err(RemoveEntryError::EntryNotFound(_))
+        ));
+
+        assert!(db.remove_entry(template_id).is_some());
+    }
+}
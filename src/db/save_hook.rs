@@ -0,0 +1,142 @@
+use std::{borrow::Cow, sync::Arc};
+
+use crate::db::Database;
+
+/// A transformation that is applied to a copy of a database every time it is saved, see
+/// [`Database::add_save_hook`]
+pub type SaveHook = Arc<dyn Fn(&mut Database) + Send + Sync>;
+
+/// The save hooks of a database. They are not part of the data of the database, so they are
+/// ignored when comparing databases.
+#[derive(Clone, Default)]
+pub(crate) struct SaveHooks(Vec<SaveHook>);
+
+impl std::fmt::Debug for SaveHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SaveHooks({})", self.0.len())
+    }
+}
+
+impl PartialEq for SaveHooks {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SaveHooks {}
+
+impl Database {
+    /// Register a transformation that is applied before the database is saved, e.g. to sort tags,
+    /// remove empty fields or protect certain fields according to the policy of an organization.
+    ///
+    /// Hooks run in the order in which they were added, on a copy of the database that is then
+    /// written instead of the database itself, so the database in memory is left unchanged.
+    /// Cloning the database clones its hooks, opening a database starts without any.
+    pub fn add_save_hook(&mut self, hook: impl Fn(&mut Database) + Send + Sync + 'static) {
+        self.save_hooks.0.push(Arc::new(hook));
+    }
+
+    /// Remove all hooks that were added with [`Database::add_save_hook`]
+    pub fn clear_save_hooks(&mut self) {
+        self.save_hooks.0.clear();
+    }
+
+    /// Get the database as it is written when it is saved, with all save hooks applied
+    pub fn with_save_hooks_applied(&self) -> Cow<'_, Database> {
+        if self.save_hooks.0.is_empty() {
+            return Cow::Borrowed(self);
+        }
+
+        let mut db = self.clone();
+        for hook in &self.save_hooks.0 {
+            hook(&mut db);
+        }
+        Cow::Owned(db)
+    }
+}
+
+#[cfg(test)]
+mod save_hook_tests {
+    use crate::db::{Database, Entry, Value};
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut entry = Entry::new();
+        entry.set_title("Entry");
+        entry.tags = vec!["b".into(), "a".into()];
+        entry
+            .fields
+            .insert("Empty".into(), Value::Unprotected(String::new()));
+        entry
+            .fields
+            .insert("PIN".into(), Value::Unprotected("1234".to_string()));
+        db.root.add_child(entry);
+        db
+    }
+
+    fn canonicalize(db: &mut Database) {
+        for entry in db.root.entries_mut() {
+            entry.tags.sort();
+            entry.fields.retain(|_, value| !value.is_empty());
+            if let Some(Value::Unprotected(pin)) = entry.fields.get("PIN").cloned() {
+                entry
+                    .fields
+                    .insert("PIN".into(), Value::Protected(pin.as_bytes().into()));
+            }
+        }
+    }
+
+    #[test]
+    fn save_hooks() {
+        let mut db = database();
+        assert!(matches!(
+            db.with_save_hooks_applied(),
+            std::borrow::Cow::Borrowed(_)
+        ));
+
+        // hooks are not part of the data of the database
+        let without_hooks = db.clone();
+        db.add_save_hook(canonicalize);
+        db.add_save_hook(|db| db.meta.generator = Some("Policy".to_string()));
+        assert_eq!(db, without_hooks);
+
+        let saved = db.with_save_hooks_applied();
+        let entry = saved.root.entries()[0];
+        assert_eq!(entry.tags, vec!["a", "b"]);
+        assert!(entry.get("Empty").is_none());
+        assert!(matches!(entry.fields.get("PIN"), Some(Value::Protected(_))));
+        assert_eq!(saved.meta.generator.as_deref(), Some("Policy"));
+
+        // the database itself is left unchanged
+        assert_eq!(db.root.entries()[0].tags, vec!["b", "a"]);
+
+        db.clear_save_hooks();
+        assert_eq!(db.with_save_hooks_applied().meta.generator, None);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn hooks_run_on_save() {
+        use crate::{
+            config::{DatabaseConfig, KdfConfig},
+            DatabaseKey,
+        };
+
+        let mut db = database();
+        db.config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        };
+        db.add_save_hook(canonicalize);
+
+        let key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        db.save(&mut data, key.clone()).unwrap();
+
+        let reopened = Database::open(&mut data.as_slice(), key).unwrap();
+        let entry = reopened.root.entries()[0];
+        assert_eq!(entry.tags, vec!["a", "b"]);
+        assert_eq!(entry.fields.len(), 2);
+        assert_eq!(entry.get("PIN"), Some("1234"));
+    }
+}
@@ -0,0 +1,287 @@
+use chrono::Duration;
+
+use crate::db::{Database, Entry, Group, Node, Times, ANDROID_APP_URL_SCHEME};
+
+/// What an autofill request is about, as passed to [`Database::rank_for_context`]. All parts
+/// are optional, and entries are ranked by whichever of them they match best.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutofillContext {
+    /// URL of the page that is filled in, e.g. `https://login.example.com/signin`
+    pub url: Option<String>,
+
+    /// Title of the window that is typed into
+    pub window_title: Option<String>,
+
+    /// Package name or bundle identifier of the app that is filled in, e.g. `com.example.app`
+    pub app_id: Option<String>,
+}
+
+/// How an entry matched an [`AutofillContext`], from the strongest to the weakest match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum AutofillMatch {
+    /// A URL of the entry is the URL of the context
+    ExactUrl,
+
+    /// A URL of the entry has the same host as the URL of the context
+    Host,
+
+    /// The entry is associated with the app of the context
+    App,
+
+    /// A URL of the entry is on a parent domain or a subdomain of the host of the context
+    Domain,
+
+    /// The entry can be auto-typed into the window of the context
+    Window,
+}
+
+impl AutofillMatch {
+    /// Points that the match contributes to the score of an entry
+    fn points(self) -> u32 {
+        match self {
+            AutofillMatch::ExactUrl => 100,
+            AutofillMatch::Host => 90,
+            AutofillMatch::App => 90,
+            AutofillMatch::Domain => 70,
+            AutofillMatch::Window => 60,
+        }
+    }
+}
+
+/// An entry that matches an [`AutofillContext`], as returned by [`Database::rank_for_context`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankedEntry<'a> {
+    pub entry: &'a Entry,
+
+    /// The best match of the entry
+    pub matched: AutofillMatch,
+
+    /// Score of the entry, higher is better. It is made up of the points of the match, plus up
+    /// to 20 points for frequent use and up to 10 points for recent use of entries that were
+    /// used before.
+    pub score: u32,
+}
+
+/// Get the lowercase host of a URL, without user info, port and a `www.` prefix. URLs without a
+/// scheme, e.g. `example.com/login`, are accepted as well.
+fn url_host(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host_port.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host_port,
+    };
+
+    let host = host.trim_end_matches('.').to_lowercase();
+    let host = host.strip_prefix("www.").map(str::to_string).unwrap_or(host);
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+fn normalize_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_lowercase()
+}
+
+/// Check whether one host is a subdomain of the other, ignoring matches on top-level domains
+fn is_related_domain(a: &str, b: &str) -> bool {
+    let (shorter, longer) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    shorter.contains('.')
+        && longer
+            .strip_suffix(shorter)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+fn url_match(entry_url: &str, url: &str, host: Option<&str>) -> Option<AutofillMatch> {
+    if normalize_url(entry_url) == normalize_url(url) {
+        return Some(AutofillMatch::ExactUrl);
+    }
+
+    let entry_host = url_host(entry_url)?;
+    let host = host?;
+    if entry_host == host {
+        Some(AutofillMatch::Host)
+    } else if is_related_domain(&entry_host, host) {
+        Some(AutofillMatch::Domain)
+    } else {
+        None
+    }
+}
+
+/// Get the best match of an entry for a context
+fn best_match(entry: &Entry, context: &AutofillContext, host: Option<&str>) -> Option<AutofillMatch> {
+    let mut matches: Vec<AutofillMatch> = Vec::new();
+
+    if let Some(url) = &context.url {
+        let urls = entry
+            .get_url()
+            .into_iter()
+            .chain(entry.additional_urls())
+            .filter(|u| !u.is_empty() && !u.starts_with(ANDROID_APP_URL_SCHEME));
+        matches.extend(urls.filter_map(|u| url_match(u, url, host)));
+    }
+
+    if let Some(app_id) = &context.app_id {
+        if entry.android_apps().contains(&app_id.as_str()) {
+            matches.push(AutofillMatch::App);
+        }
+    }
+
+    if let Some(window_title) = &context.window_title {
+        if entry.autotype_for_window(window_title).is_some() {
+            matches.push(AutofillMatch::Window);
+        }
+    }
+
+    matches.into_iter().min()
+}
+
+/// Points for frequent and recent use of an entry
+fn usage_points(entry: &Entry, now: chrono::NaiveDateTime) -> u32 {
+    // the last access time of entries that were never used is usually their creation time
+    if entry.times.usage_count == 0 {
+        return 0;
+    }
+
+    let frequency = (entry.times.usage_count.min(10) * 2) as u32;
+
+    let recency = match entry.times.get_last_access().map(|t| now - *t) {
+        Some(age) if age < Duration::days(1) => 10,
+        Some(age) if age < Duration::days(7) => 7,
+        Some(age) if age < Duration::days(30) => 4,
+        Some(age) if age < Duration::days(365) => 1,
+        _ => 0,
+    };
+
+    frequency + recency
+}
+
+fn collect_entries<'a>(group: &'a Group, skip: Option<uuid::Uuid>, entries: &mut Vec<&'a Entry>) {
+    for node in &group.children {
+        match node {
+            Node::Entry(e) => entries.push(e),
+            Node::Group(g) if Some(g.uuid) != skip => collect_entries(g, skip, entries),
+            Node::Group(_) => {}
+        }
+    }
+}
+
+impl Database {
+    /// Rank the entries that match an autofill context, best match first.
+    ///
+    /// Entries match by their URLs, including the additional URLs of Keepass2Android, by the
+    /// Android apps they are associated with, and by the windows they can be auto-typed into,
+    /// see [`Entry::autotype_for_window`]. Matching entries are scored by how well they match,
+    /// and by how often and how recently they were used, see [`RankedEntry::score`]. Entries in
+    /// the recycle bin are not included. Ties are ordered by title.
+    pub fn rank_for_context(&self, context: &AutofillContext) -> Vec<RankedEntry<'_>> {
+        let host = context.url.as_deref().and_then(url_host);
+        let now = Times::now();
+
+        let mut entries = Vec::new();
+        collect_entries(&self.root, self.meta.recyclebin_uuid, &mut entries);
+
+        let mut ranked: Vec<RankedEntry> = entries
+            .into_iter()
+            .filter_map(|entry| {
+                let matched = best_match(entry, context, host.as_deref())?;
+                Some(RankedEntry {
+                    entry,
+                    matched,
+                    score: matched.points() + usage_points(entry, now),
+                })
+            })
+            .collect();
+
+        ranked.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.entry.get_title().cmp(&b.entry.get_title()))
+        });
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use chrono::Duration;
+
+    use super::{url_host, AutofillContext, AutofillMatch};
+    use crate::db::{Database, Entry, Group, Times};
+
+    fn entry(title: &str, url: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry.set_title(title);
+        entry.set_url(url);
+        entry
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(
+            url_host("https://user@www.Example.com:8443/login?x=1").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(url_host("example.com/login").as_deref(), Some("example.com"));
+        assert_eq!(url_host("https://"), None);
+    }
+
+    #[test]
+    fn rank_for_context() {
+        let mut db = Database::new(Default::default());
+        db.root
+            .add_child(entry("Exact", "https://login.example.com/signin/"));
+        db.root.add_child(entry("Parent domain", "https://example.com"));
+        db.root.add_child(entry("Other", "https://example.org"));
+        db.root.add_child(entry("Top-level domain", "https://com"));
+
+        let mut used = entry("Same host", "https://login.example.com/");
+        used.times.usage_count = 100;
+        used.times.set_last_access(Times::now() - Duration::hours(1));
+        db.root.add_child(used);
+
+        let mut app = entry("App", "");
+        app.add_android_app("com.example.app");
+        db.root.add_child(app);
+
+        let mut recycle_bin = Group::new("Recycle Bin");
+        recycle_bin.add_child(entry("Deleted", "https://login.example.com/signin"));
+        db.meta.recyclebin_uuid = Some(recycle_bin.uuid);
+        db.root.add_child(recycle_bin);
+
+        let context = AutofillContext {
+            url: Some("https://login.example.com/signin".to_string()),
+            app_id: Some("com.example.app".to_string()),
+            ..Default::default()
+        };
+        let ranked: Vec<(&str, AutofillMatch, u32)> = db
+            .rank_for_context(&context)
+            .iter()
+            .map(|r| (r.entry.get_title().unwrap(), r.matched, r.score))
+            .collect();
+
+        assert_eq!(
+            ranked,
+            vec![
+                ("Same host", AutofillMatch::Host, 120),
+                ("Exact", AutofillMatch::ExactUrl, 100),
+                ("App", AutofillMatch::App, 90),
+                ("Parent domain", AutofillMatch::Domain, 70),
+            ]
+        );
+
+        let context = AutofillContext {
+            window_title: Some("Other - Mozilla Firefox".to_string()),
+            ..Default::default()
+        };
+        let ranked = db.rank_for_context(&context);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].entry.get_title(), Some("Other"));
+        assert_eq!(ranked[0].matched, AutofillMatch::Window);
+    }
+}
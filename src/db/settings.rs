@@ -0,0 +1,216 @@
+use uuid::Uuid;
+
+use crate::{
+    config::CompressionConfig,
+    db::{Color, CustomDataItem, Database, Times, Value},
+};
+
+/// KeePassXC: UUID of the group that is exposed through the Secret Service integration, stored in
+/// the custom data of the metadata
+pub const FDO_SECRETS_EXPOSED_GROUP: &str = "FDO_SECRETS_EXPOSED_GROUP";
+
+/// The settings shown in the "Database Settings" dialog of KeePassXC.
+///
+/// The settings are spread over the metadata, its custom data, the public custom data and the
+/// database configuration. Read them with [`Database::settings`], change the fields and write
+/// them back with [`Database::apply_settings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseSettings {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub default_username: Option<String>,
+    pub color: Option<Color>,
+
+    /// Whether deleted entries and groups are moved to the recycle bin. Databases that do not
+    /// specify this have the recycle bin enabled.
+    pub recycle_bin_enabled: bool,
+
+    /// Maximum number of history items per entry, `None` for no limit
+    pub history_max_items: Option<usize>,
+
+    /// Maximum size of the history per entry in bytes
+    pub history_max_size: Option<usize>,
+
+    /// Number of days that deleted objects and history are kept for
+    pub maintenance_history_days: Option<usize>,
+
+    pub compression: CompressionConfig,
+
+    /// The time in milliseconds that KeePassXC should aim for when choosing KDF parameters
+    pub decryption_time_preference: Option<i32>,
+
+    /// The group that is exposed through the Secret Service integration of KeePassXC
+    pub secret_service_exposed_group: Option<Uuid>,
+
+    /// Whether clients are recommended to open the database read-only, see
+    /// [`Database::read_only_recommended`]
+    pub read_only_recommended: bool,
+}
+
+impl Database {
+    /// Read the current database settings
+    pub fn settings(&self) -> DatabaseSettings {
+        let meta = &self.meta;
+
+        let secret_service_exposed_group = match meta
+            .custom_data
+            .items
+            .get(FDO_SECRETS_EXPOSED_GROUP)
+            .and_then(|item| item.value.as_ref())
+        {
+            Some(Value::Unprotected(v)) => Uuid::parse_str(v).ok(),
+            _ => None,
+        };
+
+        DatabaseSettings {
+            name: meta.database_name.clone(),
+            description: meta.database_description.clone(),
+            default_username: meta.default_username.clone(),
+            color: meta.color,
+            recycle_bin_enabled: meta.recyclebin_enabled.unwrap_or(true),
+            history_max_items: meta.history_max_items,
+            history_max_size: meta.history_max_size,
+            maintenance_history_days: meta.maintenance_history_days,
+            compression: self.config.compression_config.clone(),
+            decryption_time_preference: self.config.public_custom_data.decryption_time_preference(),
+            secret_service_exposed_group,
+            read_only_recommended: self.read_only_recommended(),
+        }
+    }
+
+    /// Whether clients are recommended to open the database read-only and avoid local edits,
+    /// e.g. because it is a reference database that is distributed by an organization.
+    ///
+    /// The flag is stored in the public custom data, which only KDBX4 databases have, see
+    /// [`READ_ONLY_RECOMMENDED`](crate::config::READ_ONLY_RECOMMENDED). It is advisory: this
+    /// crate does not prevent changes to databases that have it set.
+    pub fn read_only_recommended(&self) -> bool {
+        self.config.public_custom_data.read_only_recommended()
+    }
+
+    /// Set whether clients are recommended to open the database read-only, see
+    /// [`Database::read_only_recommended`]
+    pub fn set_read_only_recommended(&mut self, read_only: bool) {
+        self.config
+            .public_custom_data
+            .set_read_only_recommended(read_only)
+    }
+
+    /// Write the given settings to the database, updating the change times of the modified
+    /// settings. Returns whether anything changed.
+    pub fn apply_settings(&mut self, settings: &DatabaseSettings) -> bool {
+        let current = self.settings();
+        if current == *settings {
+            return false;
+        }
+
+        let now = Times::now();
+        let meta = &mut self.meta;
+
+        if current.name != settings.name {
+            meta.database_name = settings.name.clone();
+            meta.database_name_changed = Some(now);
+        }
+
+        if current.description != settings.description {
+            meta.database_description = settings.description.clone();
+            meta.database_description_changed = Some(now);
+        }
+
+        if current.default_username != settings.default_username {
+            meta.default_username = settings.default_username.clone();
+            meta.default_username_changed = Some(now);
+        }
+
+        if current.recycle_bin_enabled != settings.recycle_bin_enabled {
+            meta.recyclebin_enabled = Some(settings.recycle_bin_enabled);
+        }
+
+        meta.color = settings.color;
+        meta.history_max_items = settings.history_max_items;
+        meta.history_max_size = settings.history_max_size;
+        meta.maintenance_history_days = settings.maintenance_history_days;
+
+        if current.secret_service_exposed_group != settings.secret_service_exposed_group {
+            match settings.secret_service_exposed_group {
+                Some(uuid) => {
+                    meta.custom_data.items.insert(
+                        FDO_SECRETS_EXPOSED_GROUP.to_string(),
+                        CustomDataItem {
+                            value: Some(Value::Unprotected(uuid.hyphenated().to_string())),
+                            last_modification_time: Some(now),
+                        },
+                    );
+                }
+                None => {
+                    meta.custom_data.items.remove(FDO_SECRETS_EXPOSED_GROUP);
+                }
+            }
+        }
+
+        meta.settings_changed = Some(now);
+
+        self.config.compression_config = settings.compression.clone();
+
+        if current.decryption_time_preference != settings.decryption_time_preference {
+            let public_custom_data = &mut self.config.public_custom_data;
+            match settings.decryption_time_preference {
+                Some(milliseconds) => public_custom_data.set_decryption_time_preference(milliseconds),
+                None => {
+                    public_custom_data.remove(crate::config::KPXC_DECRYPTION_TIME_PREFERENCE);
+                }
+            }
+        }
+
+        if current.read_only_recommended != settings.read_only_recommended {
+            self.set_read_only_recommended(settings.read_only_recommended);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod settings_tests {
+    use uuid::Uuid;
+
+    use crate::{config::CompressionConfig, db::Database};
+
+    #[test]
+    fn settings_round_trip() {
+        let mut db = Database::new(Default::default());
+        db.meta.settings_changed = None;
+
+        let settings = db.settings();
+        assert!(settings.recycle_bin_enabled);
+        assert!(!db.apply_settings(&settings));
+        assert_eq!(db.meta.settings_changed, None);
+
+        let mut changed = settings.clone();
+        changed.name = Some("Passwords".to_string());
+        changed.recycle_bin_enabled = false;
+        changed.history_max_items = Some(5);
+        changed.compression = CompressionConfig::None;
+        changed.decryption_time_preference = Some(1000);
+        changed.secret_service_exposed_group = Some(Uuid::new_v4());
+        changed.read_only_recommended = true;
+
+        assert!(db.apply_settings(&changed));
+        assert_eq!(db.settings(), changed);
+        assert!(db.meta.settings_changed.is_some());
+        assert!(db.meta.database_name_changed.is_some());
+        assert_eq!(db.meta.recyclebin_enabled, Some(false));
+        assert_eq!(
+            db.config.public_custom_data.decryption_time_preference(),
+            Some(1000)
+        );
+        assert!(db.read_only_recommended());
+
+        changed.decryption_time_preference = None;
+        changed.secret_service_exposed_group = None;
+        changed.read_only_recommended = false;
+        assert!(db.apply_settings(&changed));
+        assert!(db.config.public_custom_data.is_empty());
+        assert!(db.meta.custom_data.items.is_empty());
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+#[cfg(feature = "save_kdbx4")]
+use crate::{config::DatabaseVersion, format::KDBX4_CURRENT_MINOR_VERSION};
+use crate::{
+    db::{
+        tracked::find_group_mut, tracked::take_node, Database, Entry, EntryId, Group, GroupId, Node, NodeRef,
+        Times,
+    },
+    error::SubtreeError,
+    key::DatabaseKey,
+};
+
+/// What to do with imported entries and groups whose UUID already exists in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Do not import the node. For groups, the whole imported subtree is skipped.
+    Skip,
+
+    /// Remove the existing node, including its children for groups, and import the new one
+    Replace,
+
+    /// Import the node with a new random UUID
+    NewUuid,
+}
+
+/// Which UUIDs nodes copied between databases get, see [`Database::copy_entry_from`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyUuids {
+    /// Give every copied entry and group a new random UUID, so that the copy is independent of
+    /// the original
+    New,
+
+    /// Keep the UUIDs of the original nodes, e.g. to move nodes to another database while
+    /// keeping references to them intact. Fails if a UUID already exists in the target database.
+    Keep,
+}
+
+/// Give a node, and everything below it, new random UUIDs. History items keep sharing the UUID
+/// of their entry.
+fn assign_new_uuids(node: &mut Node) {
+    match node {
+        Node::Entry(e) => {
+            e.uuid = Uuid::new_v4();
+            if let Some(history) = e.history.as_mut() {
+                for item in history.entries.iter_mut() {
+                    item.uuid = e.uuid;
+                }
+            }
+        }
+        Node::Group(g) => {
+            g.uuid = Uuid::new_v4();
+            g.children.iter_mut().for_each(assign_new_uuids);
+        }
+    }
+}
+
+/// Call `f` on every entry in the group and its subgroups, including history entries
+fn for_each_entry_version_mut<F: FnMut(&mut Entry)>(group: &mut Group, f: &mut F) {
+    group.for_each_entry_mut(&mut |e| {
+        if let Some(history) = e.history.as_mut() {
+            history.entries.iter_mut().for_each(&mut *f);
+        }
+        f(e);
+    });
+}
+
+/// Copy the attachments referenced by the entries in `group` from the binary pool of `from` to
+/// the binary pool of `to`, updating the references
+pub(crate) fn copy_attachments(group: &mut Group, from: &Database, to: &mut Database) {
+    let mut copied: HashMap<String, String> = HashMap::new();
+
+    for_each_entry_version_mut(group, &mut |entry| {
+        for attachment in entry.attachments.iter_mut() {
+            if let Some(identifier) = copied.get(&attachment.identifier) {
+                attachment.identifier = identifier.clone();
+                continue;
+            }
+
+            if let Some((content, compressed)) = from.binary_content(&attachment.identifier) {
+                let identifier = to.add_binary(content.to_vec(), compressed);
+                copied.insert(attachment.identifier.clone(), identifier.clone());
+                attachment.identifier = identifier;
+            }
+        }
+    });
+}
+
+/// Copy the custom icons used in `group` from the metadata of `from` to `to`, if missing there
+pub(crate) fn copy_custom_icons(group: &Group, from: &Database, to: &mut Database) {
+    let mut used: HashSet<Uuid> = group.custom_icon_uuid.into_iter().collect();
+    for node in group.iter() {
+        match node {
+            NodeRef::Entry(e) => used.extend(e.custom_icon_uuid),
+            NodeRef::Group(g) => used.extend(g.custom_icon_uuid),
+        }
+    }
+
+    for icon in &from.meta.custom_icons.icons {
+        if used.contains(&icon.uuid) && !to.meta.custom_icons.icons.iter().any(|i| i.uuid == icon.uuid) {
+            to.meta.custom_icons.icons.push(icon.clone());
+        }
+    }
+}
+
+/// Apply the collision policy to the children of an imported group, collecting the UUIDs of
+/// existing nodes that need to be replaced
+fn resolve_collisions(
+    group: &mut Group,
+    existing: &HashSet<Uuid>,
+    policy: CollisionPolicy,
+    replaced: &mut Vec<Uuid>,
+) {
+    group.children.retain_mut(|node| {
+        let uuid = match node {
+            Node::Entry(e) => &mut e.uuid,
+            Node::Group(g) => &mut g.uuid,
+        };
+
+        if existing.contains(uuid) {
+            match policy {
+                CollisionPolicy::Skip => return false,
+                CollisionPolicy::Replace => replaced.push(*uuid),
+                CollisionPolicy::NewUuid => *uuid = Uuid::new_v4(),
+            }
+        }
+
+        if let Node::Group(g) = node {
+            resolve_collisions(g, existing, policy, replaced);
+        }
+        true
+    });
+}
+
+impl Database {
+    /// Save the group with the given identifier and everything below it as a standalone KDBX4
+    /// database, e.g. to share a single folder with others.
+    ///
+    /// The group becomes the root group of the new database, which uses the same encryption
+    /// settings as this one. Attachments and custom icons used in the subtree are copied along.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn export_subtree(
+        &self,
+        group: GroupId,
+        destination: &mut dyn std::io::Write,
+        key: DatabaseKey,
+    ) -> Result<(), SubtreeError> {
+        let mut root = self
+            .group_by_id(group)
+            .ok_or(SubtreeError::GroupNotFound(group))?
+            .clone();
+
+        let mut config = self.config.clone();
+        config.public_custom_data = Default::default();
+        // only KDBX4 can be saved
+        config.version = DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION);
+
+        let mut export = Database::new(config);
+        export.meta.database_name = Some(root.name.clone());
+        export.meta.memory_protection = self.meta.memory_protection.clone();
+
+        copy_attachments(&mut root, self, &mut export);
+        copy_custom_icons(&root, self, &mut export);
+        export.root = root;
+
+        export.save(destination, key)?;
+        Ok(())
+    }
+
+    /// Copy an entry of another database, including its history, into the group with the given
+    /// identifier. Attachments and custom icons are copied along. Returns the identifier of the
+    /// copy.
+    pub fn copy_entry_from(
+        &mut self,
+        other: &Database,
+        entry: EntryId,
+        target: GroupId,
+        uuids: CopyUuids,
+    ) -> Result<EntryId, SubtreeError> {
+        let node = Node::Entry(
+            other
+                .entry_by_id(entry)
+                .ok_or(SubtreeError::EntryNotFound(entry))?
+                .clone(),
+        );
+
+        self.copy_node_from(other, node, target, uuids).map(EntryId::from)
+    }
+
+    /// Copy a group of another database, with everything below it, into the group with the
+    /// given identifier. Attachments and custom icons are copied along. Returns the identifier
+    /// of the copy.
+    pub fn copy_group_from(
+        &mut self,
+        other: &Database,
+        group: GroupId,
+        target: GroupId,
+        uuids: CopyUuids,
+    ) -> Result<GroupId, SubtreeError> {
+        let node = Node::Group(
+            other
+                .group_by_id(group)
+                .ok_or(SubtreeError::GroupNotFound(group))?
+                .clone(),
+        );
+
+        self.copy_node_from(other, node, target, uuids).map(GroupId::from)
+    }
+
+    fn copy_node_from(
+        &mut self,
+        other: &Database,
+        node: Node,
+        target: GroupId,
+        uuids: CopyUuids,
+    ) -> Result<Uuid, SubtreeError> {
+        if self.group_by_id(target).is_none() {
+            return Err(SubtreeError::GroupNotFound(target));
+        }
+
+        // wrap the node, so that the helpers for whole subtrees can be used for single entries
+        let mut wrapper = Group::new("");
+        wrapper.children.push(node);
+
+        match uuids {
+            CopyUuids::New => wrapper.children.iter_mut().for_each(assign_new_uuids),
+            CopyUuids::Keep => {
+                let existing: HashSet<Uuid> = self
+                    .root
+                    .iter()
+                    .map(|node| match node {
+                        NodeRef::Entry(e) => e.uuid,
+                        NodeRef::Group(g) => g.uuid,
+                    })
+                    .collect();
+
+                let collision = wrapper
+                    .iter()
+                    .skip(1)
+                    .map(|node| match node {
+                        NodeRef::Entry(e) => e.uuid,
+                        NodeRef::Group(g) => g.uuid,
+                    })
+                    .find(|uuid| existing.contains(uuid));
+                if let Some(uuid) = collision {
+                    return Err(SubtreeError::UuidExists(uuid));
+                }
+            }
+        }
+
+        copy_attachments(&mut wrapper, other, self);
+        copy_custom_icons(&wrapper, other, self);
+
+        let mut node = match wrapper.children.pop() {
+            Some(node) => node,
+            None => return Err(SubtreeError::Skipped),
+        };
+        let uuid = match &mut node {
+            Node::Entry(e) => {
+                e.times.set_location_changed(Times::now());
+                e.uuid
+            }
+            Node::Group(g) => {
+                g.times.set_location_changed(Times::now());
+                g.uuid
+            }
+        };
+
+        find_group_mut(&mut self.root, target.as_uuid())
+            .ok_or(SubtreeError::GroupNotFound(target))?
+            .add_child(node);
+
+        Ok(uuid)
+    }
+
+    /// Open another database and add its root group, with everything below it, as a child of
+    /// the group with the given identifier. Attachments and custom icons are copied along.
+    ///
+    /// `policy` determines what happens to imported nodes whose UUIDs already exist in this
+    /// database. Returns the UUID of the imported root group.
+    pub fn import_subtree(
+        &mut self,
+        parent: GroupId,
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        policy: CollisionPolicy,
+    ) -> Result<Uuid, SubtreeError> {
+        if self.group_by_id(parent).is_none() {
+            return Err(SubtreeError::GroupNotFound(parent));
+        }
+
+        let other = Database::open(source, key)?;
+
+        let existing: HashSet<Uuid> = std::iter::once(self.root.uuid)
+            .chain(self.root.iter().map(|node| match node {
+                NodeRef::Entry(e) => e.uuid,
+                NodeRef::Group(g) => g.uuid,
+            }))
+            .collect();
+
+        // treat the imported root like its own child, so that the policy applies to it as well
+        let mut wrapper = Group::new("");
+        wrapper.children.push(Node::Group(other.root.clone()));
+
+        let mut replaced = Vec::new();
+        resolve_collisions(&mut wrapper, &existing, policy, &mut replaced);
+
+        let mut imported = match wrapper.children.pop() {
+            Some(Node::Group(g)) => g,
+            _ => return Err(SubtreeError::Skipped),
+        };
+
+        let removes_parent = replaced.iter().any(|uuid| {
+            *uuid == self.root.uuid
+                || uuid == parent.as_uuid()
+                || self.group_by_id(GroupId::from(*uuid)).is_some_and(|g| {
+                    g.iter()
+                        .any(|node| matches!(node, NodeRef::Group(c) if c.uuid == *parent.as_uuid()))
+                })
+        });
+        if removes_parent {
+            return Err(SubtreeError::ReplacesParent);
+        }
+        for uuid in &replaced {
+            take_node(&mut self.root, uuid);
+        }
+
+        copy_attachments(&mut imported, &other, self);
+        copy_custom_icons(&imported, &other, self);
+        imported.times.set_location_changed(Times::now());
+
+        let uuid = imported.uuid;
+        find_group_mut(&mut self.root, parent.as_uuid())
+            .ok_or(SubtreeError::ReplacesParent)?
+            .add_child(imported);
+
+        Ok(uuid)
+    }
+}
+
+#[cfg(all(test, feature = "save_kdbx4"))]
+mod subtree_tests {
+    use super::{CollisionPolicy, CopyUuids};
+    use crate::{
+        db::{AttachmentRef, Database, Entry, EntryId, Group, HeaderAttachment, History, Icon},
+        error::SubtreeError,
+        DatabaseKey,
+    };
+
+    fn key() -> DatabaseKey {
+        DatabaseKey::new().with_password("subtree")
+    }
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        db.config.kdf_config = crate::config::KdfConfig::Aes { rounds: 10 };
+
+        let mut entry = Entry::new();
+        entry.attachments.push(AttachmentRef {
+            name: "shared.txt".to_string(),
+            identifier: "1".to_string(),
+        });
+
+        let mut shared = Group::new("Shared");
+        shared.add_child(entry);
+        db.root.add_child(shared);
+        db.root.add_child(Entry::new());
+
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: b"unrelated".to_vec(),
+        });
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: b"shared".to_vec(),
+        });
+        db
+    }
+
+    #[test]
+    fn export_import() {
+        let db = database();
+        let shared = db.root.groups()[0].id();
+
+        let mut buffer = Vec::new();
+        db.export_subtree(shared, &mut buffer, key()).unwrap();
+
+        let exported = Database::open(&mut buffer.as_slice(), key()).unwrap();
+        assert_eq!(exported.root.name, "Shared");
+        assert_eq!(exported.root.entries().len(), 1);
+        assert_eq!(exported.header_attachments.len(), 1);
+        assert_eq!(exported.root.entries()[0].attachments[0].identifier, "0");
+
+        // importing into the original database collides with the existing group
+        let mut target = db.clone();
+        let root = target.root.id();
+        assert!(matches!(
+            target.import_subtree(root, &mut buffer.as_slice(), key(), CollisionPolicy::Skip),
+            Err(SubtreeError::Skipped)
+        ));
+
+        let uuid = target
+            .import_subtree(root, &mut buffer.as_slice(), key(), CollisionPolicy::NewUuid)
+            .unwrap();
+        assert_ne!(uuid, uuid::Uuid::from(shared));
+        assert_eq!(target.root.groups().len(), 2);
+        assert_eq!(target.header_attachments.len(), 3);
+
+        let mut target = db.clone();
+        target
+            .import_subtree(root, &mut buffer.as_slice(), key(), CollisionPolicy::Replace)
+            .unwrap();
+        assert_eq!(target.root.groups().len(), 1);
+        assert_eq!(target.root.groups()[0].id(), shared);
+
+        let imported = target.root.groups()[0].entries()[0];
+        let identifier: usize = imported.attachments[0].identifier.parse().unwrap();
+        assert_eq!(target.header_attachments[identifier].content, b"shared");
+    }
+
+    #[test]
+    fn copy_between_databases() {
+        let mut source = database();
+        let icon = Icon {
+            uuid: uuid::Uuid::new_v4(),
+            data: b"icon".to_vec(),
+        };
+        source.meta.custom_icons.icons.push(icon.clone());
+
+        let shared = source.root.groups()[0].id();
+        let entry_id = {
+            let mut groups = source.root.groups_mut();
+            let entry = groups[0].entries_mut().pop().unwrap();
+            entry.custom_icon_uuid = Some(icon.uuid);
+            let mut history = History::default();
+            history.add_entry(entry.clone());
+            entry.history = Some(history);
+            EntryId::from(entry.uuid)
+        };
+
+        let mut target = Database::new(Default::default());
+        target.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: b"existing".to_vec(),
+        });
+        let root = target.root.id();
+
+        let copy = target
+            .copy_entry_from(&source, entry_id, root, CopyUuids::New)
+            .unwrap();
+        assert_ne!(copy, entry_id);
+        let copied = target.entry_by_id(copy).unwrap();
+        assert_eq!(copied.custom_icon_uuid, Some(icon.uuid));
+        assert_eq!(
+            copied.history.as_ref().unwrap().get_entries()[0].uuid,
+            *copy.as_uuid()
+        );
+        let identifier: usize = copied.attachments[0].identifier.parse().unwrap();
+        assert_eq!(target.header_attachments[identifier].content, b"shared");
+        assert_eq!(target.meta.custom_icons.icons, vec![icon]);
+
+        let group = target
+            .copy_group_from(&source, shared, root, CopyUuids::Keep)
+            .unwrap();
+        assert_eq!(group, shared);
+        let identifier: usize = target.entry_by_id(entry_id).unwrap().attachments[0]
+            .identifier
+            .parse()
+            .unwrap();
+        assert_eq!(target.header_attachments[identifier].content, b"shared");
+        // icons that already exist in the target are not copied again
+        assert_eq!(target.meta.custom_icons.icons.len(), 1);
+
+        // keeping UUIDs that already exist fails
+        assert!(matches!(
+            target.copy_entry_from(&source, entry_id, root, CopyUuids::Keep),
+            Err(SubtreeError::UuidExists(uuid)) if uuid == *entry_id.as_uuid()
+        ));
+        assert!(matches!(
+            target.copy_entry_from(&source, copy, root, CopyUuids::New),
+            Err(SubtreeError::EntryNotFound(_))
+        ));
+    }
+}
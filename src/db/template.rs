@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use crate::db::{CustomDataItem, Entry, EntryId, Group, Times, Value};
+
+/// Prefix of the custom data keys of a group that define the fields of new entries in the group,
+/// followed by the name of the field. The value of the item is the default value of the field.
+pub const TEMPLATE_FIELD_PREFIX: &str = "TemplateField:";
+
+impl Group {
+    /// Get the fields that new entries in this group are created with, mapped to their default
+    /// values
+    pub fn template_fields(&self) -> HashMap<String, String> {
+        self.custom_data
+            .items
+            .iter()
+            .filter_map(|(key, item)| {
+                let field = key.strip_prefix(TEMPLATE_FIELD_PREFIX)?;
+                match item.value.as_ref() {
+                    Some(Value::Unprotected(v)) => Some((field.to_string(), v.clone())),
+                    None => Some((field.to_string(), String::new())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Add a field to the template of this group, with a default value that may be empty
+    pub fn set_template_field(&mut self, field: &str, default: &str) {
+        self.custom_data.items.insert(
+            format!("{}{}", TEMPLATE_FIELD_PREFIX, field),
+            CustomDataItem {
+                value: Some(Value::Unprotected(default.to_string())),
+                last_modification_time: Some(Times::now()),
+            },
+        );
+    }
+
+    /// Remove a field from the template of this group, returning its default value
+    pub fn remove_template_field(&mut self, field: &str) -> Option<String> {
+        match self
+            .custom_data
+            .items
+            .remove(&format!("{}{}", TEMPLATE_FIELD_PREFIX, field))?
+            .value
+        {
+            Some(Value::Unprotected(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Add an entry to this group, adding the fields of the group's template that the entry does
+    /// not have yet with their default values
+    pub fn add_entry_with_defaults(&mut self, mut entry: Entry) -> EntryId {
+        for (field, default) in self.template_fields() {
+            entry
+                .fields
+                .entry(field.into())
+                .or_insert_with(|| Value::Unprotected(default));
+        }
+
+        let id = entry.id();
+        self.add_child(entry);
+        id
+    }
+}
+
+#[cfg(test)]
+mod template_tests {
+    use crate::db::{Entry, Group, Value};
+
+    #[test]
+    fn add_entry_with_defaults() {
+        let mut group = Group::new("Servers");
+        group.set_template_field("Server", "");
+        group.set_template_field("Port", "22");
+        group.set_template_field("Licence key", "");
+        group.remove_template_field("Licence key");
+        assert_eq!(group.template_fields().len(), 2);
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Port".into(), Value::Unprotected("2222".to_string()));
+        let id = group.add_entry_with_defaults(entry);
+
+        let entry = group.entries()[0];
+        assert_eq!(entry.id(), id);
+        assert_eq!(entry.get("Server"), Some(""));
+        assert_eq!(entry.get("Port"), Some("2222"));
+        assert_eq!(entry.get("Licence key"), None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::convert::TryInto;
+
+use base64::{engine::general_purpose as base64_engine, Engine as _};
+use chrono::NaiveDateTime;
+
+use crate::{config::DatabaseVersion, error::XmlParseError, xml_db::get_epoch_baseline};
+
+/// Format of ISO 8601 timestamps in KeePass XML documents
+const ISO8601_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// How timestamps are written in the XML document of a database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimestampFormat {
+    /// ISO 8601 strings in UTC, e.g. `2023-03-14T09:12:44Z`, as used up to KDBX 3.1
+    Iso8601,
+
+    /// Base64 encoded little-endian seconds since 0001-01-01 00:00:00, as used since KDBX 4
+    Base64,
+}
+
+impl TimestampFormat {
+    /// Get the format that KeePass uses for databases of the given version
+    pub fn for_version(version: &DatabaseVersion) -> TimestampFormat {
+        match version {
+            DatabaseVersion::KDB4(_) => TimestampFormat::Base64,
+            _ => TimestampFormat::Iso8601,
+        }
+    }
+}
+
+/// A point in time as stored in a KeePass database, with second precision and in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct Timestamp(NaiveDateTime);
+
+impl Timestamp {
+    pub fn new(time: NaiveDateTime) -> Self {
+        Timestamp(time)
+    }
+
+    pub fn as_naive(&self) -> NaiveDateTime {
+        self.0
+    }
+
+    /// Format the timestamp as ISO 8601 string, as in KDBX 3.1 databases
+    pub fn to_iso8601(&self) -> String {
+        self.0.format(ISO8601_FORMAT).to_string()
+    }
+
+    /// Format the timestamp as Base64 encoded seconds since 0001-01-01 00:00:00, as in KDBX 4
+    /// databases
+    pub fn to_kdbx4_b64(&self) -> String {
+        let seconds = self.0.and_utc().timestamp() - get_epoch_baseline().and_utc().timestamp();
+        base64_engine::STANDARD.encode(seconds.to_le_bytes())
+    }
+
+    /// Format the timestamp for an XML document in the given format
+    pub fn format(&self, format: TimestampFormat) -> String {
+        match format {
+            TimestampFormat::Iso8601 => self.to_iso8601(),
+            TimestampFormat::Base64 => self.to_kdbx4_b64(),
+        }
+    }
+
+    /// Parse an ISO 8601 timestamp, as in KDBX 3.1 databases
+    pub fn from_iso8601(value: &str) -> Result<Self, XmlParseError> {
+        NaiveDateTime::parse_from_str(value, ISO8601_FORMAT)
+            .map(Timestamp)
+            .map_err(|_| XmlParseError::InvalidTimestamp {
+                value: value.to_string(),
+            })
+    }
+
+    /// Parse a Base64 encoded timestamp, as in KDBX 4 databases
+    pub fn from_kdbx4_b64(value: &str) -> Result<Self, XmlParseError> {
+        let invalid = || XmlParseError::InvalidTimestamp {
+            value: value.to_string(),
+        };
+
+        let bytes = base64_engine::STANDARD.decode(value)?;
+        let seconds: [u8; 8] = bytes
+            .get(0..8)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(invalid)?;
+        let seconds = i64::from_le_bytes(seconds);
+        // larger values cannot be represented as a duration, and are far out of range anyway
+        if seconds.unsigned_abs() > (i64::MAX / 1000) as u64 {
+            return Err(invalid());
+        }
+        let time = get_epoch_baseline()
+            .checked_add_signed(chrono::Duration::seconds(seconds))
+            .ok_or_else(invalid)?;
+        Ok(Timestamp(time))
+    }
+
+    /// Parse a timestamp in either format.
+    ///
+    /// KeePass reads both formats regardless of the version of a database, so this is what is
+    /// used when opening databases.
+    pub fn parse(value: &str) -> Result<Self, XmlParseError> {
+        Timestamp::from_iso8601(value).or_else(|_| Timestamp::from_kdbx4_b64(value))
+    }
+}
+
+impl From<NaiveDateTime> for Timestamp {
+    fn from(time: NaiveDateTime) -> Self {
+        Timestamp(time)
+    }
+}
+
+impl From<Timestamp> for NaiveDateTime {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.0
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_iso8601())
+    }
+}
+
+#[cfg(test)]
+mod timestamp_tests {
+    use super::{Timestamp, TimestampFormat};
+    use crate::config::DatabaseVersion;
+
+    #[test]
+    fn formats() {
+        let timestamp = Timestamp::from_iso8601("2023-03-14T09:12:44Z").unwrap();
+        assert_eq!(timestamp.to_iso8601(), "2023-03-14T09:12:44Z");
+
+        let encoded = timestamp.to_kdbx4_b64();
+        assert_eq!(Timestamp::from_kdbx4_b64(&encoded).unwrap(), timestamp);
+        assert_eq!(Timestamp::parse(&encoded).unwrap(), timestamp);
+        assert_eq!(Timestamp::parse("2023-03-14T09:12:44Z").unwrap(), timestamp);
+
+        assert!(Timestamp::from_iso8601(&encoded).is_err());
+        assert!(Timestamp::from_kdbx4_b64("2023-03-14T09:12:44Z").is_err());
+        assert!(Timestamp::from_kdbx4_b64("AAAA").is_err());
+        assert!(Timestamp::from_kdbx4_b64("/////////38=").is_err());
+
+        assert_eq!(
+            TimestampFormat::for_version(&DatabaseVersion::KDB3(1)),
+            TimestampFormat::Iso8601
+        );
+        assert_eq!(timestamp.format(TimestampFormat::Iso8601), "2023-03-14T09:12:44Z");
+        assert_eq!(
+            timestamp.format(TimestampFormat::for_version(&DatabaseVersion::KDB4(1))),
+            encoded
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use uuid::Uuid;
+
+use crate::db::{
+    undo::Operation, undo::UndoStack, Database, Entry, Group, GroupCounts, Node, NodeRef, SearchIndex, Times,
+    Value,
+};
+
+/// A change to a [`TrackedDatabase`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    EntryAdded {
+        uuid: Uuid,
+        parent: Uuid,
+    },
+    GroupAdded {
+        uuid: Uuid,
+        parent: Uuid,
+    },
+
+    EntryRemoved {
+        uuid: Uuid,
+        parent: Uuid,
+    },
+    GroupRemoved {
+        uuid: Uuid,
+        parent: Uuid,
+    },
+
+    EntryMoved {
+        uuid: Uuid,
+        from: Uuid,
+        to: Uuid,
+    },
+    GroupMoved {
+        uuid: Uuid,
+        from: Uuid,
+        to: Uuid,
+    },
+
+    /// A string field of an entry was added, changed or removed
+    FieldChanged {
+        entry: Uuid,
+        field: String,
+    },
+
+    /// Something other than the string fields of an entry was changed
+    EntryModified {
+        uuid: Uuid,
+    },
+
+    /// The properties of a group were changed
+    GroupModified {
+        uuid: Uuid,
+    },
+}
+
+/// Identifies an observer registered with [`TrackedDatabase::subscribe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(usize);
+
+type Observer = Box<dyn FnMut(&ChangeEvent) + Send>;
+
+/// A database that notifies observers about every change made through it.
+///
+/// This allows user interfaces to update only the affected parts of their views instead of
+/// comparing the whole tree after every change. Changes made to the database directly, e.g.
+/// through [`TrackedDatabase::into_inner`], are not reported.
+pub struct TrackedDatabase {
+    pub(crate) db: Database,
+    observers: Vec<(ObserverId, Observer)>,
+    next_observer_id: usize,
+    pub(crate) undo: Option<UndoStack>,
+    pub(crate) index: Option<SearchIndex>,
+    pub(crate) counts: Option<GroupCounts>,
+    dirty: bool,
+}
+
+impl std::fmt::Debug for TrackedDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedDatabase")
+            .field("db", &self.db)
+            .field("observers", &self.observers.len())
+            .field("undo", &self.undo)
+            .field("index", &self.index.as_ref().map(SearchIndex::len))
+            .field("counts", &self.counts.as_ref().map(GroupCounts::total_entries))
+            .field("dirty", &self.dirty)
+            .finish()
+    }
+}
+
+pub(crate) fn find_group_mut<'a>(group: &'a mut Group, uuid: &Uuid) -> Option<&'a mut Group> {
+    if group.uuid == *uuid {
+        return Some(group);
+    }
+
+    group.children.iter_mut().find_map(|node| match node {
+        Node::Group(g) => find_group_mut(g, uuid),
+        Node::Entry(_) => None,
+    })
+}
+
+pub(crate) fn find_entry_mut<'a>(group: &'a mut Group, uuid: &Uuid) -> Option<&'a mut Entry> {
+    group.children.iter_mut().find_map(|node| match node {
+        Node::Entry(e) if e.uuid == *uuid => Some(e),
+        Node::Entry(_) => None,
+        Node::Group(g) => find_entry_mut(g, uuid),
+    })
+}
+
+/// Find the node with the given UUID below `group`, along with the groups from `group` down to
+/// its parent
+pub(crate) fn find_with_ancestors<'a>(group: &'a Group, uuid: &Uuid) -> Option<(NodeRef<'a>, Vec<&'a Group>)> {
+    for node in &group.children {
+        let found = match node {
+            Node::Entry(e) if e.uuid == *uuid => Some((NodeRef::Entry(e), Vec::new())),
+            Node::Group(g) if g.uuid == *uuid => Some((NodeRef::Group(g), Vec::new())),
+            Node::Group(g) => find_with_ancestors(g, uuid),
+            Node::Entry(_) => None,
+        };
+
+        if let Some((node, mut ancestors)) = found {
+            ancestors.insert(0, group);
+            return Some((node, ancestors));
+        }
+    }
+
+    None
+}
+
+/// Remove the node with the given UUID from the tree, returning it along with the UUID of the
+/// group it was removed from and its position in that group
+pub(crate) fn take_node(group: &mut Group, uuid: &Uuid) -> Option<(Node, Uuid, usize)> {
+    let position = group.children.iter().position(|node| match node {
+        Node::Entry(e) => e.uuid == *uuid,
+        Node::Group(g) => g.uuid == *uuid,
+    });
+
+    if let Some(position) = position {
+        return Some((group.children.remove(position), group.uuid, position));
+    }
+
+    group.children.iter_mut().find_map(|node| match node {
+        Node::Group(g) => take_node(g, uuid),
+        Node::Entry(_) => None,
+    })
+}
+
+/// Get the events describing how an entry changed: a [`ChangeEvent::FieldChanged`] for every
+/// string field that changed, and a [`ChangeEvent::EntryModified`] if anything else changed
+pub(crate) fn entry_change_events(before: &Entry, after: &Entry) -> Vec<ChangeEvent> {
+    let mut changed_fields: Vec<String> = after
+        .fields
+        .iter()
+        .filter(|(k, v)| before.fields.get(*k) != Some(v))
+        .map(|(k, _)| k.to_string())
+        .chain(
+            before
+                .fields
+                .keys()
+                .filter(|k| !after.fields.contains_key(*k))
+                .map(|k| k.to_string()),
+        )
+        .collect();
+    changed_fields.sort();
+
+    let mut events: Vec<ChangeEvent> = changed_fields
+        .into_iter()
+        .map(|field| ChangeEvent::FieldChanged {
+            entry: after.uuid,
+            field,
+        })
+        .collect();
+
+    let mut other_changes = after.clone();
+    other_changes.fields = before.fields.clone();
+    if other_changes != *before {
+        events.push(ChangeEvent::EntryModified { uuid: after.uuid });
+    }
+
+    events
+}
+
+impl TrackedDatabase {
+    pub fn new(db: Database) -> Self {
+        TrackedDatabase {
+            db,
+            observers: Vec::new(),
+            next_observer_id: 0,
+            undo: None,
+            index: None,
+            counts: None,
+            dirty: false,
+        }
+    }
+
+    /// Read access to the tracked database
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Stop tracking changes and return the database
+    pub fn into_inner(self) -> Database {
+        self.db
+    }
+
+    /// Register a callback that is called with every change to the database
+    pub fn subscribe<F: FnMut(&ChangeEvent) + Send + 'static>(&mut self, observer: F) -> ObserverId {
+        let id = ObserverId(self.next_observer_id);
+        self.next_observer_id += 1;
+        self.observers.push((id, Box::new(observer)));
+        id
+    }
+
+    /// Remove a previously registered callback, returning whether it was registered
+    pub fn unsubscribe(&mut self, id: ObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|(i, _)| *i != id);
+        self.observers.len() != len
+    }
+
+    /// Whether the database was changed since the tracking started or since it was last marked
+    /// as clean, e.g. by [`TrackedDatabase::save_if_dirty`]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Mark the database as unchanged, e.g. after it has been saved through other means
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    /// Mark the database as changed, e.g. after a background save failed
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Save the database to a storage backend if it was changed since it was last saved,
+    /// returning whether it was saved
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_if_dirty(
+        &mut self,
+        storage: &mut dyn crate::storage::Storage,
+        key: crate::DatabaseKey,
+    ) -> Result<bool, crate::error::DatabaseSaveError> {
+        if !self.dirty {
+            return Ok(false);
+        }
+
+        self.db.save_to(storage, key)?;
+        self.dirty = false;
+        Ok(true)
+    }
+
+    /// Hand a snapshot of the database to an [`AutoSaver`](crate::storage::AutoSaver) if it was
+    /// changed since it was last saved, returning whether a save was scheduled.
+    ///
+    /// The database is marked as clean right away. Errors of the background save are reported
+    /// by [`AutoSaver::flush`](crate::storage::AutoSaver::flush) and
+    /// [`AutoSaver::take_error`](crate::storage::AutoSaver::take_error).
+    #[cfg(feature = "save_kdbx4")]
+    pub fn schedule_save(&mut self, saver: &crate::storage::AutoSaver) -> bool {
+        if !self.dirty {
+            return false;
+        }
+
+        saver.schedule(&self.db);
+        self.dirty = false;
+        true
+    }
+
+    pub(crate) fn emit(&mut self, event: ChangeEvent) {
+        self.dirty = true;
+        if let Some(index) = &mut self.index {
+            index.apply(&self.db, &event);
+        }
+        if let Some(counts) = &mut self.counts {
+            counts.apply(&self.db, &event);
+        }
+        for (_, observer) in self.observers.iter_mut() {
+            observer(&event);
+        }
+    }
+
+    /// Add an entry to the group with UUID `parent`, returning `None` if there is no such group
+    pub fn add_entry(&mut self, parent: Uuid, entry: Entry) -> Option<Uuid> {
+        let uuid = entry.uuid;
+        find_group_mut(&mut self.db.root, &parent)?.add_child(entry);
+
+        self.record(Operation::Remove { uuid });
+        self.emit(ChangeEvent::EntryAdded { uuid, parent });
+        Some(uuid)
+    }
+
+    /// Add a group to the group with UUID `parent`, returning `None` if there is no such group
+    pub fn add_group(&mut self, parent: Uuid, group: Group) -> Option<Uuid> {
+        let uuid = group.uuid;
+        find_group_mut(&mut self.db.root, &parent)?.add_child(group);
+
+        self.record(Operation::Remove { uuid });
+        self.emit(ChangeEvent::GroupAdded { uuid, parent });
+        Some(uuid)
+    }
+
+    /// Set a string field of an entry, returning `None` if there is no such entry
+    pub fn set_field(&mut self, entry: Uuid, field: &str, value: Value) -> Option<()> {
+        let e = find_entry_mut(&mut self.db.root, &entry)?;
+        let before = e.clone();
+        e.fields.insert(field.into(), value);
+        e.times.set_last_modification(Times::now());
+
+        self.record(Operation::ReplaceEntry(before));
+        self.emit(ChangeEvent::FieldChanged {
+            entry,
+            field: field.to_string(),
+        });
+        Some(())
+    }
+
+    /// Remove a string field of an entry, returning its previous value
+    pub fn remove_field(&mut self, entry: Uuid, field: &str) -> Option<Value> {
+        let e = find_entry_mut(&mut self.db.root, &entry)?;
+        let before = e.clone();
+        let value = e.fields.remove(field)?;
+        e.times.set_last_modification(Times::now());
+
+        self.record(Operation::ReplaceEntry(before));
+        self.emit(ChangeEvent::FieldChanged {
+            entry,
+            field: field.to_string(),
+        });
+        Some(value)
+    }
+
+    /// Modify an entry with a closure. A [`ChangeEvent::FieldChanged`] is emitted for every
+    /// string field that changed, and a [`ChangeEvent::EntryModified`] if anything else changed.
+    pub fn modify_entry<F: FnOnce(&mut Entry)>(&mut self, uuid: Uuid, f: F) -> Option<()> {
+        let entry = find_entry_mut(&mut self.db.root, &uuid)?;
+        let before = entry.clone();
+        f(entry);
+
+        let events = entry_change_events(&before, entry);
+        if !events.is_empty() {
+            self.record(Operation::ReplaceEntry(before));
+        }
+        for event in events {
+            self.emit(event);
+        }
+
+        Some(())
+    }
+
+    /// Modify the properties of a group with a closure.
+    ///
+    /// Children should be added, removed and moved through the respective methods of the
+    /// `TrackedDatabase` instead, so that the changes are reported properly.
+    pub fn modify_group<F: FnOnce(&mut Group)>(&mut self, uuid: Uuid, f: F) -> Option<()> {
+        let group = find_group_mut(&mut self.db.root, &uuid)?;
+        let mut before = group.clone();
+        before.children.clear();
+        f(group);
+
+        self.record(Operation::ReplaceGroup(before));
+        self.emit(ChangeEvent::GroupModified { uuid });
+        Some(())
+    }
+
+    /// Remove an entry or group from the database, returning it
+    pub fn remove(&mut self, uuid: Uuid) -> Option<Node> {
+        let (node, parent, index) = take_node(&mut self.db.root, &uuid)?;
+
+        self.record(Operation::Insert {
+            parent,
+            index,
+            node: node.clone(),
+        });
+        self.emit(match node {
+            Node::Entry(_) => ChangeEvent::EntryRemoved { uuid, parent },
+            Node::Group(_) => ChangeEvent::GroupRemoved { uuid, parent },
+        });
+        Some(node)
+    }
+
+    /// Move an entry or group into the group with UUID `to`.
+    ///
+    /// Returns `None` without changing the database if either node does not exist, or if a group
+    /// would be moved into itself or one of its descendants.
+    pub fn move_node(&mut self, uuid: Uuid, to: Uuid) -> Option<()> {
+        // make sure that the target exists and is not inside the moved node
+        if let Some(moved_group) = find_group_mut(&mut self.db.root, &uuid) {
+            if find_group_mut(moved_group, &to).is_some() {
+                return None;
+            }
+        }
+        find_group_mut(&mut self.db.root, &to)?;
+
+        let (mut node, from, index) = take_node(&mut self.db.root, &uuid)?;
+
+        match &mut node {
+            Node::Entry(e) => e.times.set_location_changed(Times::now()),
+            Node::Group(g) => g.times.set_location_changed(Times::now()),
+        }
+        let is_entry = matches!(node, Node::Entry(_));
+
+        find_group_mut(&mut self.db.root, &to)?.add_child(node);
+
+        self.record(Operation::Move {
+            uuid,
+            to: from,
+            index,
+        });
+        self.emit(if is_entry {
+            ChangeEvent::EntryMoved { uuid, from, to }
+        } else {
+            ChangeEvent::GroupMoved { uuid, from, to }
+        });
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tracked_tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{ChangeEvent, TrackedDatabase};
+    use crate::db::{Database, Entry, Group, Value};
+
+    fn tracked() -> (TrackedDatabase, Arc<Mutex<Vec<ChangeEvent>>>) {
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = events.clone();
+        db.subscribe(move |e| sink.lock().unwrap().push(e.clone()));
+
+        (db, events)
+    }
+
+    #[test]
+    fn tracked_changes() {
+        let (mut db, events) = tracked();
+        let root = db.database().root.uuid;
+
+        let group = db.add_group(root, Group::new("Sub")).unwrap();
+        let entry = db.add_entry(root, Entry::new()).unwrap();
+        db.set_field(entry, "Title", Value::Unprotected("Title".to_string()))
+            .unwrap();
+        db.move_node(entry, group).unwrap();
+        db.modify_entry(entry, |e| {
+            e.fields
+                .insert("UserName".into(), Value::Unprotected("user".to_string()));
+            e.tags.push("tag".into());
+        })
+        .unwrap();
+        assert!(db.move_node(group, group).is_none());
+        db.remove(group).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ChangeEvent::GroupAdded {
+                    uuid: group,
+                    parent: root
+                },
+                ChangeEvent::EntryAdded {
+                    uuid: entry,
+                    parent: root
+                },
+                ChangeEvent::FieldChanged {
+                    entry,
+                    field: "Title".to_string()
+                },
+                ChangeEvent::EntryMoved {
+                    uuid: entry,
+                    from: root,
+                    to: group
+                },
+                ChangeEvent::FieldChanged {
+                    entry,
+                    field: "UserName".to_string()
+                },
+                ChangeEvent::EntryModified { uuid: entry },
+                ChangeEvent::GroupRemoved {
+                    uuid: group,
+                    parent: root
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unsubscribe() {
+        let (mut db, events) = tracked();
+        let root = db.database().root.uuid;
+
+        let id = db.subscribe(|_| {});
+        assert!(db.unsubscribe(id));
+        assert!(!db.unsubscribe(id));
+
+        assert!(db.add_entry(uuid::Uuid::new_v4(), Entry::new()).is_none());
+        assert!(events.lock().unwrap().is_empty());
+
+        db.add_entry(root, Entry::new()).unwrap();
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+}
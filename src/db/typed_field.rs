@@ -0,0 +1,150 @@
+use chrono::NaiveDate;
+
+use crate::db::{Entry, Times, Value};
+
+/// Field metadata attribute that stores the type of a typed field, see
+/// [`Entry::field_metadata`]
+pub const FIELD_TYPE_ATTRIBUTE: &str = "Type";
+
+/// Format of date fields, e.g. `2024-12-31`
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The type of a custom field that stores a number, boolean or date as a string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    /// A whole number in decimal notation, e.g. `-42`
+    Integer,
+
+    /// `true` or `false`
+    Boolean,
+
+    /// A date in ISO 8601 notation, e.g. `2024-12-31`
+    Date,
+}
+
+impl FieldType {
+    /// The name of the type as stored in the field metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Integer => "integer",
+            FieldType::Boolean => "boolean",
+            FieldType::Date => "date",
+        }
+    }
+
+    /// Get a type by the name that is stored in the field metadata
+    pub fn parse(name: &str) -> Option<FieldType> {
+        match name {
+            "integer" => Some(FieldType::Integer),
+            "boolean" => Some(FieldType::Boolean),
+            "date" => Some(FieldType::Date),
+            _ => None,
+        }
+    }
+}
+
+impl Value {
+    /// A value that stores a whole number in its canonical string form
+    pub fn integer(value: i64) -> Value {
+        Value::Unprotected(value.to_string())
+    }
+
+    /// A value that stores a boolean as `true` or `false`
+    pub fn boolean(value: bool) -> Value {
+        Value::Unprotected(value.to_string())
+    }
+
+    /// A value that stores a date in ISO 8601 notation, e.g. `2024-12-31`
+    pub fn date(value: NaiveDate) -> Value {
+        Value::Unprotected(value.format(DATE_FORMAT).to_string())
+    }
+}
+
+impl Entry {
+    /// Get the type of a typed field, as set by [`Entry::set_integer`], [`Entry::set_boolean`]
+    /// or [`Entry::set_date`]
+    pub fn field_type(&self, field: &str) -> Option<FieldType> {
+        FieldType::parse(self.get_field_metadata(field, FIELD_TYPE_ATTRIBUTE)?)
+    }
+
+    fn set_typed(&mut self, field: &str, value: Value, field_type: FieldType) {
+        self.fields.insert(field.into(), value);
+        self.set_field_metadata(field, FIELD_TYPE_ATTRIBUTE, field_type.as_str());
+        self.times.set_last_modification(Times::now());
+    }
+
+    /// Store a whole number in the field `field`, and mark the field as [`FieldType::Integer`]
+    pub fn set_integer(&mut self, field: &str, value: i64) {
+        self.set_typed(field, Value::integer(value), FieldType::Integer)
+    }
+
+    /// Store a boolean in the field `field`, and mark the field as [`FieldType::Boolean`]
+    pub fn set_boolean(&mut self, field: &str, value: bool) {
+        self.set_typed(field, Value::boolean(value), FieldType::Boolean)
+    }
+
+    /// Store a date in the field `field`, and mark the field as [`FieldType::Date`]
+    pub fn set_date(&mut self, field: &str, value: NaiveDate) {
+        self.set_typed(field, Value::date(value), FieldType::Date)
+    }
+
+    /// Read the field `field` as a whole number. Surrounding whitespace is ignored, since the
+    /// field might have been edited by hand in another client. Returns `None` if the field does
+    /// not exist or does not contain a number, regardless of its type.
+    pub fn get_integer(&self, field: &str) -> Option<i64> {
+        self.get(field)?.trim().parse().ok()
+    }
+
+    /// Read the field `field` as a boolean, accepting `true` and `false` in any case
+    pub fn get_boolean(&self, field: &str) -> Option<bool> {
+        match self.get(field)?.trim().to_lowercase().as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Read the field `field` as a date in ISO 8601 notation
+    pub fn get_date(&self, field: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(self.get(field)?.trim(), DATE_FORMAT).ok()
+    }
+}
+
+#[cfg(test)]
+mod typed_field_tests {
+    use chrono::NaiveDate;
+
+    use super::FieldType;
+    use crate::db::{Entry, Value};
+
+    #[test]
+    fn typed_fields() {
+        let mut entry = Entry::new();
+        let expiry = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        entry.set_integer("Port", 8443);
+        entry.set_boolean("Enabled", true);
+        entry.set_date("Expires", expiry);
+
+        assert_eq!(entry.get("Port"), Some("8443"));
+        assert_eq!(entry.get("Expires"), Some("2024-12-31"));
+        assert_eq!(entry.get_integer("Port"), Some(8443));
+        assert_eq!(entry.get_boolean("Enabled"), Some(true));
+        assert_eq!(entry.get_date("Expires"), Some(expiry));
+
+        assert_eq!(entry.field_type("Port"), Some(FieldType::Integer));
+        assert_eq!(entry.field_type("Enabled"), Some(FieldType::Boolean));
+        assert_eq!(entry.field_type("Expires"), Some(FieldType::Date));
+
+        // plain string fields can be read as well
+        entry
+            .fields
+            .insert("Retries".into(), Value::Unprotected(" 3 ".to_string()));
+        assert_eq!(entry.get_integer("Retries"), Some(3));
+        assert_eq!(entry.field_type("Retries"), None);
+        assert_eq!(entry.get_integer("Expires"), None);
+        assert_eq!(entry.get_boolean("Port"), None);
+        assert_eq!(entry.get_date("Missing"), None);
+
+        assert_eq!(Value::integer(-42), Value::Unprotected("-42".to_string()));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::VecDeque;
+
+use uuid::Uuid;
+
+use crate::db::{
+    tracked::{entry_change_events, find_entry_mut, find_group_mut, take_node},
+    ChangeEvent, Entry, Group, Node, TrackedDatabase,
+};
+
+/// A change to the tree that can be applied to a [`TrackedDatabase`], yielding the operation that
+/// reverts it
+#[derive(Debug, Clone)]
+pub(crate) enum Operation {
+    /// Insert a node into a group at a position
+    Insert { parent: Uuid, index: usize, node: Node },
+
+    /// Remove a node from the tree
+    Remove { uuid: Uuid },
+
+    /// Move a node into a group at a position
+    Move { uuid: Uuid, to: Uuid, index: usize },
+
+    /// Replace the entry with the same UUID
+    ReplaceEntry(Entry),
+
+    /// Replace the properties of the group with the same UUID, keeping its children
+    ReplaceGroup(Group),
+}
+
+/// Bounded stacks of operations that revert recent changes, and operations that redo reverted ones
+#[derive(Debug)]
+pub(crate) struct UndoStack {
+    undo: VecDeque<Operation>,
+    redo: Vec<Operation>,
+    max_depth: usize,
+}
+
+fn insert_at(group: &mut Group, index: usize, node: Node) {
+    let index = index.min(group.children.len());
+    group.children.insert(index, node);
+}
+
+impl TrackedDatabase {
+    /// Start recording changes so that they can be reverted with [`TrackedDatabase::undo`].
+    ///
+    /// At most `max_depth` changes are remembered, older ones are discarded. Calling this again
+    /// clears the recorded changes.
+    pub fn enable_undo(&mut self, max_depth: usize) {
+        self.undo = Some(UndoStack {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            max_depth,
+        });
+    }
+
+    /// Stop recording changes and discard the recorded ones
+    pub fn disable_undo(&mut self) {
+        self.undo = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        matches!(&self.undo, Some(stack) if !stack.undo.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        matches!(&self.undo, Some(stack) if !stack.redo.is_empty())
+    }
+
+    /// Revert the most recent change, returning whether it was reverted. If the change refers
+    /// to nodes that no longer exist, it is kept so that it can be retried.
+    ///
+    /// Observers are notified about the changes made by reverting, as for any other change.
+    pub fn undo(&mut self) -> bool {
+        let operation = match self.undo.as_mut().and_then(|stack| stack.undo.pop_back()) {
+            Some(operation) => operation,
+            None => return false,
+        };
+
+        match self.apply(operation.clone()) {
+            Some(inverse) => {
+                if let Some(stack) = self.undo.as_mut() {
+                    stack.redo.push(inverse);
+                }
+                true
+            }
+            None => {
+                if let Some(stack) = self.undo.as_mut() {
+                    stack.undo.push_back(operation);
+                }
+                false
+            }
+        }
+    }
+
+    /// Apply the most recently reverted change again, returning whether it was applied. If the
+    /// change refers to nodes that no longer exist, it is kept so that it can be retried.
+    pub fn redo(&mut self) -> bool {
+        let operation = match self.undo.as_mut().and_then(|stack| stack.redo.pop()) {
+            Some(operation) => operation,
+            None => return false,
+        };
+
+        match self.apply(operation.clone()) {
+            Some(inverse) => {
+                if let Some(stack) = self.undo.as_mut() {
+                    stack.undo.push_back(inverse);
+                }
+                true
+            }
+            None => {
+                if let Some(stack) = self.undo.as_mut() {
+                    stack.redo.push(operation);
+                }
+                false
+            }
+        }
+    }
+
+    /// Remember the inverse of a change that was just made
+    pub(crate) fn record(&mut self, inverse: Operation) {
+        if let Some(stack) = self.undo.as_mut() {
+            stack.redo.clear();
+            stack.undo.push_back(inverse);
+            while stack.undo.len() > stack.max_depth {
+                stack.undo.pop_front();
+            }
+        }
+    }
+
+    /// Apply an operation to the tree, returning its inverse, or `None` if it refers to nodes that
+    /// do not exist
+    fn apply(&mut self, operation: Operation) -> Option<Operation> {
+        match operation {
+            Operation::Insert { parent, index, node } => {
+                let event = match &node {
+                    Node::Entry(e) => ChangeEvent::EntryAdded { uuid: e.uuid, parent },
+                    Node::Group(g) => ChangeEvent::GroupAdded { uuid: g.uuid, parent },
+                };
+                let uuid = match &node {
+                    Node::Entry(e) => e.uuid,
+                    Node::Group(g) => g.uuid,
+                };
+
+                insert_at(find_group_mut(&mut self.db.root, &parent)?, index, node);
+
+                self.emit(event);
+                Some(Operation::Remove { uuid })
+            }
+
+            Operation::Remove { uuid } => {
+                let (node, parent, index) = take_node(&mut self.db.root, &uuid)?;
+
+                self.emit(match node {
+                    Node::Entry(_) => ChangeEvent::EntryRemoved { uuid, parent },
+                    Node::Group(_) => ChangeEvent::GroupRemoved { uuid, parent },
+                });
+                Some(Operation::Insert { parent, index, node })
+            }
+
+            Operation::Move { uuid, to, index } => {
+                find_group_mut(&mut self.db.root, &to)?;
+                let (node, from, from_index) = take_node(&mut self.db.root, &uuid)?;
+                let is_entry = matches!(node, Node::Entry(_));
+
+                insert_at(find_group_mut(&mut self.db.root, &to)?, index, node);
+
+                self.emit(if is_entry {
+                    ChangeEvent::EntryMoved { uuid, from, to }
+                } else {
+                    ChangeEvent::GroupMoved { uuid, from, to }
+                });
+                Some(Operation::Move {
+                    uuid,
+                    to: from,
+                    index: from_index,
+                })
+            }
+
+            Operation::ReplaceEntry(entry) => {
+                let target = find_entry_mut(&mut self.db.root, &entry.uuid)?;
+                let before = std::mem::replace(target, entry);
+
+                for event in entry_change_events(&before, target) {
+                    self.emit(event);
+                }
+                Some(Operation::ReplaceEntry(before))
+            }
+
+            Operation::ReplaceGroup(mut group) => {
+                let uuid = group.uuid;
+                let target = find_group_mut(&mut self.db.root, &uuid)?;
+                group.children = std::mem::take(&mut target.children);
+                let before = std::mem::replace(target, group);
+
+                self.emit(ChangeEvent::GroupModified { uuid });
+                Some(Operation::ReplaceGroup(before))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod undo_tests {
+    use crate::db::{Database, Entry, Group, Node, TrackedDatabase, Value};
+
+    fn fields(db: &TrackedDatabase) -> Vec<(usize, Option<String>)> {
+        db.database()
+            .root
+            .children
+            .iter()
+            .map(|node| match node {
+                Node::Entry(e) => (0, e.get_title().map(|t| t.to_string())),
+                Node::Group(g) => (g.children.len(), Some(g.name.clone())),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let root = db.database().root.uuid;
+        db.enable_undo(10);
+        assert!(!db.can_undo());
+
+        let group = db.add_group(root, Group::new("Sub")).unwrap();
+        let entry = db.add_entry(root, Entry::new()).unwrap();
+        db.set_field(entry, "Title", Value::Unprotected("A".to_string()))
+            .unwrap();
+        db.move_node(entry, group).unwrap();
+        db.modify_group(group, |g| g.name = "Renamed".to_string())
+            .unwrap();
+
+        let after = fields(&db);
+        assert_eq!(after, vec![(1, Some("Renamed".to_string()))]);
+
+        assert!(db.undo());
+        assert_eq!(fields(&db), vec![(1, Some("Sub".to_string()))]);
+        assert!(db.undo());
+        assert_eq!(
+            fields(&db),
+            vec![(0, Some("Sub".to_string())), (0, Some("A".to_string()))]
+        );
+        assert!(db.undo());
+        assert_eq!(fields(&db), vec![(0, Some("Sub".to_string())), (0, None)]);
+
+        assert!(db.redo());
+        assert!(db.redo());
+        assert!(db.redo());
+        assert!(!db.redo());
+        assert_eq!(fields(&db), after);
+
+        assert!(db.undo());
+        db.remove(group).unwrap();
+        assert!(!db.can_redo());
+        assert!(db.undo());
+        assert_eq!(fields(&db), vec![(1, Some("Sub".to_string()))]);
+    }
+
+    #[test]
+    fn failed_undo_keeps_operation() {
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let root = db.database().root.uuid;
+        db.enable_undo(10);
+
+        let group = db.add_group(root, Group::new("Sub")).unwrap();
+        db.add_entry(group, Entry::new()).unwrap();
+
+        // the entry to remove is missing from the tree
+        let group_node = db.db.root.children.pop().unwrap();
+        assert!(!db.undo());
+        assert!(db.can_undo());
+
+        db.db.root.children.push(group_node);
+        assert!(db.undo());
+        assert_eq!(fields(&db), vec![(0, Some("Sub".to_string()))]);
+
+        // the group to insert the entry into is missing from the tree
+        let group_node = db.db.root.children.pop().unwrap();
+        assert!(!db.redo());
+        assert!(db.can_redo());
+
+        db.db.root.children.push(group_node);
+        assert!(db.redo());
+        assert_eq!(fields(&db), vec![(1, Some("Sub".to_string()))]);
+    }
+
+    #[test]
+    fn max_depth() {
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let root = db.database().root.uuid;
+        db.enable_undo(2);
+
+        for _ in 0..3 {
+            db.add_entry(root, Entry::new()).unwrap();
+        }
+
+        assert!(db.undo());
+        assert!(db.undo());
+        assert!(!db.undo());
+        assert_eq!(db.database().root.children.len(), 1);
+    }
+}
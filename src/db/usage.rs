@@ -0,0 +1,138 @@
+use std::cmp::Ordering;
+
+use chrono::NaiveDateTime;
+
+use crate::db::{tracked::find_entry_mut, Database, Entry, EntryId, NodeRef, Times};
+
+impl Entry {
+    /// Record that the entry was used, e.g. because its password was copied, by incrementing the
+    /// usage count and updating the last access time
+    pub fn touch_usage(&mut self) {
+        self.times.usage_count += 1;
+        self.times.set_last_access(Times::now());
+    }
+}
+
+/// Get the `n` greatest entries according to `cmp`, sorted in descending order.
+///
+/// Only the selected entries are sorted, so this is linear in the number of entries for small
+/// `n`.
+fn top_entries<'a>(
+    mut entries: Vec<&'a Entry>,
+    n: usize,
+    cmp: impl Fn(&&'a Entry, &&'a Entry) -> Ordering,
+) -> Vec<&'a Entry> {
+    let descending = |a: &&'a Entry, b: &&'a Entry| cmp(b, a);
+
+    if n < entries.len() {
+        if n == 0 {
+            return Vec::new();
+        }
+        entries.select_nth_unstable_by(n - 1, descending);
+        entries.truncate(n);
+    }
+
+    entries.sort_by(descending);
+    entries
+}
+
+fn last_access(entry: &Entry) -> Option<&NaiveDateTime> {
+    entry.times.get_last_access()
+}
+
+impl Database {
+    /// Record a usage of the entry with the given identifier, see [`Entry::touch_usage`]. Returns
+    /// `false` if there is no such entry.
+    pub fn touch_usage(&mut self, id: EntryId) -> bool {
+        match find_entry_mut(&mut self.root, id.as_uuid()) {
+            Some(entry) => {
+                entry.touch_usage();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn entries(&self) -> Vec<&Entry> {
+        self.root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Entry(e) => Some(e),
+                NodeRef::Group(_) => None,
+            })
+            .collect()
+    }
+
+    /// Get the `n` most recently accessed entries, most recent first
+    pub fn recently_used(&self, n: usize) -> Vec<&Entry> {
+        top_entries(self.entries(), n, |a, b| {
+            last_access(a)
+                .cmp(&last_access(b))
+                .then(a.times.usage_count.cmp(&b.times.usage_count))
+        })
+    }
+
+    /// Get the `n` entries with the highest usage count, most used first. Entries that were
+    /// never used are not included, and ties are broken by the last access time.
+    pub fn most_used(&self, n: usize) -> Vec<&Entry> {
+        let used = self
+            .entries()
+            .into_iter()
+            .filter(|e| e.times.usage_count > 0)
+            .collect();
+
+        top_entries(used, n, |a, b| {
+            a.times
+                .usage_count
+                .cmp(&b.times.usage_count)
+                .then(last_access(a).cmp(&last_access(b)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod usage_tests {
+    use chrono::Duration;
+
+    use crate::db::{Database, Entry, Group, Times};
+
+    fn entry(title: &str, usage_count: usize, accessed_days_ago: i64) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".into(), crate::db::Value::Unprotected(title.to_string()));
+        entry.times.usage_count = usage_count;
+        entry
+            .times
+            .set_last_access(Times::now() - Duration::days(accessed_days_ago));
+        entry
+    }
+
+    fn titles(entries: Vec<&Entry>) -> Vec<&str> {
+        entries.into_iter().map(|e| e.get_title().unwrap()).collect()
+    }
+
+    #[test]
+    fn usage_queries() {
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry("a", 5, 10));
+        db.root.add_child(entry("b", 0, 1));
+
+        let mut group = Group::new("group");
+        group.add_child(entry("c", 2, 3));
+        group.add_child(entry("d", 9, 30));
+        db.root.add_child(group);
+
+        assert_eq!(titles(db.recently_used(2)), vec!["b", "c"]);
+        assert_eq!(titles(db.recently_used(10)), vec!["b", "c", "a", "d"]);
+        assert_eq!(titles(db.most_used(2)), vec!["d", "a"]);
+        assert_eq!(titles(db.most_used(10)), vec!["d", "a", "c"]);
+        assert!(db.most_used(0).is_empty());
+
+        let d = db.root.groups()[0].entries()[1].id();
+        assert!(db.touch_usage(d));
+        assert_eq!(titles(db.recently_used(1)), vec!["d"]);
+        assert_eq!(db.most_used(1)[0].times.usage_count, 10);
+        assert!(!db.touch_usage(uuid::Uuid::new_v4().into()));
+    }
+}
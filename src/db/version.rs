@@ -0,0 +1,162 @@
+use crate::{
+    config::DatabaseVersion,
+    db::{Database, Entry, NodeRef, XmlElement},
+};
+
+/// A feature of a database that KDBX 4.0 cannot store, and that is lost when the database is
+/// saved as KDBX 4.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kdbx41Feature {
+    /// An entry is excluded from the password quality check
+    QualityCheck,
+
+    /// A group has tags
+    GroupTags,
+
+    /// A node remembers the group it was in before it was moved
+    PreviousParentGroup,
+}
+
+fn unknown_element_features(elements: &[XmlElement], features: &mut Vec<Kdbx41Feature>) {
+    if elements.iter().any(|e| e.name == "PreviousParentGroup") {
+        features.push(Kdbx41Feature::PreviousParentGroup);
+    }
+}
+
+fn entry_features(entry: &Entry, features: &mut Vec<Kdbx41Feature>) {
+    if entry.quality_check == Some(false) {
+        features.push(Kdbx41Feature::QualityCheck);
+    }
+    unknown_element_features(&entry.unknown_elements, features);
+
+    for version in entry.history.iter().flat_map(|h| h.get_entries()) {
+        entry_features(version, features);
+    }
+}
+
+impl Database {
+    /// Get the features of the database that require KDBX 4.1, sorted and without duplicates
+    pub fn kdbx41_features(&self) -> Vec<Kdbx41Feature> {
+        let mut features = Vec::new();
+
+        for node in &self.root {
+            match node {
+                NodeRef::Entry(e) => entry_features(e, &mut features),
+                NodeRef::Group(g) => {
+                    if g.unknown_elements.iter().any(|e| e.name == "Tags") {
+                        features.push(Kdbx41Feature::GroupTags);
+                    }
+                    unknown_element_features(&g.unknown_elements, &mut features);
+                }
+            }
+        }
+
+        features.sort();
+        features.dedup();
+        features
+    }
+
+    /// Get the oldest KDBX 4 version that can store everything in the database: KDBX 4.1 if it
+    /// uses any of the [`Kdbx41Feature`]s, and KDBX 4.0 otherwise.
+    ///
+    /// Saving a database as an older version than this fails with
+    /// [`DatabaseSaveError::VersionTooLow`](crate::error::DatabaseSaveError::VersionTooLow)
+    /// instead of silently dropping these features. Set
+    /// [`DatabaseConfig::version`](crate::config::DatabaseConfig::version) to this version to
+    /// save such a database.
+    pub fn required_minimum_version(&self) -> DatabaseVersion {
+        if self.kdbx41_features().is_empty() {
+            DatabaseVersion::KDB4(0)
+        } else {
+            DatabaseVersion::KDB4(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod version_tests {
+    use super::Kdbx41Feature;
+    use crate::{
+        config::DatabaseVersion,
+        db::{CustomDataItem, Database, Entry, Group, Times, XmlElement},
+    };
+
+    #[test]
+    fn required_minimum_version() {
+        let mut db = Database::new(Default::default());
+        assert!(db.kdbx41_features().is_empty());
+        assert_eq!(db.required_minimum_version(), DatabaseVersion::KDB4(0));
+
+        // custom data modification times are written to KDBX 4.0 files as well
+        let mut entry = Entry::new();
+        entry.set_field_metadata("Password", "Type", "PIN");
+        entry.custom_data.items.insert(
+            "key".to_string(),
+            CustomDataItem {
+                value: None,
+                last_modification_time: Some(Times::now()),
+            },
+        );
+        db.root.add_child(entry.clone());
+        assert!(db.kdbx41_features().is_empty());
+
+        let mut previous = entry.clone();
+        previous.set_quality_check(false);
+        entry
+            .history
+            .get_or_insert_with(Default::default)
+            .add_entry(previous);
+        db.root.add_child(entry);
+        assert_eq!(db.kdbx41_features(), vec![Kdbx41Feature::QualityCheck]);
+
+        let mut group = Group::new("Tagged");
+        group.unknown_elements.push(XmlElement {
+            name: "Tags".to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+        });
+        db.root.add_child(group);
+
+        assert_eq!(
+            db.kdbx41_features(),
+            vec![Kdbx41Feature::QualityCheck, Kdbx41Feature::GroupTags]
+        );
+        assert_eq!(db.required_minimum_version(), DatabaseVersion::KDB4(1));
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn refuse_downgrade() {
+        use crate::{
+            config::{DatabaseConfig, KdfConfig},
+            error::DatabaseSaveError,
+            DatabaseKey,
+        };
+
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        });
+        let mut entry = Entry::new();
+        entry.set_field_metadata("Password", "Type", "PIN");
+        db.root.add_child(entry);
+
+        let key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        db.save(&mut data, key.clone()).unwrap();
+
+        db.root.entries_mut()[0].set_quality_check(false);
+        data.clear();
+        assert!(matches!(
+            db.save(&mut data, key.clone()),
+            Err(DatabaseSaveError::VersionTooLow { .. })
+        ));
+
+        db.config.version = db.required_minimum_version();
+        data.clear();
+        db.save(&mut data, key.clone()).unwrap();
+        let reopened = Database::open(&mut data.as_slice(), key).unwrap();
+        assert_eq!(reopened.config.version, DatabaseVersion::KDB4(1));
+        assert_eq!(reopened.kdbx41_features(), vec![Kdbx41Feature::QualityCheck]);
+    }
+}
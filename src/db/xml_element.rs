@@ -0,0 +1,57 @@
+/// An XML element of the inner database that this library does not know about, e.g. one written
+/// by a newer KeePass version or a plugin.
+///
+/// Unknown elements of entries, groups and the database metadata are kept when parsing a
+/// database and written back when saving it, so that no data is lost in a round trip. Text in
+/// elements with a `Protected="True"` attribute is stored decrypted and encrypted again on save.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct XmlElement {
+    pub name: String,
+
+    /// Attributes of the element, sorted by name
+    pub attributes: Vec<(String, String)>,
+
+    pub children: Vec<XmlNode>,
+}
+
+/// A child of an [`XmlElement`]
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+}
+
+impl XmlElement {
+    pub fn new(name: &str) -> XmlElement {
+        XmlElement {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Get the value of an attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the text of this element is encrypted with the inner cipher in the XML document
+    pub fn is_protected(&self) -> bool {
+        matches!(self.attribute("Protected"), Some(p) if p.eq_ignore_ascii_case("true"))
+    }
+
+    /// Get the text directly contained in this element
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|c| match c {
+                XmlNode::Text(t) => Some(t.as_str()),
+                XmlNode::Element(_) => None,
+            })
+            .collect()
+    }
+}
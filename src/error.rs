@@ -23,6 +23,11 @@ pub enum DatabaseOpenError {
     /// The database version cannot be read by this library
     #[error("Opening this database version is not supported")]
     UnsupportedVersion,
+
+    /// The payload of the database is larger than allowed by the
+    /// [`OpenOptions`](crate::config::OpenOptions) after decompression
+    #[error("The decompressed payload of the database exceeds {} bytes", limit)]
+    PayloadTooLarge { limit: usize },
 }
 
 /// Errors stemming from corrupted databases
@@ -91,6 +96,10 @@ pub enum DatabaseIntegrityError {
     #[error("Header hash masmatch")]
     HeaderHashMismatch,
 
+    /// The database file ended before a complete structure could be read
+    #[error("Database file appears truncated or corrupted at byte {}", offset)]
+    Truncated { offset: usize },
+
     #[error("Invalid outer header entry: {}", entry_type)]
     InvalidOuterHeaderEntry { entry_type: u8 },
 
@@ -138,6 +147,11 @@ pub enum DatabaseSaveError {
     #[error("Saving this database version is not supported")]
     UnsupportedVersion,
 
+    /// The database uses features that the version it is saved as does not support, see
+    /// [`Database::required_minimum_version`](crate::Database::required_minimum_version)
+    #[error("Saving as {} would lose features that require {}", version, required)]
+    VersionTooLow { version: String, required: String },
+
     /// Error while writing out the inner XML database
     #[error("Error while generating XML")]
     Xml(#[from] xml::writer::Error),
@@ -157,6 +171,36 @@ pub enum DatabaseSaveError {
     /// An error getting randomness for keys occurred
     #[error(transparent)]
     Random(#[from] getrandom::Error),
+
+    /// The generated XML document does not describe the database that was saved
+    #[cfg(feature = "validate")]
+    #[error("Generated XML failed validation: {}", _0)]
+    Validation(#[from] ValidationError),
+}
+
+/// Errors found when checking the XML document generated while saving a database
+#[cfg(feature = "validate")]
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    /// The generated XML document could not be parsed again
+    #[error("Cannot parse the generated XML: {}", _0)]
+    Reparse(#[from] XmlParseError),
+
+    /// The metadata parsed from the XML document differs from the metadata of the database
+    #[error("The metadata differs from the database")]
+    MetaMismatch,
+
+    /// The deleted objects parsed from the XML document differ from those of the database
+    #[error("The deleted objects differ from the database")]
+    DeletedObjectsMismatch,
+
+    /// The group with this UUID differs from the one parsed from the XML document
+    #[error("The group {} differs from the database", uuid)]
+    GroupMismatch { uuid: uuid::Uuid },
+
+    /// The entry with this UUID differs from the one parsed from the XML document
+    #[error("The entry {} differs from the database", uuid)]
+    EntryMismatch { uuid: uuid::Uuid },
 }
 
 /// Errors related to the database key
@@ -174,6 +218,14 @@ pub enum DatabaseKeyError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
+    /// The keyfile given by its path could not be read, e.g. because it is on removable media
+    /// that is not plugged in
+    #[error("Cannot read the keyfile {}: {}", path.display(), source)]
+    KeyfileUnavailable {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
     /// An XML error occurred while loading the keyfile
     #[error(transparent)]
     Xml(#[from] xml::reader::Error),
@@ -182,9 +234,36 @@ pub enum DatabaseKeyError {
     #[error("Could not obtain a key from the keyfile")]
     InvalidKeyFile,
 
+    /// The checksum of a re-typed keyfile does not match its key, i.e. there is a typo
+    #[error("Keyfile checksum mismatch: expected {expected}, but the key has checksum {actual}")]
+    InvalidKeyfileChecksum { expected: String, actual: String },
+
+    /// An error getting randomness for a new key occurred
+    #[error(transparent)]
+    Random(#[from] getrandom::Error),
+
+    /// A key could not be split into shares or restored from them
+    #[error("Invalid key shares: {0}")]
+    InvalidKeyShares(String),
+
     /// Could not get challenge response key.
     #[error("Error with the challenge-response key: {0}")]
     ChallengeResponseKeyError(String),
+
+    /// A YubiKey slot did not answer a challenge in time
+    #[cfg(feature = "challenge_response")]
+    #[error("Slot {slot} of the YubiKey with serial number {serial_number} did not answer in time. Make sure that the slot is configured for HMAC-SHA1 challenge-response, and touch the key if it requires it.")]
+    SlotNotResponding { serial_number: u32, slot: String },
+
+    /// An error occurred while accessing the keychain of the operating system
+    #[cfg(feature = "keyring")]
+    #[error(transparent)]
+    Keyring(#[from] keyring::Error),
+
+    /// An encrypted keyfile could not be decrypted, e.g. because the passphrase is wrong
+    #[cfg(feature = "age")]
+    #[error("Cannot decrypt the keyfile: {0}")]
+    KeyfileDecryption(#[from] age::DecryptError),
 }
 
 /// Errors with the configuration of the outer encryption
@@ -252,6 +331,9 @@ pub enum BlockStreamError {
 
     #[error("Block hash mismatch for block {}", block_index)]
     BlockHashMismatch { block_index: u64 },
+
+    #[error("Block stream ended unexpectedly in block {}", block_index)]
+    Truncated { block_index: u64 },
 }
 
 /// Errors while parsing a VariantDictionary
@@ -271,13 +353,16 @@ pub enum VariantDictionaryError {
 
     #[error("VariantDictionary did not end with null byte, when it should")]
     NotTerminated,
+
+    #[error("VariantDictionary ended unexpectedly at byte {}", offset)]
+    Truncated { offset: usize },
 }
 
 /// Errors while parsing the XML document inside of a KeePass database
 #[derive(Debug, Error)]
 pub enum XmlParseError {
     #[error(transparent)]
-    Xml(#[from] xml::reader::Error),
+    Xml(#[from] quick_xml::Error),
 
     #[error(transparent)]
     Base64(#[from] base64::DecodeError),
@@ -314,6 +399,98 @@ pub enum XmlParseError {
     /// The stream of XML events ended when more events were expected
     #[error("Unexpected end of XML document")]
     Eof,
+
+    /// A timestamp was neither an ISO 8601 string nor a Base64-encoded number of seconds
+    #[error("Invalid timestamp: {}", value)]
+    InvalidTimestamp { value: String },
+}
+
+/// Errors while attaching a file to an entry
+#[derive(Debug, Error)]
+pub enum AttachmentError {
+    /// The entry to attach the file to does not exist
+    #[error("Entry {} was not found", _0)]
+    EntryNotFound(uuid::Uuid),
+
+    /// The path does not name a file
+    #[error("Invalid attachment path: {}", _0)]
+    InvalidPath(String),
+
+    /// The file is larger than allowed
+    #[error("Attachment has {} bytes, but at most {} are allowed", size, max_size)]
+    TooLarge { size: u64, max_size: u64 },
+
+    /// The file could not be read
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Errors while removing an entry from a database
+#[derive(Debug, Error)]
+pub enum RemoveEntryError {
+    /// The entry to remove does not exist
+    #[error("Entry {} was not found", _0)]
+    EntryNotFound(crate::db::EntryId),
+
+    /// Other parts of the database depend on the entry
+    #[error("The entry cannot be removed, {} dependencies would break", _0.len())]
+    HasDependencies(Vec<crate::db::EntryDependency>),
+}
+
+/// Errors while exporting or importing a group subtree
+#[derive(Debug, Error)]
+pub enum SubtreeError {
+    /// The group to export or import into does not exist
+    #[error("Group {} was not found", _0)]
+    GroupNotFound(crate::db::GroupId),
+
+    /// The entry to copy does not exist
+    #[error("Entry {} was not found", _0)]
+    EntryNotFound(crate::db::EntryId),
+
+    /// A copied node would keep a UUID that already exists in the target database
+    #[error("A node with UUID {} already exists", _0)]
+    UuidExists(uuid::Uuid),
+
+    /// The imported root group was skipped because its UUID already exists
+    #[error("The imported group already exists")]
+    Skipped,
+
+    /// Replacing existing nodes would remove the group to import into
+    #[error("Importing would replace the group to import into")]
+    ReplacesParent,
+
+    /// The database to import could not be opened
+    #[error(transparent)]
+    Open(#[from] DatabaseOpenError),
+
+    /// The exported database could not be saved
+    #[error(transparent)]
+    Save(#[from] DatabaseSaveError),
+}
+
+/// Errors while creating or restoring an emergency sheet
+#[derive(Debug, Error)]
+pub enum EmergencySheetError {
+    /// An entry to include does not exist
+    #[error("Entry {} was not found", _0)]
+    EntryNotFound(crate::db::EntryId),
+
+    /// The encrypted data is not a complete armored block, or its checksum does not match
+    #[error("The encrypted data of the emergency sheet is incomplete or mistyped")]
+    InvalidArmor,
+
+    /// The recovery key does not have the expected format
+    #[error("The recovery key is incomplete or mistyped")]
+    InvalidRecoveryKey,
+
+    /// The encrypted data could not be opened, e.g. because the recovery key is wrong
+    #[error(transparent)]
+    Open(#[from] DatabaseOpenError),
+
+    /// The selected entries could not be encrypted
+    #[error(transparent)]
+    Save(#[from] DatabaseSaveError),
 }
 
 /// Error parsing a color code
@@ -321,6 +498,783 @@ pub enum XmlParseError {
 #[error("Cannot parse color: '{}'", _0)]
 pub struct ParseColorError(pub String);
 
+/// Error converting a number that is not the ID of a standard icon
+#[derive(Debug, Error)]
+#[error("Not a standard icon ID: {}", _0)]
+pub struct InvalidIconIdError(pub usize);
+
+/// Top-level error type that wraps all errors this crate can return.
+///
+/// Use [`Error::kind`] to match on the broad category of an error without having to pattern
+/// match through the nested error enums. The original error is preserved and can be reached
+/// through [`std::error::Error::source`] or by matching on the variants.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error occurred while opening a database
+    #[error(transparent)]
+    Open(#[from] DatabaseOpenError),
+
+    /// An error occurred while saving a database
+    #[error(transparent)]
+    Save(#[from] DatabaseSaveError),
+
+    /// An error occurred while building or using the database key
+    #[error(transparent)]
+    Key(#[from] DatabaseKeyError),
+
+    /// A general I/O error
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// An error occurred while attaching a file to an entry
+    #[error(transparent)]
+    Attachment(#[from] AttachmentError),
+
+    /// An error occurred while exporting or importing a group subtree
+    #[error(transparent)]
+    Subtree(#[from] SubtreeError),
+
+    /// An error occurred while removing an entry
+    #[error(transparent)]
+    RemoveEntry(#[from] RemoveEntryError),
+
+    /// An error occurred while creating or restoring an emergency sheet
+    #[error(transparent)]
+    EmergencySheet(#[from] EmergencySheetError),
+
+    /// An error occurred while merging two databases
+    #[cfg(feature = "_merge")]
+    #[error(transparent)]
+    Merge(#[from] crate::db::merge::MergeError),
+
+    /// An error occurred while parsing a TOTP URL
+    #[cfg(feature = "totp")]
+    #[error(transparent)]
+    Totp(#[from] TOTPError),
+}
+
+/// Stable categories of errors, as returned by [`Error::kind`].
+///
+/// New kinds may be added in the future, so matches should always include a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The key was incorrect, e.g. because of a wrong password or keyfile
+    WrongKey,
+
+    /// The keyfile or challenge-response key could not be used
+    InvalidKey,
+
+    /// The database file is corrupted or malformed
+    Corrupted,
+
+    /// The database uses a version or feature that this library does not support
+    Unsupported,
+
+    /// An I/O error occurred
+    Io,
+
+    /// An error occurred in an underlying cryptographic operation
+    Cryptography,
+
+    /// Two databases could not be merged
+    Merge,
+
+    /// Any other error
+    Other,
+}
+
+impl ErrorKind {
+    /// A stable, machine-readable identifier for this error kind
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::WrongKey => "wrong_key",
+            ErrorKind::InvalidKey => "invalid_key",
+            ErrorKind::Corrupted => "corrupted",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::Io => "io",
+            ErrorKind::Cryptography => "cryptography",
+            ErrorKind::Merge => "merge",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Machine-readable identifier of an error along with its parameters, as returned by
+/// [`Error::code`].
+///
+/// The `Display` implementations of the errors render English messages. Applications that show
+/// errors in other languages can look up a translated message by [`ErrorCode::id`] and fill in
+/// [`ErrorCode::params`] instead. Errors of underlying libraries, e.g. I/O errors, only have a
+/// generic code.
+///
+/// New codes may be added in the future, so matches should always include a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    IncorrectKey,
+    InvalidKeyFile,
+    KeyfileUnavailable {
+        path: std::path::PathBuf,
+    },
+    InvalidKeyfileChecksum {
+        expected: String,
+        actual: String,
+    },
+    InvalidKeyShares,
+    ChallengeResponseKey,
+    SlotNotResponding {
+        serial_number: u32,
+        slot: String,
+    },
+    Keyring,
+    KeyfileDecryption,
+
+    UnsupportedVersion,
+    VersionTooLow {
+        version: String,
+        required: String,
+    },
+    PayloadTooLarge {
+        limit: usize,
+    },
+    InvalidKdbxIdentifier,
+    InvalidKdbxVersion {
+        version: u32,
+        file_major_version: u32,
+        file_minor_version: u32,
+    },
+    InvalidFixedHeader {
+        size: usize,
+    },
+    InvalidKdbFieldLength {
+        field_type: u16,
+        field_size: u32,
+        expected_field_size: u32,
+    },
+    MissingKdbGroupLevel,
+    InvalidKdbGroupLevel {
+        group_level: u16,
+        current_level: u16,
+    },
+    MissingKdbGroupId,
+    InvalidKdbGroupId {
+        group_id: u32,
+    },
+    InvalidKdbGroupFieldType {
+        field_type: u16,
+    },
+    InvalidKdbEntryFieldType {
+        field_type: u16,
+    },
+    IncompleteKdbGroup,
+    IncompleteKdbEntry,
+    InvalidFixedCipherId {
+        cid: u32,
+    },
+    HeaderHashMismatch,
+    Truncated {
+        offset: usize,
+    },
+    InvalidOuterHeaderEntry {
+        entry_type: u8,
+    },
+    IncompleteOuterHeader {
+        missing_field: String,
+    },
+    InvalidInnerHeaderEntry {
+        entry_type: u8,
+    },
+    IncompleteInnerHeader {
+        missing_field: String,
+    },
+    InvalidOuterCipherId {
+        cid: Vec<u8>,
+    },
+    InvalidInnerCipherId {
+        cid: u32,
+    },
+    InvalidCompression {
+        cid: u32,
+    },
+    InvalidKdfVersion {
+        version: u32,
+    },
+    InvalidKdfUuid {
+        uuid: Vec<u8>,
+    },
+    InvalidVariantDictionary,
+    BlockHashMismatch {
+        block_index: u64,
+    },
+    BlockStreamTruncated {
+        block_index: u64,
+    },
+    InvalidXml,
+    InvalidTimestamp {
+        value: String,
+    },
+
+    XmlWrite,
+    ValidationFailed,
+
+    EntryNotFound {
+        uuid: uuid::Uuid,
+    },
+    GroupNotFound {
+        uuid: uuid::Uuid,
+    },
+    InvalidAttachmentPath {
+        path: String,
+    },
+    AttachmentTooLarge {
+        size: u64,
+        max_size: u64,
+    },
+    EntryHasDependencies {
+        count: usize,
+    },
+    UuidExists {
+        uuid: uuid::Uuid,
+    },
+    SubtreeSkipped,
+    SubtreeReplacesParent,
+    InvalidEmergencySheet,
+    InvalidRecoveryKey,
+
+    Merge,
+    Totp,
+    Cryptography,
+    Io,
+}
+
+impl ErrorCode {
+    /// A stable, machine-readable identifier for this error, e.g. `invalid_outer_header_entry`
+    pub fn id(&self) -> &'static str {
+        match self {
+            ErrorCode::IncorrectKey => "incorrect_key",
+            ErrorCode::InvalidKeyFile => "invalid_key_file",
+            ErrorCode::KeyfileUnavailable { .. } => "keyfile_unavailable",
+            ErrorCode::InvalidKeyfileChecksum { .. } => "invalid_keyfile_checksum",
+            ErrorCode::InvalidKeyShares => "invalid_key_shares",
+            ErrorCode::ChallengeResponseKey => "challenge_response_key",
+            ErrorCode::SlotNotResponding { .. } => "slot_not_responding",
+            ErrorCode::Keyring => "keyring",
+            ErrorCode::KeyfileDecryption => "keyfile_decryption",
+            ErrorCode::UnsupportedVersion => "unsupported_version",
+            ErrorCode::VersionTooLow { .. } => "version_too_low",
+            ErrorCode::PayloadTooLarge { .. } => "payload_too_large",
+            ErrorCode::InvalidKdbxIdentifier => "invalid_kdbx_identifier",
+            ErrorCode::InvalidKdbxVersion { .. } => "invalid_kdbx_version",
+            ErrorCode::InvalidFixedHeader { .. } => "invalid_fixed_header",
+            ErrorCode::InvalidKdbFieldLength { .. } => "invalid_kdb_field_length",
+            ErrorCode::MissingKdbGroupLevel => "missing_kdb_group_level",
+            ErrorCode::InvalidKdbGroupLevel { .. } => "invalid_kdb_group_level",
+            ErrorCode::MissingKdbGroupId => "missing_kdb_group_id",
+            ErrorCode::InvalidKdbGroupId { .. } => "invalid_kdb_group_id",
+            ErrorCode::InvalidKdbGroupFieldType { .. } => "invalid_kdb_group_field_type",
+            ErrorCode::InvalidKdbEntryFieldType { .. } => "invalid_kdb_entry_field_type",
+            ErrorCode::IncompleteKdbGroup => "incomplete_kdb_group",
+            ErrorCode::IncompleteKdbEntry => "incomplete_kdb_entry",
+            ErrorCode::InvalidFixedCipherId { .. } => "invalid_fixed_cipher_id",
+            ErrorCode::HeaderHashMismatch => "header_hash_mismatch",
+            ErrorCode::Truncated { .. } => "truncated",
+            ErrorCode::InvalidOuterHeaderEntry { .. } => "invalid_outer_header_entry",
+            ErrorCode::IncompleteOuterHeader { .. } => "incomplete_outer_header",
+            ErrorCode::InvalidInnerHeaderEntry { .. } => "invalid_inner_header_entry",
+            ErrorCode::IncompleteInnerHeader { .. } => "incomplete_inner_header",
+            ErrorCode::InvalidOuterCipherId { .. } => "invalid_outer_cipher_id",
+            ErrorCode::InvalidInnerCipherId { .. } => "invalid_inner_cipher_id",
+            ErrorCode::InvalidCompression { .. } => "invalid_compression",
+            ErrorCode::InvalidKdfVersion { .. } => "invalid_kdf_version",
+            ErrorCode::InvalidKdfUuid { .. } => "invalid_kdf_uuid",
+            ErrorCode::InvalidVariantDictionary => "invalid_variant_dictionary",
+            ErrorCode::BlockHashMismatch { .. } => "block_hash_mismatch",
+            ErrorCode::BlockStreamTruncated { .. } => "block_stream_truncated",
+            ErrorCode::InvalidXml => "invalid_xml",
+            ErrorCode::InvalidTimestamp { .. } => "invalid_timestamp",
+            ErrorCode::XmlWrite => "xml_write",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::EntryNotFound { .. } => "entry_not_found",
+            ErrorCode::GroupNotFound { .. } => "group_not_found",
+            ErrorCode::InvalidAttachmentPath { .. } => "invalid_attachment_path",
+            ErrorCode::AttachmentTooLarge { .. } => "attachment_too_large",
+            ErrorCode::EntryHasDependencies { .. } => "entry_has_dependencies",
+            ErrorCode::UuidExists { .. } => "uuid_exists",
+            ErrorCode::SubtreeSkipped => "subtree_skipped",
+            ErrorCode::SubtreeReplacesParent => "subtree_replaces_parent",
+            ErrorCode::InvalidEmergencySheet => "invalid_emergency_sheet",
+            ErrorCode::InvalidRecoveryKey => "invalid_recovery_key",
+            ErrorCode::Merge => "merge",
+            ErrorCode::Totp => "totp",
+            ErrorCode::Cryptography => "cryptography",
+            ErrorCode::Io => "io",
+        }
+    }
+
+    /// The parameters of this error as pairs of names and values, e.g. to fill in the
+    /// placeholders of a translated message
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        match self {
+            ErrorCode::KeyfileUnavailable { path } => vec![("path", path.display().to_string())],
+            ErrorCode::InvalidKeyfileChecksum { expected, actual } => {
+                vec![("expected", expected.clone()), ("actual", actual.clone())]
+            }
+            ErrorCode::SlotNotResponding { serial_number, slot } => {
+                vec![
+                    ("serial_number", serial_number.to_string()),
+                    ("slot", slot.clone()),
+                ]
+            }
+            ErrorCode::VersionTooLow { version, required } => {
+                vec![("version", version.clone()), ("required", required.clone())]
+            }
+            ErrorCode::PayloadTooLarge { limit } => vec![("limit", limit.to_string())],
+            ErrorCode::InvalidKdbxVersion {
+                version,
+                file_major_version,
+                file_minor_version,
+            } => vec![
+                ("version", version.to_string()),
+                ("file_major_version", file_major_version.to_string()),
+                ("file_minor_version", file_minor_version.to_string()),
+            ],
+            ErrorCode::InvalidFixedHeader { size } => vec![("size", size.to_string())],
+            ErrorCode::InvalidKdbFieldLength {
+                field_type,
+                field_size,
+                expected_field_size,
+            } => vec![
+                ("field_type", field_type.to_string()),
+                ("field_size", field_size.to_string()),
+                ("expected_field_size", expected_field_size.to_string()),
+            ],
+            ErrorCode::InvalidKdbGroupLevel {
+                group_level,
+                current_level,
+            } => vec![
+                ("group_level", group_level.to_string()),
+                ("current_level", current_level.to_string()),
+            ],
+            ErrorCode::InvalidKdbGroupId { group_id } => vec![("group_id", group_id.to_string())],
+            ErrorCode::InvalidKdbGroupFieldType { field_type }
+            | ErrorCode::InvalidKdbEntryFieldType { field_type } => {
+                vec![("field_type", field_type.to_string())]
+            }
+            ErrorCode::InvalidFixedCipherId { cid }
+            | ErrorCode::InvalidInnerCipherId { cid }
+            | ErrorCode::InvalidCompression { cid } => vec![("cid", cid.to_string())],
+            ErrorCode::Truncated { offset } => vec![("offset", offset.to_string())],
+            ErrorCode::InvalidOuterHeaderEntry { entry_type }
+            | ErrorCode::InvalidInnerHeaderEntry { entry_type } => {
+                vec![("entry_type", entry_type.to_string())]
+            }
+            ErrorCode::IncompleteOuterHeader { missing_field }
+            | ErrorCode::IncompleteInnerHeader { missing_field } => {
+                vec![("missing_field", missing_field.clone())]
+            }
+            ErrorCode::InvalidOuterCipherId { cid } => vec![("cid", hex::encode(cid))],
+            ErrorCode::InvalidKdfVersion { version } => vec![("version", version.to_string())],
+            ErrorCode::InvalidKdfUuid { uuid } => vec![("uuid", hex::encode(uuid))],
+            ErrorCode::BlockHashMismatch { block_index } | ErrorCode::BlockStreamTruncated { block_index } => {
+                vec![("block_index", block_index.to_string())]
+            }
+            ErrorCode::InvalidTimestamp { value } => vec![("value", value.clone())],
+            ErrorCode::EntryNotFound { uuid }
+            | ErrorCode::GroupNotFound { uuid }
+            | ErrorCode::UuidExists { uuid } => {
+                vec![("uuid", uuid.to_string())]
+            }
+            ErrorCode::InvalidAttachmentPath { path } => vec![("path", path.clone())],
+            ErrorCode::AttachmentTooLarge { size, max_size } => {
+                vec![("size", size.to_string()), ("max_size", max_size.to_string())]
+            }
+            ErrorCode::EntryHasDependencies { count } => vec![("count", count.to_string())],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl Error {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Open(e) => e.kind(),
+            Error::Save(e) => e.kind(),
+            Error::Key(e) => e.kind(),
+            Error::Io(_) => ErrorKind::Io,
+            Error::Attachment(e) => e.kind(),
+            Error::Subtree(e) => e.kind(),
+            Error::RemoveEntry(_) => ErrorKind::Other,
+            Error::EmergencySheet(e) => e.kind(),
+            #[cfg(feature = "_merge")]
+            Error::Merge(_) => ErrorKind::Merge,
+            #[cfg(feature = "totp")]
+            Error::Totp(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Open(e) => e.code(),
+            Error::Save(e) => e.code(),
+            Error::Key(e) => e.code(),
+            Error::Io(_) => ErrorCode::Io,
+            Error::Attachment(e) => e.code(),
+            Error::Subtree(e) => e.code(),
+            Error::RemoveEntry(e) => e.code(),
+            Error::EmergencySheet(e) => e.code(),
+            #[cfg(feature = "_merge")]
+            Error::Merge(_) => ErrorCode::Merge,
+            #[cfg(feature = "totp")]
+            Error::Totp(_) => ErrorCode::Totp,
+        }
+    }
+}
+
+impl DatabaseOpenError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DatabaseOpenError::Io(_) => ErrorKind::Io,
+            DatabaseOpenError::Key(e) => e.kind(),
+            DatabaseOpenError::DatabaseIntegrity(e) => e.kind(),
+            DatabaseOpenError::UnsupportedVersion => ErrorKind::Unsupported,
+            DatabaseOpenError::PayloadTooLarge { .. } => ErrorKind::Unsupported,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DatabaseOpenError::Io(_) => ErrorCode::Io,
+            DatabaseOpenError::Key(e) => e.code(),
+            DatabaseOpenError::DatabaseIntegrity(e) => e.code(),
+            DatabaseOpenError::UnsupportedVersion => ErrorCode::UnsupportedVersion,
+            DatabaseOpenError::PayloadTooLarge { limit } => ErrorCode::PayloadTooLarge { limit: *limit },
+        }
+    }
+}
+
+impl DatabaseIntegrityError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DatabaseIntegrityError::Io(_) => ErrorKind::Io,
+            _ => ErrorKind::Corrupted,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DatabaseIntegrityError::InvalidKDBXIdentifier => ErrorCode::InvalidKdbxIdentifier,
+            DatabaseIntegrityError::InvalidKDBXVersion {
+                version,
+                file_major_version,
+                file_minor_version,
+            } => ErrorCode::InvalidKdbxVersion {
+                version: *version,
+                file_major_version: *file_major_version,
+                file_minor_version: *file_minor_version,
+            },
+            DatabaseIntegrityError::InvalidFixedHeader { size } => {
+                ErrorCode::InvalidFixedHeader { size: *size }
+            }
+            DatabaseIntegrityError::InvalidKDBFieldLength {
+                field_type,
+                field_size,
+                expected_field_size,
+            } => ErrorCode::InvalidKdbFieldLength {
+                field_type: *field_type,
+                field_size: *field_size,
+                expected_field_size: *expected_field_size,
+            },
+            DatabaseIntegrityError::MissingKDBGroupLevel => ErrorCode::MissingKdbGroupLevel,
+            DatabaseIntegrityError::InvalidKDBGroupLevel {
+                group_level,
+                current_level,
+            } => ErrorCode::InvalidKdbGroupLevel {
+                group_level: *group_level,
+                current_level: *current_level,
+            },
+            DatabaseIntegrityError::MissingKDBGroupId => ErrorCode::MissingKdbGroupId,
+            DatabaseIntegrityError::InvalidKDBGroupId { group_id } => {
+                ErrorCode::InvalidKdbGroupId { group_id: *group_id }
+            }
+            DatabaseIntegrityError::InvalidKDBGroupFieldType { field_type } => {
+                ErrorCode::InvalidKdbGroupFieldType {
+                    field_type: *field_type,
+                }
+            }
+            DatabaseIntegrityError::InvalidKDBEntryFieldType { field_type } => {
+                ErrorCode::InvalidKdbEntryFieldType {
+                    field_type: *field_type,
+                }
+            }
+            DatabaseIntegrityError::IncompleteKDBGroup => ErrorCode::IncompleteKdbGroup,
+            DatabaseIntegrityError::IncompleteKDBEntry => ErrorCode::IncompleteKdbEntry,
+            DatabaseIntegrityError::InvalidFixedCipherID { cid } => {
+                ErrorCode::InvalidFixedCipherId { cid: *cid }
+            }
+            DatabaseIntegrityError::HeaderHashMismatch => ErrorCode::HeaderHashMismatch,
+            DatabaseIntegrityError::Truncated { offset } => ErrorCode::Truncated { offset: *offset },
+            DatabaseIntegrityError::InvalidOuterHeaderEntry { entry_type } => {
+                ErrorCode::InvalidOuterHeaderEntry {
+                    entry_type: *entry_type,
+                }
+            }
+            DatabaseIntegrityError::IncompleteOuterHeader { missing_field } => {
+                ErrorCode::IncompleteOuterHeader {
+                    missing_field: missing_field.clone(),
+                }
+            }
+            DatabaseIntegrityError::InvalidInnerHeaderEntry { entry_type } => {
+                ErrorCode::InvalidInnerHeaderEntry {
+                    entry_type: *entry_type,
+                }
+            }
+            DatabaseIntegrityError::IncompleteInnerHeader { missing_field } => {
+                ErrorCode::IncompleteInnerHeader {
+                    missing_field: missing_field.clone(),
+                }
+            }
+            DatabaseIntegrityError::Cryptography(_) => ErrorCode::Cryptography,
+            DatabaseIntegrityError::Xml(XmlParseError::InvalidTimestamp { value }) => {
+                ErrorCode::InvalidTimestamp { value: value.clone() }
+            }
+            DatabaseIntegrityError::Xml(XmlParseError::Cryptography(_)) => ErrorCode::Cryptography,
+            DatabaseIntegrityError::Xml(_) => ErrorCode::InvalidXml,
+            DatabaseIntegrityError::OuterCipher(OuterCipherConfigError::InvalidOuterCipherID { cid }) => {
+                ErrorCode::InvalidOuterCipherId { cid: cid.clone() }
+            }
+            DatabaseIntegrityError::InnerCipher(InnerCipherConfigError::InvalidInnerCipherID { cid }) => {
+                ErrorCode::InvalidInnerCipherId { cid: *cid }
+            }
+            DatabaseIntegrityError::OuterCipher(_) | DatabaseIntegrityError::InnerCipher(_) => {
+                ErrorCode::Cryptography
+            }
+            DatabaseIntegrityError::Compression(CompressionConfigError::InvalidCompressionSuite { cid }) => {
+                ErrorCode::InvalidCompression { cid: *cid }
+            }
+            DatabaseIntegrityError::BlockStream(BlockStreamError::BlockHashMismatch { block_index }) => {
+                ErrorCode::BlockHashMismatch {
+                    block_index: *block_index,
+                }
+            }
+            DatabaseIntegrityError::BlockStream(BlockStreamError::Truncated { block_index }) => {
+                ErrorCode::BlockStreamTruncated {
+                    block_index: *block_index,
+                }
+            }
+            DatabaseIntegrityError::BlockStream(BlockStreamError::Cryptography(_)) => ErrorCode::Cryptography,
+            DatabaseIntegrityError::VariantDictionary(_)
+            | DatabaseIntegrityError::KdfSettings(KdfConfigError::VariantDictionary(_)) => {
+                ErrorCode::InvalidVariantDictionary
+            }
+            DatabaseIntegrityError::KdfSettings(KdfConfigError::InvalidKDFVersion { version }) => {
+                ErrorCode::InvalidKdfVersion { version: *version }
+            }
+            DatabaseIntegrityError::KdfSettings(KdfConfigError::InvalidKDFUUID { uuid }) => {
+                ErrorCode::InvalidKdfUuid { uuid: uuid.clone() }
+            }
+            DatabaseIntegrityError::Io(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl DatabaseSaveError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DatabaseSaveError::UnsupportedVersion => ErrorKind::Unsupported,
+            DatabaseSaveError::VersionTooLow { .. } => ErrorKind::Unsupported,
+            DatabaseSaveError::Xml(_) => ErrorKind::Other,
+            DatabaseSaveError::Io(_) => ErrorKind::Io,
+            DatabaseSaveError::Key(e) => e.kind(),
+            DatabaseSaveError::Cryptography(_) => ErrorKind::Cryptography,
+            DatabaseSaveError::Random(_) => ErrorKind::Cryptography,
+            #[cfg(feature = "validate")]
+            DatabaseSaveError::Validation(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DatabaseSaveError::UnsupportedVersion => ErrorCode::UnsupportedVersion,
+            DatabaseSaveError::VersionTooLow { version, required } => ErrorCode::VersionTooLow {
+                version: version.clone(),
+                required: required.clone(),
+            },
+            DatabaseSaveError::Xml(_) => ErrorCode::XmlWrite,
+            DatabaseSaveError::Io(_) => ErrorCode::Io,
+            DatabaseSaveError::Key(e) => e.code(),
+            DatabaseSaveError::Cryptography(_) => ErrorCode::Cryptography,
+            DatabaseSaveError::Random(_) => ErrorCode::Cryptography,
+            #[cfg(feature = "validate")]
+            DatabaseSaveError::Validation(_) => ErrorCode::ValidationFailed,
+        }
+    }
+}
+
+impl AttachmentError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AttachmentError::Io(_) => ErrorKind::Io,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AttachmentError::EntryNotFound(uuid) => ErrorCode::EntryNotFound { uuid: *uuid },
+            AttachmentError::InvalidPath(path) => ErrorCode::InvalidAttachmentPath { path: path.clone() },
+            AttachmentError::TooLarge { size, max_size } => ErrorCode::AttachmentTooLarge {
+                size: *size,
+                max_size: *max_size,
+            },
+            AttachmentError::Io(_) => ErrorCode::Io,
+        }
+    }
+}
+
+impl SubtreeError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            SubtreeError::Open(e) => e.kind(),
+            SubtreeError::Save(e) => e.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SubtreeError::GroupNotFound(id) => ErrorCode::GroupNotFound { uuid: *id.as_uuid() },
+            SubtreeError::EntryNotFound(id) => ErrorCode::EntryNotFound { uuid: *id.as_uuid() },
+            SubtreeError::UuidExists(uuid) => ErrorCode::UuidExists { uuid: *uuid },
+            SubtreeError::Skipped => ErrorCode::SubtreeSkipped,
+            SubtreeError::ReplacesParent => ErrorCode::SubtreeReplacesParent,
+            SubtreeError::Open(e) => e.code(),
+            SubtreeError::Save(e) => e.code(),
+        }
+    }
+}
+
+impl EmergencySheetError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            EmergencySheetError::Open(e) => e.kind(),
+            EmergencySheetError::Save(e) => e.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            EmergencySheetError::EntryNotFound(id) => ErrorCode::EntryNotFound { uuid: *id.as_uuid() },
+            EmergencySheetError::InvalidArmor => ErrorCode::InvalidEmergencySheet,
+            EmergencySheetError::InvalidRecoveryKey => ErrorCode::InvalidRecoveryKey,
+            EmergencySheetError::Open(e) => e.code(),
+            EmergencySheetError::Save(e) => e.code(),
+        }
+    }
+}
+
+impl RemoveEntryError {
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RemoveEntryError::EntryNotFound(id) => ErrorCode::EntryNotFound { uuid: *id.as_uuid() },
+            RemoveEntryError::HasDependencies(dependencies) => ErrorCode::EntryHasDependencies {
+                count: dependencies.len(),
+            },
+        }
+    }
+}
+
+impl DatabaseKeyError {
+    /// Get the category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DatabaseKeyError::IncorrectKey => ErrorKind::WrongKey,
+            DatabaseKeyError::Cryptography(_) => ErrorKind::Cryptography,
+            DatabaseKeyError::Io(_) => ErrorKind::Io,
+            DatabaseKeyError::KeyfileUnavailable { .. } => ErrorKind::Io,
+            DatabaseKeyError::Xml(_) => ErrorKind::InvalidKey,
+            DatabaseKeyError::InvalidKeyFile => ErrorKind::InvalidKey,
+            DatabaseKeyError::InvalidKeyfileChecksum { .. } => ErrorKind::InvalidKey,
+            DatabaseKeyError::Random(_) => ErrorKind::Cryptography,
+            DatabaseKeyError::InvalidKeyShares(_) => ErrorKind::InvalidKey,
+            DatabaseKeyError::ChallengeResponseKeyError(_) => ErrorKind::InvalidKey,
+            #[cfg(feature = "challenge_response")]
+            DatabaseKeyError::SlotNotResponding { .. } => ErrorKind::InvalidKey,
+            #[cfg(feature = "keyring")]
+            DatabaseKeyError::Keyring(_) => ErrorKind::Other,
+            #[cfg(feature = "age")]
+            DatabaseKeyError::KeyfileDecryption(
+                age::DecryptError::DecryptionFailed
+                | age::DecryptError::KeyDecryptionFailed
+                | age::DecryptError::NoMatchingKeys,
+            ) => ErrorKind::WrongKey,
+            #[cfg(feature = "age")]
+            DatabaseKeyError::KeyfileDecryption(_) => ErrorKind::InvalidKey,
+        }
+    }
+
+    /// Get the machine-readable code of this error, e.g. to show a localized message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DatabaseKeyError::IncorrectKey => ErrorCode::IncorrectKey,
+            DatabaseKeyError::Cryptography(_) => ErrorCode::Cryptography,
+            DatabaseKeyError::Io(_) => ErrorCode::Io,
+            DatabaseKeyError::KeyfileUnavailable { path, .. } => {
+                ErrorCode::KeyfileUnavailable { path: path.clone() }
+            }
+            DatabaseKeyError::Xml(_) => ErrorCode::InvalidKeyFile,
+            DatabaseKeyError::InvalidKeyFile => ErrorCode::InvalidKeyFile,
+            DatabaseKeyError::InvalidKeyfileChecksum { expected, actual } => {
+                ErrorCode::InvalidKeyfileChecksum {
+                    expected: expected.clone(),
+                    actual: actual.clone(),
+                }
+            }
+            DatabaseKeyError::Random(_) => ErrorCode::Cryptography,
+            DatabaseKeyError::InvalidKeyShares(_) => ErrorCode::InvalidKeyShares,
+            DatabaseKeyError::ChallengeResponseKeyError(_) => ErrorCode::ChallengeResponseKey,
+            #[cfg(feature = "challenge_response")]
+            DatabaseKeyError::SlotNotResponding { serial_number, slot } => ErrorCode::SlotNotResponding {
+                serial_number: *serial_number,
+                slot: slot.clone(),
+            },
+            #[cfg(feature = "keyring")]
+            DatabaseKeyError::Keyring(_) => ErrorCode::Keyring,
+            #[cfg(feature = "age")]
+            DatabaseKeyError::KeyfileDecryption(_) => ErrorCode::KeyfileDecryption,
+        }
+    }
+}
+
 // move error type conversions to a module and exclude them from coverage counting.
 #[cfg(not(tarpaulin_include))]
 mod conversions {
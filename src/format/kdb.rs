@@ -1,14 +1,17 @@
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OpenOptions, OuterCipherConfig},
     crypt::calculate_sha256,
-    db::{Database, Entry, Group, NodeRefMut, Value},
+    db::{AttachmentRef, BinaryAttachment, Database, Entry, Group, Meta, NodeRefMut, Times, Value},
     error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
-    format::DatabaseVersion,
+    format::{get_bytes, get_tail, DatabaseVersion},
     key::DatabaseKey,
+    trace::phase,
 };
 
 use byteorder::{ByteOrder, LittleEndian};
-use cipher::generic_array::GenericArray;
+use chrono::{NaiveDate, NaiveDateTime};
+use cipher::generic_array::{typenum::U32, GenericArray};
+use uuid::Uuid;
 
 use std::{collections::HashMap, convert::TryInto, str};
 
@@ -34,18 +37,42 @@ fn parse_header(data: &[u8]) -> Result<KDBHeader, DatabaseIntegrityError> {
     }
 
     Ok(KDBHeader {
-        flags: LittleEndian::read_u32(&data[8..]),
-        subversion: LittleEndian::read_u32(&data[12..]),
-        master_seed: data[16..32].to_vec(),
-        encryption_iv: data[32..48].to_vec(),
-        num_groups: LittleEndian::read_u32(&data[48..]),
-        num_entries: LittleEndian::read_u32(&data[52..]),
-        contents_hash: data[56..88].to_vec(),
-        transform_seed: data[88..120].to_vec(),
-        transform_rounds: LittleEndian::read_u32(&data[120..]),
+        flags: LittleEndian::read_u32(get_bytes(data, 8, 4)?),
+        subversion: LittleEndian::read_u32(get_bytes(data, 12, 4)?),
+        master_seed: get_bytes(data, 16, 16)?.to_vec(),
+        encryption_iv: get_bytes(data, 32, 16)?.to_vec(),
+        num_groups: LittleEndian::read_u32(get_bytes(data, 48, 4)?),
+        num_entries: LittleEndian::read_u32(get_bytes(data, 52, 4)?),
+        contents_hash: get_bytes(data, 56, 32)?.to_vec(),
+        transform_seed: get_bytes(data, 88, 32)?.to_vec(),
+        transform_rounds: LittleEndian::read_u32(get_bytes(data, 120, 4)?),
     })
 }
 
+/// Get the KDF parameters and the seed of a KDB database
+pub(crate) fn kdf_parameters(data: &[u8]) -> Result<(KdfConfig, Vec<u8>), DatabaseIntegrityError> {
+    let header = parse_header(data)?;
+    let kdf_config = KdfConfig::Aes {
+        rounds: header.transform_rounds as u64,
+    };
+    Ok((kdf_config, header.transform_seed))
+}
+
+/// Hash the key elements into the composite key, which unlike in KDBX databases is not hashed
+/// again if the key consists of a single element
+pub(crate) fn composite_key(db_key: &DatabaseKey) -> Result<GenericArray<u8, U32>, DatabaseKeyError> {
+    let key_elements = db_key.get_key_elements()?;
+    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+    if let [key_element] = key_elements.as_slice() {
+        let key_element: [u8; 32] = (*key_element)
+            .try_into()
+            .map_err(|_| DatabaseKeyError::InvalidKeyFile)?;
+        Ok(GenericArray::from(key_element)) // single pass of SHA256, already done before the call to parse()
+    } else {
+        Ok(calculate_sha256(&key_elements)?) // second pass of SHA256
+    }
+}
+
 fn from_utf8(data: &[u8]) -> String {
     String::from_utf8_lossy(data).trim_end_matches('\0').to_owned()
 }
@@ -67,15 +94,57 @@ fn ensure_length(
     }
 }
 
-fn entry_name(field_type: u16) -> &'static str {
+fn entry_name(field_type: u16) -> Option<&'static str> {
     match field_type {
-        0x0004 => "Title",
-        0x0005 => "URL",
-        0x0006 => "UserName",
-        0x0008 => "Additional",
-        0x000d => "BinaryDesc",
+        0x0004 => Some("Title"),
+        0x0005 => Some("URL"),
+        0x0006 => Some("UserName"),
+        0x0008 => Some("Notes"),
+        _ => None,
+    }
+}
+
+/// Group flag of KeePass 1.x for groups that are expanded in the tree view
+const GROUP_FLAG_EXPANDED: u32 = 1;
+
+/// Name of the group that KeePass 1.x moves deleted entries and old versions of entries to
+const BACKUP_GROUP_NAME: &str = "Backup";
+
+/// Parse a timestamp, which is packed into 5 bytes as year (14 bits), month (4), day (5),
+/// hour (5), minute (6) and second (6)
+fn parse_time(data: &[u8]) -> Option<NaiveDateTime> {
+    let b: [u8; 5] = data.try_into().ok()?;
+    let [b0, b1, b2, b3, b4] = b.map(u32::from);
+
+    let year = (b0 << 6) | (b1 >> 2);
+    let month = ((b1 & 0x03) << 2) | (b2 >> 6);
+    let day = (b2 >> 1) & 0x1f;
+    let hour = ((b2 & 0x01) << 4) | (b3 >> 4);
+    let minute = ((b3 & 0x0f) << 2) | (b4 >> 6);
+    let second = b4 & 0x3f;
+
+    NaiveDate::from_ymd_opt(year as i32, month, day)?.and_hms_opt(hour, minute, second)
+}
+
+/// Whether an expiry time is the one that KeePass 1.x uses for items that never expire
+fn is_never(time: &NaiveDateTime) -> bool {
+    NaiveDate::from_ymd_opt(2999, 12, 28).is_some_and(|never| time.date() == never)
+}
+
+/// Set the time field of the given KDB field type, where 0 is the creation time
+fn set_time(times: &mut Times, index: u16, data: &[u8]) {
+    let time = match parse_time(data) {
+        Some(time) => time,
+        None => return,
+    };
+
+    match index {
+        0 => times.set_creation(time),
+        1 => times.set_last_modification(time),
+        2 => times.set_last_access(time),
         _ => {
-            panic!("Unsupported field type!");
+            times.expires = !is_never(&time);
+            times.set_expiry(time);
         }
     }
 }
@@ -95,6 +164,18 @@ fn collapse_tail_groups(branch: &mut Vec<Group>, level: usize, root: &mut Group)
 // A map from a GroupId to a path identifying (by name) a group in the group tree.
 type GidMap = HashMap<u32, Vec<String>>;
 
+/// Get the UUID of a KDB group from its group id.
+///
+/// KDB groups are only identified by numbers, so the number is embedded in the last bytes of an
+/// otherwise fixed UUID (version 8, i.e. custom). This way, opening the same file twice yields
+/// the same groups, which is needed e.g. to merge the two. The root group, which is not stored
+/// in the file, uses the group id 0, which KeePass never assigns.
+fn kdb_group_uuid(group_id: u32) -> Uuid {
+    let mut bytes = *b"KDB1gr\x80\x00\x80\x00\x00\x00\x00\x00\x00\x00";
+    bytes[12..].copy_from_slice(&group_id.to_be_bytes());
+    Uuid::from_bytes(bytes)
+}
+
 fn parse_groups(
     root: &mut Group,
     header_num_groups: u32,
@@ -103,16 +184,16 @@ fn parse_groups(
     // Loop over group TLVs
     let mut gid_map: HashMap<u32, Vec<String>> = HashMap::new(); // the gid to group path map
     let mut branch: Vec<Group> = Vec::new(); // the current branch in the group tree
-    let mut group: Group = Default::default(); // the current group (will be added as a leaf of the branch)
+    let mut group: Group = new_group(); // the current group (will be added as a leaf of the branch)
     let mut level: Option<u16> = None; // the current group's level
     let mut gid: Option<u32> = None; // the current group's id
     let mut group_path: Vec<String> = Vec::new(); // the current group path
     let mut num_groups = 0; // the total number of parsed groups
     while num_groups < header_num_groups as usize {
         // Read group TLV
-        let field_type = LittleEndian::read_u16(&data[0..]);
-        let field_size = LittleEndian::read_u32(&data[2..]);
-        let field_value = &data[6..6 + field_size as usize];
+        let field_type = LittleEndian::read_u16(get_bytes(data, 0, 2)?);
+        let field_size = LittleEndian::read_u32(get_bytes(data, 2, 4)?);
+        let field_value = get_bytes(data, 6, field_size as usize)?;
 
         match field_type {
             0x0000 => {} // KeePass ignores this field type
@@ -125,10 +206,12 @@ fn parse_groups(
             0x0003..=0x0006 => {
                 // Creation/LastMod/LastAccess/Expire
                 ensure_length(field_type, field_size, 5)?;
+                set_time(&mut group.times, field_type - 0x0003, field_value);
             }
             0x0007 => {
                 //ImageId
                 ensure_length(field_type, field_size, 4)?;
+                group.icon_id = Some(LittleEndian::read_u32(field_value) as usize);
             }
             0x0008 => {
                 // Level
@@ -138,10 +221,14 @@ fn parse_groups(
             0x0009 => {
                 // Flags
                 ensure_length(field_type, field_size, 4)?;
+                group.is_expanded = LittleEndian::read_u32(field_value) & GROUP_FLAG_EXPANDED != 0;
             }
             0xffff => {
                 ensure_length(field_type, field_size, 0)?;
 
+                let group_id = gid.ok_or_else(|| DatabaseIntegrityError::MissingKDBGroupId)?;
+                group.uuid = kdb_group_uuid(group_id);
+
                 let level = level.ok_or_else(|| DatabaseIntegrityError::MissingKDBGroupLevel)? as usize;
 
                 // Update the current group tree branch (collapse previous sub-branch, initiate
@@ -163,9 +250,8 @@ fn parse_groups(
                 }
 
                 // Update the GroupId map and reset state for the next group
-                let group_id = gid.ok_or_else(|| DatabaseIntegrityError::MissingKDBGroupId)?;
                 gid_map.insert(group_id, group_path.clone());
-                group = Default::default();
+                group = new_group();
                 gid = None;
                 num_groups += 1;
             }
@@ -174,7 +260,7 @@ fn parse_groups(
             }
         }
 
-        *data = &data[6 + field_size as usize..];
+        *data = get_tail(data, 6 + field_size as usize)?;
     }
     if gid != None {
         return Err(DatabaseIntegrityError::IncompleteKDBGroup);
@@ -187,25 +273,29 @@ fn parse_groups(
 
 fn parse_entries(
     root: &mut Group,
+    meta: &mut Meta,
     gid_map: GidMap,
     header_num_entries: u32,
     data: &mut &[u8],
 ) -> Result<(), DatabaseIntegrityError> {
     // Loop over entry TLVs
-    let mut entry: Entry = Default::default(); // the current entry
+    let mut entry: Entry = new_entry(); // the current entry
     let mut gid: Option<u32> = None; // the current entry's group id
+    let mut attachment_name = String::new(); // the name of the current entry's attachment
+    let mut attachment: Vec<u8> = Vec::new(); // the content of the current entry's attachment
     let mut num_entries = 0;
     while num_entries < header_num_entries {
         // Read entry TLV
-        let field_type = LittleEndian::read_u16(&data[0..]);
-        let field_size = LittleEndian::read_u32(&data[2..]);
-        let field_value = &data[6..6 + field_size as usize];
+        let field_type = LittleEndian::read_u16(get_bytes(data, 0, 2)?);
+        let field_size = LittleEndian::read_u32(get_bytes(data, 2, 4)?);
+        let field_value = get_bytes(data, 6, field_size as usize)?;
 
         match field_type {
             0x0000 => {} // KeePass ignores this field type
             0x0001 => {
                 // uuid
                 ensure_length(field_type, field_size, 16)?;
+                entry.uuid = Uuid::from_slice(field_value).unwrap_or_default();
             }
             0x0002 => {
                 // GroupId
@@ -215,31 +305,29 @@ fn parse_entries(
             0x0003 => {
                 // ImageId
                 ensure_length(field_type, field_size, 4)?;
+                entry.icon_id = Some(LittleEndian::read_u32(field_value) as usize);
             }
-            0x0004 | 0x0005 | 0x0006 | 0x0008 | 0x000d => {
-                // Title/URL/UserName/Additional/BinaryDesc
-                entry.fields.insert(
-                    String::from(entry_name(field_type)),
-                    Value::Unprotected(from_utf8(field_value)),
-                );
+            0x0004 | 0x0005 | 0x0006 | 0x0008 => {
+                // Title/URL/UserName/Additional
+                let name = entry_name(field_type)
+                    .ok_or(DatabaseIntegrityError::InvalidKDBEntryFieldType { field_type })?;
+                entry
+                    .fields
+                    .insert(name.into(), Value::Unprotected(from_utf8(field_value)));
             }
             0x0007 => {
                 // Password
-                entry.fields.insert(
-                    String::from("Password"),
-                    Value::Protected(from_utf8(field_value).into()),
-                );
+                entry
+                    .fields
+                    .insert("Password".into(), Value::Protected(from_utf8(field_value).into()));
             }
             0x0009..=0x000c => {
                 // Creation/LastMod/LastAccess/Expire
                 ensure_length(field_type, field_size, 5)?;
+                set_time(&mut entry.times, field_type - 0x0009, field_value);
             }
-            0x000e => {
-                // BinaryData
-                entry
-                    .fields
-                    .insert(String::from("BinaryData"), Value::Bytes(field_value.to_vec()));
-            }
+            0x000d => attachment_name = from_utf8(field_value), // BinaryDesc
+            0x000e => attachment = field_value.to_vec(),        // BinaryData
             0xffff => {
                 ensure_length(field_type, field_size, 0)?;
 
@@ -251,15 +339,28 @@ fn parse_entries(
                     .map(|v| v.as_str())
                     .collect();
 
-                let group = root.get_mut(group_path.as_slice());
-                let group = if let Some(NodeRefMut::Group(g)) = group {
-                    g
-                } else {
-                    panic!("Follow group_path")
+                let group = match root.get_mut(group_path.as_slice()) {
+                    Some(NodeRefMut::Group(g)) => g,
+                    _ => return Err(DatabaseIntegrityError::InvalidKDBGroupId { group_id }),
                 };
 
+                // every entry can have a single attachment, which goes into the binary pool
+                if !attachment_name.is_empty() || !attachment.is_empty() {
+                    let identifier = meta.binaries.binaries.len().to_string();
+                    meta.binaries.binaries.push(BinaryAttachment {
+                        identifier: Some(identifier.clone()),
+                        compressed: false,
+                        protected: false,
+                        content: std::mem::take(&mut attachment),
+                    });
+                    entry.attachments.push(AttachmentRef {
+                        name: std::mem::take(&mut attachment_name),
+                        identifier,
+                    });
+                }
+
                 group.add_child(entry);
-                entry = Default::default();
+                entry = new_entry();
                 gid = None;
                 num_entries += 1;
             }
@@ -268,7 +369,7 @@ fn parse_entries(
             }
         }
 
-        *data = &data[6 + field_size as usize..];
+        *data = get_tail(data, 6 + field_size as usize)?;
     }
     if gid != None {
         return Err(DatabaseIntegrityError::IncompleteKDBEntry.into());
@@ -277,46 +378,70 @@ fn parse_entries(
     Ok(())
 }
 
-fn parse_db(header: &KDBHeader, data: &[u8]) -> Result<Group, DatabaseIntegrityError> {
-    let mut root = Group {
-        name: "Root".to_owned(),
-        ..Default::default()
-    };
+fn parse_db(
+    header: &KDBHeader,
+    data: &[u8],
+    options: &OpenOptions,
+) -> Result<(Group, Meta), DatabaseIntegrityError> {
+    let mut root = Group::new("Root");
+    root.uuid = kdb_group_uuid(0);
+    let mut meta = Meta::default();
 
-    let mut pos = &data[..];
+    let mut pos = data;
 
     let gid_map = parse_groups(&mut root, header.num_groups, &mut pos)?;
 
-    parse_entries(&mut root, gid_map, header.num_entries, &mut pos)?;
+    parse_entries(&mut root, &mut meta, gid_map, header.num_entries, &mut pos)?;
 
-    Ok(root)
+    if options.kdb_backup_as_recycle_bin {
+        if let Some(backup) = root.groups().into_iter().find(|g| g.name == BACKUP_GROUP_NAME) {
+            meta.recyclebin_enabled = Some(true);
+            meta.recyclebin_uuid = Some(backup.uuid);
+        }
+    }
+
+    Ok((root, meta))
 }
 
-pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
-    let header = parse_header(data)?;
+/// A group without any times, which are set from the fields of the file
+fn new_group() -> Group {
+    Group {
+        times: Times::default(),
+        ..Group::new("")
+    }
+}
+
+/// An entry without any times, which are set from the fields of the file
+fn new_entry() -> Entry {
+    Entry {
+        times: Times::default(),
+        ..Entry::new()
+    }
+}
+
+pub(crate) fn parse_kdb(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+) -> Result<Database, DatabaseOpenError> {
+    let header = phase!("header", parse_header(data))?;
     let version = DatabaseVersion::KDB(header.subversion as u16);
 
     // Rest of file after header is payload
-    let payload_encrypted = &data[HEADER_SIZE..];
+    let payload_encrypted = get_tail(data, HEADER_SIZE)?;
 
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
-    let key_elements = db_key.get_key_elements()?;
-    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = if key_elements.len() == 1 {
-        let key_element: [u8; 32] = key_elements[0].try_into().unwrap();
-        GenericArray::from(key_element) // single pass of SHA256, already done before the call to parse()
-    } else {
-        calculate_sha256(&key_elements)? // second pass of SHA256
-    };
+    let composite_key = composite_key(db_key)?;
 
     // KDF is always AES
     let kdf_config = KdfConfig::Aes {
         rounds: header.transform_rounds as u64,
     };
 
-    let transformed_key = kdf_config
-        .get_kdf_seeded(&header.transform_seed)
-        .transform_key(&composite_key)?;
+    let transformed_key = phase!(
+        "kdf",
+        db_key.transform_key(&kdf_config, &header.transform_seed, &composite_key)
+    )?;
 
     let master_key = calculate_sha256(&[&header.master_seed, &transformed_key])?;
 
@@ -329,11 +454,18 @@ pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, D
     };
 
     // Decrypt payload
-    let payload_padded = outer_cipher_config
-        .get_cipher(&master_key, header.encryption_iv.as_ref())?
-        .decrypt(payload_encrypted)?;
-    let padlen = payload_padded[payload_padded.len() - 1] as usize;
-    let payload = &payload_padded[..payload_padded.len() - padlen];
+    let payload_padded = phase!(
+        "decrypt",
+        outer_cipher_config
+            .get_cipher(&master_key, header.encryption_iv.as_ref())?
+            .decrypt(payload_encrypted)
+    )?;
+    let padlen = *payload_padded.last().ok_or(DatabaseKeyError::IncorrectKey)? as usize;
+    let payload = payload_padded
+        .len()
+        .checked_sub(padlen)
+        .and_then(|len| payload_padded.get(..len))
+        .ok_or(DatabaseKeyError::IncorrectKey)?;
 
     // Check if we decrypted correctly
     let hash = calculate_sha256(&[&payload])?;
@@ -341,7 +473,7 @@ pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, D
         return Err(DatabaseKeyError::IncorrectKey.into());
     }
 
-    let root_group = parse_db(&header, &payload)?;
+    let (root_group, meta) = parse_db(&header, &payload, options)?;
 
     let config = DatabaseConfig {
         version,
@@ -349,6 +481,8 @@ pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, D
         compression_config: CompressionConfig::None,
         inner_cipher_config: InnerCipherConfig::Plain,
         kdf_config,
+        public_custom_data: Default::default(),
+        deleted_objects_retention: None,
     };
 
     Ok(Database {
@@ -356,6 +490,7 @@ pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, D
         header_attachments: Default::default(),
         root: root_group,
         deleted_objects: Default::default(),
-        meta: Default::default(),
+        meta,
+        save_hooks: Default::default(),
     })
 }
@@ -1,15 +1,20 @@
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-    crypt::{calculate_sha256, ciphers::Cipher},
+    compression::LimitedReader,
+    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OpenOptions, OuterCipherConfig},
+    crypt::{
+        calculate_sha256,
+        ciphers::{Cipher, LazyDecryptor},
+    },
     db::Database,
     error::{BlockStreamError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
-    format::DatabaseVersion,
+    format::{get_byte, get_bytes, get_tail, payload_read_error, DatabaseVersion},
     key::DatabaseKey,
+    trace::phase,
 };
 
 use byteorder::{ByteOrder, LittleEndian};
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, io::Read};
 
 #[derive(Debug)]
 struct KDBX3Header {
@@ -28,6 +33,12 @@ struct KDBX3Header {
     body_start: usize,
 }
 
+/// Get the KDF parameters and the seed of a KDBX3 database
+pub(crate) fn kdf_parameters(data: &[u8]) -> Result<(KdfConfig, Vec<u8>), DatabaseOpenError> {
+    let header = parse_outer_header(data)?;
+    Ok((header.kdf_config, header.transform_seed))
+}
+
 fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
     let mut outer_cipher: Option<OuterCipherConfig> = None;
     let mut compression: Option<CompressionConfig> = None;
@@ -54,9 +65,9 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
         //   entry_buffer: [u8; entry_length]       // the entry buffer
         // )
 
-        let entry_type = data[pos];
-        let entry_length: usize = LittleEndian::read_u16(&data[pos + 1..(pos + 3)]) as usize;
-        let entry_buffer = &data[(pos + 3)..(pos + 3 + entry_length)];
+        let entry_type = get_byte(data, pos)?;
+        let entry_length: usize = LittleEndian::read_u16(get_bytes(data, pos + 1, 2)?) as usize;
+        let entry_buffer = get_bytes(data, pos + 3, entry_length)?;
 
         pos += 3 + entry_length;
 
@@ -80,7 +91,7 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
             // COMPRESSIONFLAGS - first byte determines compression of payload
             3 => {
                 compression = Some(
-                    CompressionConfig::try_from(LittleEndian::read_u32(&entry_buffer))
+                    CompressionConfig::try_from(LittleEndian::read_u32(get_bytes(entry_buffer, 0, 4)?))
                         .map_err(|e| DatabaseIntegrityError::from(e))?,
                 );
             }
@@ -92,7 +103,7 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
             5 => transform_seed = Some(entry_buffer.to_vec()),
 
             // TRANSFORMROUNDS - Number of rounds used in derivation of transformed key
-            6 => transform_rounds = Some(LittleEndian::read_u64(entry_buffer)),
+            6 => transform_rounds = Some(LittleEndian::read_u64(get_bytes(entry_buffer, 0, 8)?)),
 
             // ENCRYPTIONIV - Initialization Vector for decrypting the payload
             7 => outer_iv = Some(entry_buffer.to_vec()),
@@ -107,7 +118,7 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
             //                       to use for decrypting the inner protected values
             10 => {
                 inner_cipher = Some(
-                    InnerCipherConfig::try_from(LittleEndian::read_u32(entry_buffer))
+                    InnerCipherConfig::try_from(LittleEndian::read_u32(get_bytes(entry_buffer, 0, 4)?))
                         .map_err(|e| DatabaseIntegrityError::from(e))?,
                 );
             }
@@ -160,12 +171,22 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
 }
 
 /// Open, decrypt and parse a KeePass database from a source and a password
-pub(crate) fn parse_kdbx3(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
-    let (config, mut inner_decryptor, xml) = decrypt_kdbx3(data, db_key)?;
-
-    // Parse XML data blocks
-    let database_content = crate::xml_db::parse::parse(&xml, &mut *inner_decryptor)
-        .map_err(|e| DatabaseIntegrityError::from(e))?;
+pub(crate) fn parse_kdbx3(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+) -> Result<Database, DatabaseOpenError> {
+    let (config, mut inner_decryptor, mut payload) = open_kdbx3(data, db_key, options)?;
+
+    // Parse XML data blocks while they are being decompressed
+    let database_content = phase!(
+        "xml_parse",
+        crate::xml_db::parse::parse_reader(&mut payload, &mut *inner_decryptor)
+    )
+    .map_err(|e| match payload.exceeded() {
+        Some(limit) => DatabaseOpenError::PayloadTooLarge { limit },
+        None => DatabaseIntegrityError::from(e).into(),
+    })?;
 
     let db = Database {
         config,
@@ -173,6 +194,7 @@ pub(crate) fn parse_kdbx3(data: &[u8], db_key: &DatabaseKey) -> Result<Database,
         root: database_content.root.group,
         deleted_objects: database_content.root.deleted_objects,
         meta: database_content.meta,
+        save_hooks: Default::default(),
     };
 
     Ok(db)
@@ -182,18 +204,42 @@ pub(crate) fn parse_kdbx3(data: &[u8], db_key: &DatabaseKey) -> Result<Database,
 pub(crate) fn decrypt_kdbx3(
     data: &[u8],
     db_key: &DatabaseKey,
+    options: &OpenOptions,
 ) -> Result<(DatabaseConfig, Box<dyn Cipher>, Vec<u8>), DatabaseOpenError> {
+    let (config, inner_decryptor, mut payload) = open_kdbx3(data, db_key, options)?;
+
+    let mut xml = Vec::new();
+    phase!("decompress", payload.read_to_end(&mut xml)).map_err(|e| payload_read_error(&payload, e))?;
+
+    Ok((config, inner_decryptor, xml))
+}
+
+/// Decrypt a KeePass KDBX3 database, returning a reader for the XML document that decompresses
+/// it on the fly
+#[allow(clippy::type_complexity)]
+fn open_kdbx3(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+) -> Result<(DatabaseConfig, Box<dyn Cipher>, LimitedReader<Box<dyn Read>>), DatabaseOpenError> {
     let version = DatabaseVersion::parse(data)?;
-    let header = parse_outer_header(data)?;
+    let header = phase!("header", parse_outer_header(data))?;
 
     // Derive stream key for decrypting inner protected values and set up decryption context
     let stream_key = calculate_sha256(&[header.protected_stream_key.as_ref()])
         .map_err(|e| DatabaseIntegrityError::from(e))?;
 
-    let inner_decryptor = header
-        .inner_cipher
-        .get_cipher(&stream_key)
-        .map_err(|e| DatabaseIntegrityError::from(e))?;
+    let inner_decryptor: Box<dyn Cipher> = if options.lazy_protected_values {
+        Box::new(
+            LazyDecryptor::new(header.inner_cipher.clone(), &stream_key)
+                .map_err(DatabaseIntegrityError::from)?,
+        )
+    } else {
+        header
+            .inner_cipher
+            .get_cipher(&stream_key)
+            .map_err(|e| DatabaseIntegrityError::from(e))?
+    };
 
     let config = DatabaseConfig {
         version,
@@ -201,6 +247,8 @@ pub(crate) fn decrypt_kdbx3(
         compression_config: header.compression,
         inner_cipher_config: header.inner_cipher,
         kdf_config: header.kdf_config,
+        public_custom_data: Default::default(),
+        deleted_objects_retention: None,
     };
 
     let mut pos = header.body_start;
@@ -209,29 +257,33 @@ pub(crate) fn decrypt_kdbx3(
     let compression = config.compression_config.get_compression();
 
     // Rest of file after header is payload
-    let payload_encrypted = &data[pos..];
+    let payload_encrypted = get_tail(data, pos)?;
+    if payload_encrypted.is_empty() {
+        return Err(DatabaseIntegrityError::Truncated { offset: data.len() }.into());
+    }
 
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
-    let key_elements = db_key.get_key_elements()?;
-    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = calculate_sha256(&key_elements)?;
+    let composite_key = db_key.composite_key()?;
 
     // transform the key
-    let transformed_key = config
-        .kdf_config
-        .get_kdf_seeded(&header.transform_seed)
-        .transform_key(&composite_key)?;
+    let transformed_key = phase!(
+        "kdf",
+        db_key.transform_key(&config.kdf_config, &header.transform_seed, &composite_key)
+    )?;
 
     let master_key = calculate_sha256(&[header.master_seed.as_ref(), &transformed_key])?;
 
     // Decrypt payload
-    let payload = config
-        .outer_cipher_config
-        .get_cipher(&master_key, header.outer_iv.as_ref())?
-        .decrypt(payload_encrypted)?;
+    let payload = phase!(
+        "decrypt",
+        config
+            .outer_cipher_config
+            .get_cipher(&master_key, header.outer_iv.as_ref())?
+            .decrypt(payload_encrypted)
+    )?;
 
     // Check if we decrypted correctly
-    if &payload[0..header.stream_start.len()] != header.stream_start.as_slice() {
+    if payload.get(0..header.stream_start.len()) != Some(header.stream_start.as_slice()) {
         return Err(DatabaseKeyError::IncorrectKey.into());
     }
 
@@ -252,15 +304,15 @@ pub(crate) fn decrypt_kdbx3(
         // )
 
         // let block_id = LittleEndian::read_u32(&payload[pos..(pos + 4)]);
-        let block_hash = &payload[(pos + 4)..(pos + 36)];
-        let block_size = LittleEndian::read_u32(&payload[(pos + 36)..(pos + 40)]) as usize;
+        let block_hash = get_bytes(&payload, pos + 4, 32)?;
+        let block_size = LittleEndian::read_u32(get_bytes(&payload, pos + 36, 4)?) as usize;
 
         // A block with size 0 means we have hit EOF
         if block_size == 0 {
             break;
         }
 
-        let block_buffer_compressed = &payload[(pos + 40)..(pos + 40 + block_size)];
+        let block_buffer_compressed = get_bytes(&payload, pos + 40, block_size)?;
 
         // Test block hash
         let block_hash_check = calculate_sha256(&[&block_buffer_compressed])?;
@@ -275,7 +327,7 @@ pub(crate) fn decrypt_kdbx3(
         block_index += 1;
     }
 
-    let xml = compression.decompress(&buf)?;
+    let payload = LimitedReader::new(compression.decompress_reader(buf), options.max_decompressed_size);
 
-    Ok((config, inner_decryptor, xml))
+    Ok((config, inner_decryptor, payload))
 }
@@ -2,35 +2,100 @@ use std::io::Write;
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
+use cipher::generic_array::{
+    typenum::{U32, U64},
+    GenericArray,
+};
+use zeroize::Zeroize;
+
 use crate::{
+    config::{DatabaseConfig, OuterCipherConfig},
     crypt,
-    db::{Database, HeaderAttachment},
+    db::{Database, HeaderAttachment, IncrementalSaveReport},
     error::DatabaseSaveError,
     format::{
         kdbx4::{
             KDBX4InnerHeader, KDBX4OuterHeader, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END,
             HEADER_KDF_PARAMS, HEADER_MASTER_SEED, HEADER_MASTER_SEED_SIZE, HEADER_OUTER_ENCRYPTION_ID,
-            INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END, INNER_HEADER_RANDOM_STREAM_ID,
-            INNER_HEADER_RANDOM_STREAM_KEY,
+            HEADER_PUBLIC_CUSTOM_DATA, INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END,
+            INNER_HEADER_RANDOM_STREAM_ID, INNER_HEADER_RANDOM_STREAM_KEY,
         },
         DatabaseVersion,
     },
     hmac_block_stream,
     io::WriteLengthTaggedExt,
     key::DatabaseKey,
+    trace::phase,
     variant_dictionary::VariantDictionary,
 };
 
+/// Size of the blocks of the CBC ciphers for the outer encryption
+const CBC_BLOCK_SIZE: usize = 16;
+
+/// Size of the HMAC and length that precede every block of a HMAC block stream
+const HMAC_BLOCK_HEADER_SIZE: usize = 36;
+
 /// Dump a KeePass database using the key elements
 pub fn dump_kdbx4(
     db: &Database,
     db_key: &DatabaseKey,
     writer: &mut dyn Write,
 ) -> Result<(), DatabaseSaveError> {
+    let db = db.with_save_hooks_applied();
+    let file = encrypt_kdbx4(&db, db_key)?;
+
+    writer.write_all(&file.header)?;
+    writer.write_all(&file.block_stream)?;
+
+    Ok(())
+}
+
+/// The parts of a saved KDBX4 file that an incremental save can reuse
+pub(crate) struct SavedKdbx4 {
+    key: DatabaseKey,
+    config: DatabaseConfig,
+
+    /// Outer header, followed by its SHA-256 hash and HMAC
+    header: Vec<u8>,
+    master_key: GenericArray<u8, U32>,
+    hmac_key: GenericArray<u8, U64>,
+    outer_iv: Vec<u8>,
+    inner_random_stream_key: Vec<u8>,
+
+    /// Compressed plaintext of the payload
+    payload: Vec<u8>,
+    payload_encrypted: Vec<u8>,
+    block_stream: Vec<u8>,
+}
+
+impl Drop for SavedKdbx4 {
+    fn drop(&mut self) {
+        self.master_key.as_mut_slice().zeroize();
+        self.hmac_key.as_mut_slice().zeroize();
+        self.inner_random_stream_key.zeroize();
+        self.payload.zeroize();
+    }
+}
+
+/// Encrypt a database into a KDBX4 file with freshly generated seeds
+fn encrypt_kdbx4(db: &Database, db_key: &DatabaseKey) -> Result<SavedKdbx4, DatabaseSaveError> {
     if !matches!(db.config.version, DatabaseVersion::KDB4(_)) {
         return Err(DatabaseSaveError::UnsupportedVersion.into());
     }
 
+    // refuse to drop the features of a newer minor version
+    let required = db.required_minimum_version();
+    if let (DatabaseVersion::KDB4(minor), DatabaseVersion::KDB4(required_minor)) =
+        (&db.config.version, &required)
+    {
+        if minor < required_minor {
+            return Err(DatabaseSaveError::VersionTooLow {
+                version: db.config.version.to_string(),
+                required: required.to_string(),
+            });
+        }
+    }
+
     // generate encryption keys and seeds on the fly when saving
     let mut master_seed = vec![0; HEADER_MASTER_SEED_SIZE];
     getrandom::fill(&mut master_seed)?;
@@ -44,7 +109,9 @@ pub fn dump_kdbx4(
     let (kdf, kdf_seed) = db.config.kdf_config.get_kdf_and_seed()?;
 
     #[cfg(feature = "challenge_response")]
-    let db_key = db_key.clone().perform_challenge(&kdf_seed)?;
+    let challenged_key = db_key.clone().perform_challenge(&kdf_seed)?;
+    #[cfg(not(feature = "challenge_response"))]
+    let challenged_key = db_key;
 
     // dump the outer header - need to buffer so that SHA256 can be computed
     let mut header_data = Vec::new();
@@ -56,20 +123,17 @@ pub fn dump_kdbx4(
         outer_iv: outer_iv.clone(),
         kdf_config: db.config.kdf_config.clone(),
         kdf_seed,
+        public_custom_data: db.config.public_custom_data.clone(),
     }
     .dump(&mut header_data)?;
 
     let header_sha256 = crypt::calculate_sha256(&[&header_data])?;
 
-    // write out header and header hash
-    writer.write(&header_data)?;
-    writer.write(&header_sha256)?;
-
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
-    let key_elements = db_key.get_key_elements()?;
+    let key_elements = challenged_key.get_key_elements()?;
     let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
     let composite_key = crypt::calculate_sha256(&key_elements)?;
-    let transformed_key = kdf.transform_key(&composite_key)?;
+    let transformed_key = phase!("kdf", kdf.transform_key(&composite_key))?;
     let master_key = crypt::calculate_sha256(&[&master_seed, &transformed_key])?;
 
     // verify credentials
@@ -78,47 +142,197 @@ pub fn dump_kdbx4(
     let header_hmac_key = hmac_block_stream::get_hmac_block_key(u64::max_value(), &hmac_key)?;
     let header_hmac = crypt::calculate_hmac(&[&header_data], &header_hmac_key)?;
 
-    writer.write(&header_hmac)?;
+    // header, header hash and header HMAC are written out in this order
+    let mut header = header_data;
+    header.extend_from_slice(&header_sha256);
+    header.extend_from_slice(&header_hmac);
+
+    let payload = dump_payload(db, &inner_random_stream_key)?;
+
+    let (payload_encrypted, block_stream) = phase!("encrypt", {
+        let payload_encrypted = db
+            .config
+            .outer_cipher_config
+            .get_cipher(&master_key, &outer_iv)?
+            .encrypt(&payload)?;
+
+        let block_stream = hmac_block_stream::write_hmac_block_stream(&payload_encrypted, &hmac_key)?;
+        (payload_encrypted, block_stream)
+    });
+
+    Ok(SavedKdbx4 {
+        key: db_key.clone(),
+        config: db.config.clone(),
+        header,
+        master_key,
+        hmac_key,
+        outer_iv,
+        inner_random_stream_key,
+        payload,
+        payload_encrypted,
+        block_stream,
+    })
+}
 
+/// Dump the inner header and the XML document into a compressed, but not yet encrypted, payload
+fn dump_payload(db: &Database, inner_random_stream_key: &[u8]) -> Result<Vec<u8>, DatabaseSaveError> {
     // Initialize inner encryptor from inner header params
     let mut inner_cipher = db
         .config
         .inner_cipher_config
-        .get_cipher(&inner_random_stream_key)?;
+        .get_cipher(inner_random_stream_key)?;
 
     // dump inner header into buffer
     let mut payload = Vec::new();
     KDBX4InnerHeader {
         inner_random_stream: db.config.inner_cipher_config.clone(),
-        inner_random_stream_key,
+        inner_random_stream_key: inner_random_stream_key.to_vec(),
     }
     .dump(&db.header_attachments, &mut payload)?;
 
     // after inner header is one XML document
-    crate::xml_db::dump::dump(&db, &mut *inner_cipher, &mut payload)?;
-
-    let payload_compressed = db
-        .config
-        .compression_config
-        .get_compression()
-        .compress(&payload)?;
-
-    let payload_encrypted = db
-        .config
-        .outer_cipher_config
-        .get_cipher(&master_key, &outer_iv)?
-        .encrypt(&payload_compressed)?;
-
-    let payload_hmac = hmac_block_stream::write_hmac_block_stream(&payload_encrypted, &hmac_key)?;
-    writer.write(&payload_hmac)?;
+    #[cfg(feature = "validate")]
+    let xml_start = payload.len();
+    phase!(
+        "xml_dump",
+        crate::xml_db::dump::dump(&db, &mut *inner_cipher, &mut payload)
+    )?;
+
+    #[cfg(feature = "validate")]
+    phase!(
+        "validate",
+        crate::xml_db::validate::validate(
+            db,
+            payload.get(xml_start..).unwrap_or_default(),
+            &db.config.inner_cipher_config,
+            inner_random_stream_key,
+        )
+    )?;
+
+    let payload_compressed = phase!(
+        "compress",
+        db.config.compression_config.get_compression().compress(&payload)
+    )?;
+
+    Ok(payload_compressed)
+}
 
-    Ok(())
+/// Dump a KeePass database, re-using the encrypted blocks of the previous save in `saved` that
+/// did not change. Falls back to a full save if that is not possible.
+pub(crate) fn dump_kdbx4_incremental(
+    db: &Database,
+    db_key: &DatabaseKey,
+    saved: &mut Option<SavedKdbx4>,
+    writer: &mut dyn Write,
+) -> Result<IncrementalSaveReport, DatabaseSaveError> {
+    let db = &*db.with_save_hooks_applied();
+    let mut previous = match saved.take() {
+        // re-using the IV of a stream cipher would re-use its key stream
+        Some(previous)
+            if previous.config == db.config
+                && previous.key == *db_key
+                && previous.config.outer_cipher_config != OuterCipherConfig::ChaCha20 =>
+        {
+            previous
+        }
+        _ => {
+            let file = encrypt_kdbx4(db, db_key)?;
+            writer.write_all(&file.header)?;
+            writer.write_all(&file.block_stream)?;
+
+            let report = IncrementalSaveReport {
+                full_save: true,
+                reused_bytes: 0,
+                total_bytes: file.payload_encrypted.len(),
+            };
+            *saved = Some(file);
+            return Ok(report);
+        }
+    };
+
+    let payload = dump_payload(db, &previous.inner_random_stream_key)?;
+
+    // in CBC mode, every ciphertext block only depends on the plaintext up to that block, so the
+    // ciphertext of an unchanged prefix of full blocks stays valid. The last block of the previous
+    // save contains padding and can only be re-used if nothing changed.
+    let (payload_encrypted, reused_bytes) = phase!("encrypt", {
+        if payload == previous.payload {
+            (
+                previous.payload_encrypted.clone(),
+                previous.payload_encrypted.len(),
+            )
+        } else {
+            let common = payload
+                .iter()
+                .zip(&previous.payload)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let reused = common - common % CBC_BLOCK_SIZE;
+
+            let mut payload_encrypted = previous
+                .payload_encrypted
+                .get(..reused)
+                .unwrap_or_default()
+                .to_vec();
+
+            // the last re-used ciphertext block is the IV for the rest of the payload
+            let iv = reused
+                .checked_sub(CBC_BLOCK_SIZE)
+                .and_then(|start| payload_encrypted.get(start..))
+                .unwrap_or(&previous.outer_iv);
+
+            let rest = db
+                .config
+                .outer_cipher_config
+                .get_cipher(&previous.master_key, iv)?
+                .encrypt(payload.get(reused..).unwrap_or_default())?;
+            payload_encrypted.extend(rest);
+            (payload_encrypted, reused)
+        }
+    });
+
+    // HMAC blocks that only cover re-used ciphertext stay the same as well
+    let reused_blocks = reused_bytes / hmac_block_stream::HMAC_BLOCK_SIZE;
+    let mut block_stream = previous
+        .block_stream
+        .get(..reused_blocks * (HMAC_BLOCK_HEADER_SIZE + hmac_block_stream::HMAC_BLOCK_SIZE))
+        .unwrap_or_default()
+        .to_vec();
+    block_stream.extend(hmac_block_stream::write_hmac_blocks(
+        payload_encrypted
+            .get(reused_blocks * hmac_block_stream::HMAC_BLOCK_SIZE..)
+            .unwrap_or_default(),
+        &previous.hmac_key,
+        reused_blocks as u64,
+    )?);
+
+    // the end of the HMAC block stream should be an empty block, but with a valid HMAC
+    let block_count = payload_encrypted
+        .chunks(hmac_block_stream::HMAC_BLOCK_SIZE)
+        .count() as u64;
+    hmac_block_stream::write_hmac_block(&[], &previous.hmac_key, block_count, &mut block_stream)?;
+
+    writer.write_all(&previous.header)?;
+    writer.write_all(&block_stream)?;
+
+    let report = IncrementalSaveReport {
+        full_save: false,
+        reused_bytes,
+        total_bytes: payload_encrypted.len(),
+    };
+
+    previous.payload = payload;
+    previous.payload_encrypted = payload_encrypted;
+    previous.block_stream = block_stream;
+    *saved = Some(previous);
+
+    Ok(report)
 }
 
 impl HeaderAttachment {
     fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
         writer.write_u8(self.flags)?;
-        writer.write(&self.content)?;
+        writer.write_all(&self.content)?;
         Ok(())
     }
 }
@@ -146,6 +360,14 @@ impl KDBX4OuterHeader {
         writer.write_u8(HEADER_KDF_PARAMS)?;
         writer.write_with_len(&vd_buffer)?;
 
+        if !self.public_custom_data.is_empty() {
+            let mut custom_data_buffer = Vec::new();
+            self.public_custom_data.dump(&mut custom_data_buffer)?;
+
+            writer.write_u8(HEADER_PUBLIC_CUSTOM_DATA)?;
+            writer.write_with_len(&custom_data_buffer)?;
+        }
+
         writer.write_u8(HEADER_END)?;
         writer.write_with_len(&[])?;
 
@@ -159,7 +381,7 @@ impl KDBX4InnerHeader {
         header_attachments: &[HeaderAttachment],
         writer: &mut dyn Write,
     ) -> Result<(), DatabaseSaveError> {
-        writer.write(&[INNER_HEADER_RANDOM_STREAM_ID])?;
+        writer.write_all(&[INNER_HEADER_RANDOM_STREAM_ID])?;
         writer.write_u32::<LittleEndian>(4)?;
         writer.write_u32::<LittleEndian>(self.inner_random_stream.dump())?;
 
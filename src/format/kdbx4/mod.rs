@@ -3,13 +3,13 @@ mod dump;
 mod parse;
 
 use crate::{
-    config::{CompressionConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    config::{CompressionConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig, PublicCustomData},
     format::DatabaseVersion,
 };
 
 #[cfg(feature = "save_kdbx4")]
-pub(crate) use crate::format::kdbx4::dump::dump_kdbx4;
-pub(crate) use crate::format::kdbx4::parse::{decrypt_kdbx4, parse_kdbx4};
+pub(crate) use crate::format::kdbx4::dump::{dump_kdbx4, dump_kdbx4_incremental, SavedKdbx4};
+pub(crate) use crate::format::kdbx4::parse::{decrypt_kdbx4, kdf_parameters, parse_kdbx4};
 
 #[cfg(feature = "save_kdbx4")]
 /// Size for a master seed in bytes
@@ -29,6 +29,8 @@ pub const HEADER_MASTER_SEED: u8 = 4;
 pub const HEADER_ENCRYPTION_IV: u8 = 7;
 /// Parameters for the key derivation function
 pub const HEADER_KDF_PARAMS: u8 = 11;
+/// Unencrypted custom data of applications
+pub const HEADER_PUBLIC_CUSTOM_DATA: u8 = 12;
 
 /// Inner header entry denoting the end of the inner header
 pub const INNER_HEADER_END: u8 = 0x00;
@@ -47,6 +49,7 @@ struct KDBX4OuterHeader {
     outer_iv: Vec<u8>,
     kdf_config: KdfConfig,
     kdf_seed: Vec<u8>,
+    public_custom_data: PublicCustomData,
 }
 
 struct KDBX4InnerHeader {
@@ -56,17 +59,254 @@ struct KDBX4InnerHeader {
 
 #[cfg(feature = "save_kdbx4")]
 #[cfg(test)]
+#[allow(clippy::indexing_slicing)]
 mod kdbx4_tests {
     use super::*;
 
     use crate::format::kdbx4::dump::dump_kdbx4;
     use crate::{
-        config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-        db::{Database, Entry, Group, HeaderAttachment, NodeRef, Value},
+        config::{
+            CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OpenOptions, OuterCipherConfig,
+        },
+        db::{AttachmentRef, Database, Entry, Group, HeaderAttachment, NodeRef, Value},
+        error::DatabaseOpenError,
         format::KDBX4_CURRENT_MINOR_VERSION,
         key::DatabaseKey,
     };
 
+    #[test]
+    fn test_lazy_protected_values() {
+        for inner_cipher_config in [InnerCipherConfig::Salsa20, InnerCipherConfig::ChaCha20] {
+            let mut db = Database::new(DatabaseConfig {
+                kdf_config: KdfConfig::Aes { rounds: 10 },
+                inner_cipher_config,
+                ..Default::default()
+            });
+            for i in 0..5 {
+                let mut entry = Entry::new();
+                entry.set_title(&format!("Entry {}", i));
+                entry.set_password(&format!("secret {}", "x".repeat(i * 7)));
+                db.root.add_child(entry);
+            }
+            let db_key = DatabaseKey::new().with_password("test");
+            let mut data = Vec::new();
+            dump_kdbx4(&db, &db_key, &mut data).unwrap();
+
+            let options = OpenOptions {
+                lazy_protected_values: true,
+                ..Default::default()
+            };
+            let lazy = parse_kdbx4(&data, &db_key, &options).unwrap();
+            let encrypted: Vec<&Value> = lazy
+                .root
+                .entries()
+                .iter()
+                .map(|e| &e.fields["Password"])
+                .collect();
+            assert!(encrypted
+                .iter()
+                .all(|v| matches!(v, Value::Encrypted(e) if !e.is_decrypted())));
+
+            // values can be decrypted in any order
+            for i in [3, 0, 4] {
+                let entry = lazy.root.entries()[i];
+                assert_eq!(
+                    entry.get_password(),
+                    Some(format!("secret {}", "x".repeat(i * 7)).as_str())
+                );
+            }
+            assert!(matches!(encrypted[4], Value::Encrypted(e) if e.is_decrypted()));
+            assert!(matches!(encrypted[1], Value::Encrypted(e) if !e.is_decrypted()));
+
+            let eager = parse_kdbx4(&data, &db_key, &OpenOptions::default()).unwrap();
+            assert_eq!(lazy, eager);
+
+            let mut resaved = Vec::new();
+            dump_kdbx4(&lazy, &db_key, &mut resaved).unwrap();
+            assert_eq!(
+                parse_kdbx4(&resaved, &db_key, &OpenOptions::default()).unwrap(),
+                eager
+            );
+        }
+    }
+
+    #[test]
+    fn test_keys_regenerated_on_save() {
+        let db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        });
+        let db_key = DatabaseKey::new().with_password("test");
+
+        let mut first = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut first).unwrap();
+        let mut second = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut second).unwrap();
+
+        let (first_header, _) = parse::parse_outer_header(&first).unwrap();
+        let (second_header, _) = parse::parse_outer_header(&second).unwrap();
+        assert_ne!(first_header.master_seed, second_header.master_seed);
+        assert_ne!(first_header.outer_iv, second_header.outer_iv);
+        assert_ne!(first_header.kdf_seed, second_header.kdf_seed);
+
+        // different inner stream keys result in different key streams
+        let (_, _, mut first_inner, _) = decrypt_kdbx4(&first, &db_key, &Default::default()).unwrap();
+        let (_, _, mut second_inner, _) = decrypt_kdbx4(&second, &db_key, &Default::default()).unwrap();
+        assert_ne!(
+            first_inner.decrypt(&[0; 32]).unwrap(),
+            second_inner.decrypt(&[0; 32]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decompressed_size_limit() {
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            compression_config: CompressionConfig::GZip,
+            ..Default::default()
+        });
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: vec![0; 4096],
+        });
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut data).unwrap();
+
+        // the limits are exceeded within the attachment and within the XML document
+        assert!(data.len() < 4096);
+        for limit in [100, 4500] {
+            let options = OpenOptions {
+                max_decompressed_size: Some(limit),
+                ..Default::default()
+            };
+            assert!(matches!(
+                parse_kdbx4(&data, &db_key, &options),
+                Err(DatabaseOpenError::PayloadTooLarge { limit: l }) if l == limit
+            ));
+            assert!(matches!(
+                decrypt_kdbx4(&data, &db_key, &options),
+                Err(DatabaseOpenError::PayloadTooLarge { .. })
+            ));
+        }
+
+        let reopened = parse_kdbx4(&data, &db_key, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.header_attachments, db.header_attachments);
+        let options = OpenOptions {
+            max_decompressed_size: None,
+            ..Default::default()
+        };
+        assert!(parse_kdbx4(&data, &db_key, &options).is_ok());
+    }
+
+    #[test]
+    fn test_argon2_secret_and_associated_data() {
+        let kdf_config = KdfConfig::Argon2id {
+            iterations: 1,
+            memory: 65536,
+            parallelism: 1,
+            version: argon2::Version::Version13,
+            secret: Some(b"secret".to_vec()),
+            associated_data: Some(b"associated data".to_vec()),
+        };
+        let db = Database::new(DatabaseConfig {
+            kdf_config: kdf_config.clone(),
+            ..Default::default()
+        });
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut data).unwrap();
+
+        let reopened = parse_kdbx4(&data, &db_key, &Default::default()).unwrap();
+        assert_eq!(reopened.config.kdf_config, kdf_config);
+        assert!(format!("{:?}", kdf_config).contains("secret: Some(<redacted>)"));
+
+        // the secret changes the derived key
+        let (header, _) = parse::parse_outer_header(&data).unwrap();
+        let derive = |kdf_config: &KdfConfig| {
+            kdf_config
+                .get_kdf_seeded(&header.kdf_seed)
+                .transform_key(&Default::default())
+                .unwrap()
+        };
+        let mut without_secret = kdf_config.clone();
+        if let KdfConfig::Argon2id { secret, .. } = &mut without_secret {
+            *secret = None;
+        }
+        assert_ne!(derive(&kdf_config), derive(&without_secret));
+    }
+
+    /// Public custom data as written by KeePassXC, including an item of a type that is not
+    /// known to this library
+    fn keepassxc_public_custom_data() -> Vec<u8> {
+        let mut data = vec![0x00, 0x01];
+
+        let mut item = |value_type: u8, key: &str, value: &[u8]| {
+            data.push(value_type);
+            data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            data.extend_from_slice(key.as_bytes());
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(value);
+        };
+        item(0x0c, "KPXC_DECRYPTION_TIME_PREFERENCE", &1000i32.to_le_bytes());
+        item(0x18, "KPXC_RANDOM_SLUG", b"0f1e2d3c4b5a69788796a5b4c3d2e1f0");
+        item(0x77, "Unknown", &[1, 2, 3]);
+
+        data.push(0x00);
+        data
+    }
+
+    #[test]
+    fn test_public_custom_data() {
+        let raw = keepassxc_public_custom_data();
+        let mut custom_data = PublicCustomData::parse(&raw).unwrap();
+
+        assert_eq!(custom_data.decryption_time_preference(), Some(1000));
+        assert_eq!(
+            custom_data.random_slug().as_deref(),
+            Some("0f1e2d3c4b5a69788796a5b4c3d2e1f0")
+        );
+        assert_eq!(custom_data.get_u32("KPXC_DECRYPTION_TIME_PREFERENCE"), None);
+        let mut dumped = Vec::new();
+        custom_data.dump(&mut dumped).unwrap();
+        assert_eq!(dumped, raw);
+
+        let mut config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        };
+        custom_data.set_decryption_time_preference(2000);
+        custom_data.set_bool("MyApp.Flag", true);
+        config.public_custom_data = custom_data.clone();
+
+        let db = Database::new(config);
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut data).unwrap();
+
+        let reopened = parse_kdbx4(&data, &db_key, &Default::default()).unwrap();
+        assert_eq!(reopened.config.public_custom_data, custom_data);
+
+        let reopened = &reopened.config.public_custom_data;
+        assert_eq!(reopened.decryption_time_preference(), Some(2000));
+        assert_eq!(reopened.get_bool("MyApp.Flag"), Some(true));
+        assert_eq!(
+            reopened.keys().collect::<Vec<_>>(),
+            vec![
+                "KPXC_DECRYPTION_TIME_PREFERENCE",
+                "KPXC_RANDOM_SLUG",
+                "Unknown",
+                "MyApp.Flag"
+            ]
+        );
+
+        // the unknown item is written back unchanged
+        let unknown_item = b"\x77\x07\x00\x00\x00Unknown\x03\x00\x00\x00\x01\x02\x03";
+        let mut dumped = Vec::new();
+        reopened.dump(&mut dumped).unwrap();
+        assert!(dumped.windows(unknown_item.len()).any(|w| w == unknown_item));
+    }
+
     #[cfg(feature = "challenge_response")]
     #[test]
     fn test_with_challenge_response() {
@@ -95,7 +335,7 @@ mod kdbx4_tests {
         let mut encrypted_db = Vec::new();
         dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 3);
     }
@@ -108,11 +348,11 @@ mod kdbx4_tests {
         let mut entry_with_password = Entry::new();
         entry_with_password
             .fields
-            .insert("Title".to_string(), Value::Unprotected("Demo Entry".into()));
+            .insert("Title".into(), Value::Unprotected("Demo Entry".into()));
 
         entry_with_password
             .fields
-            .insert("Password".to_string(), Value::Protected("secret".into()));
+            .insert("Password".into(), Value::Protected("secret".into()));
 
         root_group.add_child(entry_with_password);
         root_group.add_child(Entry::new());
@@ -132,7 +372,7 @@ mod kdbx4_tests {
         let mut encrypted_db = Vec::new();
         dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 3);
 
@@ -166,12 +406,16 @@ mod kdbx4_tests {
                 memory: 65536,
                 parallelism: 2,
                 version: argon2::Version::Version13,
+                secret: None,
+                associated_data: None,
             },
             KdfConfig::Argon2id {
                 iterations: 10,
                 memory: 65536,
                 parallelism: 2,
                 version: argon2::Version::Version13,
+                secret: None,
+                associated_data: None,
             },
         ];
 
@@ -185,6 +429,8 @@ mod kdbx4_tests {
                             compression_config: compression_config.clone(),
                             inner_cipher_config: inner_cipher_config.clone(),
                             kdf_config: kdf_config.clone(),
+                            public_custom_data: Default::default(),
+                            deleted_objects_retention: None,
                         };
 
                         println!("Testing with config: {config:?}");
@@ -217,7 +463,7 @@ mod kdbx4_tests {
         let mut entry = Entry::new();
         entry
             .fields
-            .insert("Title".to_string(), Value::Unprotected("Demo entry".to_string()));
+            .insert("Title".into(), Value::Unprotected("Demo entry".to_string()));
 
         db.root.add_child(entry);
 
@@ -226,7 +472,7 @@ mod kdbx4_tests {
         let mut encrypted_db = Vec::new();
         dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 1);
 
@@ -235,4 +481,51 @@ mod kdbx4_tests {
         assert_eq!(header_attachments[0].flags, 1);
         assert_eq!(header_attachments[0].content, [0x01, 0x02, 0x03, 0x04]);
     }
+
+    #[test]
+    pub fn attachment_identifiers_are_stable() {
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        });
+
+        // binaries that are not referenced, or referenced out of order, keep their positions
+        for content in [b"unused", b"second", b"first_"] {
+            db.header_attachments.push(HeaderAttachment {
+                flags: 0,
+                content: content.to_vec(),
+            });
+        }
+
+        let mut entry = Entry::new();
+        for (name, identifier) in [("first.txt", "2"), ("second.txt", "1")] {
+            entry.attachments.push(AttachmentRef {
+                name: name.to_string(),
+                identifier: identifier.to_string(),
+            });
+        }
+        db.root.add_child(entry);
+
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        let mut reopened = parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
+        assert_eq!(reopened, db);
+
+        // new binaries are appended without renumbering the existing ones
+        let identifier = reopened.add_binary(b"new".to_vec(), false);
+        assert_eq!(identifier, "3");
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&reopened, &db_key, &mut encrypted_db).unwrap();
+        let reopened = parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
+
+        let entry = reopened.root.entries().into_iter().next().unwrap();
+        let identifiers: Vec<&str> = entry.attachments.iter().map(|a| a.identifier.as_str()).collect();
+        assert_eq!(identifiers, ["2", "1"]);
+        assert_eq!(
+            reopened.binary_content("2").map(|(content, _)| content),
+            Some(&b"first_"[..])
+        );
+        assert_eq!(reopened.header_attachments.len(), 4);
+    }
 }
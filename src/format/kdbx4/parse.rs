@@ -1,22 +1,35 @@
-use std::convert::{TryFrom, TryInto};
+use std::{
+    convert::{TryFrom, TryInto},
+    io::Read,
+};
 
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-    crypt::{self, ciphers::Cipher},
+    compression::LimitedReader,
+    config::{
+        CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OpenOptions, OuterCipherConfig,
+        PublicCustomData,
+    },
+    crypt::{
+        self,
+        ciphers::{Cipher, LazyDecryptor},
+    },
     db::{Database, HeaderAttachment},
     error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
     format::{
+        get_byte, get_bytes, get_tail,
         kdbx4::{
             KDBX4OuterHeader, HEADER_COMMENT, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END,
-            HEADER_KDF_PARAMS, HEADER_MASTER_SEED, HEADER_OUTER_ENCRYPTION_ID, INNER_HEADER_BINARY_ATTACHMENTS,
-            INNER_HEADER_END, INNER_HEADER_RANDOM_STREAM_ID, INNER_HEADER_RANDOM_STREAM_KEY,
+            HEADER_KDF_PARAMS, HEADER_MASTER_SEED, HEADER_OUTER_ENCRYPTION_ID, HEADER_PUBLIC_CUSTOM_DATA,
+            INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END, INNER_HEADER_RANDOM_STREAM_ID,
+            INNER_HEADER_RANDOM_STREAM_KEY,
         },
-        DatabaseVersion,
+        payload_read_error, DatabaseVersion,
     },
     hmac_block_stream,
     key::DatabaseKey,
+    trace::phase,
     variant_dictionary::VariantDictionary,
 };
 
@@ -24,18 +37,32 @@ use super::KDBX4InnerHeader;
 
 impl From<&[u8]> for HeaderAttachment {
     fn from(data: &[u8]) -> Self {
-        let flags = data[0];
-        let content = data[1..].to_vec();
+        let (flags, content) = match data.split_first() {
+            Some((flags, content)) => (*flags, content.to_vec()),
+            None => (0, Vec::new()),
+        };
 
         HeaderAttachment { flags, content }
     }
 }
 
 /// Open, decrypt and parse a KeePass database from a source and key elements
-pub(crate) fn parse_kdbx4(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
-    let (config, header_attachments, mut inner_decryptor, xml) = decrypt_kdbx4(data, db_key)?;
-
-    let database_content = crate::xml_db::parse::parse(&xml, &mut *inner_decryptor)?;
+pub(crate) fn parse_kdbx4(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+) -> Result<Database, DatabaseOpenError> {
+    let (config, header_attachments, mut inner_decryptor, mut payload) = open_kdbx4(data, db_key, options)?;
+
+    // the XML document is parsed while it is being decompressed
+    let database_content = phase!(
+        "xml_parse",
+        crate::xml_db::parse::parse_reader(&mut payload, &mut *inner_decryptor)
+    )
+    .map_err(|e| match payload.exceeded() {
+        Some(limit) => DatabaseOpenError::PayloadTooLarge { limit },
+        None => DatabaseIntegrityError::from(e).into(),
+    })?;
 
     let db = Database {
         config,
@@ -43,6 +70,7 @@ pub(crate) fn parse_kdbx4(data: &[u8], db_key: &DatabaseKey) -> Result<Database,
         root: database_content.root.group,
         deleted_objects: database_content.root.deleted_objects,
         meta: database_content.meta,
+        save_hooks: Default::default(),
     };
 
     Ok(db)
@@ -52,36 +80,76 @@ pub(crate) fn parse_kdbx4(data: &[u8], db_key: &DatabaseKey) -> Result<Database,
 pub(crate) fn decrypt_kdbx4(
     data: &[u8],
     db_key: &DatabaseKey,
+    options: &OpenOptions,
 ) -> Result<(DatabaseConfig, Vec<HeaderAttachment>, Box<dyn Cipher>, Vec<u8>), DatabaseOpenError> {
+    let (config, header_attachments, inner_decryptor, mut payload) = open_kdbx4(data, db_key, options)?;
+
+    let mut xml = Vec::new();
+    payload
+        .read_to_end(&mut xml)
+        .map_err(|e| payload_read_error(&payload, e))?;
+
+    Ok((config, header_attachments, inner_decryptor, xml))
+}
+
+/// Get the KDF parameters and the seed of a KDBX4 database
+pub(crate) fn kdf_parameters(data: &[u8]) -> Result<(KdfConfig, Vec<u8>), DatabaseOpenError> {
+    let (outer_header, _) = parse_outer_header(data)?;
+    Ok((outer_header.kdf_config, outer_header.kdf_seed))
+}
+
+/// Decrypt a KeePass KDBX4 database and read its inner header, returning a reader for the XML
+/// document that decompresses it on the fly
+#[allow(clippy::type_complexity)]
+fn open_kdbx4(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+) -> Result<
+    (
+        DatabaseConfig,
+        Vec<HeaderAttachment>,
+        Box<dyn Cipher>,
+        LimitedReader<Box<dyn Read>>,
+    ),
+    DatabaseOpenError,
+> {
     // parse header
-    let (outer_header, inner_header_start) = parse_outer_header(data)?;
+    let (outer_header, inner_header_start) = phase!("header", parse_outer_header(data))?;
 
     // split file into segments:
     //      header_data         - The outer header data
     //      header_sha256       - A Sha256 hash of header_data (for verification of header integrity)
     //      header_hmac         - A HMAC of the header_data (for verification of the key_elements)
     //      hmac_block_stream   - A HMAC-verified block stream of encrypted and compressed blocks
-    let header_data = &data[0..inner_header_start];
-    let header_sha256 = &data[inner_header_start..(inner_header_start + 32)];
-    let header_hmac = &data[(inner_header_start + 32)..(inner_header_start + 64)];
-    let hmac_block_stream = &data[(inner_header_start + 64)..];
+    let header_data = get_bytes(data, 0, inner_header_start)?;
+    let header_sha256 = get_bytes(data, inner_header_start, 32)?;
+    let header_hmac = get_bytes(data, inner_header_start + 32, 32)?;
+    let hmac_block_stream = get_tail(data, inner_header_start + 64)?;
 
     // verify header
     if header_sha256 != crypt::calculate_sha256(&[header_data])?.as_slice() {
         return Err(DatabaseIntegrityError::HeaderHashMismatch.into());
     }
 
+    // verify that the block stream is structurally complete before deriving the key, so that a
+    // truncated file is not reported as an incorrect key
+    if let Some(offset) = hmac_block_stream::find_truncation(hmac_block_stream) {
+        return Err(DatabaseIntegrityError::Truncated {
+            offset: inner_header_start + 64 + offset,
+        }
+        .into());
+    }
+
     #[cfg(feature = "challenge_response")]
     let db_key = db_key.clone().perform_challenge(&outer_header.kdf_seed)?;
 
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
-    let key_elements = db_key.get_key_elements()?;
-    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = crypt::calculate_sha256(&key_elements)?;
-    let transformed_key = outer_header
-        .kdf_config
-        .get_kdf_seeded(&outer_header.kdf_seed)
-        .transform_key(&composite_key)?;
+    let composite_key = db_key.composite_key()?;
+    let transformed_key = phase!(
+        "kdf",
+        db_key.transform_key(&outer_header.kdf_config, &outer_header.kdf_seed, &composite_key)
+    )?;
     let master_key = crypt::calculate_sha256(&[outer_header.master_seed.as_ref(), &transformed_key])?;
 
     // verify credentials
@@ -96,29 +164,40 @@ pub(crate) fn decrypt_kdbx4(
     }
 
     // read encrypted payload from hmac-verified block stream
-    let payload_encrypted = hmac_block_stream::read_hmac_block_stream(&hmac_block_stream, &hmac_key)?;
-
     // Decrypt and decompress encrypted payload
-    let payload_compressed = outer_header
-        .outer_cipher_config
-        .get_cipher(&master_key, &outer_header.outer_iv)?
-        .decrypt(&payload_encrypted)?;
-
-    let payload = outer_header
-        .compression_config
-        .get_compression()
-        .decompress(&payload_compressed)?;
+    let payload_compressed = phase!("decrypt", {
+        let payload_encrypted = hmac_block_stream::read_hmac_block_stream(&hmac_block_stream, &hmac_key)?;
+
+        outer_header
+            .outer_cipher_config
+            .get_cipher(&master_key, &outer_header.outer_iv)?
+            .decrypt(&payload_encrypted)?
+    });
+
+    let mut payload = LimitedReader::new(
+        outer_header
+            .compression_config
+            .get_compression()
+            .decompress_reader(payload_compressed),
+        options.max_decompressed_size,
+    );
 
     // KDBX4 has inner header, too - parse it
-    let (header_attachments, inner_header, body_start) = parse_inner_header(&payload)?;
+    let (header_attachments, inner_header) = phase!("decompress", read_inner_header(&mut payload))?;
 
-    // after inner header is one XML document
-    let xml = &payload[body_start..];
+    // after inner header is one XML document, which is left in the reader
 
     // initialize the inner decryptor
-    let inner_decryptor = inner_header
-        .inner_random_stream
-        .get_cipher(&inner_header.inner_random_stream_key)?;
+    let inner_decryptor: Box<dyn Cipher> = if options.lazy_protected_values {
+        Box::new(LazyDecryptor::new(
+            inner_header.inner_random_stream.clone(),
+            &inner_header.inner_random_stream_key,
+        )?)
+    } else {
+        inner_header
+            .inner_random_stream
+            .get_cipher(&inner_header.inner_random_stream_key)?
+    };
 
     let config = DatabaseConfig {
         version: outer_header.version,
@@ -126,12 +205,14 @@ pub(crate) fn decrypt_kdbx4(
         compression_config: outer_header.compression_config,
         inner_cipher_config: inner_header.inner_random_stream,
         kdf_config: outer_header.kdf_config,
+        public_custom_data: outer_header.public_custom_data,
+        deleted_objects_retention: None,
     };
 
-    Ok((config, header_attachments, inner_decryptor, xml.to_vec()))
+    Ok((config, header_attachments, inner_decryptor, payload))
 }
 
-fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), DatabaseOpenError> {
+pub(super) fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), DatabaseOpenError> {
     let version = DatabaseVersion::parse(data)?;
 
     // skip over the version header
@@ -143,6 +224,7 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
     let mut outer_iv: Option<Vec<u8>> = None;
     let mut kdf_config: Option<KdfConfig> = None;
     let mut kdf_seed: Option<Vec<u8>> = None;
+    let mut public_custom_data = PublicCustomData::default();
 
     // parse header
     loop {
@@ -156,9 +238,9 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
         //   entry_buffer: [u8; entry_length]       // the entry buffer
         // )
 
-        let entry_type = data[pos];
-        let entry_length: usize = LittleEndian::read_u32(&data[pos + 1..(pos + 5)]) as usize;
-        let entry_buffer = &data[(pos + 5)..(pos + 5 + entry_length)];
+        let entry_type = get_byte(data, pos)?;
+        let entry_length: usize = LittleEndian::read_u32(get_bytes(data, pos + 1, 4)?) as usize;
+        let entry_buffer = get_bytes(data, pos + 5, entry_length)?;
 
         pos += 5 + entry_length;
 
@@ -174,9 +256,11 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
             }
 
             HEADER_COMPRESSION_ID => {
-                compression_config = Some(CompressionConfig::try_from(LittleEndian::read_u32(
-                    &entry_buffer,
-                ))?);
+                compression_config = Some(CompressionConfig::try_from(LittleEndian::read_u32(get_bytes(
+                    entry_buffer,
+                    0,
+                    4,
+                )?))?);
             }
 
             HEADER_MASTER_SEED => master_seed = Some(entry_buffer.to_vec()),
@@ -190,6 +274,10 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
                 kdf_seed = Some(kseed)
             }
 
+            HEADER_PUBLIC_CUSTOM_DATA => {
+                public_custom_data = PublicCustomData::parse(entry_buffer)?;
+            }
+
             _ => {
                 return Err(DatabaseIntegrityError::InvalidOuterHeaderEntry { entry_type }.into());
             }
@@ -224,34 +312,50 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
             outer_iv,
             kdf_config,
             kdf_seed,
+            public_custom_data,
         },
         pos,
     ))
 }
 
-fn parse_inner_header(
-    data: &[u8],
-) -> Result<(Vec<HeaderAttachment>, KDBX4InnerHeader, usize), DatabaseOpenError> {
-    let mut pos = 0;
-
+fn read_inner_header(
+    payload: &mut LimitedReader<Box<dyn Read>>,
+) -> Result<(Vec<HeaderAttachment>, KDBX4InnerHeader), DatabaseOpenError> {
     let mut inner_random_stream = None;
     let mut inner_random_stream_key = None;
     let mut header_attachments = Vec::new();
 
     loop {
-        let entry_type = data[pos];
-        let entry_length: usize = LittleEndian::read_u32(&data[pos + 1..(pos + 5)]) as usize;
-        let entry_buffer = &data[(pos + 5)..(pos + 5 + entry_length)];
-
-        pos += 5 + entry_length;
+        let mut entry_header = [0u8; 5];
+        payload
+            .read_exact(&mut entry_header)
+            .map_err(|e| payload_read_error(payload, e))?;
+        let [entry_type, entry_length @ ..] = entry_header;
+        let entry_length = u32::from_le_bytes(entry_length) as usize;
+
+        // do not trust the length for allocating the buffer up front
+        let mut entry_buffer = Vec::new();
+        (&mut *payload)
+            .take(entry_length as u64)
+            .read_to_end(&mut entry_buffer)
+            .map_err(|e| payload_read_error(payload, e))?;
+        if entry_buffer.len() != entry_length {
+            return Err(DatabaseIntegrityError::Truncated {
+                offset: payload.position(),
+            }
+            .into());
+        }
+        let entry_buffer = entry_buffer.as_slice();
 
         match entry_type {
             INNER_HEADER_END => break,
 
             INNER_HEADER_RANDOM_STREAM_ID => {
-                inner_random_stream = Some(InnerCipherConfig::try_from(LittleEndian::read_u32(
-                    &entry_buffer,
-                ))?);
+                inner_random_stream = Some(InnerCipherConfig::try_from(LittleEndian::read_u32(get_bytes(
+                    entry_buffer,
+                    0,
+                    4,
+                )?))?);
             }
 
             INNER_HEADER_RANDOM_STREAM_KEY => inner_random_stream_key = Some(entry_buffer.to_vec()),
@@ -284,5 +388,5 @@ fn parse_inner_header(
         inner_random_stream_key,
     };
 
-    Ok((header_attachments, inner_header, pos))
+    Ok((header_attachments, inner_header))
 }
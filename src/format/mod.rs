@@ -1,3 +1,5 @@
+#![deny(clippy::indexing_slicing)]
+
 pub(crate) mod kdb;
 pub(crate) mod kdbx3;
 pub(crate) mod kdbx4;
@@ -9,7 +11,10 @@ use std::io::Write;
 use byteorder::WriteBytesExt;
 use byteorder::{ByteOrder, LittleEndian};
 
-use crate::error::DatabaseIntegrityError;
+use crate::{
+    compression::LimitedReader,
+    error::{DatabaseIntegrityError, DatabaseOpenError},
+};
 
 const KDBX_IDENTIFIER: [u8; 4] = [0x03, 0xd9, 0xa2, 0x9a];
 
@@ -25,6 +30,48 @@ pub const KDBX4_MAJOR_VERSION: u16 = 4;
 
 pub const KDBX4_CURRENT_MINOR_VERSION: u16 = 0;
 
+/// Get `len` bytes of `data` starting at `offset`, reporting a truncated database instead of
+/// panicking if `data` is too short
+pub(crate) fn get_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], DatabaseIntegrityError> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(DatabaseIntegrityError::Truncated { offset: data.len() })
+}
+
+/// Get the byte of `data` at `offset`, reporting a truncated database instead of panicking if
+/// `data` is too short
+pub(crate) fn get_byte(data: &[u8], offset: usize) -> Result<u8, DatabaseIntegrityError> {
+    data.get(offset)
+        .copied()
+        .ok_or(DatabaseIntegrityError::Truncated { offset: data.len() })
+}
+
+/// Get the remainder of `data` starting at `offset`, reporting a truncated database instead of
+/// panicking if `data` is too short
+pub(crate) fn get_tail(data: &[u8], offset: usize) -> Result<&[u8], DatabaseIntegrityError> {
+    data.get(offset..)
+        .ok_or(DatabaseIntegrityError::Truncated { offset: data.len() })
+}
+
+/// Convert an error while reading the decompressed payload, reporting a payload that exceeds the
+/// size limit or ends early as such
+pub(crate) fn payload_read_error<R: std::io::Read>(
+    reader: &LimitedReader<R>,
+    e: std::io::Error,
+) -> DatabaseOpenError {
+    if let Some(limit) = reader.exceeded() {
+        DatabaseOpenError::PayloadTooLarge { limit }
+    } else if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        DatabaseIntegrityError::Truncated {
+            offset: reader.position(),
+        }
+        .into()
+    } else {
+        e.into()
+    }
+}
+
 /// Supported KDB database versions, with the associated
 /// minor version.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -43,13 +90,13 @@ impl DatabaseVersion {
         }
 
         // check identifier
-        if data[0..4] != KDBX_IDENTIFIER {
+        if get_bytes(data, 0, 4)? != KDBX_IDENTIFIER {
             return Err(DatabaseIntegrityError::InvalidKDBXIdentifier.into());
         }
 
-        let version = LittleEndian::read_u32(&data[4..8]);
-        let file_minor_version = LittleEndian::read_u16(&data[8..10]);
-        let file_major_version = LittleEndian::read_u16(&data[10..12]);
+        let version = LittleEndian::read_u32(get_bytes(data, 4, 4)?);
+        let file_minor_version = LittleEndian::read_u16(get_bytes(data, 8, 2)?);
+        let file_major_version = LittleEndian::read_u16(get_bytes(data, 10, 2)?);
 
         let response = match version {
             KEEPASS_1_ID => DatabaseVersion::KDB(file_minor_version),
@@ -1,11 +1,48 @@
+//! The HMAC block stream that protects the integrity of the encrypted payload of KDBX4 databases.
+//!
+//! The payload is split into blocks, each preceded by its HMAC-SHA-256 and its size, and the
+//! stream is ended by an empty block. The HMAC key of each block is derived from its index and a
+//! 64-byte key, which KDBX4 computes as the SHA-512 of the master seed, the transformed key and
+//! [`HMAC_KEY_END`].
+//!
+//! [`HmacBlockReader`] and [`HmacBlockWriter`] adapt readers and writers to the block stream, so
+//! that payloads can be processed without holding them in memory as a whole.
+#![deny(clippy::indexing_slicing)]
+
+use std::io::{Read, Write};
+
 use byteorder::{ByteOrder, LittleEndian};
 use cipher::generic_array::{typenum::U64, GenericArray};
 use hex_literal::hex;
 
 use crate::error::{BlockStreamError, CryptographyError};
 
+/// Byte appended to the master seed and transformed key to derive the key of the block stream
 pub const HMAC_KEY_END: [u8; 1] = hex!("01");
 
+/// Size of the header of each block, i.e. its HMAC and its size
+const BLOCK_HEADER_SIZE: usize = 36;
+
+/// Check the HMAC of a block, given its index and its encoded size
+fn verify_block(
+    block_index: u64,
+    size_bytes: &[u8],
+    block: &[u8],
+    hmac: &[u8],
+    key: &GenericArray<u8, U64>,
+) -> Result<(), BlockStreamError> {
+    let hmac_block_key = get_hmac_block_key(block_index, key)?;
+    let mut block_index_buf = [0u8; 8];
+    LittleEndian::write_u64(&mut block_index_buf, block_index);
+
+    if hmac != crate::crypt::calculate_hmac(&[&block_index_buf, size_bytes, block], &hmac_block_key)?.as_slice()
+    {
+        return Err(BlockStreamError::BlockHashMismatch { block_index });
+    }
+
+    Ok(())
+}
+
 /// Read from a HMAC block stream into a raw buffer
 pub(crate) fn read_hmac_block_stream(
     data: &[u8],
@@ -19,24 +56,19 @@ pub(crate) fn read_hmac_block_stream(
     let mut block_index: u64 = 0;
 
     while pos < data.len() {
-        let hmac = &data[pos..(pos + 32)];
-        let size_bytes = &data[(pos + 32)..(pos + 36)];
+        let truncated = || BlockStreamError::Truncated { block_index };
+
+        let hmac = data.get(pos..(pos + 32)).ok_or_else(truncated)?;
+        let size_bytes = data.get((pos + 32)..(pos + 36)).ok_or_else(truncated)?;
         let size = LittleEndian::read_u32(size_bytes) as usize;
-        let block = &data[(pos + 36)..(pos + 36 + size)];
-
-        // verify block hmac
-        let hmac_block_key = get_hmac_block_key(block_index, key)?;
-        let mut block_index_buf = [0u8; 8];
-        LittleEndian::write_u64(&mut block_index_buf, block_index as u64);
-
-        if hmac
-            != crate::crypt::calculate_hmac(&[&block_index_buf, size_bytes, &block], &hmac_block_key)?
-                .as_slice()
-        {
-            return Err(BlockStreamError::BlockHashMismatch { block_index }.into());
-        }
+        let block = data
+            .get((pos + 36)..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or_else(truncated)?;
 
-        pos += 36 + size;
+        verify_block(block_index, size_bytes, block, hmac, key)?;
+
+        pos += BLOCK_HEADER_SIZE + size;
         block_index += 1;
 
         if size == 0 {
@@ -49,56 +81,91 @@ pub(crate) fn read_hmac_block_stream(
     Ok(out)
 }
 
+/// Walk the block headers of a HMAC block stream without verifying any HMACs, and return the
+/// offset at which the stream is cut off, if it is incomplete.
+pub(crate) fn find_truncation(data: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+
+    loop {
+        let size = match data.get((pos + 32)..(pos + 36)) {
+            Some(size_bytes) => LittleEndian::read_u32(size_bytes) as usize,
+            None => return Some(data.len()),
+        };
+
+        if data.len() - (pos + 36) < size {
+            return Some(data.len());
+        }
+
+        pos += 36 + size;
+
+        if size == 0 {
+            return None;
+        }
+    }
+}
+
+/// Size of the blocks that a payload is split into when writing a HMAC block stream, as used by
+/// KeePass
+pub const HMAC_BLOCK_SIZE: usize = 1024 * 1024;
+
 #[cfg(feature = "save_kdbx4")]
 /// Write a raw buffer as a HMAC block stream
 pub(crate) fn write_hmac_block_stream(
     data: &[u8],
     key: &GenericArray<u8, U64>,
 ) -> Result<Vec<u8>, CryptographyError> {
-    let mut out = Vec::new();
+    let mut out = write_hmac_blocks(data, key, 0)?;
 
-    let mut pos = 0;
-    let mut block_index = 0;
-
-    while pos < data.len() {
-        let size = data.len() - pos;
-
-        let block = &data[pos..(pos + size)];
-
-        let mut size_bytes: Vec<u8> = vec![];
-        size_bytes.resize(4, 0);
-        LittleEndian::write_u32(&mut size_bytes, size as u32);
+    // the end of the HMAC block stream should be an empty block, but with a valid HMAC
+    let block_count = data.chunks(HMAC_BLOCK_SIZE).count() as u64;
+    write_hmac_block(&[], key, block_count, &mut out)?;
+    Ok(out)
+}
 
-        // Generate block hmac
-        let hmac_block_key = get_hmac_block_key(block_index, key)?;
-        let mut block_index_buf = [0u8; 8];
-        LittleEndian::write_u64(&mut block_index_buf, block_index as u64);
+#[cfg(feature = "save_kdbx4")]
+/// Write a raw buffer as blocks of a HMAC block stream, numbering the blocks starting at
+/// `first_block_index`. The empty block that ends the stream is not written.
+pub(crate) fn write_hmac_blocks(
+    data: &[u8],
+    key: &GenericArray<u8, U64>,
+    first_block_index: u64,
+) -> Result<Vec<u8>, CryptographyError> {
+    let mut out = Vec::new();
 
-        let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, &block], &hmac_block_key)?;
+    for (block_index, block) in (first_block_index..).zip(data.chunks(HMAC_BLOCK_SIZE)) {
+        write_hmac_block(block, key, block_index, &mut out)?;
+    }
 
-        pos += 36 + size;
-        block_index += 1;
+    Ok(out)
+}
 
-        out.extend_from_slice(&hmac);
-        out.extend_from_slice(&size_bytes);
-        out.extend_from_slice(&block);
-    }
+/// Write a single block of a HMAC block stream
+pub(crate) fn write_hmac_block(
+    block: &[u8],
+    key: &GenericArray<u8, U64>,
+    block_index: u64,
+    out: &mut Vec<u8>,
+) -> Result<(), CryptographyError> {
+    let mut size_bytes = [0u8; 4];
+    LittleEndian::write_u32(&mut size_bytes, block.len() as u32);
 
-    // the end of the HMAC block stream should be an empty block, but with a valid HMAC
+    // Generate block hmac
     let hmac_block_key = get_hmac_block_key(block_index, key)?;
     let mut block_index_buf = [0u8; 8];
-    LittleEndian::write_u64(&mut block_index_buf, block_index as u64);
+    LittleEndian::write_u64(&mut block_index_buf, block_index);
 
-    let size_bytes = vec![0; 4];
-    let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, &[]], &hmac_block_key)?;
+    let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, block], &hmac_block_key)?;
 
     out.extend_from_slice(&hmac);
     out.extend_from_slice(&size_bytes);
-
-    Ok(out)
+    out.extend_from_slice(block);
+    Ok(())
 }
 
-pub(crate) fn get_hmac_block_key(
+/// Derive the HMAC key of the block with the given index from the key of the block stream.
+///
+/// KDBX4 also uses the key of the block with index `u64::MAX` for the HMAC of the outer header.
+pub fn get_hmac_block_key(
     block_index: u64,
     key: &GenericArray<u8, U64>,
 ) -> Result<GenericArray<u8, U64>, CryptographyError> {
@@ -106,3 +173,251 @@ pub(crate) fn get_hmac_block_key(
     LittleEndian::write_u64(&mut buf, block_index as u64);
     crate::crypt::calculate_sha512(&[&buf, key])
 }
+
+fn invalid_data(error: BlockStreamError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+/// Reads the payload of a HMAC block stream, verifying the HMAC of every block before its data
+/// is returned.
+///
+/// Blocks with an invalid HMAC or a stream that ends before its final empty block cause an
+/// [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`] that wraps a
+/// [`BlockStreamError`]. Reading stops at the final block, so data following the block stream
+/// is left in the underlying reader.
+pub struct HmacBlockReader<R> {
+    inner: R,
+    key: GenericArray<u8, U64>,
+    block_index: u64,
+    block: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl<R: Read> HmacBlockReader<R> {
+    pub fn new(inner: R, key: &GenericArray<u8, U64>) -> Self {
+        HmacBlockReader {
+            inner,
+            key: *key,
+            block_index: 0,
+            block: Vec::new(),
+            pos: 0,
+            finished: false,
+        }
+    }
+
+    /// Get back the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read and verify the next block, returning `false` at the end of the stream
+    fn read_block(&mut self) -> std::io::Result<bool> {
+        let block_index = self.block_index;
+        let truncated = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => invalid_data(BlockStreamError::Truncated { block_index }),
+            _ => e,
+        };
+
+        let mut header = [0u8; BLOCK_HEADER_SIZE];
+        self.inner.read_exact(&mut header).map_err(truncated)?;
+        let (hmac, size_bytes) = header.split_at(32);
+        let size = LittleEndian::read_u32(size_bytes) as usize;
+
+        // do not trust the size before the HMAC is verified, and only allocate what can be read
+        self.block.clear();
+        (&mut self.inner).take(size as u64).read_to_end(&mut self.block)?;
+        if self.block.len() < size {
+            return Err(invalid_data(BlockStreamError::Truncated { block_index }));
+        }
+
+        verify_block(block_index, size_bytes, &self.block, hmac, &self.key).map_err(invalid_data)?;
+
+        self.block_index += 1;
+        self.pos = 0;
+        Ok(size > 0)
+    }
+}
+
+impl<R: Read> Read for HmacBlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.block.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            if !self.read_block()? {
+                self.finished = true;
+                self.block.clear();
+                return Ok(0);
+            }
+        }
+
+        let available = self.block.get(self.pos..).unwrap_or_default();
+        let n = available.len().min(buf.len());
+        if let (Some(dest), Some(src)) = (buf.get_mut(..n), available.get(..n)) {
+            dest.copy_from_slice(src);
+        }
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Writes data as a HMAC block stream, splitting it into blocks of [`HMAC_BLOCK_SIZE`] bytes.
+///
+/// [`HmacBlockWriter::finish`] has to be called to write the last block and the empty block
+/// that ends the stream, otherwise the stream is incomplete.
+pub struct HmacBlockWriter<W: Write> {
+    inner: W,
+    key: GenericArray<u8, U64>,
+    block_size: usize,
+    block_index: u64,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> HmacBlockWriter<W> {
+    pub fn new(inner: W, key: &GenericArray<u8, U64>) -> Self {
+        HmacBlockWriter::with_block_size(inner, key, HMAC_BLOCK_SIZE)
+    }
+
+    /// Create a writer that splits the data into blocks of `block_size` bytes instead of
+    /// [`HMAC_BLOCK_SIZE`]. Readers accept blocks of any size.
+    pub fn with_block_size(inner: W, key: &GenericArray<u8, U64>, block_size: usize) -> Self {
+        HmacBlockWriter {
+            inner,
+            key: *key,
+            block_size: block_size.max(1),
+            block_index: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn write_block(&mut self, len: usize) -> std::io::Result<()> {
+        let mut out = Vec::with_capacity(BLOCK_HEADER_SIZE + len);
+        let block = self.buffer.get(..len).unwrap_or_default();
+        write_hmac_block(block, &self.key, self.block_index, &mut out)
+            .map_err(|e| std::io::Error::other(BlockStreamError::from(e)))?;
+        self.inner.write_all(&out)?;
+
+        self.buffer.drain(..len);
+        self.block_index += 1;
+        Ok(())
+    }
+
+    /// Write the remaining data and the empty block that ends the stream, and return the
+    /// underlying writer
+    pub fn finish(mut self) -> std::io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_block(self.buffer.len())?;
+        }
+        self.write_block(0)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for HmacBlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= self.block_size {
+            self.write_block(self.block_size)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Flush the underlying writer. Buffered data that does not fill a block yet is only
+    /// written by [`HmacBlockWriter::finish`], so that the blocks are independent of how the
+    /// data was written.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod hmac_block_stream_tests {
+    use std::io::{Read, Write};
+
+    use cipher::generic_array::GenericArray;
+
+    use super::{read_hmac_block_stream, HmacBlockReader, HmacBlockWriter};
+    use crate::error::BlockStreamError;
+
+    fn payload() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn block_stream_error(error: std::io::Error) -> BlockStreamError {
+        *error
+            .into_inner()
+            .unwrap()
+            .downcast::<BlockStreamError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = GenericArray::from([7u8; 64]);
+        let data = payload();
+
+        let mut writer = HmacBlockWriter::with_block_size(Vec::new(), &key, 4096);
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        let mut stream = writer.finish().unwrap();
+        assert_eq!(read_hmac_block_stream(&stream, &key).unwrap(), data);
+
+        // data after the end of the block stream is not consumed
+        stream.extend_from_slice(b"trailer");
+        let mut reader = HmacBlockReader::new(stream.as_slice(), &key);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(reader.into_inner(), b"trailer");
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn compatible_with_buffers() {
+        let key = GenericArray::from([3u8; 64]);
+        let data = payload();
+
+        let mut writer = HmacBlockWriter::new(Vec::new(), &key);
+        writer.write_all(&data).unwrap();
+        assert_eq!(
+            writer.finish().unwrap(),
+            super::write_hmac_block_stream(&data, &key).unwrap()
+        );
+    }
+
+    #[test]
+    fn detects_tampering_and_truncation() {
+        let key = GenericArray::from([7u8; 64]);
+        let mut writer = HmacBlockWriter::with_block_size(Vec::new(), &key, 4096);
+        writer.write_all(&payload()).unwrap();
+        let stream = writer.finish().unwrap();
+
+        let mut tampered = stream.clone();
+        if let Some(byte) = tampered.get_mut(4096 + 36 + 40) {
+            *byte ^= 1;
+        }
+        let err = HmacBlockReader::new(tampered.as_slice(), &key)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            block_stream_error(err),
+            BlockStreamError::BlockHashMismatch { block_index: 1 }
+        ));
+
+        let err = HmacBlockReader::new(stream.get(..stream.len() - 10).unwrap(), &key)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            block_stream_error(err),
+            BlockStreamError::Truncated { block_index: 3 }
+        ));
+
+        let other_key = GenericArray::from([8u8; 64]);
+        assert!(HmacBlockReader::new(stream.as_slice(), &other_key)
+            .read_to_end(&mut Vec::new())
+            .is_err());
+    }
+}
@@ -1,17 +1,31 @@
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use xml::name::OwnedName;
 use xml::reader::{EventReader, XmlEvent};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
+#[cfg(feature = "challenge_response")]
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
 #[cfg(feature = "challenge_response")]
 use challenge_response::{
     config::{Config, Mode, Slot},
     ChallengeResponse,
 };
 
-use crate::{crypt::calculate_sha256, error::DatabaseKeyError};
+use cipher::generic_array::{typenum::U32, GenericArray};
+
+use crate::{
+    config::KdfConfig,
+    crypt::calculate_sha256,
+    error::{CryptographyError, DatabaseKeyError, DatabaseOpenError, ErrorKind},
+    format::{kdb, kdbx3, kdbx4, DatabaseVersion},
+};
 
 pub type KeyElement = Vec<u8>;
 pub type KeyElements = Vec<KeyElement>;
@@ -69,11 +83,8 @@ fn parse_xml_keyfile(xml: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
     if key_version == Some("2.0".to_string()) {
         // TODO we should also validate the integrity of a v2 keyfile using the hash value
 
-        let trimmed_key = key_value
-            .trim()
-            .replace(" ", "")
-            .replace("\n", "")
-            .replace("\r", "");
+        // KeePass indents the hex groups with tabs
+        let trimmed_key: String = key_value.chars().filter(|c| !c.is_whitespace()).collect();
 
         return if let Ok(key) = hex::decode(&trimmed_key) {
             Ok(key)
@@ -90,7 +101,7 @@ fn parse_xml_keyfile(xml: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
     };
 }
 
-fn parse_keyfile(buffer: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
+pub(crate) fn parse_keyfile(buffer: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
     // try to parse the buffer as XML, if successful, use that data instead of full file
     if let Ok(v) = parse_xml_keyfile(&buffer) {
         Ok(v)
@@ -103,21 +114,245 @@ fn parse_keyfile(buffer: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
 }
 
 #[cfg(feature = "challenge_response")]
-#[derive(Debug, Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
+#[derive(Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
 pub enum ChallengeResponseKey {
     LocalChallenge(String),
     YubikeyChallenge(Yubikey, String),
 }
 
+#[cfg(feature = "challenge_response")]
+impl std::fmt::Debug for ChallengeResponseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChallengeResponseKey::LocalChallenge(secret) => f
+                .debug_tuple("LocalChallenge")
+                .field(crate::redact::secret(secret))
+                .finish(),
+            ChallengeResponseKey::YubikeyChallenge(yubikey, slot) => f
+                .debug_tuple("YubikeyChallenge")
+                .field(yubikey)
+                .field(slot)
+                .finish(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
 pub struct Yubikey {
     pub serial_number: u32,
     pub name: Option<String>,
 }
 
+/// Suggested time to wait for a YubiKey to answer a challenge, including the time it takes to
+/// press its button, e.g. for [`TouchPrompt::new`]. Challenges without a touch prompt do not time
+/// out.
+#[cfg(feature = "challenge_response")]
+pub const DEFAULT_CHALLENGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time after which a YubiKey that has not answered a challenge yet is assumed to wait
+/// for its button to be pressed
+#[cfg(feature = "challenge_response")]
+pub const DEFAULT_TOUCH_PROMPT_DELAY: Duration = Duration::from_millis(500);
+
+/// The configuration slots of a YubiKey
+#[cfg(feature = "challenge_response")]
+pub const YUBIKEY_SLOTS: [&str; 2] = ["1", "2"];
+
+/// Callback that is invoked while a YubiKey waits for its button to be pressed, e.g. to ask the
+/// user to touch the key
+#[cfg(feature = "challenge_response")]
+#[derive(Clone)]
+pub struct TouchPrompt {
+    callback: Arc<dyn Fn(&Yubikey) + Send + Sync>,
+    delay: Duration,
+    timeout: Duration,
+}
+
+#[cfg(feature = "challenge_response")]
+impl TouchPrompt {
+    /// Call `callback` if the YubiKey has not answered a challenge after
+    /// [`DEFAULT_TOUCH_PROMPT_DELAY`], and give up if there is no answer after `timeout`.
+    pub fn new(callback: impl Fn(&Yubikey) + Send + Sync + 'static, timeout: Duration) -> Self {
+        TouchPrompt {
+            callback: Arc::new(callback),
+            delay: DEFAULT_TOUCH_PROMPT_DELAY,
+            timeout,
+        }
+    }
+
+    /// Set the time after which the callback is called
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+#[cfg(feature = "challenge_response")]
+impl std::fmt::Debug for TouchPrompt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TouchPrompt")
+            .field("delay", &self.delay)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "challenge_response")]
+impl PartialEq for TouchPrompt {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.callback, &other.callback)
+            && self.delay == other.delay
+            && self.timeout == other.timeout
+    }
+}
+
+/// Capabilities of a configuration slot of a YubiKey, see [`ChallengeResponseKey::probe_slot`]
+#[cfg(feature = "challenge_response")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotStatus {
+    /// The slot answered an HMAC-SHA1 challenge
+    HmacSha1,
+
+    /// The slot did not answer in time. It is either not configured for HMAC-SHA1
+    /// challenge-response, or requires the button to be pressed and was not touched.
+    NoResponse,
+}
+
+/// A connected YubiKey and the capabilities of its slots, see [`ChallengeResponseKey::discover`]
+#[cfg(feature = "challenge_response")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct YubikeyInfo {
+    pub yubikey: Yubikey,
+
+    /// The status of every slot in [`YUBIKEY_SLOTS`], in that order
+    pub slots: Vec<(String, SlotStatus)>,
+}
+
+/// Perform an HMAC-SHA1 challenge with the YubiKey with the given serial number, blocking until
+/// it answers
+#[cfg(feature = "challenge_response")]
+fn yubikey_challenge(serial_number: u32, slot: Slot, challenge: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
+    let mut challenge_response_client = ChallengeResponse::new().map_err(|e| {
+        DatabaseKeyError::ChallengeResponseKeyError(format!("Could not search for yubikey: {}", e.to_string()))
+    })?;
+
+    let yubikey_device = match challenge_response_client.find_device_from_serial(serial_number) {
+        Ok(d) => d,
+        Err(_e) => {
+            return Err(DatabaseKeyError::ChallengeResponseKeyError(
+                "Yubikey not found".to_string(),
+            ))
+        }
+    };
+
+    let mut config = Config::new_from(yubikey_device);
+    config = config.set_variable_size(true);
+    config = config.set_mode(Mode::Sha1);
+    config = config.set_slot(slot);
+
+    match challenge_response_client.challenge_response_hmac(challenge, config) {
+        Ok(hmac_result) => Ok(hmac_result.to_vec()),
+        Err(e) => Err(DatabaseKeyError::ChallengeResponseKeyError(format!(
+            "Could not perform challenge response: {}",
+            e.to_string(),
+        ))),
+    }
+}
+
+/// Serial numbers of the YubiKeys that are currently answering a challenge
+#[cfg(feature = "challenge_response")]
+static BUSY_YUBIKEYS: (Mutex<Vec<u32>>, Condvar) = (Mutex::new(Vec::new()), Condvar::new());
+
+/// Exclusive access to a YubiKey, released when dropped
+#[cfg(feature = "challenge_response")]
+struct YubikeyLock(u32);
+
+#[cfg(feature = "challenge_response")]
+impl YubikeyLock {
+    /// Wait until no other challenge is running on the YubiKey with the given serial number. A
+    /// YubiKey can only answer one challenge at a time, and challenges that timed out keep it busy
+    /// until it gives up.
+    fn acquire(serial_number: u32) -> Self {
+        let (busy, freed) = &BUSY_YUBIKEYS;
+        let mut busy = freed
+            .wait_while(busy.lock().unwrap(), |busy| busy.contains(&serial_number))
+            .unwrap();
+        busy.push(serial_number);
+        YubikeyLock(serial_number)
+    }
+}
+
+#[cfg(feature = "challenge_response")]
+impl Drop for YubikeyLock {
+    fn drop(&mut self) {
+        let (busy, freed) = &BUSY_YUBIKEYS;
+        busy.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|serial_number| *serial_number != self.0);
+        freed.notify_all();
+    }
+}
+
+/// Run `challenge` on a separate thread, calling the touch prompt if it takes longer than the
+/// prompt delay and giving up after the timeout.
+///
+/// A YubiKey never answers a challenge sent to a slot that is not configured for HMAC-SHA1, so
+/// the challenge must not block the caller indefinitely. After a timeout, the thread is left
+/// running until the device gives up, and later challenges on the same YubiKey wait for it before
+/// their own timeout starts.
+#[cfg(feature = "challenge_response")]
+fn run_with_timeout(
+    yubikey: &Yubikey,
+    slot_number: &str,
+    touch_prompt: &TouchPrompt,
+    challenge: impl FnOnce() -> Result<KeyElement, DatabaseKeyError> + Send + 'static,
+) -> Result<KeyElement, DatabaseKeyError> {
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+
+    let lock = YubikeyLock::acquire(yubikey.serial_number);
+
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        let result = challenge();
+        drop(lock);
+
+        // the receiver is gone if the challenge timed out
+        let _ = sender.send(result);
+    });
+
+    let not_responding = || DatabaseKeyError::SlotNotResponding {
+        serial_number: yubikey.serial_number,
+        slot: slot_number.to_string(),
+    };
+
+    match receiver.recv_timeout(touch_prompt.delay) {
+        Ok(result) => return result,
+        Err(RecvTimeoutError::Disconnected) => return Err(not_responding()),
+        Err(RecvTimeoutError::Timeout) => (touch_prompt.callback)(yubikey),
+    }
+
+    let timeout = touch_prompt.timeout.saturating_sub(touch_prompt.delay);
+    receiver.recv_timeout(timeout).map_err(|_| not_responding())?
+}
+
 #[cfg(feature = "challenge_response")]
 impl ChallengeResponseKey {
-    fn perform_challenge(self: &Self, challenge: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
+    /// Use the given slot of the YubiKey with the given serial number. If no serial number is
+    /// given, exactly one YubiKey must be connected.
+    pub fn for_yubikey_slot(serial_number: Option<u32>, slot_number: &str) -> Result<Self, DatabaseKeyError> {
+        parse_yubikey_slot(slot_number)?;
+        let yubikey = ChallengeResponseKey::get_yubikey(serial_number)?;
+        Ok(ChallengeResponseKey::YubikeyChallenge(
+            yubikey,
+            slot_number.to_string(),
+        ))
+    }
+
+    fn perform_challenge(
+        self: &Self,
+        challenge: &[u8],
+        touch_prompt: Option<&TouchPrompt>,
+    ) -> Result<KeyElement, DatabaseKeyError> {
         match self {
             ChallengeResponseKey::LocalChallenge(secret) => {
                 let secret_bytes = hex::decode(&secret).map_err(|e| {
@@ -128,40 +363,66 @@ impl ChallengeResponseKey {
                 Ok(response)
             }
             ChallengeResponseKey::YubikeyChallenge(yubikey, slot_number) => {
-                let mut challenge_response_client = ChallengeResponse::new().map_err(|e| {
-                    DatabaseKeyError::ChallengeResponseKeyError(format!(
-                        "Could not search for yubikey: {}",
-                        e.to_string()
-                    ))
-                })?;
                 let slot = parse_yubikey_slot(slot_number)?;
+                let serial_number = yubikey.serial_number;
 
-                let yubikey_device =
-                    match challenge_response_client.find_device_from_serial(yubikey.serial_number) {
-                        Ok(d) => d,
-                        Err(_e) => {
-                            return Err(DatabaseKeyError::ChallengeResponseKeyError(
-                                "Yubikey not found".to_string(),
-                            ))
-                        }
-                    };
-
-                let mut config = Config::new_from(yubikey_device);
-                config = config.set_variable_size(true);
-                config = config.set_mode(Mode::Sha1);
-                config = config.set_slot(slot);
-
-                match challenge_response_client.challenge_response_hmac(challenge, config) {
-                    Ok(hmac_result) => Ok(hmac_result.to_vec()),
-                    Err(e) => Err(DatabaseKeyError::ChallengeResponseKeyError(format!(
-                        "Could not perform challenge response: {}",
-                        e.to_string(),
-                    ))),
+                match touch_prompt {
+                    Some(touch_prompt) => {
+                        let challenge = challenge.to_vec();
+                        run_with_timeout(yubikey, slot_number, touch_prompt, move || {
+                            yubikey_challenge(serial_number, slot, &challenge)
+                        })
+                    }
+                    None => {
+                        let _lock = YubikeyLock::acquire(serial_number);
+                        yubikey_challenge(serial_number, slot, challenge)
+                    }
                 }
             }
         }
     }
 
+    /// Check whether a slot of a YubiKey answers HMAC-SHA1 challenges, waiting at most `timeout`.
+    ///
+    /// Slots that require the button to be pressed only answer when touched within the timeout.
+    pub fn probe_slot(
+        yubikey: &Yubikey,
+        slot_number: &str,
+        timeout: Duration,
+    ) -> Result<SlotStatus, DatabaseKeyError> {
+        let slot = parse_yubikey_slot(slot_number)?;
+        let serial_number = yubikey.serial_number;
+
+        let touch_prompt = TouchPrompt::new(|_| {}, timeout).with_delay(timeout);
+        match run_with_timeout(yubikey, slot_number, &touch_prompt, move || {
+            yubikey_challenge(serial_number, slot, b"keepass-rs slot probe")
+        }) {
+            Ok(_) => Ok(SlotStatus::HmacSha1),
+            Err(DatabaseKeyError::SlotNotResponding { .. }) => Ok(SlotStatus::NoResponse),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Find all connected YubiKeys that report a serial number, and probe all of their slots
+    /// using [`ChallengeResponseKey::probe_slot`] with the given timeout per slot.
+    pub fn discover(timeout: Duration) -> Result<Vec<YubikeyInfo>, DatabaseKeyError> {
+        ChallengeResponseKey::get_available_yubikeys()?
+            .into_iter()
+            .map(|yubikey| {
+                let slots = YUBIKEY_SLOTS
+                    .iter()
+                    .map(|slot| {
+                        Ok((
+                            slot.to_string(),
+                            ChallengeResponseKey::probe_slot(&yubikey, slot, timeout)?,
+                        ))
+                    })
+                    .collect::<Result<_, DatabaseKeyError>>()?;
+                Ok(YubikeyInfo { yubikey, slots })
+            })
+            .collect()
+    }
+
     pub fn get_available_yubikeys() -> Result<Vec<Yubikey>, DatabaseKeyError> {
         let mut challenge_response_client = ChallengeResponse::new().map_err(|e| {
             DatabaseKeyError::ChallengeResponseKeyError(format!(
@@ -224,15 +485,131 @@ impl ChallengeResponseKey {
     }
 }
 
-/// A KeePass key, which might consist of a password and/or a keyfile
-#[derive(Debug, Clone, Default, PartialEq, Zeroize, ZeroizeOnDrop)]
+/// A keyfile of a [`DatabaseKey`]
+#[derive(Clone, PartialEq)]
+enum KeyfileSource {
+    /// Contents of a keyfile that was read when the key was built
+    Contents(Vec<u8>),
+
+    /// Path of a keyfile that is read again whenever the key is used
+    Path(PathBuf),
+}
+
+impl KeyfileSource {
+    fn parse(&self) -> Result<KeyElement, DatabaseKeyError> {
+        match self {
+            KeyfileSource::Contents(contents) => parse_keyfile(contents),
+            KeyfileSource::Path(path) => {
+                let mut contents =
+                    std::fs::read(path).map_err(|source| DatabaseKeyError::KeyfileUnavailable {
+                        path: path.clone(),
+                        source,
+                    })?;
+                let key = parse_keyfile(&contents);
+                contents.zeroize();
+                key
+            }
+        }
+    }
+}
+
+impl KeyfileSource {
+    /// The keyfile with its whole contents hashed, the way keyfiles are used that are neither
+    /// XML keyfiles nor legacy 32-byte keyfiles
+    fn hashed(&self) -> Result<KeyfileSource, DatabaseKeyError> {
+        let mut contents = match self {
+            KeyfileSource::Contents(contents) => contents.clone(),
+            KeyfileSource::Path(path) => {
+                std::fs::read(path).map_err(|source| DatabaseKeyError::KeyfileUnavailable {
+                    path: path.clone(),
+                    source,
+                })?
+            }
+        };
+        let hash = calculate_sha256(&[&contents]);
+        contents.zeroize();
+
+        // 32 bytes are used as they are, see `parse_keyfile`
+        Ok(KeyfileSource::Contents(hash?.to_vec()))
+    }
+}
+
+impl Zeroize for KeyfileSource {
+    fn zeroize(&mut self) {
+        if let KeyfileSource::Contents(contents) = self {
+            contents.zeroize();
+        }
+    }
+}
+
+impl std::fmt::Debug for KeyfileSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyfileSource::Contents(contents) => crate::redact::secret(contents).fmt(f),
+            KeyfileSource::Path(path) => f.debug_tuple("Path").field(path).finish(),
+        }
+    }
+}
+
+/// A KeePass key, which might consist of a password and/or one or more keyfiles
+#[derive(Clone, Default, PartialEq, Zeroize, ZeroizeOnDrop)]
 pub struct DatabaseKey {
     password: Option<String>,
-    keyfile: Option<Vec<u8>>,
+    keyfiles: Vec<KeyfileSource>,
     #[cfg(feature = "challenge_response")]
     challenge_response_key: Option<ChallengeResponseKey>,
     #[cfg(feature = "challenge_response")]
     challenge_response_result: Option<KeyElement>,
+    #[cfg(feature = "challenge_response")]
+    #[zeroize(skip)]
+    touch_prompt: Option<TouchPrompt>,
+    #[cfg(feature = "keyring")]
+    cached_key_elements: Option<KeyElements>,
+    derived_keys: Vec<DerivedKey>,
+}
+
+/// The result of the key derivation of a [`DatabaseKey`] for the KDF parameters of one database,
+/// as computed by [`DatabaseKey::derive_batch`]
+#[derive(Clone, PartialEq)]
+struct DerivedKey {
+    kdf_config: KdfConfig,
+    seed: Vec<u8>,
+    composite_key: Vec<u8>,
+    transformed_key: Vec<u8>,
+}
+
+impl Zeroize for DerivedKey {
+    fn zeroize(&mut self) {
+        self.seed.zeroize();
+        self.composite_key.zeroize();
+        self.transformed_key.zeroize();
+    }
+}
+
+impl std::fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::redact::secret;
+
+        let mut s = f.debug_struct("DatabaseKey");
+        s.field("password", &self.password.as_ref().map(secret));
+        s.field("keyfiles", &self.keyfiles);
+        #[cfg(feature = "challenge_response")]
+        s.field("challenge_response_key", &self.challenge_response_key);
+        #[cfg(feature = "challenge_response")]
+        s.field(
+            "challenge_response_result",
+            &self.challenge_response_result.as_ref().map(secret),
+        );
+        #[cfg(feature = "challenge_response")]
+        s.field("touch_prompt", &self.touch_prompt);
+        #[cfg(feature = "keyring")]
+        s.field(
+            "cached_key_elements",
+            &self.cached_key_elements.as_ref().map(secret),
+        );
+        s.field("derived_keys", &self.derived_keys.len());
+        s.finish()
+    }
 }
 
 impl DatabaseKey {
@@ -259,21 +636,95 @@ impl DatabaseKey {
         let mut buf = Vec::new();
         keyfile.read_to_end(&mut buf)?;
 
-        self.keyfile = Some(buf);
+        self.keyfiles.zeroize();
+        self.keyfiles = vec![KeyfileSource::Contents(buf)];
+
+        Ok(self)
+    }
+
+    /// Use a keyfile that is encrypted with [age](https://age-encryption.org) under a passphrase,
+    /// replacing all keyfiles set before.
+    ///
+    /// Both binary and ASCII-armored age files are accepted. The keyfile is decrypted in memory
+    /// only, and its decrypted contents are zeroized when the key is dropped. A wrong passphrase
+    /// results in a [`DatabaseKeyError::KeyfileDecryption`] error.
+    #[cfg(feature = "age")]
+    pub fn with_encrypted_keyfile(
+        mut self,
+        keyfile: &mut dyn Read,
+        passphrase: &str,
+    ) -> Result<Self, DatabaseKeyError> {
+        let identity = age::scrypt::Identity::new(age::secrecy::SecretString::from(passphrase.to_string()));
+        let decryptor = age::Decryptor::new(age::armor::ArmoredReader::new(keyfile))?;
+        let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity))?;
+
+        let mut buf = zeroize::Zeroizing::new(Vec::new());
+        reader.read_to_end(&mut buf)?;
+
+        self.keyfiles.zeroize();
+        self.keyfiles = vec![KeyfileSource::Contents(std::mem::take(&mut *buf))];
+
+        Ok(self)
+    }
+
+    /// Use the keyfile at `path`, replacing all keyfiles set before.
+    ///
+    /// Unlike [`DatabaseKey::with_keyfile`], the keyfile is not read right away, but every time
+    /// the key is used to open or save a database, and its contents are zeroized afterwards. If
+    /// the keyfile cannot be read, e.g. because it is on removable media that is not plugged in,
+    /// opening or saving fails with [`DatabaseKeyError::KeyfileUnavailable`] and can be retried
+    /// with the same key once the user has made the keyfile available again.
+    pub fn with_keyfile_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.keyfiles.zeroize();
+        self.keyfiles = vec![KeyfileSource::Path(path.as_ref().to_path_buf())];
+        self
+    }
+
+    /// Add another keyfile to the key, in addition to those set before.
+    ///
+    /// The keys of all keyfiles are hashed into the composite key in the order in which they
+    /// were added, so the same keyfiles have to be given in the same order to open the database.
+    /// KeePass and other clients only support a single keyfile and cannot open databases that
+    /// are protected by several keyfiles.
+    pub fn with_additional_keyfile(mut self, keyfile: &mut dyn Read) -> Result<Self, std::io::Error> {
+        let mut buf = Vec::new();
+        keyfile.read_to_end(&mut buf)?;
+
+        self.keyfiles.push(KeyfileSource::Contents(buf));
 
         Ok(self)
     }
 
+    /// Use the keyfile that sits next to the database at `database_path`, following the KeePass
+    /// naming convention (e.g. `passwords.keyx` or `passwords.key` for `passwords.kdbx`).
+    ///
+    /// If no such keyfile exists, the key is returned unchanged.
+    pub fn with_keyfile_for(self, database_path: &Path) -> Result<Self, std::io::Error> {
+        match find_keyfile_for(database_path) {
+            Some(keyfile_path) => self.with_keyfile(&mut std::fs::File::open(keyfile_path)?),
+            None => Ok(self),
+        }
+    }
+
     #[cfg(feature = "challenge_response")]
     pub fn with_challenge_response_key(mut self, challenge_response_key: ChallengeResponseKey) -> Self {
         self.challenge_response_key = Some(challenge_response_key);
         self
     }
 
+    /// Call the given prompt while a YubiKey waits for its button to be pressed, and give up
+    /// after its timeout. Without a touch prompt, the challenge waits for the YubiKey
+    /// indefinitely.
+    #[cfg(feature = "challenge_response")]
+    pub fn with_touch_prompt(mut self, touch_prompt: TouchPrompt) -> Self {
+        self.touch_prompt = Some(touch_prompt);
+        self
+    }
+
     #[cfg(feature = "challenge_response")]
     pub fn perform_challenge(mut self, kdf_seed: &[u8]) -> Result<Self, DatabaseKeyError> {
         if let Some(challenge_response_key) = &self.challenge_response_key {
-            let response = challenge_response_key.perform_challenge(kdf_seed)?;
+            let response = challenge_response_key.perform_challenge(kdf_seed, self.touch_prompt.as_ref())?;
             self.challenge_response_result = Some(response);
         }
 
@@ -284,17 +735,31 @@ impl DatabaseKey {
         Default::default()
     }
 
-    pub(crate) fn get_key_elements(&self) -> Result<KeyElements, DatabaseKeyError> {
+    /// Get the key elements of the password and keyfiles, which unlike the challenge-response
+    /// result do not depend on the database
+    fn static_key_elements(&self) -> Result<KeyElements, DatabaseKeyError> {
         let mut out = Vec::new();
 
+        #[cfg(feature = "keyring")]
+        if let Some(elements) = &self.cached_key_elements {
+            out.extend(elements.iter().cloned());
+        }
+
         if let Some(p) = &self.password {
             out.push(calculate_sha256(&[p.as_bytes()])?.to_vec());
         }
 
-        if let Some(ref f) = self.keyfile {
-            out.push(parse_keyfile(f)?);
+        for f in &self.keyfiles {
+            out.push(f.parse()?);
         }
 
+        Ok(out)
+    }
+
+    pub(crate) fn get_key_elements(&self) -> Result<KeyElements, DatabaseKeyError> {
+        #[cfg_attr(not(feature = "challenge_response"), allow(unused_mut))]
+        let mut out = self.static_key_elements()?;
+
         if out.is_empty() {
             return Err(DatabaseKeyError::IncorrectKey);
         }
@@ -311,25 +776,393 @@ impl DatabaseKey {
         Ok(out)
     }
 
+    /// Hash the key elements into the composite key, as used by KDBX databases
+    pub(crate) fn composite_key(&self) -> Result<GenericArray<u8, U32>, DatabaseKeyError> {
+        let key_elements = self.get_key_elements()?;
+        let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+        Ok(calculate_sha256(&key_elements)?)
+    }
+
+    /// Derive the transformed key from a composite key of this key, using a result of
+    /// [`DatabaseKey::derive_batch`] for the same KDF parameters if there is one
+    pub(crate) fn transform_key(
+        &self,
+        kdf_config: &KdfConfig,
+        seed: &[u8],
+        composite_key: &GenericArray<u8, U32>,
+    ) -> Result<GenericArray<u8, U32>, CryptographyError> {
+        let derived = self.derived_keys.iter().find(|d| {
+            d.kdf_config == *kdf_config && d.seed == seed && d.composite_key == composite_key.as_slice()
+        });
+        if let Some(derived) = derived {
+            return Ok(GenericArray::clone_from_slice(&derived.transformed_key));
+        }
+
+        kdf_config.get_kdf_seeded(seed).transform_key(composite_key)
+    }
+
+    /// Run the key derivation of a database ahead of opening it
+    fn derive(mut self, data: &[u8]) -> Result<DatabaseKey, DatabaseOpenError> {
+        let (kdf_config, seed, composite_key) = match DatabaseVersion::parse(data)? {
+            DatabaseVersion::KDB(_) => {
+                let (kdf_config, seed) = kdb::kdf_parameters(data)?;
+                (kdf_config, seed, kdb::composite_key(&self)?)
+            }
+            DatabaseVersion::KDB2(_) => return Err(DatabaseOpenError::UnsupportedVersion),
+            DatabaseVersion::KDB3(_) => {
+                let (kdf_config, seed) = kdbx3::kdf_parameters(data)?;
+                (kdf_config, seed, self.composite_key()?)
+            }
+            DatabaseVersion::KDB4(_) => {
+                let (kdf_config, seed) = kdbx4::kdf_parameters(data)?;
+                #[cfg(feature = "challenge_response")]
+                {
+                    self = self.perform_challenge(&seed)?;
+                }
+                (kdf_config, seed, self.composite_key()?)
+            }
+        };
+
+        let transformed_key = kdf_config.get_kdf_seeded(&seed).transform_key(&composite_key)?;
+        self.derived_keys.push(DerivedKey {
+            kdf_config,
+            seed,
+            composite_key: composite_key.to_vec(),
+            transformed_key: transformed_key.to_vec(),
+        });
+        Ok(self)
+    }
+
+    /// Run the key derivations for several databases in parallel, e.g. for the databases of an
+    /// AutoOpen chain, instead of one after the other while opening them.
+    ///
+    /// Each item of `databases` is the data of a database and the key to open it with. The
+    /// returned keys, in the same order, hold the derived key in addition to the key elements,
+    /// so that [`Database::parse`](crate::Database::parse) skips the key derivation when it is
+    /// given the same data. Saving a database generates new KDF parameters, so the derived key
+    /// does not speed up saving or opening the saved file.
+    ///
+    /// The derivations are spread over as many threads as there are CPUs. For KDBX4 databases,
+    /// a challenge-response key is asked here and again when the database is opened.
+    pub fn derive_batch(databases: &[(&[u8], DatabaseKey)]) -> Vec<Result<DatabaseKey, DatabaseOpenError>> {
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(databases.len())
+            .max(1);
+
+        let mut results: Vec<(usize, Result<DatabaseKey, DatabaseOpenError>)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    scope.spawn(move || {
+                        databases
+                            .iter()
+                            .enumerate()
+                            .skip(worker)
+                            .step_by(threads)
+                            .map(|(index, (data, key))| (index, key.clone().derive(data)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("key derivation thread panicked"))
+                .collect()
+        });
+
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
     /// Returns true if the database key is not associated with any key component.
     pub fn is_empty(&self) -> bool {
-        if self.password.is_some() || self.keyfile.is_some() {
+        if self.password.is_some() || !self.keyfiles.is_empty() {
             return false;
         }
         #[cfg(feature = "challenge_response")]
         if self.challenge_response_key.is_some() {
             return false;
         }
+        #[cfg(feature = "keyring")]
+        if self.cached_key_elements.is_some() {
+            return false;
+        }
         true
     }
 }
 
+/// A variation of a [`DatabaseKey`] that is tried by [`DatabaseKey::open_with_fallbacks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyVariant {
+    /// The key as it was given
+    AsGiven,
+
+    /// The password without trailing whitespace, e.g. a newline that was read from a file
+    PasswordTrimmed,
+
+    /// The keyfiles with their whole contents hashed, instead of reading XML keyfiles and
+    /// 32-byte keyfiles as the key itself
+    KeyfilesHashed,
+
+    /// The keyfiles in reverse order
+    KeyfilesReversed,
+}
+
+/// A database that was opened by [`DatabaseKey::open_with_fallbacks`]
+#[derive(Debug)]
+pub struct FallbackOpen {
+    pub database: crate::Database,
+
+    /// Index of the candidate key that opened the database
+    pub candidate: usize,
+
+    /// The variation of the candidate key that opened the database
+    pub variant: KeyVariant,
+
+    /// The key that opened the database, which should be used from now on
+    pub key: DatabaseKey,
+}
+
+impl DatabaseKey {
+    /// Get the variations of this key that [`DatabaseKey::open_with_fallbacks`] tries, in order.
+    /// Variations that would not change the key are left out.
+    pub fn variants(&self) -> Result<Vec<(KeyVariant, DatabaseKey)>, DatabaseKeyError> {
+        let mut variants = vec![(KeyVariant::AsGiven, self.clone())];
+
+        if let Some(password) = &self.password {
+            let trimmed = password.trim_end();
+            if trimmed.len() != password.len() {
+                variants.push((KeyVariant::PasswordTrimmed, self.clone().with_password(trimmed)));
+            }
+        }
+
+        if !self.keyfiles.is_empty() {
+            let mut key = self.clone();
+            key.keyfiles = self
+                .keyfiles
+                .iter()
+                .map(KeyfileSource::hashed)
+                .collect::<Result<_, _>>()?;
+            variants.push((KeyVariant::KeyfilesHashed, key));
+        }
+
+        if self.keyfiles.len() > 1 {
+            let mut key = self.clone();
+            key.keyfiles.reverse();
+            variants.push((KeyVariant::KeyfilesReversed, key));
+        }
+
+        Ok(variants)
+    }
+
+    /// Open a database with the first of several candidate keys that works, also trying a few
+    /// variations of each key that users commonly get wrong, see [`KeyVariant`]. The result
+    /// reports which candidate and variation opened the database, so that applications can fix
+    /// the credentials they stored.
+    ///
+    /// Every attempt derives the key again, so this takes as long as opening the database once
+    /// per attempt. Only wrong keys lead to further attempts: other errors, e.g. for a corrupted
+    /// database, are returned right away. If no key works, the error of the first attempt is
+    /// returned.
+    pub fn open_with_fallbacks(
+        source: &mut dyn Read,
+        candidates: &[DatabaseKey],
+    ) -> Result<FallbackOpen, DatabaseOpenError> {
+        let mut data = Vec::new();
+        source.read_to_end(&mut data)?;
+
+        let mut first_error = None;
+        for (candidate, key) in candidates.iter().enumerate() {
+            for (variant, key) in key.variants()? {
+                match crate::Database::parse(&data, key.clone()) {
+                    Ok(database) => {
+                        return Ok(FallbackOpen {
+                            database,
+                            candidate,
+                            variant,
+                            key,
+                        })
+                    }
+                    Err(e) if e.kind() == ErrorKind::WrongKey => {
+                        first_error.get_or_insert(e);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Err(first_error.unwrap_or_else(|| DatabaseKeyError::IncorrectKey.into()))
+    }
+}
+
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "keepass-rs";
+
+/// Get the keychain entry for a database, identified by both its path and its UUID so that a
+/// different database moved to the same path does not pick up the stored key
+#[cfg(feature = "keyring")]
+fn keyring_entry(database_path: &Path, database_uuid: uuid::Uuid) -> Result<keyring::Entry, DatabaseKeyError> {
+    let database_path = database_path
+        .canonicalize()
+        .unwrap_or_else(|_| database_path.to_path_buf());
+
+    Ok(keyring::Entry::new(
+        KEYRING_SERVICE,
+        &format!("{} ({})", database_path.to_string_lossy(), database_uuid),
+    )?)
+}
+
+#[cfg(feature = "keyring")]
+impl DatabaseKey {
+    /// Store the derived key material of this key in the keychain of the operating system, so
+    /// that the database at `database_path` can later be opened using
+    /// [`DatabaseKey::from_keyring`] without asking for the password or keyfile again.
+    ///
+    /// `database_uuid` identifies the database itself, use the UUID of its root group
+    /// (`db.root.uuid`) and remember it alongside the path.
+    ///
+    /// Only the hashed password and keyfiles are stored, never the plain password. The response
+    /// of a challenge-response key changes whenever the database is saved, so it is not stored:
+    /// add the challenge-response key to the key returned by [`DatabaseKey::from_keyring`] again.
+    pub fn store_in_keyring(
+        &self,
+        database_path: &Path,
+        database_uuid: uuid::Uuid,
+    ) -> Result<(), DatabaseKeyError> {
+        let mut elements = self.static_key_elements()?;
+        if elements.is_empty() {
+            return Err(DatabaseKeyError::IncorrectKey);
+        }
+        let mut secret = elements.iter().map(hex::encode).collect::<Vec<_>>().join(":");
+        elements.zeroize();
+
+        let res = keyring_entry(database_path, database_uuid)?.set_password(&secret);
+        secret.zeroize();
+
+        Ok(res?)
+    }
+
+    /// Load the key for the database with the UUID `database_uuid` at `database_path` from the
+    /// keychain of the operating system. Returns `None` if no key has been stored for this
+    /// database.
+    pub fn from_keyring(
+        database_path: &Path,
+        database_uuid: uuid::Uuid,
+    ) -> Result<Option<Self>, DatabaseKeyError> {
+        let mut secret = match keyring_entry(database_path, database_uuid)?.get_password() {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let elements = secret
+            .split(':')
+            .map(hex::decode)
+            .collect::<Result<KeyElements, _>>()
+            .map_err(|_| DatabaseKeyError::InvalidKeyFile);
+        secret.zeroize();
+
+        let mut key = DatabaseKey::new();
+        key.cached_key_elements = Some(elements?);
+        Ok(Some(key))
+    }
+
+    /// Remove the key stored for the database with the UUID `database_uuid` at `database_path`
+    /// from the keychain of the operating system, if there is one.
+    pub fn remove_from_keyring(
+        database_path: &Path,
+        database_uuid: uuid::Uuid,
+    ) -> Result<(), DatabaseKeyError> {
+        match keyring_entry(database_path, database_uuid)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Look for a keyfile next to the database at `database_path`, following the KeePass naming
+/// convention. `.keyx` files are preferred over `.key` files.
+pub fn find_keyfile_for(database_path: &Path) -> Option<PathBuf> {
+    ["keyx", "key"]
+        .iter()
+        .map(|extension| database_path.with_extension(extension))
+        .find(|candidate| candidate.is_file())
+}
+
+/// An association between a database and the key sources used to open it, as remembered by
+/// KeePass in the `Defaults/KeySources` section of its `KeePass.config.xml`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeySourceAssociation {
+    /// Path to the database file
+    pub database_path: String,
+
+    /// Whether a password was used to open the database
+    pub password: bool,
+
+    /// Path to the keyfile that was used to open the database, if any
+    pub keyfile_path: Option<String>,
+
+    /// Whether the Windows user account was used to open the database
+    pub user_account: bool,
+}
+
+impl KeySourceAssociation {
+    /// Read all key source associations from a KeePass configuration file
+    pub fn parse_config(config: &mut dyn Read) -> Result<Vec<KeySourceAssociation>, DatabaseKeyError> {
+        let parser = EventReader::new(config);
+
+        let mut tag_stack = Vec::new();
+        let mut associations = Vec::new();
+        let mut current: Option<KeySourceAssociation> = None;
+
+        for ev in parser {
+            match ev? {
+                XmlEvent::StartElement {
+                    name: OwnedName { ref local_name, .. },
+                    ..
+                } => {
+                    tag_stack.push(local_name.clone());
+                    if tag_stack == ["Configuration", "Defaults", "KeySources", "Association"] {
+                        current = Some(KeySourceAssociation::default());
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    if tag_stack == ["Configuration", "Defaults", "KeySources", "Association"] {
+                        if let Some(association) = current.take() {
+                            associations.push(association);
+                        }
+                    }
+                    tag_stack.pop();
+                }
+                XmlEvent::Characters(s) => {
+                    let association = match current {
+                        Some(ref mut a) => a,
+                        None => continue,
+                    };
+
+                    match tag_stack.last().map(|t| t.as_str()) {
+                        Some("DatabasePath") => association.database_path = s,
+                        Some("Password") => association.password = s.eq_ignore_ascii_case("true"),
+                        Some("KeyFilePath") => association.keyfile_path = Some(s),
+                        Some("UserAccount") => association.user_account = s.eq_ignore_ascii_case("true"),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(associations)
+    }
+}
+
 #[cfg(test)]
 mod key_tests {
 
     use crate::error::DatabaseKeyError;
 
-    use super::DatabaseKey;
+    use super::{DatabaseKey, KeySourceAssociation};
 
     #[test]
     fn test_key() -> Result<(), DatabaseKeyError> {
@@ -393,17 +1226,492 @@ mod key_tests {
 
         assert_eq!(ke.len(), 1);
 
+        // several keyfiles are hashed in order
+        let first = "first-key-file";
+        let second = "0123456789ABCDEF0123456789ABCDEF";
+        let ke = DatabaseKey::new()
+            .with_password("asdf")
+            .with_keyfile(&mut first.as_bytes())?
+            .with_additional_keyfile(&mut second.as_bytes())?
+            .get_key_elements()?;
+        assert_eq!(ke.len(), 3);
+        assert_eq!(ke[1], super::parse_keyfile(first.as_bytes())?);
+        assert_eq!(ke[2], super::parse_keyfile(second.as_bytes())?);
+
+        // setting a keyfile replaces all previous ones
+        let ke = DatabaseKey::new()
+            .with_keyfile(&mut first.as_bytes())?
+            .with_additional_keyfile(&mut second.as_bytes())?
+            .with_keyfile(&mut second.as_bytes())?
+            .get_key_elements()?;
+        assert_eq!(ke.len(), 1);
+
         assert!(DatabaseKey {
             password: None,
-            keyfile: None,
+            keyfiles: Vec::new(),
             #[cfg(feature = "challenge_response")]
             challenge_response_key: None,
             #[cfg(feature = "challenge_response")]
             challenge_response_result: None,
+            #[cfg(feature = "challenge_response")]
+            touch_prompt: None,
+            #[cfg(feature = "keyring")]
+            cached_key_elements: None,
+            derived_keys: Vec::new(),
         }
         .get_key_elements()
         .is_err());
 
         Ok(())
     }
+
+    #[cfg(feature = "keyring")]
+    mod mock_keyring {
+        use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+        use std::{
+            any::Any,
+            collections::HashMap,
+            sync::{Arc, Mutex, OnceLock},
+        };
+
+        pub type Store = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+        // the mock backend of the keyring crate forgets the secret together with the entry
+        #[derive(Debug)]
+        struct MockBuilder(Store);
+
+        #[derive(Debug)]
+        struct MockCredential {
+            store: Store,
+            user: String,
+        }
+
+        impl CredentialBuilderApi for MockBuilder {
+            fn build(&self, _: Option<&str>, _: &str, user: &str) -> keyring::Result<Box<Credential>> {
+                Ok(Box::new(MockCredential {
+                    store: self.0.clone(),
+                    user: user.to_string(),
+                }))
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+
+        impl CredentialApi for MockCredential {
+            fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+                self.store
+                    .lock()
+                    .unwrap()
+                    .insert(self.user.clone(), secret.to_vec());
+                Ok(())
+            }
+
+            fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+                self.store
+                    .lock()
+                    .unwrap()
+                    .get(&self.user)
+                    .cloned()
+                    .ok_or(keyring::Error::NoEntry)
+            }
+
+            fn delete_credential(&self) -> keyring::Result<()> {
+                self.store
+                    .lock()
+                    .unwrap()
+                    .remove(&self.user)
+                    .map(|_| ())
+                    .ok_or(keyring::Error::NoEntry)
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+
+        /// Use an in-memory keychain for all tests, which is shared since the backend is global
+        pub fn install() -> Store {
+            static STORE: OnceLock<Store> = OnceLock::new();
+            STORE
+                .get_or_init(|| {
+                    let store = Store::default();
+                    keyring::set_default_credential_builder(Box::new(MockBuilder(store.clone())));
+                    store
+                })
+                .clone()
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn test_keyring() -> Result<(), DatabaseKeyError> {
+        let store = mock_keyring::install();
+
+        let path = std::path::Path::new("tests/resources/test_db_kdbx4_with_password_aes.kdbx");
+        let uuid = uuid::Uuid::new_v4();
+        let key = DatabaseKey::new()
+            .with_password("demopass")
+            .with_keyfile(&mut "bare-key-file".as_bytes())?;
+
+        assert!(DatabaseKey::from_keyring(path, uuid)?.is_none());
+        key.store_in_keyring(path, uuid)?;
+        assert!(store
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|k| k.contains(&uuid.to_string())));
+
+        let cached = DatabaseKey::from_keyring(path, uuid)?.unwrap();
+        assert_eq!(cached.get_key_elements()?, key.get_key_elements()?);
+        assert!(!cached.is_empty());
+
+        // a different database at the same path does not get the key
+        assert!(DatabaseKey::from_keyring(path, uuid::Uuid::new_v4())?.is_none());
+
+        DatabaseKey::remove_from_keyring(path, uuid)?;
+        assert!(DatabaseKey::from_keyring(path, uuid)?.is_none());
+        DatabaseKey::remove_from_keyring(path, uuid)?;
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "keyring", feature = "challenge_response", feature = "save_kdbx4"))]
+    #[test]
+    fn test_keyring_challenge_response() -> Result<(), crate::error::Error> {
+        use super::ChallengeResponseKey;
+        use crate::db::{Database, Entry};
+
+        mock_keyring::install();
+
+        let challenge_response_key =
+            || ChallengeResponseKey::LocalChallenge("0102030405060708090a0b0c0d0e0f1011121314".to_string());
+        let key = DatabaseKey::new()
+            .with_password("demopass")
+            .with_challenge_response_key(challenge_response_key());
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(Entry::new());
+        let mut data = Vec::new();
+        db.save(&mut data, key.clone())?;
+
+        let path = std::path::Path::new("keyring-challenge-response.kdbx");
+        let uuid = db.root.uuid;
+        key.store_in_keyring(path, uuid)?;
+
+        let cached = || -> Result<DatabaseKey, DatabaseKeyError> {
+            Ok(DatabaseKey::from_keyring(path, uuid)?
+                .unwrap()
+                .with_challenge_response_key(challenge_response_key()))
+        };
+
+        // every save uses a new KDF seed, and with it a new challenge
+        for _ in 0..2 {
+            let reopened = Database::parse(&data, cached()?)?;
+            assert_eq!(reopened.root.entries().len(), 1);
+
+            data.clear();
+            reopened.save(&mut data, cached()?)?;
+        }
+
+        // the response is not cached, so the challenge-response key is still needed
+        let without_challenge = DatabaseKey::from_keyring(path, uuid)?.unwrap();
+        assert!(Database::parse(&data, without_challenge).is_err());
+
+        DatabaseKey::remove_from_keyring(path, uuid)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_open_with_fallbacks() -> Result<(), crate::error::Error> {
+        use super::KeyVariant;
+        use crate::{
+            config::{DatabaseConfig, KdfConfig},
+            error::ErrorKind,
+            Database,
+        };
+
+        let config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        };
+        let save = |key: DatabaseKey| -> Result<Vec<u8>, crate::error::Error> {
+            let mut data = Vec::new();
+            Database::new(config.clone()).save(&mut data, key)?;
+            Ok(data)
+        };
+
+        let data = save(DatabaseKey::new().with_password("secret"))?;
+        let wrong = DatabaseKey::new().with_password("wrong");
+        let trailing_newline = DatabaseKey::new().with_password("secret\n");
+        let opened =
+            DatabaseKey::open_with_fallbacks(&mut data.as_slice(), &[wrong.clone(), trailing_newline])?;
+        assert_eq!(opened.candidate, 1);
+        assert_eq!(opened.variant, KeyVariant::PasswordTrimmed);
+        assert_eq!(opened.key, DatabaseKey::new().with_password("secret"));
+
+        let err = DatabaseKey::open_with_fallbacks(&mut data.as_slice(), &[wrong]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WrongKey);
+
+        // a 32-byte keyfile that was hashed like any other file
+        let keyfile = "0123456789ABCDEF0123456789ABCDEF";
+        let hash = crate::crypt::calculate_sha256(&[keyfile.as_bytes()]).unwrap();
+        let data = save(DatabaseKey::new().with_keyfile(&mut hash.as_slice())?)?;
+        let key = DatabaseKey::new().with_keyfile(&mut keyfile.as_bytes())?;
+        let opened = DatabaseKey::open_with_fallbacks(&mut data.as_slice(), &[key])?;
+        assert_eq!(opened.variant, KeyVariant::KeyfilesHashed);
+
+        // keyfiles given in the wrong order
+        let data = save(
+            DatabaseKey::new()
+                .with_keyfile(&mut "first".as_bytes())?
+                .with_additional_keyfile(&mut "second".as_bytes())?,
+        )?;
+        let key = DatabaseKey::new()
+            .with_keyfile(&mut "second".as_bytes())?
+            .with_additional_keyfile(&mut "first".as_bytes())?;
+        let opened = DatabaseKey::open_with_fallbacks(&mut data.as_slice(), &[key])?;
+        assert_eq!(opened.variant, KeyVariant::KeyfilesReversed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_derive_batch() -> Result<(), crate::error::Error> {
+        use crate::{error::ErrorKind, Database};
+
+        let files = [
+            ("tests/resources/test_db_kdbx4_with_password_aes.kdbx", "demopass"),
+            ("tests/resources/test_db_kdbx4_with_password_aes.kdbx", "demopass"),
+            ("tests/resources/test_db_kdb_with_password.kdb", "foobar"),
+        ];
+        let data = files
+            .iter()
+            .map(|(path, _)| std::fs::read(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut databases: Vec<(&[u8], DatabaseKey)> = data
+            .iter()
+            .zip(files.iter())
+            .map(|(data, (_, password))| (&data[..], DatabaseKey::new().with_password(password)))
+            .collect();
+        databases.push((b"not a database", DatabaseKey::new().with_password("demopass")));
+        let mut derived = DatabaseKey::derive_batch(&databases);
+        assert!(derived.pop().unwrap().is_err());
+
+        for (data, key) in data.iter().zip(derived) {
+            let key = key?;
+            assert_eq!(key.derived_keys.len(), 1);
+            Database::parse(data, key)?;
+        }
+
+        // the derived key is used instead of running the key derivation again
+        let mut key = DatabaseKey::derive_batch(&databases[1..2]).pop().unwrap()?;
+        key.derived_keys[0].transformed_key = vec![0; 32];
+        let err = Database::parse(&data[1], key).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WrongKey);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyfile_path() -> Result<(), DatabaseKeyError> {
+        let path = std::env::temp_dir().join(format!("keepass-keyfile-{}.key", uuid::Uuid::new_v4()));
+        let key = DatabaseKey::new().with_keyfile_path(&path);
+
+        // the keyfile is only read when the key is used
+        assert!(matches!(
+            key.get_key_elements(),
+            Err(DatabaseKeyError::KeyfileUnavailable { .. })
+        ));
+
+        std::fs::write(&path, "first-key-file")?;
+        let ke = key.get_key_elements()?;
+        assert_eq!(ke[0], super::parse_keyfile(b"first-key-file")?);
+
+        // and read again every time
+        std::fs::write(&path, "second-key-file")?;
+        let ke = key.get_key_elements()?;
+        assert_eq!(ke[0], super::parse_keyfile(b"second-key-file")?);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_source_associations() -> Result<(), DatabaseKeyError> {
+        let config = r###"
+            <?xml version="1.0" encoding="utf-8"?>
+            <Configuration>
+                <Defaults>
+                    <KeySources>
+                        <Association>
+                            <DatabasePath>C:\Users\me\passwords.kdbx</DatabasePath>
+                            <Password>true</Password>
+                            <KeyFilePath>C:\Users\me\passwords.keyx</KeyFilePath>
+                        </Association>
+                        <Association>
+                            <DatabasePath>D:\other.kdbx</DatabasePath>
+                            <Password>true</Password>
+                        </Association>
+                    </KeySources>
+                </Defaults>
+            </Configuration>
+        "###;
+
+        let associations = KeySourceAssociation::parse_config(&mut config.trim().as_bytes())?;
+        assert_eq!(associations.len(), 2);
+        assert_eq!(associations[0].database_path, "C:\\Users\\me\\passwords.kdbx");
+        assert!(associations[0].password);
+        assert_eq!(
+            associations[0].keyfile_path.as_deref(),
+            Some("C:\\Users\\me\\passwords.keyx")
+        );
+        assert!(!associations[0].user_account);
+        assert_eq!(associations[1].keyfile_path, None);
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "age"))]
+mod age_tests {
+    use std::io::Write;
+
+    use age::{
+        armor::{ArmoredWriter, Format},
+        secrecy::SecretString,
+    };
+
+    use super::DatabaseKey;
+    use crate::error::{DatabaseKeyError, ErrorKind};
+
+    fn encrypt(data: &[u8], passphrase: &str, format: Format) -> Vec<u8> {
+        let mut recipient = age::scrypt::Recipient::new(SecretString::from(passphrase.to_string()));
+        // keep the test fast
+        recipient.set_work_factor(2);
+        let encryptor =
+            age::Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient)).unwrap();
+
+        let mut encrypted = Vec::new();
+        let armored = ArmoredWriter::wrap_output(&mut encrypted, format).unwrap();
+        let mut writer = encryptor.wrap_output(armored).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap().finish().unwrap();
+        encrypted
+    }
+
+    #[test]
+    fn encrypted_keyfile() -> Result<(), DatabaseKeyError> {
+        let expected = DatabaseKey::new()
+            .with_keyfile(&mut &b"secret keyfile"[..])?
+            .get_key_elements()?;
+
+        for format in [Format::Binary, Format::AsciiArmor] {
+            let encrypted = encrypt(b"secret keyfile", "passphrase", format);
+            let key = DatabaseKey::new().with_encrypted_keyfile(&mut encrypted.as_slice(), "passphrase")?;
+            assert_eq!(key.get_key_elements()?, expected);
+
+            let err = DatabaseKey::new()
+                .with_encrypted_keyfile(&mut encrypted.as_slice(), "wrong")
+                .unwrap_err();
+            assert!(matches!(err, DatabaseKeyError::KeyfileDecryption(_)));
+            assert_eq!(err.kind(), ErrorKind::WrongKey);
+        }
+
+        let err = DatabaseKey::new()
+            .with_encrypted_keyfile(&mut &b"not encrypted"[..], "passphrase")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidKey);
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "challenge_response"))]
+mod challenge_response_tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{run_with_timeout, TouchPrompt, Yubikey};
+    use crate::error::DatabaseKeyError;
+
+    #[test]
+    fn touch_prompt() {
+        let yubikey = Yubikey {
+            serial_number: 1234,
+            name: None,
+        };
+
+        let prompts = Arc::new(AtomicUsize::new(0));
+        let counter = prompts.clone();
+        let touch_prompt = TouchPrompt::new(
+            move |yubikey| {
+                assert_eq!(yubikey.serial_number, 1234);
+                counter.fetch_add(1, Ordering::SeqCst);
+            },
+            Duration::from_millis(500),
+        )
+        .with_delay(Duration::from_millis(50));
+
+        // a fast answer does not need a touch
+        let response = run_with_timeout(&yubikey, "2", &touch_prompt, || Ok(vec![1])).unwrap();
+        assert_eq!(response, vec![1]);
+        assert_eq!(prompts.load(Ordering::SeqCst), 0);
+
+        let response = run_with_timeout(&yubikey, "2", &touch_prompt, || {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(vec![2])
+        })
+        .unwrap();
+        assert_eq!(response, vec![2]);
+        assert_eq!(prompts.load(Ordering::SeqCst), 1);
+
+        let result = run_with_timeout(&yubikey, "2", &touch_prompt, || {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(vec![3])
+        });
+        assert!(matches!(
+            result,
+            Err(DatabaseKeyError::SlotNotResponding { serial_number: 1234, ref slot }) if slot == "2"
+        ));
+        assert_eq!(prompts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn timed_out_challenge_blocks_yubikey() {
+        let yubikey = Yubikey {
+            serial_number: 5678,
+            name: None,
+        };
+        let touch_prompt =
+            TouchPrompt::new(|_| {}, Duration::from_millis(200)).with_delay(Duration::from_millis(100));
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        let result = run_with_timeout(&yubikey, "1", &touch_prompt, move || {
+            std::thread::sleep(Duration::from_millis(600));
+            flag.store(true, Ordering::SeqCst);
+            Ok(vec![1])
+        });
+        assert!(matches!(result, Err(DatabaseKeyError::SlotNotResponding { .. })));
+        assert!(!finished.load(Ordering::SeqCst));
+
+        // the next challenge only starts once the device is done with the previous one, and the
+        // wait does not count against its timeout
+        let flag = finished.clone();
+        let response = run_with_timeout(&yubikey, "2", &touch_prompt, move || {
+            assert!(flag.load(Ordering::SeqCst));
+            Ok(vec![2])
+        })
+        .unwrap();
+        assert_eq!(response, vec![2]);
+    }
 }
@@ -0,0 +1,435 @@
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::{crypt::calculate_sha256, error::DatabaseKeyError};
+
+/// Number of hex digits per group in a version 2.0 keyfile
+const HEX_GROUP_LENGTH: usize = 8;
+
+/// Number of hex groups per line in a version 2.0 keyfile
+const HEX_GROUPS_PER_LINE: usize = 4;
+
+/// Prefix of the line holding the checksum in a printable backup
+const BACKUP_HASH_PREFIX: &str = "Hash:";
+
+/// A key for a keyfile in the KeePass XML format version 2.0, which stores 32 random bytes as
+/// groups of hex digits along with a checksum that allows detecting typos when re-typing it.
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct Keyfile {
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for Keyfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyfile")
+            .field("key", crate::redact::secret(&self.key))
+            .finish()
+    }
+}
+
+impl Keyfile {
+    /// Generate a new key using the random number generator of the operating system
+    pub fn generate() -> Result<Self, DatabaseKeyError> {
+        let mut key = [0u8; 32];
+        getrandom::fill(&mut key)?;
+        Ok(Keyfile { key })
+    }
+
+    /// Use the given key bytes
+    pub fn from_key(key: [u8; 32]) -> Self {
+        Keyfile { key }
+    }
+
+    /// The raw key bytes
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    /// The checksum of the key, i.e. the first four bytes of its SHA-256 hash as hex digits
+    pub fn hash(&self) -> Result<String, DatabaseKeyError> {
+        Ok(hex::encode_upper(&calculate_sha256(&[&self.key])?[..4]))
+    }
+
+    /// The key as lines of hex digit groups
+    fn hex_lines(&self) -> Vec<String> {
+        let hex = hex::encode_upper(self.key);
+        let groups: Vec<&str> = (0..hex.len())
+            .step_by(HEX_GROUP_LENGTH)
+            .map(|i| &hex[i..i + HEX_GROUP_LENGTH])
+            .collect();
+
+        groups
+            .chunks(HEX_GROUPS_PER_LINE)
+            .map(|line| line.join(" "))
+            .collect()
+    }
+
+    /// Render the keyfile in the XML format version 2.0, as written by KeePass
+    pub fn to_xml(&self) -> Result<String, DatabaseKeyError> {
+        let data: String = self
+            .hex_lines()
+            .iter()
+            .map(|line| format!("\t\t\t{}\n", line))
+            .collect();
+
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <KeyFile>\n\
+             \t<Meta>\n\
+             \t\t<Version>2.0</Version>\n\
+             \t</Meta>\n\
+             \t<Key>\n\
+             \t\t<Data Hash=\"{}\">\n\
+             {}\
+             \t\t</Data>\n\
+             \t</Key>\n\
+             </KeyFile>\n",
+            self.hash()?,
+            data
+        ))
+    }
+
+    /// Render the key as a block of hex digit groups followed by its checksum, suitable for
+    /// printing on paper. It can be re-typed and read with [`Keyfile::from_printable_backup`].
+    pub fn to_printable_backup(&self) -> Result<String, DatabaseKeyError> {
+        let mut out = String::from("KeePass keyfile (version 2.0)\n\n");
+        for line in self.hex_lines() {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str(&format!("\n{} {}\n", BACKUP_HASH_PREFIX, self.hash()?));
+        Ok(out)
+    }
+
+    /// Read a key from a printable backup created by [`Keyfile::to_printable_backup`].
+    ///
+    /// Lines consisting of hex digits and whitespace make up the key and other lines are ignored,
+    /// except for the line starting with `Hash:` (in any case), which must match the checksum of the key.
+    pub fn from_printable_backup(backup: &str) -> Result<Self, DatabaseKeyError> {
+        let (mut hex, hash) = read_hex_and_hash(backup);
+
+        let mut key = [0u8; 32];
+        let res = hex::decode_to_slice(&hex, &mut key);
+        hex.zeroize();
+        res.map_err(|_| DatabaseKeyError::InvalidKeyFile)?;
+
+        let keyfile = Keyfile { key };
+        let expected = hash.ok_or(DatabaseKeyError::InvalidKeyFile)?;
+        let actual = keyfile.hash()?;
+        if expected != actual {
+            return Err(DatabaseKeyError::InvalidKeyfileChecksum { expected, actual });
+        }
+
+        Ok(keyfile)
+    }
+}
+
+/// Prefix of the line holding the index of a key share in its printable form
+const SHARE_INDEX_PREFIX: &str = "Share:";
+
+/// Prefix of the line holding the number of shares needed to restore a key
+const SHARE_THRESHOLD_PREFIX: &str = "Threshold:";
+
+/// Collect the hex digits of the lines consisting of hex digits and whitespace, and the checksum
+/// from the line starting with `Hash:` (in any case)
+fn read_hex_and_hash(text: &str) -> (String, Option<String>) {
+    let mut hex = String::new();
+    let mut hash = None;
+
+    for line in text.lines().map(str::trim) {
+        let prefix = line.get(..BACKUP_HASH_PREFIX.len());
+        if prefix.is_some_and(|p| p.eq_ignore_ascii_case(BACKUP_HASH_PREFIX)) {
+            hash = Some(line[BACKUP_HASH_PREFIX.len()..].trim().to_uppercase());
+        } else if !line.is_empty() && line.chars().all(|c| c.is_ascii_hexdigit() || c.is_whitespace()) {
+            hex.extend(line.chars().filter(|c| !c.is_whitespace()));
+        }
+    }
+
+    (hex, hash)
+}
+
+/// Multiply two elements of GF(2^8) with the polynomial of AES
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8), i.e. `a^254`
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+/// One of the shares of a [`Keyfile`] that was split with [`Keyfile::split`]
+#[derive(Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
+pub struct KeyShare {
+    index: u8,
+    threshold: u8,
+    data: [u8; 32],
+    hash: String,
+}
+
+impl std::fmt::Debug for KeyShare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("index", &self.index)
+            .field("threshold", &self.threshold)
+            .field("data", crate::redact::secret(&self.data))
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+impl KeyShare {
+    /// Number of the share, starting at 1
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Number of shares needed to restore the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Render the share as text, e.g. to hand it to one of the holders of the key. It can be read
+    /// back with [`KeyShare::from_printable`].
+    pub fn to_printable(&self) -> String {
+        let mut out = String::from("KeePass keyfile share\n\n");
+        out.push_str(&format!("{} {}\n", SHARE_INDEX_PREFIX, self.index));
+        out.push_str(&format!("{} {}\n\n", SHARE_THRESHOLD_PREFIX, self.threshold));
+        for line in Keyfile::from_key(self.data).hex_lines() {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str(&format!("\n{} {}\n", BACKUP_HASH_PREFIX, self.hash));
+        out
+    }
+
+    /// Read a share created by [`KeyShare::to_printable`]
+    pub fn from_printable(text: &str) -> Result<Self, DatabaseKeyError> {
+        let invalid = |reason: &str| DatabaseKeyError::InvalidKeyShares(reason.to_string());
+
+        let mut index = None;
+        let mut threshold = None;
+        for line in text.lines().map(str::trim) {
+            if let Some(value) = line.strip_prefix(SHARE_INDEX_PREFIX) {
+                index = value.trim().parse::<u8>().ok();
+            } else if let Some(value) = line.strip_prefix(SHARE_THRESHOLD_PREFIX) {
+                threshold = value.trim().parse::<u8>().ok();
+            }
+        }
+
+        let index = index
+            .filter(|i| *i > 0)
+            .ok_or_else(|| invalid("missing share index"))?;
+        let threshold = threshold
+            .filter(|t| *t > 0)
+            .ok_or_else(|| invalid("missing threshold"))?;
+
+        // the hash is the one of the restored key, so it cannot be checked for a single share
+        let (mut hex, hash) = read_hex_and_hash(text);
+
+        let mut data = [0u8; 32];
+        let res = hex::decode_to_slice(&hex, &mut data);
+        hex.zeroize();
+        res.map_err(|_| DatabaseKeyError::InvalidKeyFile)?;
+
+        Ok(KeyShare {
+            index,
+            threshold,
+            data,
+            hash: hash.ok_or(DatabaseKeyError::InvalidKeyFile)?,
+        })
+    }
+}
+
+impl Keyfile {
+    /// Split the key into `count` shares, any `threshold` of which can restore it with
+    /// [`Keyfile::combine`], using Shamir's secret sharing. Fewer shares do not reveal anything
+    /// about the key, except for its checksum.
+    ///
+    /// This allows protecting a database of an organization such that several people need to
+    /// come together to open it.
+    pub fn split(&self, threshold: u8, count: u8) -> Result<Vec<KeyShare>, DatabaseKeyError> {
+        if threshold == 0 || threshold > count {
+            return Err(DatabaseKeyError::InvalidKeyShares(format!(
+                "cannot split a key into {} shares with a threshold of {}",
+                count, threshold
+            )));
+        }
+
+        // random coefficients of a polynomial of degree threshold - 1 for every byte of the key
+        let mut coefficients = vec![0u8; self.key.len() * (threshold as usize - 1)];
+        getrandom::fill(&mut coefficients)?;
+
+        let hash = self.hash()?;
+        let shares = (1..=count)
+            .map(|x| {
+                let mut data = [0u8; 32];
+                for (i, byte) in data.iter_mut().enumerate() {
+                    let higher = coefficients.chunks(self.key.len()).map(|c| c[i]);
+                    *byte = higher.rev().fold(0, |acc, c| gf_mul(acc, x) ^ c);
+                    *byte = gf_mul(*byte, x) ^ self.key[i];
+                }
+                KeyShare {
+                    index: x,
+                    threshold,
+                    data,
+                    hash: hash.clone(),
+                }
+            })
+            .collect();
+
+        coefficients.zeroize();
+        Ok(shares)
+    }
+
+    /// Restore a key from shares created by [`Keyfile::split`]. At least as many distinct shares
+    /// as the threshold of the split are needed.
+    pub fn combine(shares: &[KeyShare]) -> Result<Self, DatabaseKeyError> {
+        let invalid = |reason: &str| DatabaseKeyError::InvalidKeyShares(reason.to_string());
+
+        let first = shares.first().ok_or_else(|| invalid("no shares given"))?;
+        if shares
+            .iter()
+            .any(|s| s.threshold != first.threshold || s.hash != first.hash)
+        {
+            return Err(invalid("the shares belong to different keys"));
+        }
+
+        let mut indices: Vec<u8> = shares.iter().map(|s| s.index).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.len() != shares.len() {
+            return Err(invalid("the same share was given more than once"));
+        }
+        if shares.len() < first.threshold as usize {
+            return Err(DatabaseKeyError::InvalidKeyShares(format!(
+                "{} shares are needed, but only {} were given",
+                first.threshold,
+                shares.len()
+            )));
+        }
+
+        // Lagrange interpolation at x = 0, where subtraction is XOR
+        let shares = &shares[..first.threshold as usize];
+        let mut key = [0u8; 32];
+        for share in shares {
+            let mut basis = 1;
+            for other in shares.iter().filter(|o| o.index != share.index) {
+                basis = gf_mul(basis, gf_mul(other.index, gf_inv(other.index ^ share.index)));
+            }
+            for (byte, y) in key.iter_mut().zip(share.data.iter()) {
+                *byte ^= gf_mul(*y, basis);
+            }
+        }
+
+        let keyfile = Keyfile { key };
+        let actual = keyfile.hash()?;
+        if actual != first.hash {
+            return Err(DatabaseKeyError::InvalidKeyfileChecksum {
+                expected: first.hash.clone(),
+                actual,
+            });
+        }
+
+        Ok(keyfile)
+    }
+}
+
+#[cfg(test)]
+mod keyfile_tests {
+    use super::{KeyShare, Keyfile};
+    use crate::{error::DatabaseKeyError, key::parse_keyfile};
+
+    #[test]
+    fn generate_and_parse() -> Result<(), DatabaseKeyError> {
+        let keyfile = Keyfile::generate()?;
+        assert_ne!(keyfile, Keyfile::generate()?);
+
+        let xml = keyfile.to_xml()?;
+        assert!(xml.contains(&format!("<Data Hash=\"{}\">", keyfile.hash()?)));
+        assert_eq!(parse_keyfile(xml.as_bytes())?, keyfile.key());
+
+        let backup = keyfile.to_printable_backup()?;
+        assert_eq!(Keyfile::from_printable_backup(&backup)?, keyfile);
+        assert_eq!(Keyfile::from_printable_backup(&backup.to_lowercase())?, keyfile);
+        Ok(())
+    }
+
+    #[test]
+    fn printable_backup() -> Result<(), DatabaseKeyError> {
+        let keyfile = Keyfile::from_key([0xab; 32]);
+        let backup = keyfile.to_printable_backup()?;
+
+        let lines: Vec<&str> = backup.lines().collect();
+        assert_eq!(lines[2], "ABABABAB ABABABAB ABABABAB ABABABAB");
+        assert_eq!(lines.len(), 6);
+
+        // a typo in the key is detected by the checksum
+        let typo = backup.replacen("ABABABAB", "ABABABBA", 1);
+        assert!(matches!(
+            Keyfile::from_printable_backup(&typo),
+            Err(DatabaseKeyError::InvalidKeyfileChecksum { .. })
+        ));
+
+        let truncated = backup.replacen("ABABABAB ", "", 1);
+        assert!(matches!(
+            Keyfile::from_printable_backup(&truncated),
+            Err(DatabaseKeyError::InvalidKeyFile)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn split_and_combine() -> Result<(), DatabaseKeyError> {
+        let keyfile = Keyfile::generate()?;
+        let shares = keyfile.split(3, 5)?;
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|s| s.data != *keyfile.key()));
+
+        // any three shares restore the key
+        for (a, b, c) in [(0, 1, 2), (4, 2, 0), (1, 3, 4)] {
+            let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+            assert_eq!(Keyfile::combine(&subset)?, keyfile);
+        }
+        assert_eq!(Keyfile::combine(&shares)?, keyfile);
+
+        // but two do not
+        assert!(matches!(
+            Keyfile::combine(&shares[..2]),
+            Err(DatabaseKeyError::InvalidKeyShares(_))
+        ));
+        let repeated = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(Keyfile::combine(&repeated).is_err());
+
+        let other = Keyfile::generate()?.split(3, 5)?;
+        let mixed = [shares[0].clone(), shares[1].clone(), other[2].clone()];
+        assert!(Keyfile::combine(&mixed).is_err());
+
+        let printed: Vec<KeyShare> = shares[2..]
+            .iter()
+            .map(|s| KeyShare::from_printable(&s.to_printable()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(printed[0], shares[2]);
+        assert_eq!(Keyfile::combine(&printed)?, keyfile);
+
+        assert!(keyfile.split(0, 3).is_err());
+        assert!(keyfile.split(4, 3).is_err());
+        assert_eq!(Keyfile::combine(&keyfile.split(1, 1)?)?, keyfile);
+        Ok(())
+    }
+}
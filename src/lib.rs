@@ -3,18 +3,38 @@
 
 mod compression;
 pub mod config;
-pub(crate) mod crypt;
+pub mod crypt;
 pub mod db;
 pub mod error;
 pub(crate) mod format;
-pub(crate) mod hmac_block_stream;
+pub mod hmac_block_stream;
 #[cfg(feature = "save_kdbx4")]
 mod io;
 mod key;
-pub(crate) mod variant_dictionary;
+mod keyfile;
+pub(crate) mod redact;
+pub mod storage;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub(crate) mod trace;
+pub mod variant_dictionary;
 pub(crate) mod xml_db;
 
+/// Entry points for the fuzz targets in the `fuzz` directory
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod fuzzing {
+    pub fn parse_keyfile(data: &[u8]) -> Result<Vec<u8>, crate::error::DatabaseKeyError> {
+        crate::key::parse_keyfile(data)
+    }
+}
+
 pub use self::db::Database;
+pub use self::error::{Error, ErrorCode, ErrorKind};
+pub use self::key::{find_keyfile_for, DatabaseKey, FallbackOpen, KeySourceAssociation, KeyVariant};
 #[cfg(feature = "challenge_response")]
-pub use self::key::ChallengeResponseKey;
-pub use self::key::DatabaseKey;
+pub use self::key::{
+    ChallengeResponseKey, SlotStatus, TouchPrompt, YubikeyInfo, DEFAULT_CHALLENGE_TIMEOUT,
+    DEFAULT_TOUCH_PROMPT_DELAY, YUBIKEY_SLOTS,
+};
+pub use self::keyfile::{KeyShare, Keyfile};
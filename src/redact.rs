@@ -0,0 +1,88 @@
+//! Redaction of secrets in `Debug` output, so that passwords and key material do not end up in
+//! logs by accident
+
+use std::fmt::Debug;
+
+/// Placeholder that is printed instead of a secret
+pub(crate) const REDACTED: &str = "<redacted>";
+
+struct Redacted;
+
+impl Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+#[cfg(feature = "debug_secrets")]
+thread_local! {
+    static REVEAL: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn revealed() -> bool {
+    #[cfg(feature = "debug_secrets")]
+    return REVEAL.with(|r| r.get());
+
+    #[cfg(not(feature = "debug_secrets"))]
+    false
+}
+
+/// Get a value for formatting a secret, which prints [`REDACTED`] unless secrets are revealed
+pub(crate) fn secret<T: Debug>(value: &T) -> &dyn Debug {
+    if revealed() {
+        value
+    } else {
+        &Redacted
+    }
+}
+
+/// Run `f` with secrets revealed in `Debug` output on the current thread
+#[cfg(feature = "debug_secrets")]
+pub(crate) fn reveal<T>(f: impl FnOnce() -> T) -> T {
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            REVEAL.with(|r| r.set(false));
+        }
+    }
+
+    REVEAL.with(|r| r.set(true));
+    let _reset = Reset;
+    f()
+}
+
+#[cfg(test)]
+mod redact_tests {
+    use crate::db::{Database, Entry, Value};
+    use crate::DatabaseKey;
+
+    #[test]
+    fn redacted_debug() {
+        let key = DatabaseKey::new().with_password("hunter2");
+        let debug = format!("{:?}", key);
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains("<redacted>"));
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Password".into(), Value::Protected("hunter2".as_bytes().into()));
+        entry
+            .fields
+            .insert("UserName".into(), Value::Unprotected("jdoe".to_string()));
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry);
+
+        let debug = format!("{:?}", db);
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains("jdoe"));
+
+        #[cfg(feature = "debug_secrets")]
+        {
+            assert!(db.debug_full().contains("hunter2"));
+            assert!(!format!("{:?}", db).contains("hunter2"));
+        }
+    }
+}
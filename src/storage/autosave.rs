@@ -0,0 +1,242 @@
+use std::{
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{error::DatabaseSaveError, key::DatabaseKey, storage::Storage, Database};
+
+enum Message {
+    Save(Box<Database>),
+    Flush(Sender<()>),
+}
+
+/// Saves snapshots of a database to a storage backend on a background thread, once no newer
+/// snapshot has been scheduled for a while.
+///
+/// This gives interactive applications the usual "save after edit" behavior: schedule a snapshot
+/// after every change, e.g. with [`TrackedDatabase::schedule_save`](crate::db::TrackedDatabase::schedule_save),
+/// and a burst of changes results in a single save. Pending snapshots are saved when the
+/// `AutoSaver` is dropped.
+pub struct AutoSaver {
+    sender: Option<Sender<Message>>,
+    worker: Option<JoinHandle<()>>,
+    error: Arc<Mutex<Option<DatabaseSaveError>>>,
+}
+
+impl AutoSaver {
+    /// Start a background thread that saves scheduled snapshots to `storage` with `key`, once no
+    /// newer snapshot has been scheduled for the duration of `debounce`
+    pub fn new<S: Storage + Send + 'static>(storage: S, key: DatabaseKey, debounce: Duration) -> Self {
+        let (sender, receiver) = channel::<Message>();
+        let error = Arc::new(Mutex::new(None));
+
+        let worker_error = error.clone();
+        let worker = std::thread::spawn(move || {
+            let mut storage = storage;
+            let mut pending: Option<Box<Database>> = None;
+
+            let mut save = |pending: &mut Option<Box<Database>>| {
+                if let Some(db) = pending.take() {
+                    if let Err(e) = db.save_to(&mut storage, key.clone()) {
+                        if let Ok(mut error) = worker_error.lock() {
+                            *error = Some(e);
+                        }
+                    }
+                }
+            };
+
+            loop {
+                let message = if pending.is_some() {
+                    match receiver.recv_timeout(debounce) {
+                        Ok(message) => Some(message),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                } else {
+                    match receiver.recv() {
+                        Ok(message) => Some(message),
+                        Err(_) => break,
+                    }
+                };
+
+                match message {
+                    Some(Message::Save(db)) => pending = Some(db),
+                    Some(Message::Flush(done)) => {
+                        save(&mut pending);
+                        let _ = done.send(());
+                    }
+                    None => save(&mut pending),
+                }
+            }
+
+            save(&mut pending);
+        });
+
+        AutoSaver {
+            sender: Some(sender),
+            worker: Some(worker),
+            error,
+        }
+    }
+
+    /// Schedule a snapshot of the database to be saved, replacing any snapshot that has not been
+    /// saved yet
+    pub fn schedule(&self, db: &Database) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Message::Save(Box::new(db.clone())));
+        }
+    }
+
+    /// Save the pending snapshot right away and wait until it is saved, returning the error of
+    /// the last failed save since the error was last taken
+    pub fn flush(&self) -> Result<(), DatabaseSaveError> {
+        if let Some(sender) = &self.sender {
+            let (done, wait) = channel();
+            if sender.send(Message::Flush(done)).is_ok() {
+                let _ = wait.recv();
+            }
+        }
+
+        match self.take_error() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Take the error of the last failed save, if any
+    pub fn take_error(&self) -> Option<DatabaseSaveError> {
+        self.error.lock().ok().and_then(|mut error| error.take())
+    }
+}
+
+impl std::fmt::Debug for AutoSaver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AutoSaver")
+            .field("running", &self.worker.is_some())
+            .finish()
+    }
+}
+
+impl Drop for AutoSaver {
+    fn drop(&mut self) {
+        // closing the channel makes the worker save the pending snapshot and stop
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod autosave_tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::AutoSaver;
+    use crate::{
+        db::{Database, Entry, TrackedDatabase},
+        storage::{MemoryStorage, Storage, StorageMetadata},
+        DatabaseKey,
+    };
+
+    /// A storage that can be inspected while the `AutoSaver` owns it, counting the saves
+    #[derive(Clone, Default)]
+    struct SharedStorage(Arc<Mutex<(MemoryStorage, usize)>>);
+
+    impl Storage for SharedStorage {
+        fn read(&self) -> std::io::Result<Vec<u8>> {
+            self.0.lock().unwrap().0.read()
+        }
+
+        fn write_atomic(&mut self, data: &[u8]) -> std::io::Result<()> {
+            let mut inner = self.0.lock().unwrap();
+            inner.1 += 1;
+            inner.0.write_atomic(data)
+        }
+
+        fn metadata(&self) -> std::io::Result<StorageMetadata> {
+            self.0.lock().unwrap().0.metadata()
+        }
+    }
+
+    impl SharedStorage {
+        fn saves(&self) -> usize {
+            self.0.lock().unwrap().1
+        }
+    }
+
+    fn key() -> DatabaseKey {
+        DatabaseKey::new().with_password("autosave")
+    }
+
+    #[test]
+    fn save_if_dirty() {
+        let mut storage = MemoryStorage::new();
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        assert!(!db.is_dirty());
+        assert!(!db.save_if_dirty(&mut storage, key()).unwrap());
+        assert!(storage.data().is_none());
+
+        let root = db.database().root.uuid;
+        db.add_entry(root, Entry::new()).unwrap();
+        assert!(db.is_dirty());
+        assert!(db.save_if_dirty(&mut storage, key()).unwrap());
+        assert!(!db.is_dirty());
+
+        let saved = Database::open_from(&storage, key()).unwrap();
+        assert_eq!(saved.root.entries().len(), 1);
+    }
+
+    #[test]
+    fn auto_saver() {
+        let storage = SharedStorage::default();
+        let saver = AutoSaver::new(storage.clone(), key(), Duration::from_secs(3600));
+        let mut db = TrackedDatabase::new(Database::new(Default::default()));
+        let root = db.database().root.uuid;
+
+        assert!(!db.schedule_save(&saver));
+        for _ in 0..3 {
+            db.add_entry(root, Entry::new()).unwrap();
+            assert!(db.schedule_save(&saver));
+        }
+        assert!(!db.is_dirty());
+
+        // the debounce time has not passed, so only flushing saves, and only the latest snapshot
+        assert_eq!(storage.saves(), 0);
+        saver.flush().unwrap();
+        assert_eq!(storage.saves(), 1);
+        assert_eq!(
+            Database::open_from(&storage, key()).unwrap().root.entries().len(),
+            3
+        );
+
+        // pending snapshots are saved on drop
+        db.add_entry(root, Entry::new()).unwrap();
+        db.schedule_save(&saver);
+        drop(saver);
+        assert_eq!(storage.saves(), 2);
+        assert_eq!(
+            Database::open_from(&storage, key()).unwrap().root.entries().len(),
+            4
+        );
+
+        // without flushing, snapshots are saved after the debounce time
+        let saver = AutoSaver::new(storage.clone(), key(), Duration::from_millis(10));
+        db.add_entry(root, Entry::new()).unwrap();
+        db.schedule_save(&saver);
+        for _ in 0..500 {
+            if storage.saves() == 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(storage.saves(), 3);
+        assert!(saver.take_error().is_none());
+    }
+}
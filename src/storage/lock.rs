@@ -0,0 +1,293 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use base64::{engine::general_purpose as base64_engine, Engine as _};
+use chrono::{Duration, NaiveDateTime};
+
+use crate::db::Times;
+
+/// First line of a lock file, as written by KeePass
+const LOCK_FILE_HEADER: &str = "KeePass Lock File";
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Age after which a lock file is considered to be left over from a client that crashed
+pub const DEFAULT_STALE_LOCK_AGE: Duration = Duration::hours(24);
+
+/// Identifiers of the locks held by [`LockFile`]s of this process
+static HELD_LOCKS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Contents of a lock file, describing who opened the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockInfo {
+    /// Random identifier of the lock
+    pub id: String,
+
+    /// Time at which the lock was acquired, in UTC
+    pub time: NaiveDateTime,
+
+    pub user: String,
+    pub machine: String,
+    pub domain: String,
+}
+
+impl LockInfo {
+    fn new() -> std::io::Result<LockInfo> {
+        let mut id = [0u8; 16];
+        getrandom::fill(&mut id)?;
+
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let machine = match var("COMPUTERNAME") {
+            m if !m.is_empty() => m,
+            _ => std::fs::read_to_string("/etc/hostname")
+                .map(|h| h.trim().to_string())
+                .unwrap_or_else(|_| var("HOSTNAME")),
+        };
+        let user = match var("USERNAME") {
+            u if !u.is_empty() => u,
+            _ => var("USER"),
+        };
+
+        Ok(LockInfo {
+            id: base64_engine::STANDARD.encode(id),
+            time: Times::now(),
+            user,
+            machine,
+            domain: var("USERDOMAIN"),
+        })
+    }
+
+    /// Parse the contents of a lock file, returning `None` if it is not a valid lock file
+    fn parse(content: &str) -> Option<LockInfo> {
+        let mut lines = content.lines().map(str::trim);
+        if lines.next()? != LOCK_FILE_HEADER {
+            return None;
+        }
+
+        let id = lines.next()?.to_string();
+        let time = NaiveDateTime::parse_from_str(lines.next()?, TIME_FORMAT).ok()?;
+        let mut next = || lines.next().unwrap_or_default().to_string();
+
+        Some(LockInfo {
+            id,
+            time,
+            user: next(),
+            machine: next(),
+            domain: next(),
+        })
+    }
+
+    fn to_content(&self) -> String {
+        [
+            LOCK_FILE_HEADER,
+            &self.id,
+            &self.time.format(TIME_FORMAT).to_string(),
+            &self.user,
+            &self.machine,
+            &self.domain,
+        ]
+        .map(|line| format!("{}\n", line))
+        .concat()
+    }
+
+    /// Whether the lock is older than `max_age`, e.g. because the client holding it crashed
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        Times::now() - self.time > max_age
+    }
+
+    /// Whether the lock is held by a [`LockFile`] of this process
+    pub fn is_own(&self) -> bool {
+        HELD_LOCKS
+            .lock()
+            .map(|held| held.contains(&self.id))
+            .unwrap_or(false)
+    }
+}
+
+/// Error returned when a database is locked by another client.
+///
+/// It is wrapped in an [`std::io::Error`]; use [`is_locked`] to detect it.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("The database was opened by {} on {} at {}", .0.user, .0.machine, .0.time)]
+pub struct DatabaseLocked(pub LockInfo);
+
+impl From<DatabaseLocked> for std::io::Error {
+    fn from(locked: DatabaseLocked) -> Self {
+        std::io::Error::other(locked)
+    }
+}
+
+/// Check whether an I/O error is a [`DatabaseLocked`] error, returning the lock if so
+pub fn is_locked(error: &std::io::Error) -> Option<&LockInfo> {
+    error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<DatabaseLocked>())
+        .map(|locked| &locked.0)
+}
+
+/// Path of the lock file of a database, i.e. the path of the database with `.lock` appended
+pub fn lock_file_path(database: &Path) -> PathBuf {
+    let mut path = database.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// Read the lock file of a database, if there is one.
+///
+/// Lock files that cannot be parsed are returned as a lock by an unknown client that was
+/// acquired at the Unix epoch, so that they are treated as stale.
+pub fn read_lock(database: &Path) -> std::io::Result<Option<LockInfo>> {
+    match std::fs::read_to_string(lock_file_path(database)) {
+        Ok(content) => Ok(Some(LockInfo::parse(&content).unwrap_or(LockInfo {
+            id: String::new(),
+            time: Times::epoch(),
+            user: String::new(),
+            machine: String::new(),
+            domain: String::new(),
+        }))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check that no other client holds a lock on a database that is younger than `stale_after`
+pub(crate) fn check_lock(database: &Path, stale_after: Duration) -> std::io::Result<()> {
+    match read_lock(database)? {
+        Some(info) if !info.is_own() && !info.is_stale(stale_after) => Err(DatabaseLocked(info).into()),
+        _ => Ok(()),
+    }
+}
+
+/// An advisory lock on a database file, compatible with the lock files of KeePass.
+///
+/// The lock file is created next to the database and removed again when the `LockFile` is
+/// dropped. Other clients are not prevented from opening the database, but they can detect that
+/// it is in use and warn the user.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    info: LockInfo,
+}
+
+impl LockFile {
+    /// Lock a database, replacing locks that are older than [`DEFAULT_STALE_LOCK_AGE`]
+    pub fn acquire(database: impl AsRef<Path>) -> std::io::Result<LockFile> {
+        LockFile::acquire_with_stale_age(database, DEFAULT_STALE_LOCK_AGE)
+    }
+
+    /// Lock a database, replacing locks that are older than `stale_after`.
+    ///
+    /// Fails with a [`DatabaseLocked`] error if another client holds a lock on the database.
+    pub fn acquire_with_stale_age(
+        database: impl AsRef<Path>,
+        stale_after: Duration,
+    ) -> std::io::Result<LockFile> {
+        let database = database.as_ref();
+        let path = lock_file_path(database);
+        let info = LockInfo::new()?;
+
+        let create = || {
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            file.write_all(info.to_content().as_bytes())?;
+            file.sync_all()
+        };
+
+        match create() {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                check_lock(database, stale_after)?;
+                std::fs::remove_file(&path)?;
+                create()?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        if let Ok(mut held) = HELD_LOCKS.lock() {
+            held.push(info.id.clone());
+        }
+
+        Ok(LockFile { path, info })
+    }
+
+    /// Information about the lock, as written to the lock file
+    pub fn info(&self) -> &LockInfo {
+        &self.info
+    }
+
+    /// Path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if let Ok(mut held) = HELD_LOCKS.lock() {
+            held.retain(|id| *id != self.info.id);
+        }
+
+        // only remove the lock file if it was not taken over by another client in the meantime
+        let still_ours = std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| LockInfo::parse(&content))
+            .is_some_and(|info| info.id == self.info.id);
+        if still_ours {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod lock_tests {
+    use chrono::Duration;
+
+    use super::{is_locked, lock_file_path, read_lock, LockFile, LockInfo};
+
+    fn database_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("keepass-lock-{}.kdbx", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn content_round_trip() {
+        let info = LockInfo::new().unwrap();
+        let content = info.to_content();
+        assert!(content.starts_with("KeePass Lock File\n"));
+        assert_eq!(LockInfo::parse(&content), Some(info));
+        assert_eq!(LockInfo::parse("not a lock file"), None);
+    }
+
+    #[test]
+    fn acquire_and_release() {
+        let path = database_path();
+        let lock = LockFile::acquire(&path).unwrap();
+        assert!(lock.path().exists());
+        assert!(lock.info().is_own());
+        assert_eq!(read_lock(&path).unwrap().as_ref(), Some(lock.info()));
+
+        // a lock held by another client is respected
+        let mut other = lock.info().clone();
+        other.id = "other".to_string();
+        std::fs::write(lock_file_path(&path), other.to_content()).unwrap();
+        let err = LockFile::acquire(&path).unwrap_err();
+        assert_eq!(is_locked(&err), Some(&other));
+
+        // the lock file of the other client is not removed
+        drop(lock);
+        assert!(lock_file_path(&path).exists());
+
+        // unless it is stale
+        other.time -= Duration::hours(25);
+        std::fs::write(lock_file_path(&path), other.to_content()).unwrap();
+        let lock = LockFile::acquire(&path).unwrap();
+        let lock_path = lock.path().to_path_buf();
+        drop(lock);
+        assert!(!lock_path.exists());
+        assert_eq!(read_lock(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,478 @@
+//! Storage backends that databases can be opened from and saved to.
+//!
+//! The [`Storage`] trait abstracts over where the bytes of a database file live, so that
+//! functionality like reloading, merging or backups can be written once for local files,
+//! in-memory buffers and remote backends implemented in other crates.
+
+#[cfg(feature = "save_kdbx4")]
+mod autosave;
+mod lock;
+#[cfg(feature = "webdav")]
+mod webdav;
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{error::DatabaseOpenError, key::DatabaseKey, Database};
+
+#[cfg(feature = "save_kdbx4")]
+pub use self::autosave::AutoSaver;
+pub use self::lock::{
+    is_locked, lock_file_path, read_lock, DatabaseLocked, LockFile, LockInfo, DEFAULT_STALE_LOCK_AGE,
+};
+#[cfg(feature = "webdav")]
+pub use self::webdav::{WebDavAuth, WebDavStorage};
+
+/// Error returned by [`Storage::write_atomic`] when the stored database was changed by someone
+/// else since it was last read, and writing would overwrite these changes.
+///
+/// It is wrapped in an [`std::io::Error`]; use [`is_conflict`] to detect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("The stored database was modified concurrently")]
+pub struct StorageConflict;
+
+impl From<StorageConflict> for std::io::Error {
+    fn from(conflict: StorageConflict) -> Self {
+        std::io::Error::other(conflict)
+    }
+}
+
+/// Check whether an I/O error returned by a storage backend is a [`StorageConflict`]
+pub fn is_conflict(error: &std::io::Error) -> bool {
+    error
+        .get_ref()
+        .map(|inner| inner.is::<StorageConflict>())
+        .unwrap_or(false)
+}
+
+/// Metadata about the database file in a storage backend
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageMetadata {
+    /// Size of the stored database file in bytes
+    pub len: u64,
+
+    /// Time of the last modification, if known
+    pub modified: Option<SystemTime>,
+
+    /// An opaque version identifier (e.g. an ETag) that changes whenever the file changes, if
+    /// supported by the backend
+    pub version: Option<String>,
+}
+
+/// A location that a database file can be read from and written to
+pub trait Storage {
+    /// Read the complete database file
+    fn read(&self) -> std::io::Result<Vec<u8>>;
+
+    /// Replace the database file with `data`. Readers must either see the old or the new
+    /// content, never a partially written file.
+    ///
+    /// Backends that can detect concurrent modifications should fail with a
+    /// [`StorageConflict`] if the file was changed since it was last read.
+    fn write_atomic(&mut self, data: &[u8]) -> std::io::Result<()>;
+
+    /// Get metadata about the database file
+    fn metadata(&self) -> std::io::Result<StorageMetadata>;
+}
+
+/// A database file on the local file system.
+///
+/// Writing fails with a [`DatabaseLocked`] error while another client holds a [`LockFile`] on
+/// the database, unless this is disabled with [`FileStorage::with_lock_check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStorage {
+    path: PathBuf,
+    lock_check: bool,
+}
+
+impl FileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStorage {
+            path: path.into(),
+            lock_check: true,
+        }
+    }
+
+    /// Whether to refuse writing while another client holds a lock on the database
+    pub fn with_lock_check(mut self, lock_check: bool) -> Self {
+        self.lock_check = lock_check;
+        self
+    }
+
+    /// Path of the database file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn temporary_path(&self) -> PathBuf {
+        let mut file_name = std::ffi::OsString::from(".");
+        file_name.push(self.path.file_name().unwrap_or_default());
+        file_name.push(".tmp");
+        self.path.with_file_name(file_name)
+    }
+}
+
+/// Create the temporary file for [`FileStorage::write_atomic`], only readable by the current
+/// user until its final permissions are set
+fn create_temporary_file(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
+
+impl Storage for FileStorage {
+    fn read(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&self.path)
+    }
+
+    fn write_atomic(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if self.lock_check {
+            lock::check_lock(&self.path, DEFAULT_STALE_LOCK_AGE)?;
+        }
+
+        // write to a temporary file next to the database and move it into place, so that the
+        // database file is never left in a partially written state
+        let temporary_path = self.temporary_path();
+
+        let result = (|| {
+            let mut file = create_temporary_file(&temporary_path)?;
+
+            // keep the permissions of the database file, and do not make new databases readable
+            // for other users
+            match std::fs::metadata(&self.path) {
+                Ok(metadata) => file.set_permissions(metadata.permissions())?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+
+            file.write_all(data)?;
+            file.sync_all()?;
+            std::fs::rename(&temporary_path, &self.path)
+        })();
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temporary_path);
+        }
+
+        result
+    }
+
+    fn metadata(&self) -> std::io::Result<StorageMetadata> {
+        let metadata = std::fs::metadata(&self.path)?;
+        Ok(StorageMetadata {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            version: None,
+        })
+    }
+}
+
+/// A database file held in memory, e.g. for tests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStorage {
+    data: Option<Vec<u8>>,
+    modified: Option<SystemTime>,
+    generation: u64,
+}
+
+impl MemoryStorage {
+    /// Create an empty in-memory storage. Reading from it fails until data has been written.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create an in-memory storage that holds `data`
+    pub fn with_data(data: Vec<u8>) -> Self {
+        MemoryStorage {
+            data: Some(data),
+            modified: Some(SystemTime::now()),
+            generation: 1,
+        }
+    }
+
+    /// The stored bytes, if any
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self) -> std::io::Result<Vec<u8>> {
+        self.data
+            .clone()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No data stored"))
+    }
+
+    fn write_atomic(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.data = Some(data.to_vec());
+        self.modified = Some(SystemTime::now());
+        self.generation += 1;
+        Ok(())
+    }
+
+    fn metadata(&self) -> std::io::Result<StorageMetadata> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No data stored"))?;
+
+        Ok(StorageMetadata {
+            len: data.len() as u64,
+            modified: self.modified,
+            version: Some(self.generation.to_string()),
+        })
+    }
+}
+
+impl Database {
+    /// Open a database from a storage backend
+    pub fn open_from(storage: &dyn Storage, key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
+        Database::parse(&storage.read()?, key)
+    }
+
+    /// Save a database to a storage backend, replacing the stored database atomically
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_to(
+        &self,
+        storage: &mut dyn Storage,
+        key: DatabaseKey,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        let mut data = Vec::new();
+        self.save(&mut data, key)?;
+        storage.write_atomic(&data)?;
+        Ok(())
+    }
+
+    /// Save a database to a file, replacing it atomically.
+    ///
+    /// Fails with a [`DatabaseLocked`] error, wrapped in [`DatabaseSaveError::Io`](crate::error::DatabaseSaveError::Io),
+    /// if another client holds a lock on the database. Use [`Database::save_to`] with a
+    /// [`FileStorage`] to configure this.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_to_path(
+        &self,
+        path: impl AsRef<Path>,
+        key: DatabaseKey,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        self.save_to(&mut FileStorage::new(path.as_ref()), key)
+    }
+
+    /// Lock a database file like KeePass does, to let other clients know that it is in use and
+    /// changes might be overwritten. See [`LockFile::acquire`].
+    pub fn acquire_lock(path: impl AsRef<Path>) -> std::io::Result<LockFile> {
+        LockFile::acquire(path)
+    }
+
+    /// Save a database to a storage backend. If the stored database has been modified since it
+    /// was last read, the stored database is merged into this one before trying again, so that
+    /// concurrent changes are not overwritten.
+    ///
+    /// Returns the log of the merge if merging was necessary.
+    #[cfg(all(feature = "save_kdbx4", feature = "_merge"))]
+    pub fn save_to_merging(
+        &mut self,
+        storage: &mut dyn Storage,
+        key: DatabaseKey,
+    ) -> Result<Option<crate::db::merge::MergeLog>, crate::error::Error> {
+        use crate::error::DatabaseSaveError;
+
+        const MAX_ATTEMPTS: usize = 3;
+
+        let mut merge_log: Option<crate::db::merge::MergeLog> = None;
+
+        for _ in 0..MAX_ATTEMPTS {
+            match self.save_to(storage, key.clone()) {
+                Err(DatabaseSaveError::Io(e)) if is_conflict(&e) => {
+                    let stored = Database::open_from(storage, key.clone())?;
+                    let log = self.merge(&stored)?;
+
+                    match merge_log.as_mut() {
+                        Some(merge_log) => merge_log.append(&log),
+                        None => merge_log = Some(log),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+                Ok(()) => return Ok(merge_log),
+            }
+        }
+
+        Err(DatabaseSaveError::Io(StorageConflict.into()).into())
+    }
+}
+
+#[cfg(test)]
+mod storage_tests {
+    use super::{FileStorage, MemoryStorage, Storage};
+
+    #[test]
+    fn memory_storage() {
+        let mut storage = MemoryStorage::new();
+        assert!(storage.read().is_err());
+        assert!(storage.metadata().is_err());
+
+        storage.write_atomic(b"first").unwrap();
+        let first_version = storage.metadata().unwrap().version;
+        assert_eq!(storage.read().unwrap(), b"first");
+
+        storage.write_atomic(b"second").unwrap();
+        assert_eq!(storage.read().unwrap(), b"second");
+        assert_eq!(storage.metadata().unwrap().len, 6);
+        assert_ne!(storage.metadata().unwrap().version, first_version);
+    }
+
+    #[test]
+    fn file_storage() {
+        let path = std::env::temp_dir().join(format!("keepass-storage-{}.kdbx", uuid::Uuid::new_v4()));
+        let mut storage = FileStorage::new(&path);
+        assert!(storage.read().is_err());
+
+        storage.write_atomic(b"first").unwrap();
+        storage.write_atomic(b"second").unwrap();
+        assert_eq!(storage.read().unwrap(), b"second");
+        assert_eq!(storage.metadata().unwrap().len, 6);
+        assert!(!storage.temporary_path().exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_storage_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("keepass-storage-{}.kdbx", uuid::Uuid::new_v4()));
+        let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let mut storage = FileStorage::new(&path);
+
+        // new databases are only readable by the current user
+        storage.write_atomic(b"first").unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // existing permissions are kept when overwriting
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        storage.write_atomic(b"second").unwrap();
+        assert_eq!(mode(&path), 0o640);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn database_roundtrip() {
+        use crate::{config::DatabaseConfig, config::KdfConfig, Database, DatabaseKey};
+
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        });
+        db.meta.database_name = Some("Stored".to_string());
+
+        let mut storage = MemoryStorage::new();
+        let key = DatabaseKey::new().with_password("storage");
+        db.save_to(&mut storage, key.clone()).unwrap();
+
+        let reopened = Database::open_from(&storage, key).unwrap();
+        assert_eq!(reopened.meta.database_name.as_deref(), Some("Stored"));
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn save_to_locked_path() {
+        use super::{is_locked, lock_file_path};
+        use crate::{config::DatabaseConfig, config::KdfConfig, Database, DatabaseKey};
+
+        let db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        });
+        let key = DatabaseKey::new().with_password("storage");
+        let path = std::env::temp_dir().join(format!("keepass-locked-{}.kdbx", uuid::Uuid::new_v4()));
+
+        // saving while holding the lock ourselves works
+        let lock = Database::acquire_lock(&path).unwrap();
+        db.save_to_path(&path, key.clone()).unwrap();
+        drop(lock);
+
+        // but not while another client holds it
+        let now = crate::db::Times::now().format("%Y-%m-%dT%H:%M:%SZ");
+        let content = format!("KeePass Lock File\nother\n{}\nuser\nmachine\n\n", now);
+        std::fs::write(lock_file_path(&path), content).unwrap();
+
+        match db.save_to_path(&path, key.clone()) {
+            Err(crate::error::DatabaseSaveError::Io(e)) => assert!(is_locked(&e).is_some()),
+            other => panic!("expected a lock error, got {:?}", other),
+        }
+        db.save_to(&mut FileStorage::new(&path).with_lock_check(false), key)
+            .unwrap();
+
+        std::fs::remove_file(lock_file_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A storage that rejects the first write as a concurrent modification
+    #[cfg(feature = "_merge")]
+    struct ConflictOnce(MemoryStorage, bool);
+
+    #[cfg(feature = "_merge")]
+    impl Storage for ConflictOnce {
+        fn read(&self) -> std::io::Result<Vec<u8>> {
+            self.0.read()
+        }
+
+        fn write_atomic(&mut self, data: &[u8]) -> std::io::Result<()> {
+            if std::mem::replace(&mut self.1, false) {
+                return Err(super::StorageConflict.into());
+            }
+            self.0.write_atomic(data)
+        }
+
+        fn metadata(&self) -> std::io::Result<super::StorageMetadata> {
+            self.0.metadata()
+        }
+    }
+
+    #[cfg(all(feature = "save_kdbx4", feature = "_merge"))]
+    #[test]
+    fn save_merging_on_conflict() {
+        use crate::{config::DatabaseConfig, config::KdfConfig, db::Entry, Database, DatabaseKey};
+
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        });
+        let key = DatabaseKey::new().with_password("storage");
+
+        let mut storage = ConflictOnce(MemoryStorage::new(), false);
+        db.save_to(&mut storage, key.clone()).unwrap();
+
+        // another client adds an entry to the stored database
+        let mut remote = Database::open_from(&storage, key.clone()).unwrap();
+        remote.root.add_child(Entry::new());
+        remote.save_to(&mut storage, key.clone()).unwrap();
+
+        db.root.add_child(Entry::new());
+        storage.1 = true;
+
+        let merge_log = db.save_to_merging(&mut storage, key.clone()).unwrap();
+        assert!(merge_log.is_some());
+
+        let stored = Database::open_from(&storage, key).unwrap();
+        assert_eq!(stored.root.children.len(), 2);
+    }
+}
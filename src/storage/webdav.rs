@@ -0,0 +1,398 @@
+use std::{borrow::Cow, io::Read, sync::Mutex, time::SystemTime};
+
+use crate::storage::{Storage, StorageConflict, StorageMetadata};
+
+/// Credentials for a WebDAV server
+#[derive(Clone, PartialEq, Eq)]
+pub enum WebDavAuth {
+    /// Do not authenticate
+    None,
+
+    /// HTTP basic authentication
+    Basic { username: String, password: String },
+
+    /// HTTP digest authentication
+    Digest { username: String, password: String },
+}
+
+impl std::fmt::Debug for WebDavAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebDavAuth::None => f.write_str("None"),
+            WebDavAuth::Basic { username, password } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", crate::redact::secret(password))
+                .finish(),
+            WebDavAuth::Digest { username, password } => f
+                .debug_struct("Digest")
+                .field("username", username)
+                .field("password", crate::redact::secret(password))
+                .finish(),
+        }
+    }
+}
+
+/// A database file on a WebDAV server, e.g. a Nextcloud instance.
+///
+/// The ETag of the file is remembered whenever it is read or written, and sent along with the
+/// next write, so that concurrent changes by other clients are detected as a
+/// [`StorageConflict`] instead of being overwritten. If the server does not send the new ETag in
+/// the response to a write, it is requested separately.
+pub struct WebDavStorage {
+    agent: ureq::Agent,
+    url: String,
+    auth: WebDavAuth,
+    etag: Mutex<Option<String>>,
+
+    /// The last digest authentication challenge of the server, which is reused for later requests
+    digest_challenge: Mutex<Option<digest_auth::WwwAuthenticateHeader>>,
+}
+
+impl std::fmt::Debug for WebDavStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebDavStorage")
+            .field("url", &self.url)
+            .field("auth", &self.auth)
+            .field("etag", &self.etag)
+            .finish_non_exhaustive()
+    }
+}
+
+fn io_error<E: std::fmt::Display>(error: E) -> std::io::Error {
+    std::io::Error::other(error.to_string())
+}
+
+/// Get the path and query of a URL, which is needed for digest authentication
+fn request_uri(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    match without_scheme.find('/') {
+        Some(start) => &without_scheme[start..],
+        None => "/",
+    }
+}
+
+impl WebDavStorage {
+    pub fn new(url: &str, auth: WebDavAuth) -> Self {
+        WebDavStorage {
+            agent: ureq::Agent::new(),
+            url: url.to_string(),
+            auth,
+            etag: Mutex::new(None),
+            digest_challenge: Mutex::new(None),
+        }
+    }
+
+    /// The ETag of the database file as it was last read or written
+    pub fn etag(&self) -> Option<String> {
+        self.etag.lock().ok().and_then(|etag| etag.clone())
+    }
+
+    /// Remember the ETag of a response, returning whether it had one. Responses without an ETag
+    /// leave the previous one in place, so that conflict detection stays enabled.
+    fn set_etag(&self, response: &ureq::Response) -> bool {
+        match (response.header("ETag"), self.etag.lock()) {
+            (Some(new_etag), Ok(mut etag)) => {
+                *etag = Some(new_etag.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remember the digest authentication challenge of a response with status 401
+    fn set_digest_challenge(&self, response: &ureq::Response) -> std::io::Result<()> {
+        let challenge = response
+            .header("WWW-Authenticate")
+            .ok_or_else(|| io_error("Missing WWW-Authenticate header"))?;
+        let challenge = digest_auth::parse(challenge).map_err(io_error)?;
+
+        if let Ok(mut digest_challenge) = self.digest_challenge.lock() {
+            *digest_challenge = Some(challenge);
+        }
+        Ok(())
+    }
+
+    /// Answer the remembered digest authentication challenge for a request
+    fn digest_authorization(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        body: Option<&[u8]>,
+    ) -> std::io::Result<Option<String>> {
+        let mut digest_challenge = self.digest_challenge.lock().map_err(io_error)?;
+        let Some(challenge) = digest_challenge.as_mut() else {
+            return Ok(None);
+        };
+
+        let context = digest_auth::AuthContext::new_with_method(
+            username,
+            password,
+            request_uri(&self.url),
+            body,
+            digest_auth::HttpMethod(Cow::Borrowed(method)),
+        );
+        let authorization = challenge.respond(&context).map_err(io_error)?;
+        Ok(Some(authorization.to_header_string()))
+    }
+
+    fn request(&self, method: &str, body: Option<&[u8]>) -> std::io::Result<ureq::Response> {
+        // ureq reports HTTP error statuses with the full response, which makes its error type large
+        #[allow(clippy::result_large_err)]
+        let send = |authorization: Option<&str>| {
+            let mut request = self.agent.request(method, &self.url);
+
+            if let Some(authorization) = authorization {
+                request = request.set("Authorization", authorization);
+            }
+
+            if method == "PUT" {
+                if let Some(etag) = self.etag() {
+                    request = request.set("If-Match", &etag);
+                }
+            }
+
+            match body {
+                Some(body) => request.send_bytes(body),
+                None => request.call(),
+            }
+        };
+
+        let result = match &self.auth {
+            WebDavAuth::None => send(None),
+            WebDavAuth::Basic { username, password } => {
+                use base64::{engine::general_purpose as base64_engine, Engine as _};
+
+                let credentials = base64_engine::STANDARD.encode(format!("{}:{}", username, password));
+                send(Some(&format!("Basic {}", credentials)))
+            }
+            WebDavAuth::Digest { username, password } => {
+                // get a challenge without a body first, so that the body is only uploaded once
+                if body.is_some()
+                    && self
+                        .digest_authorization(username, password, method, body)?
+                        .is_none()
+                {
+                    if let Err(ureq::Error::Status(401, response)) =
+                        self.agent.request("HEAD", &self.url).call()
+                    {
+                        self.set_digest_challenge(&response)?;
+                    }
+                }
+
+                let authorization = self.digest_authorization(username, password, method, body)?;
+                match send(authorization.as_deref()) {
+                    // no challenge yet, or the server wants a new one, e.g. because the nonce expired
+                    Err(ureq::Error::Status(401, response)) => {
+                        self.set_digest_challenge(&response)?;
+                        let authorization = self.digest_authorization(username, password, method, body)?;
+                        send(authorization.as_deref())
+                    }
+                    result => result,
+                }
+            }
+        };
+
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(404, _)) => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} was not found", self.url),
+            )),
+            Err(ureq::Error::Status(412, _)) => Err(StorageConflict.into()),
+            Err(ureq::Error::Status(status, _)) => Err(io_error(format!(
+                "{} {} failed with status {}",
+                method, self.url, status
+            ))),
+            Err(ureq::Error::Transport(transport)) => Err(io_error(transport)),
+        }
+    }
+}
+
+impl Storage for WebDavStorage {
+    fn read(&self) -> std::io::Result<Vec<u8>> {
+        let response = self.request("GET", None)?;
+        self.set_etag(&response);
+
+        let mut data = Vec::new();
+        response.into_reader().read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn write_atomic(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let response = self.request("PUT", Some(data))?;
+
+        // the previous ETag is outdated now, so it is requested if the server did not send it
+        if !self.set_etag(&response) {
+            let response = self.request("HEAD", None)?;
+            if !self.set_etag(&response) {
+                if let Ok(mut etag) = self.etag.lock() {
+                    *etag = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> std::io::Result<StorageMetadata> {
+        let response = self.request("HEAD", None)?;
+
+        let modified = response
+            .header("Last-Modified")
+            .and_then(|m| chrono::DateTime::parse_from_rfc2822(m).ok())
+            .map(SystemTime::from);
+
+        Ok(StorageMetadata {
+            len: response
+                .header("Content-Length")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or_default(),
+            modified,
+            version: response.header("ETag").map(|e| e.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod webdav_tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    use super::{request_uri, WebDavAuth, WebDavStorage};
+    use crate::storage::{is_conflict, Storage};
+
+    /// Serve the given raw HTTP responses to consecutive requests, returning the requests
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dav/db.kdbx", listener.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn test_debug_redacts_password() {
+        for auth in [
+            WebDavAuth::Basic {
+                username: "user".to_string(),
+                password: "hunter2".to_string(),
+            },
+            WebDavAuth::Digest {
+                username: "user".to_string(),
+                password: "hunter2".to_string(),
+            },
+        ] {
+            let storage = WebDavStorage::new("https://example.com/db.kdbx", auth);
+            let debug = format!("{:?}", storage);
+            assert!(debug.contains("user"));
+            assert!(debug.contains(crate::redact::REDACTED));
+            assert!(!debug.contains("hunter2"));
+        }
+    }
+
+    #[test]
+    fn test_request_uri() {
+        assert_eq!(request_uri("https://example.com/dav/db.kdbx"), "/dav/db.kdbx");
+        assert_eq!(request_uri("https://example.com"), "/");
+    }
+
+    #[test]
+    fn test_etag_conflict() {
+        let (url, handle) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata",
+            "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+
+        let mut storage = WebDavStorage::new(
+            &url,
+            WebDavAuth::Basic {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            },
+        );
+
+        assert_eq!(storage.read().unwrap(), b"data");
+        assert_eq!(storage.etag().as_deref(), Some("\"v1\""));
+
+        let err = storage.write_atomic(b"new data").unwrap_err();
+        assert!(is_conflict(&err));
+
+        let requests = handle.join().unwrap();
+        assert!(requests[0].contains("Authorization: Basic dXNlcjpwYXNz"));
+        assert!(requests[1].contains("If-Match: \"v1\""));
+    }
+
+    #[test]
+    fn test_digest_auth_uploads_once() {
+        let (url, handle) = serve(vec![
+            "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Digest realm=\"dav\", nonce=\"abc\", qop=\"auth\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 201 Created\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+
+        let mut storage = WebDavStorage::new(
+            &url,
+            WebDavAuth::Digest {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            },
+        );
+        storage.write_atomic(b"new data").unwrap();
+
+        // the new ETag was not sent with the upload, so it is requested separately
+        assert_eq!(storage.etag().as_deref(), Some("\"v2\""));
+
+        let requests = handle.join().unwrap();
+        assert!(requests[0].starts_with("HEAD "));
+        assert!(requests[1].starts_with("PUT "));
+        assert!(requests[1].contains("Authorization: Digest "));
+        assert!(requests[2].starts_with("HEAD "));
+        assert!(requests[2].contains("nc=00000002"));
+    }
+
+    #[test]
+    fn test_missing_etag_keeps_previous() {
+        let (url, handle) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata",
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata",
+        ]);
+
+        let storage = WebDavStorage::new(&url, WebDavAuth::None);
+        storage.read().unwrap();
+        storage.read().unwrap();
+        assert_eq!(storage.etag().as_deref(), Some("\"v1\""));
+
+        handle.join().unwrap();
+    }
+}
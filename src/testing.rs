@@ -0,0 +1,357 @@
+//! Deterministic database fixtures for regression tests.
+//!
+//! The [`FixtureBuilder`] creates databases that exercise most features of the KeePass format
+//! (protected fields, attachments, custom icons, history, nested groups), using fixed UUIDs and
+//! timestamps so that the generated content is the same on every run. Together with
+//! [`all_configs`] and [`assert_roundtrip`], this can be used to check that a database survives
+//! being saved and opened again with every supported cipher and KDF.
+//!
+//! ```
+//! use keepass::{testing::{all_configs, assert_roundtrip, FixtureBuilder}, DatabaseKey};
+//!
+//! let key = DatabaseKey::new().with_password("fixture");
+//! for config in all_configs() {
+//!     let db = FixtureBuilder::new(config).with_entries(2).build();
+//!     assert_roundtrip(&db, key.clone());
+//! }
+//! ```
+//!
+//! Databases written by this crate can also be checked against KeePassXC with
+//! [`check_with_keepassxc`], if `keepassxc-cli` is available.
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::{
+    config::{
+        CompressionConfig, DatabaseConfig, DatabaseVersion, InnerCipherConfig, KdfConfig, OuterCipherConfig,
+    },
+    db::{AttachmentRef, Color, Database, Entry, Group, HeaderAttachment, History, Icon, Times, Value},
+    error::Error,
+    key::DatabaseKey,
+};
+
+/// All combinations of outer ciphers, inner ciphers, KDFs and compression settings that can be
+/// saved by this crate. The KDF parameters are kept small so that the fixtures are fast to
+/// save and open.
+pub fn all_configs() -> Vec<DatabaseConfig> {
+    let outer_ciphers = [
+        OuterCipherConfig::AES256,
+        OuterCipherConfig::Twofish,
+        OuterCipherConfig::ChaCha20,
+    ];
+
+    let inner_ciphers = [
+        InnerCipherConfig::Plain,
+        InnerCipherConfig::Salsa20,
+        InnerCipherConfig::ChaCha20,
+    ];
+
+    let kdfs = [
+        KdfConfig::Aes { rounds: 10 },
+        KdfConfig::Argon2 {
+            iterations: 1,
+            memory: 64 * 1024,
+            parallelism: 1,
+            version: argon2::Version::Version13,
+            secret: None,
+            associated_data: None,
+        },
+        KdfConfig::Argon2id {
+            iterations: 1,
+            memory: 64 * 1024,
+            parallelism: 1,
+            version: argon2::Version::Version13,
+            secret: None,
+            associated_data: None,
+        },
+    ];
+
+    let compressions = [CompressionConfig::None, CompressionConfig::GZip];
+
+    let mut out = Vec::new();
+    for outer_cipher_config in &outer_ciphers {
+        for inner_cipher_config in &inner_ciphers {
+            for kdf_config in &kdfs {
+                for compression_config in &compressions {
+                    out.push(DatabaseConfig {
+                        outer_cipher_config: outer_cipher_config.clone(),
+                        inner_cipher_config: inner_cipher_config.clone(),
+                        kdf_config: kdf_config.clone(),
+                        compression_config: compression_config.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Builder for deterministic fixture databases
+#[derive(Debug, Clone)]
+pub struct FixtureBuilder {
+    config: DatabaseConfig,
+    entries: usize,
+    groups: usize,
+    history: usize,
+    attachments: bool,
+    custom_icons: bool,
+}
+
+impl FixtureBuilder {
+    /// Create a builder for a fixture database with the given configuration. By default, the
+    /// database contains a single group with a single entry, an attachment and a custom icon.
+    pub fn new(config: DatabaseConfig) -> Self {
+        FixtureBuilder {
+            config,
+            entries: 1,
+            groups: 1,
+            history: 0,
+            attachments: true,
+            custom_icons: true,
+        }
+    }
+
+    /// Number of entries to create in each group
+    pub fn with_entries(mut self, entries: usize) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Number of groups to create below the root group
+    pub fn with_groups(mut self, groups: usize) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Number of history items to create for each entry
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Whether to attach a binary attachment to each entry
+    pub fn with_attachments(mut self, attachments: bool) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    /// Whether to create a custom icon and assign it to the groups
+    pub fn with_custom_icons(mut self, custom_icons: bool) -> Self {
+        self.custom_icons = custom_icons;
+        self
+    }
+
+    /// Build the fixture database
+    pub fn build(&self) -> Database {
+        let mut next_uuid = 0u128;
+        let mut uuid = move || {
+            next_uuid += 1;
+            Uuid::from_u128(next_uuid)
+        };
+
+        let mut db = Database::new(self.config.clone());
+        db.root.uuid = uuid();
+        db.root.times = fixture_times(0);
+        db.meta.database_name = Some("Fixture".to_string());
+        db.meta.default_username = Some("fixture".to_string());
+        db.meta.color = Some(Color {
+            r: 0x12,
+            g: 0x34,
+            b: 0x56,
+        });
+
+        let icon_uuid = if self.custom_icons {
+            let icon_uuid = uuid();
+            db.meta.custom_icons.icons.push(Icon {
+                uuid: icon_uuid,
+                data: b"\x89PNG fixture icon".to_vec(),
+            });
+            Some(icon_uuid)
+        } else {
+            None
+        };
+
+        for g in 0..self.groups {
+            let mut group = Group::new(&format!("Group {}", g));
+            group.uuid = uuid();
+            group.times = fixture_times(g as i64);
+            group.notes = Some(format!("Notes for group {}", g));
+            group.icon_id = Some(48);
+            group.custom_icon_uuid = icon_uuid;
+
+            for e in 0..self.entries {
+                let mut entry = fixture_entry(uuid(), &format!("Entry {}.{}", g, e), 0);
+
+                if self.attachments {
+                    let identifier = db.header_attachments.len();
+                    db.header_attachments.push(HeaderAttachment {
+                        flags: 1,
+                        content: format!("Attachment of entry {}.{}", g, e).into_bytes(),
+                    });
+                    entry.attachments.push(AttachmentRef {
+                        name: format!("attachment-{}-{}.txt", g, e),
+                        identifier: identifier.to_string(),
+                    });
+                }
+
+                if self.history > 0 {
+                    let mut history = History::default();
+                    for h in (1..=self.history).rev() {
+                        history.add_entry(fixture_entry(entry.uuid, &format!("Entry {}.{}", g, e), h));
+                    }
+                    entry.history = Some(history);
+                }
+
+                group.add_child(entry);
+            }
+
+            db.root.add_child(group);
+        }
+
+        db
+    }
+}
+
+fn fixture_time(offset: i64) -> NaiveDateTime {
+    chrono::DateTime::from_timestamp(1_600_000_000 + offset * 60, 0)
+        .unwrap()
+        .naive_utc()
+}
+
+fn fixture_times(offset: i64) -> Times {
+    let mut times = Times::default();
+    let time = fixture_time(offset);
+    times.set_creation(time);
+    times.set_last_modification(time);
+    times.set_last_access(time);
+    times.set_location_changed(time);
+    times.set_expiry(time);
+    times.expires = false;
+    times.usage_count = 0;
+    times
+}
+
+fn fixture_entry(uuid: Uuid, title: &str, revision: usize) -> Entry {
+    let mut entry = Entry::new();
+    entry.uuid = uuid;
+    entry.times = fixture_times(-(revision as i64));
+
+    let suffix = if revision > 0 {
+        format!(" (revision {})", revision)
+    } else {
+        String::new()
+    };
+
+    entry
+        .fields
+        .insert("Title".into(), Value::Unprotected(title.to_string()));
+    entry
+        .fields
+        .insert("UserName".into(), Value::Unprotected(format!("user{}", suffix)));
+    entry.fields.insert(
+        "Password".into(),
+        Value::Protected(format!("password{}", suffix).as_bytes().into()),
+    );
+    entry.fields.insert(
+        "URL".into(),
+        Value::Unprotected("https://example.com".to_string()),
+    );
+    entry.tags = vec!["fixture".into()];
+    entry
+}
+
+/// Save `db` with `key` and open it again
+pub fn roundtrip(db: &Database, key: DatabaseKey) -> Result<Database, Error> {
+    let mut buffer = Vec::new();
+    db.save(&mut buffer, key.clone())?;
+    Ok(Database::open(&mut buffer.as_slice(), key)?)
+}
+
+/// Assert that `db` is unchanged after being saved and opened again, and that the decrypted XML
+/// of the re-opened database is byte-for-byte identical to the XML of the original database.
+pub fn assert_roundtrip(db: &Database, key: DatabaseKey) {
+    let reopened = roundtrip(db, key).expect("Database should survive a round-trip");
+    assert_eq!(
+        &reopened, db,
+        "Round-trip of {:?} changed the database",
+        db.config
+    );
+
+    let mut original_xml = Vec::new();
+    db.export_xml(&mut original_xml, true)
+        .expect("Database should be exportable");
+
+    let mut reopened_xml = Vec::new();
+    reopened
+        .export_xml(&mut reopened_xml, true)
+        .expect("Database should be exportable");
+
+    assert!(
+        original_xml == reopened_xml,
+        "Round-trip of {:?} changed the XML of the database",
+        db.config
+    );
+
+    // KDBX3 databases keep their binaries in the metadata, while imports always use KDBX4
+    if let DatabaseVersion::KDB4(_) = db.config.version {
+        let imported = Database::import_xml(&mut original_xml.as_slice()).expect("XML should be importable");
+        assert_eq!(
+            imported.root, db.root,
+            "XML export of {:?} changed the groups",
+            db.config
+        );
+        assert_eq!(
+            imported.meta, db.meta,
+            "XML export of {:?} changed the metadata",
+            db.config
+        );
+        assert_eq!(
+            imported.header_attachments, db.header_attachments,
+            "XML export of {:?} changed the attachments",
+            db.config
+        );
+    }
+}
+
+/// Environment variable with the path of the `keepassxc-cli` executable that
+/// [`check_with_keepassxc`] runs
+pub const KEEPASSXC_CLI_ENV: &str = "KEEPASSXC_CLI";
+
+/// Check that KeePassXC accepts the database file at `path`, by listing all of its entries with
+/// `keepassxc-cli` unlocked with `password`.
+///
+/// Returns `None` if the [`KEEPASSXC_CLI_ENV`] environment variable is not set. Otherwise,
+/// returns the error output of `keepassxc-cli` if it failed.
+pub fn check_with_keepassxc(path: &Path, password: &str) -> Option<Result<(), String>> {
+    let cli = std::env::var_os(KEEPASSXC_CLI_ENV)?;
+
+    let run = || -> std::io::Result<std::process::Output> {
+        let mut child = Command::new(&cli)
+            .args(["ls", "--recursive", "--quiet"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "{}", password)?;
+        }
+        child.wait_with_output()
+    };
+
+    Some(match run() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+        Err(e) => Err(e.to_string()),
+    })
+}
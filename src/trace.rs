@@ -0,0 +1,16 @@
+//! Instrumentation of the phases of opening and saving a database.
+//!
+//! With the `tracing` feature, every phase runs inside a span of the `tracing` crate, so that
+//! subscribers can measure how long each phase takes. Without the feature, the instrumentation
+//! compiles to nothing.
+
+/// Evaluate an expression inside a span with the given name
+macro_rules! phase {
+    ($name:literal, $body:expr) => {{
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($name).entered();
+        $body
+    }};
+}
+
+pub(crate) use phase;
@@ -1,7 +1,10 @@
+#![deny(clippy::indexing_slicing)]
+
+//! The variant dictionary format of KDBX4 databases
+
 #[cfg(feature = "save_kdbx4")]
 use byteorder::WriteBytesExt;
 use byteorder::{ByteOrder, LittleEndian};
-use std::collections::HashMap;
 #[cfg(feature = "save_kdbx4")]
 use std::io::Write;
 
@@ -9,6 +12,8 @@ use crate::error::VariantDictionaryError;
 #[cfg(feature = "save_kdbx4")]
 use crate::io::WriteLengthTaggedExt;
 
+/// Version of the variant dictionary format written by this library. Dictionaries with the same
+/// major version (the high byte) can be read.
 pub const VARIANT_DICTIONARY_VERSION: u16 = 0x100;
 pub const VARIANT_DICTIONARY_END: u8 = 0x0;
 
@@ -20,115 +25,126 @@ pub const I64_TYPE_ID: u8 = 0x0d;
 pub const STR_TYPE_ID: u8 = 0x18;
 pub const BYTES_TYPE_ID: u8 = 0x42;
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct VariantDictionary {
-    pub data: HashMap<String, VariantDictionaryValue>,
+/// A typed key-value store as used by the KDBX4 format for the parameters of the key derivation
+/// function and the public custom data.
+///
+/// Items keep the order they were read or inserted in. Items of unknown types are kept as
+/// [`VariantDictionaryValue::Unknown`], so that they are written out again unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize, serde::Deserialize))]
+pub struct VariantDictionary {
+    items: Vec<(String, VariantDictionaryValue)>,
 }
 
 impl VariantDictionary {
-    #[cfg(feature = "save_kdbx4")]
-    pub(crate) fn new() -> Self {
-        Self { data: HashMap::new() }
+    pub fn new() -> Self {
+        Default::default()
     }
 
-    pub(crate) fn parse(buffer: &[u8]) -> Result<VariantDictionary, VariantDictionaryError> {
-        let version = LittleEndian::read_u16(&buffer[0..2]);
+    /// Parse a variant dictionary from its binary representation
+    pub fn parse(buffer: &[u8]) -> Result<VariantDictionary, VariantDictionaryError> {
+        let get_bytes = |offset: usize, len: usize| {
+            offset
+                .checked_add(len)
+                .and_then(|end| buffer.get(offset..end))
+                .ok_or(VariantDictionaryError::Truncated { offset })
+        };
 
-        if version != VARIANT_DICTIONARY_VERSION {
+        let version = LittleEndian::read_u16(get_bytes(0, 2)?);
+
+        // newer minor versions are compatible, newer major versions are not
+        if version & 0xff00 != VARIANT_DICTIONARY_VERSION {
             return Err(VariantDictionaryError::InvalidVersion { version });
         }
 
         let mut pos = 2;
-        let mut data = HashMap::new();
+        let mut dictionary = VariantDictionary::new();
 
-        while pos + 9 < buffer.len() {
-            let value_type = buffer[pos];
+        loop {
+            // there should always be a value_type = 0 entry to denote that a VariantDictionary is
+            // finished
+            let value_type = *buffer.get(pos).ok_or(VariantDictionaryError::NotTerminated)?;
             pos += 1;
 
-            let key_length = LittleEndian::read_u32(&buffer[pos..(pos + 4)]) as usize;
+            if value_type == VARIANT_DICTIONARY_END {
+                break;
+            }
+
+            let key_length = LittleEndian::read_u32(get_bytes(pos, 4)?) as usize;
             pos += 4;
 
-            let key = String::from_utf8_lossy(&buffer[pos..(pos + key_length)]).to_string();
+            let key = String::from_utf8_lossy(get_bytes(pos, key_length)?).to_string();
             pos += key_length;
 
-            let value_length = LittleEndian::read_u32(&buffer[pos..(pos + 4)]) as usize;
+            let value_length = LittleEndian::read_u32(get_bytes(pos, 4)?) as usize;
             pos += 4;
 
-            let value_buffer = &buffer[pos..(pos + value_length)];
+            let value_buffer = get_bytes(pos, value_length)?;
             pos += value_length;
 
+            // numeric values need to be at least as long as their type
+            let numeric_buffer = |len: usize| {
+                value_buffer
+                    .get(..len)
+                    .ok_or_else(|| VariantDictionaryError::Mistyped { key: key.clone() })
+            };
+
             let value = match value_type {
-                U32_TYPE_ID => VariantDictionaryValue::UInt32(LittleEndian::read_u32(value_buffer)),
-                U64_TYPE_ID => VariantDictionaryValue::UInt64(LittleEndian::read_u64(value_buffer)),
+                U32_TYPE_ID => VariantDictionaryValue::UInt32(LittleEndian::read_u32(numeric_buffer(4)?)),
+                U64_TYPE_ID => VariantDictionaryValue::UInt64(LittleEndian::read_u64(numeric_buffer(8)?)),
                 BOOL_TYPE_ID => VariantDictionaryValue::Bool(value_buffer != [0]),
-                I32_TYPE_ID => VariantDictionaryValue::Int32(LittleEndian::read_i32(value_buffer)),
-                I64_TYPE_ID => VariantDictionaryValue::Int64(LittleEndian::read_i64(value_buffer)),
+                I32_TYPE_ID => VariantDictionaryValue::Int32(LittleEndian::read_i32(numeric_buffer(4)?)),
+                I64_TYPE_ID => VariantDictionaryValue::Int64(LittleEndian::read_i64(numeric_buffer(8)?)),
                 STR_TYPE_ID => {
                     VariantDictionaryValue::String(String::from_utf8_lossy(value_buffer).to_string())
                 }
                 BYTES_TYPE_ID => VariantDictionaryValue::ByteArray(value_buffer.to_vec()),
-                _ => {
-                    return Err(VariantDictionaryError::InvalidValueType { value_type });
-                }
+                _ => VariantDictionaryValue::Unknown {
+                    value_type,
+                    value: value_buffer.to_vec(),
+                },
             };
 
-            data.insert(key, value);
+            dictionary.insert(key, value);
         }
 
-        if pos == buffer.len() || buffer[pos] != VARIANT_DICTIONARY_END {
-            // even though we can determine when to stop parsing a VariantDictionary by where we
-            // are in the buffer, there should always be a value_type = 0 entry to denote that a
-            // VariantDictionary is finished
-            return Err(VariantDictionaryError::NotTerminated);
-        }
-
-        Ok(VariantDictionary { data })
+        Ok(dictionary)
     }
 
+    /// Write the binary representation of the variant dictionary
     #[cfg(feature = "save_kdbx4")]
-    pub(crate) fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+    pub fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
         writer.write_u16::<LittleEndian>(VARIANT_DICTIONARY_VERSION)?;
 
-        for (field_name, field_value) in &self.data {
+        for (field_name, field_value) in &self.items {
+            writer.write_u8(field_value.type_id())?;
+            writer.write_with_len(field_name.as_bytes())?;
+
             match field_value {
                 VariantDictionaryValue::UInt32(value) => {
-                    writer.write_u8(U32_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
                     writer.write_u32::<LittleEndian>(4)?;
                     writer.write_u32::<LittleEndian>(*value)?;
                 }
                 VariantDictionaryValue::UInt64(value) => {
-                    writer.write_u8(U64_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
                     writer.write_u32::<LittleEndian>(8)?;
                     writer.write_u64::<LittleEndian>(*value)?;
                 }
                 VariantDictionaryValue::Bool(value) => {
-                    writer.write_u8(BOOL_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
                     writer.write_u32::<LittleEndian>(1)?;
                     writer.write_u8(if *value { 1 } else { 0 })?;
                 }
                 VariantDictionaryValue::Int32(value) => {
-                    writer.write_u8(I32_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
                     writer.write_u32::<LittleEndian>(4)?;
                     writer.write_i32::<LittleEndian>(*value)?;
                 }
                 VariantDictionaryValue::Int64(value) => {
-                    writer.write_u8(I64_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
                     writer.write_u32::<LittleEndian>(8)?;
                     writer.write_i64::<LittleEndian>(*value)?;
                 }
                 VariantDictionaryValue::String(value) => {
-                    writer.write_u8(STR_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
                     writer.write_with_len(value.as_bytes())?;
                 }
-                VariantDictionaryValue::ByteArray(value) => {
-                    writer.write_u8(BYTES_TYPE_ID)?;
-                    writer.write_with_len(field_name.as_bytes())?;
+                VariantDictionaryValue::ByteArray(value) | VariantDictionaryValue::Unknown { value, .. } => {
                     writer.write_with_len(value)?;
                 }
             };
@@ -139,30 +155,63 @@ impl VariantDictionary {
         Ok(())
     }
 
-    pub(crate) fn get<'a, T: 'a>(&'a self, key: &str) -> Result<&'a T, VariantDictionaryError>
+    /// Get the value of an item with the expected type
+    pub fn get<'a, T: 'a>(&'a self, key: &str) -> Result<&'a T, VariantDictionaryError>
     where
         &'a VariantDictionaryValue: Into<Option<&'a T>>,
     {
         let vdv = self
-            .data
-            .get(key)
+            .get_value(key)
             .ok_or_else(|| VariantDictionaryError::MissingKey { key: key.to_owned() })?;
 
         vdv.into()
             .ok_or_else(|| VariantDictionaryError::Mistyped { key: key.to_owned() })
     }
 
-    #[cfg(feature = "save_kdbx4")]
-    pub(crate) fn set<T>(&mut self, key: &str, value: T)
+    /// Get the value of an item, whatever its type
+    pub fn get_value(&self, key: &str) -> Option<&VariantDictionaryValue> {
+        self.items.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Set the value of an item, keeping its position if it already exists
+    pub fn set<T>(&mut self, key: &str, value: T)
     where
         T: Into<VariantDictionaryValue>,
     {
-        self.data.insert(key.to_string(), value.into());
+        self.insert(key.to_string(), value.into());
+    }
+
+    fn insert(&mut self, key: String, value: VariantDictionaryValue) {
+        match self.items.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.items.push((key, value)),
+        }
+    }
+
+    /// Remove an item, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<VariantDictionaryValue> {
+        let position = self.items.iter().position(|(k, _)| k == key)?;
+        Some(self.items.remove(position).1)
+    }
+
+    /// Iterate over the items in the order they are stored in
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &VariantDictionaryValue)> {
+        self.items.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) enum VariantDictionaryValue {
+/// A value of a [`VariantDictionary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize, serde::Deserialize))]
+pub enum VariantDictionaryValue {
     UInt32(u32),
     UInt64(u64),
     Bool(bool),
@@ -170,6 +219,28 @@ pub(crate) enum VariantDictionaryValue {
     Int64(i64),
     String(String),
     ByteArray(Vec<u8>),
+
+    /// A value of a type that is not known to this library, kept as raw bytes
+    Unknown {
+        value_type: u8,
+        value: Vec<u8>,
+    },
+}
+
+impl VariantDictionaryValue {
+    /// The identifier of the type of the value in the binary representation
+    pub fn type_id(&self) -> u8 {
+        match self {
+            VariantDictionaryValue::UInt32(_) => U32_TYPE_ID,
+            VariantDictionaryValue::UInt64(_) => U64_TYPE_ID,
+            VariantDictionaryValue::Bool(_) => BOOL_TYPE_ID,
+            VariantDictionaryValue::Int32(_) => I32_TYPE_ID,
+            VariantDictionaryValue::Int64(_) => I64_TYPE_ID,
+            VariantDictionaryValue::String(_) => STR_TYPE_ID,
+            VariantDictionaryValue::ByteArray(_) => BYTES_TYPE_ID,
+            VariantDictionaryValue::Unknown { value_type, .. } => *value_type,
+        }
+    }
 }
 
 impl From<u32> for VariantDictionaryValue {
@@ -301,11 +372,22 @@ mod variant_dictionary_tests {
 
         //                                        ver t key_len key val_len termination
         //                                        |   | |       |   |       |
-        let res = VariantDictionary::parse(&hex!("0001AA0200000041420000000000"));
-        dbg!(&res);
+        // items of unknown types are kept
+        let res = VariantDictionary::parse(&hex!("0001AA02000000414202000000010200"))?;
+        assert_eq!(
+            res.get_value("AB"),
+            Some(&VariantDictionaryValue::Unknown {
+                value_type: 0xAA,
+                value: vec![1, 2]
+            })
+        );
+
+        // newer minor versions are compatible, newer major versions are not
+        assert!(VariantDictionary::parse(&hex!("050100")).is_ok());
+        let res = VariantDictionary::parse(&hex!("000200"));
         assert!(matches!(
             res,
-            Err(VariantDictionaryError::InvalidValueType { value_type: 0xAA })
+            Err(VariantDictionaryError::InvalidVersion { version: 0x200 })
         ));
 
         Ok(())
@@ -323,6 +405,13 @@ mod variant_dictionary_tests {
         vd.set("a-bool", true);
         vd.set("a-string", "Testing".to_string());
         vd.set("a-bytes", "testing".as_bytes().to_vec());
+        vd.set(
+            "an-unknown",
+            VariantDictionaryValue::Unknown {
+                value_type: 0x99,
+                value: vec![1, 2, 3],
+            },
+        );
 
         assert!(vd.get::<bool>("key-not-exist").is_err());
 
@@ -347,5 +436,13 @@ mod variant_dictionary_tests {
 
         let vd_parsed = VariantDictionary::parse(&vd_data).unwrap();
         assert_eq!(vd_parsed, vd);
+
+        // items keep their order
+        let keys: Vec<&str> = vd_parsed.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys.first(), Some(&"a-u32"));
+        assert_eq!(keys.last(), Some(&"an-unknown"));
+
+        assert_eq!(vd.remove("a-u32"), Some(VariantDictionaryValue::UInt32(42)));
+        assert_eq!(vd.len(), 7);
     }
 }
@@ -13,17 +13,52 @@ use xml::{
 
 use crate::{
     crypt::ciphers::Cipher,
-    db::{Color, CustomData, CustomDataItem, Database, DeletedObject, DeletedObjects, Times},
-    xml_db::get_epoch_baseline,
+    db::{
+        BinaryAttachment, Color, CustomData, CustomDataItem, Database, DeletedObject, DeletedObjects, Times,
+        Timestamp, TimestampFormat, XmlElement, XmlNode, HEADER_ATTACHMENT_PROTECTED,
+    },
 };
 
-/// Format a timestamp suitable for an XML database
-pub fn format_xml_timestamp(timestamp: &chrono::NaiveDateTime) -> String {
-    let timestamp = timestamp.and_utc().timestamp() - get_epoch_baseline().and_utc().timestamp();
-    let timestamp_bytes = i64::to_le_bytes(timestamp);
-    base64_engine::STANDARD.encode(timestamp_bytes)
+/// Wrap an error that occurred while preparing a value for the XML document, so that it can be
+/// reported by the writer instead of panicking
+pub(crate) fn invalid_data<E: std::fmt::Display>(error: E) -> xml::writer::Error {
+    xml::writer::Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        error.to_string(),
+    ))
 }
 
+/// Format a timestamp suitable for an XML database in the given format, see [`Timestamp::format`]
+pub fn format_xml_timestamp(timestamp: &chrono::NaiveDateTime, format: TimestampFormat) -> String {
+    Timestamp::new(*timestamp).format(format)
+}
+
+/// State that is needed while writing the objects of a database into an XML document
+pub(crate) struct DumpContext<'a> {
+    /// The cipher that protected values are encrypted with
+    pub inner_cipher: &'a mut dyn Cipher,
+
+    /// How timestamps are written, which depends on the version of the database
+    pub timestamp_format: TimestampFormat,
+
+    /// Whether a standalone XML document is written, like the XML export of KeePass. Protected
+    /// values are then passed through the inner cipher, but written as text and marked with
+    /// `ProtectInMemory` instead of `Protected`, and the binaries of the inner header are written
+    /// to the metadata.
+    pub plain_xml: bool,
+}
+
+impl<'a> DumpContext<'a> {
+    pub fn new(inner_cipher: &'a mut dyn Cipher, timestamp_format: TimestampFormat) -> Self {
+        DumpContext {
+            inner_cipher,
+            timestamp_format,
+            plain_xml: false,
+        }
+    }
+}
+
+/// Write the XML document of a database, using the timestamp format of its version
 pub(crate) fn dump(
     db: &Database,
     inner_cipher: &mut dyn Cipher,
@@ -31,7 +66,23 @@ pub(crate) fn dump(
 ) -> Result<(), xml::writer::Error> {
     let mut xml_writer = EmitterConfig::new().perform_indent(false).create_writer(writer);
 
-    db.dump_xml(&mut xml_writer, inner_cipher)?;
+    let mut context = DumpContext::new(inner_cipher, TimestampFormat::for_version(&db.config.version));
+    db.dump_xml(&mut xml_writer, &mut context)?;
+
+    Ok(())
+}
+
+/// Write a database as a standalone XML document, see [`DumpContext::plain_xml`]
+pub(crate) fn dump_plain_xml(
+    db: &Database,
+    inner_cipher: &mut dyn Cipher,
+    writer: &mut dyn Write,
+) -> Result<(), xml::writer::Error> {
+    let mut xml_writer = EmitterConfig::new().perform_indent(false).create_writer(writer);
+
+    let mut context = DumpContext::new(inner_cipher, TimestampFormat::for_version(&db.config.version));
+    context.plain_xml = true;
+    db.dump_xml(&mut xml_writer, &mut context)?;
 
     Ok(())
 }
@@ -44,7 +95,7 @@ pub(crate) trait DumpXml {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error>;
 
     fn normalize_empty_elements(&self) -> bool {
@@ -56,9 +107,12 @@ impl DumpXml for &chrono::NaiveDateTime {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
-        writer.write(WriterEvent::characters(&format_xml_timestamp(self)))
+        writer.write(WriterEvent::characters(&format_xml_timestamp(
+            self,
+            context.timestamp_format,
+        )))
     }
 }
 
@@ -66,7 +120,7 @@ impl DumpXml for bool {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(if *self { "True" } else { "False" }))
     }
@@ -76,7 +130,7 @@ impl DumpXml for usize {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(&format!("{}", self)))
     }
@@ -86,7 +140,7 @@ impl DumpXml for isize {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(&format!("{}", self)))
     }
@@ -96,7 +150,7 @@ impl DumpXml for &str {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(self))
     }
@@ -110,7 +164,7 @@ impl DumpXml for &String {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(self))
     }
@@ -124,7 +178,7 @@ impl DumpXml for &Uuid {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         let b64 = base64_engine::STANDARD.encode(self.as_bytes());
         writer.write(WriterEvent::Characters(&b64))
@@ -135,7 +189,7 @@ impl DumpXml for &Color {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::Characters(&self.to_string()))
     }
@@ -148,11 +202,11 @@ impl<S: AsRef<str>, D: DumpXml> DumpXml for SimpleTag<S, D> {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element(self.0.as_ref()))?;
         if !self.1.normalize_empty_elements() {
-            self.1.dump_xml(writer, inner_cipher)?;
+            self.1.dump_xml(writer, context)?;
         }
         writer.write(WriterEvent::end_element())?;
         Ok(())
@@ -163,17 +217,37 @@ impl DumpXml for Database {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("KeePassFile"))?;
 
-        self.meta.dump_xml(writer, inner_cipher)?;
+        if context.plain_xml && !self.header_attachments.is_empty() {
+            // a standalone document has no inner header, so its binaries go into the metadata,
+            // with the indices that attachments refer to them by as identifiers
+            let mut meta = self.meta.clone();
+            meta.binaries
+                .binaries
+                .extend(
+                    self.header_attachments
+                        .iter()
+                        .enumerate()
+                        .map(|(index, attachment)| BinaryAttachment {
+                            identifier: Some(index.to_string()),
+                            compressed: false,
+                            protected: attachment.flags & HEADER_ATTACHMENT_PROTECTED != 0,
+                            content: attachment.content.clone(),
+                        }),
+                );
+            meta.dump_xml(writer, context)?;
+        } else {
+            self.meta.dump_xml(writer, context)?;
+        }
 
         writer.write(WriterEvent::start_element("Root"))?;
 
-        self.root.dump_xml(writer, inner_cipher)?;
+        self.root.dump_xml(writer, context)?;
 
-        self.deleted_objects.dump_xml(writer, inner_cipher)?;
+        self.deleted_objects.dump_xml(writer, context)?;
 
         writer.write(WriterEvent::end_element())?; // Root
 
@@ -187,15 +261,18 @@ impl DumpXml for Times {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("Times"))?;
-        for (time_name, time) in &self.times {
-            SimpleTag(time_name, time).dump_xml(writer, inner_cipher)?;
+        let mut times: Vec<_> = self.times.iter().collect();
+        times.sort_by_key(|(k, _)| *k);
+
+        for (time_name, time) in times {
+            SimpleTag(time_name, time).dump_xml(writer, context)?;
         }
 
-        SimpleTag("Expires", self.expires).dump_xml(writer, inner_cipher)?;
-        SimpleTag("UsageCount", self.usage_count).dump_xml(writer, inner_cipher)?;
+        SimpleTag("Expires", self.expires).dump_xml(writer, context)?;
+        SimpleTag("UsageCount", self.usage_count).dump_xml(writer, context)?;
 
         writer.write(WriterEvent::end_element())?;
 
@@ -207,15 +284,18 @@ impl DumpXml for CustomData {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("CustomData"))?;
 
-        for (key, item) in &self.items {
+        let mut items: Vec<_> = self.items.iter().collect();
+        items.sort_by_key(|(k, _)| *k);
+
+        for (key, item) in items {
             writer.write(WriterEvent::start_element("Item"))?;
 
-            SimpleTag("Key", key).dump_xml(writer, inner_cipher)?;
-            item.dump_xml(writer, inner_cipher)?;
+            SimpleTag("Key", key).dump_xml(writer, context)?;
+            item.dump_xml(writer, context)?;
 
             writer.write(WriterEvent::end_element())?;
         }
@@ -230,30 +310,63 @@ impl DumpXml for CustomDataItem {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         if let Some(ref value) = self.value {
-            value.dump_xml(writer, inner_cipher)?;
+            value.dump_xml(writer, context)?;
         }
 
         if let Some(ref value) = self.last_modification_time {
-            SimpleTag("LastModificationTime", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("LastModificationTime", value).dump_xml(writer, context)?;
         }
 
         Ok(())
     }
 }
 
+impl DumpXml for XmlElement {
+    fn dump_xml<E: std::io::Write>(
+        &self,
+        writer: &mut EventWriter<E>,
+        context: &mut DumpContext,
+    ) -> Result<(), xml::writer::Error> {
+        let mut start = WriterEvent::start_element(self.name.as_str());
+        for (name, value) in &self.attributes {
+            start = start.attr(name.as_str(), value);
+        }
+        writer.write(start)?;
+
+        for child in &self.children {
+            match child {
+                XmlNode::Element(element) => element.dump_xml(writer, context)?,
+                XmlNode::Text(text) if self.is_protected() => {
+                    let encrypted = context
+                        .inner_cipher
+                        .encrypt(text.as_bytes())
+                        .map_err(invalid_data)?;
+                    writer.write(WriterEvent::characters(
+                        &base64_engine::STANDARD.encode(encrypted),
+                    ))?;
+                }
+                XmlNode::Text(text) => writer.write(WriterEvent::characters(text))?,
+            }
+        }
+
+        writer.write(WriterEvent::end_element())?;
+        Ok(())
+    }
+}
+
 impl DumpXml for DeletedObjects {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("DeletedObjects"))?;
 
         for object in &self.objects {
-            object.dump_xml(writer, inner_cipher)?;
+            object.dump_xml(writer, context)?;
         }
 
         writer.write(WriterEvent::end_element())?;
@@ -265,12 +378,12 @@ impl DumpXml for DeletedObject {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        context: &mut DumpContext,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("DeletedObject"))?;
 
-        SimpleTag("UUID", &self.uuid).dump_xml(writer, inner_cipher)?;
-        SimpleTag("DeletionTime", &self.deletion_time).dump_xml(writer, inner_cipher)?;
+        SimpleTag("UUID", &self.uuid).dump_xml(writer, context)?;
+        SimpleTag("DeletionTime", &self.deletion_time).dump_xml(writer, context)?;
 
         writer.write(WriterEvent::end_element())?;
         Ok(())
@@ -1,6 +1,8 @@
 #[cfg(feature = "save_kdbx4")]
 pub mod dump;
 pub mod parse;
+#[cfg(feature = "validate")]
+pub(crate) mod validate;
 
 /// In KDBX4, timestamps are stored as seconds, Base64 encoded, since 0001-01-01 00:00:00.
 /// This function returns the epoch baseline used by KDBX for date serialization.
@@ -18,15 +20,16 @@ mod tests {
 
     use crate::{
         config::{DatabaseConfig, InnerCipherConfig},
+        db::TimestampFormat,
         db::{
             entry::History,
             meta::{BinaryAttachments, CustomIcons, Icon, MemoryProtection},
-            AutoType, AutoTypeAssociation, BinaryAttachment, CustomData, CustomDataItem, Database,
-            DeletedObject, Entry, Group, Meta, Node, Times, Value,
+            AttachmentRef, AutoType, AutoTypeAssociation, BinaryAttachment, CustomData, CustomDataItem,
+            Database, DeletedObject, Entry, Group, Meta, Node, Times, Value, XmlElement, XmlNode,
         },
         format::kdbx4,
         key::DatabaseKey,
-        xml_db::dump::DumpXml,
+        xml_db::dump::{DumpContext, DumpXml},
     };
 
     fn make_key() -> DatabaseKey {
@@ -48,16 +51,16 @@ mod tests {
 
         entry
             .fields
-            .insert("Title".to_string(), Value::Unprotected("ASDF".to_string()));
+            .insert("Title".into(), Value::Unprotected("ASDF".to_string()));
         entry
             .fields
-            .insert("UserName".to_string(), Value::Unprotected("ghj".to_string()));
+            .insert("UserName".into(), Value::Unprotected("ghj".to_string()));
         entry.fields.insert(
-            "Password".to_string(),
+            "Password".into(),
             Value::Protected(std::str::from_utf8(b"klmno").unwrap().into()),
         );
-        entry.tags.push("test".to_string());
-        entry.tags.push("keepass-rs".to_string());
+        entry.tags.push("test".into());
+        entry.tags.push("keepass-rs".into());
         entry.times.expires = true;
         entry.times.usage_count = 42;
         entry.times.set_creation(NaiveDateTime::default());
@@ -88,6 +91,13 @@ mod tests {
                 last_modification_time: Some(NaiveDateTime::default()),
             },
         );
+        entry.custom_data.items.insert(
+            "CDI-protected-key".to_string(),
+            CustomDataItem {
+                value: Some(Value::Protected("CDI-Secret".into())),
+                last_modification_time: None,
+            },
+        );
 
         entry.icon_id = Some(123);
         entry.custom_icon_uuid = Some(uuid!("22222222222222222222222222222222"));
@@ -98,6 +108,11 @@ mod tests {
         entry.override_url = Some("https://docs.rs/keepass-rs/".to_string());
         entry.quality_check = Some(true);
 
+        entry.attachments.push(AttachmentRef {
+            name: "attachment.txt".to_string(),
+            identifier: "0".to_string(),
+        });
+
         let mut history = History::default();
         history.entries.push(entry.clone());
 
@@ -112,7 +127,7 @@ mod tests {
 
         let mut encrypted_db = Vec::new();
         kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 1);
 
@@ -124,12 +139,165 @@ mod tests {
         assert_eq!(decrypted_entry, &entry);
     }
 
+    #[test]
+    pub fn test_invalid_custom_data_bytes() {
+        let mut custom_data = CustomData::default();
+        custom_data.items.insert(
+            "binary".to_string(),
+            CustomDataItem {
+                value: Some(Value::Bytes(vec![0xff, 0xfe, 0x00])),
+                last_modification_time: None,
+            },
+        );
+
+        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+        let mut writer = xml::EventWriter::new(Vec::new());
+        assert!(custom_data
+            .dump_xml(
+                &mut writer,
+                &mut DumpContext::new(&mut *inner_cipher, TimestampFormat::Base64),
+            )
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_unknown_elements() {
+        let protected_element = XmlElement {
+            name: "PluginSecret".to_string(),
+            attributes: vec![("Protected".to_string(), "True".to_string())],
+            children: vec![XmlNode::Text("plugin secret".to_string())],
+        };
+
+        let mut db = Database::new(DatabaseConfig::default());
+        db.meta.unknown_elements.push(protected_element.clone());
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Password".into(), Value::Protected(SecStr::from("secret")));
+        entry.unknown_elements.push(protected_element);
+        entry.unknown_elements.push(XmlElement::new("FutureFlag"));
+        db.root.add_child(entry.clone());
+        db.root.unknown_elements.push(XmlElement {
+            name: "FutureGroupSetting".to_string(),
+            attributes: Vec::new(),
+            children: vec![XmlNode::Text("value".to_string())],
+        });
+
+        let db_key = make_key();
+
+        let mut encrypted_db = Vec::new();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
+
+        // protected values after the unknown elements are still decrypted correctly
+        assert_eq!(decrypted_db, db);
+        assert_eq!(decrypted_db.root.entries()[0].get_password(), Some("secret"));
+        assert_eq!(decrypted_db.meta.unknown_elements[0].text(), "plugin secret");
+    }
+
+    #[test]
+    pub fn test_unknown_group_element_position() {
+        let group = crate::xml_db::parse::parse_test::parse_test_xml::<Group>(
+            "<Group><Name>Outer</Name><Before/><Group><Name>A</Name></Group><Between/>\
+             <Group><Name>B</Name></Group><After/></Group>",
+        )
+        .unwrap();
+        assert_eq!(group.unknown_elements.len(), 3);
+
+        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+        let mut writer = xml::EventWriter::new(Vec::new());
+        group
+            .dump_xml(
+                &mut writer,
+                &mut DumpContext::new(&mut *inner_cipher, TimestampFormat::Base64),
+            )
+            .unwrap();
+        let xml = String::from_utf8(writer.into_inner()).unwrap();
+
+        let find = |needle: &str| xml.find(needle).unwrap();
+        assert!(find("<Before") < find("<Name>A</Name>"));
+        assert!(find("<Name>A</Name>") < find("<Between"));
+        assert!(find("<Between") < find("<Name>B</Name>"));
+        assert!(find("<Name>B</Name>") < find("<After"));
+    }
+
+    #[test]
+    pub fn test_protected_custom_data() {
+        let item = |value: Value| CustomDataItem {
+            value: Some(value),
+            last_modification_time: None,
+        };
+        let protected = |secret: &str| item(Value::Protected(SecStr::from(secret)));
+
+        let mut db = Database::new(DatabaseConfig::default());
+        db.meta
+            .custom_data
+            .items
+            .insert("meta-secret".to_string(), protected("meta"));
+
+        let mut group = Group::new("Group");
+        group
+            .custom_data
+            .items
+            .insert("group-secret".to_string(), protected("group"));
+        group.custom_data.items.insert(
+            "group-plain".to_string(),
+            item(Value::Unprotected("plain".to_string())),
+        );
+
+        let mut entry = Entry::new();
+        entry
+            .custom_data
+            .items
+            .insert("entry-secret".to_string(), protected("entry"));
+        entry
+            .fields
+            .insert("Password".into(), Value::Protected(SecStr::from("password")));
+        let mut old = entry.clone();
+        old.custom_data
+            .items
+            .insert("entry-secret".to_string(), protected("old entry"));
+        entry.history = Some(History { entries: vec![old] });
+
+        group.add_child(entry);
+        db.root.add_child(group);
+
+        // the values have to be decrypted in the order in which they were encrypted, with every
+        // inner cipher
+        for inner_cipher_config in [
+            InnerCipherConfig::Plain,
+            InnerCipherConfig::Salsa20,
+            InnerCipherConfig::ChaCha20,
+        ] {
+            db.config.inner_cipher_config = inner_cipher_config;
+
+            let db_key = make_key();
+            let mut encrypted_db = Vec::new();
+            kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+            let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
+
+            assert_eq!(decrypted_db, db);
+            let entry = &decrypted_db.root.groups()[0].entries()[0];
+            assert_eq!(
+                entry.custom_data.items["entry-secret"].value,
+                Some(Value::Protected(SecStr::from("entry")))
+            );
+            assert_eq!(entry.get_password(), Some("password"));
+        }
+    }
+
     #[test]
     pub fn test_group() {
         let group = Group::new("");
         let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
         let mut writer = xml::EventWriter::new(Vec::new());
-        let _v = group.dump_xml(&mut writer, &mut *inner_cipher).unwrap();
+        let _v = group
+            .dump_xml(
+                &mut writer,
+                &mut DumpContext::new(&mut *inner_cipher, TimestampFormat::Base64),
+            )
+            .unwrap();
         let xml = writer.into_inner();
         assert!(String::from_utf8(xml).unwrap().contains("<Name />"));
 
@@ -138,7 +306,7 @@ mod tests {
         let new_entry_uuid = entry.uuid.clone();
         entry
             .fields
-            .insert("Title".to_string(), Value::Unprotected("ASDF".to_string()));
+            .insert("Title".into(), Value::Unprotected("ASDF".to_string()));
 
         root_group.add_child(entry);
 
@@ -178,7 +346,7 @@ mod tests {
 
         let mut encrypted_db = Vec::new();
         kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 2);
 
@@ -238,18 +406,27 @@ mod tests {
                     BinaryAttachment {
                         identifier: Some("1".to_string()),
                         compressed: false,
+                        protected: false,
                         content: b"i am binary data".to_vec(),
                     },
                     BinaryAttachment {
                         identifier: Some("2".to_string()),
                         compressed: true,
+                        protected: false,
                         content: b"i am compressed binary data".to_vec(),
                     },
                     BinaryAttachment {
                         identifier: None,
                         compressed: true,
+                        protected: false,
                         content: b"i am compressed binary data without an identifier".to_vec(),
                     },
+                    BinaryAttachment {
+                        identifier: Some("3".to_string()),
+                        compressed: true,
+                        protected: true,
+                        content: b"i am compressed and protected binary data".to_vec(),
+                    },
                 ],
             },
             custom_data: CustomData {
@@ -277,6 +454,15 @@ mod tests {
                     ),
                 ]),
             },
+            unknown_elements: vec![XmlElement {
+                name: "PluginSettings".to_string(),
+                attributes: vec![("Version".to_string(), "2".to_string())],
+                children: vec![XmlNode::Element(XmlElement {
+                    name: "Setting".to_string(),
+                    attributes: Vec::new(),
+                    children: vec![XmlNode::Text("some value".to_string())],
+                })],
+            }],
         };
 
         db.meta = meta.clone();
@@ -285,7 +471,7 @@ mod tests {
 
         let mut encrypted_db = Vec::new();
         kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db.meta, meta);
     }
@@ -308,8 +494,34 @@ mod tests {
 
         let mut encrypted_db = Vec::new();
         kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &Default::default()).unwrap();
 
         assert_eq!(decrypted_db, db);
     }
+
+    #[test]
+    fn test_timestamp_format() {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.deleted_objects.objects = vec![DeletedObject {
+            uuid: uuid!("123e4567-e89b-12d3-a456-426655440000"),
+            deletion_time: "2000-12-31T12:34:56".parse().unwrap(),
+        }];
+
+        let mut xml = Vec::new();
+        db.export_xml(&mut xml, true).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        let encoded = crate::db::Timestamp::new(db.deleted_objects.objects[0].deletion_time).to_kdbx4_b64();
+        assert!(xml.contains(&format!("<DeletionTime>{}</DeletionTime>", encoded)));
+
+        // databases from before KDBX4 use ISO 8601 timestamps
+        db.config.version = crate::config::DatabaseVersion::KDB3(1);
+        let mut xml = Vec::new();
+        db.export_xml(&mut xml, true).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("<DeletionTime>2000-12-31T12:34:56Z</DeletionTime>"));
+        assert_eq!(
+            Database::import_xml(&mut xml.as_bytes()).unwrap().deleted_objects,
+            db.deleted_objects
+        );
+    }
 }
@@ -6,7 +6,10 @@ use uuid::Uuid;
 
 use crate::{
     crypt::ciphers::Cipher,
-    db::{AutoType, AutoTypeAssociation, Color, Entry, History, Times, Value},
+    db::{
+        AttachmentRef, AutoType, AutoTypeAssociation, Color, Entry, History, InternedStr, Times, Value,
+        XmlElement,
+    },
     xml_db::parse::{bad_event, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
 };
 
@@ -35,23 +38,25 @@ impl FromXml for Entry {
                         {
                             out.tags = tags
                                 .split(|c| c == ';' || c == ',')
-                                .map(|x| x.to_owned())
+                                .map(InternedStr::from)
                                 .collect();
                         }
                     }
                     "String" => {
                         let field = StringField::from_xml(iterator, inner_cipher)?;
                         if let Some(value) = field.value {
-                            out.fields.insert(field.key, value);
+                            out.fields.insert(field.key.into(), value);
                         }
                     }
                     "CustomData" => {
                         out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
                     }
                     "Binary" => {
-                        let _field = BinaryField::from_xml(iterator, inner_cipher)?;
-                        // TODO reference into a binary field from the Meta. Might only appear in
-                        // kdbx3
+                        let field = BinaryField::from_xml(iterator, inner_cipher)?;
+                        out.attachments.push(AttachmentRef {
+                            name: field.key,
+                            identifier: field.identifier,
+                        });
                     }
                     "AutoType" => {
                         out.autotype = Some(AutoType::from_xml(iterator, inner_cipher)?);
@@ -83,7 +88,9 @@ impl FromXml for Entry {
                     "History" => {
                         out.history = Some(History::from_xml(iterator, inner_cipher)?);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => out
+                        .unknown_elements
+                        .push(XmlElement::from_xml(iterator, inner_cipher)?),
                 },
                 SimpleXmlEvent::End(name) if name == "Entry" => break,
                 _ => return Err(bad_event("start tag or close entry", event.clone())),
@@ -144,7 +151,6 @@ impl FromXml for StringField {
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub(crate) struct BinaryField {
     pub key: String,
     pub identifier: String,
@@ -201,13 +207,27 @@ impl FromXml for Value {
                     .map(|v| v.to_lowercase().parse::<bool>())
                     .unwrap_or(Ok(false))?;
 
+                // plain XML exports of KeePass and Keepass2Android mark values that should be
+                // protected but store them in plaintext
+                let protect_in_memory: bool = attributes
+                    .get("ProtectInMemory")
+                    .map(|v| v.to_lowercase().parse::<bool>())
+                    .unwrap_or(Ok(false))?;
+
                 let content = Option::<String>::from_xml(iterator, inner_cipher)?.unwrap_or(String::new());
 
                 let value = if protected {
                     let buf = base64_engine::STANDARD.decode(&content)?;
-                    let buf_decrypted = inner_cipher.decrypt(&buf)?;
-                    let value = String::from_utf8_lossy(&buf_decrypted).to_string();
-                    Value::Protected(SecStr::from(value))
+                    match inner_cipher.defer(&buf) {
+                        Some(encrypted) => Value::Encrypted(encrypted),
+                        None => {
+                            let buf_decrypted = inner_cipher.decrypt(&buf)?;
+                            let value = String::from_utf8_lossy(&buf_decrypted).to_string();
+                            Value::Protected(SecStr::from(value))
+                        }
+                    }
+                } else if protect_in_memory {
+                    Value::Protected(SecStr::from(content))
                 } else {
                     Value::Unprotected(content)
                 };
@@ -1,8 +1,8 @@
 use uuid::Uuid;
 
 use crate::{
-    db::{CustomData, Entry, Group, Times},
-    xml_db::parse::{bad_event, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
+    db::{CustomData, Entry, Group, Times, XmlElement},
+    xml_db::parse::{bad_event, FromXml, SimpleTag, SimpleXmlEvent, XmlParseError},
 };
 
 impl FromXml for Group {
@@ -73,7 +73,14 @@ impl FromXml for Group {
                     "CustomData" => {
                         out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => {
+                        let element = XmlElement::from_xml(iterator, inner_cipher)?;
+                        if !out.children.is_empty() {
+                            out.unknown_element_positions
+                                .push((out.unknown_elements.len(), out.children.len()));
+                        }
+                        out.unknown_elements.push(element);
+                    }
                 },
                 SimpleXmlEvent::End(name) if name == "Group" => break,
                 _ => return Err(bad_event("start tag or close Group", event.clone())),
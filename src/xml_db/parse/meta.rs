@@ -6,7 +6,7 @@ use crate::{
     compression::{Compression, GZipCompression},
     db::{
         meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-        Color,
+        Color, XmlElement,
     },
     xml_db::parse::{bad_event, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
 };
@@ -128,7 +128,14 @@ impl FromXml for Meta {
                     "CustomData" => {
                         out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    "HeaderHash" => {
+                        // KDBX 3.1 hash of the outer header. It is stale as soon as the database
+                        // is saved again, so it must not be written back.
+                        IgnoreSubfield::from_xml(iterator, inner_cipher)?;
+                    }
+                    _ => out
+                        .unknown_elements
+                        .push(XmlElement::from_xml(iterator, inner_cipher)?),
                 },
                 SimpleXmlEvent::End(name) if name == "Meta" => break,
                 _ => return Err(bad_event("start tag or close Meta", event.clone())),
@@ -233,28 +240,51 @@ impl FromXml for BinaryAttachment {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
 
         let mut out = Self::default();
-        let (identifier, compressed) = if let SimpleXmlEvent::Start(ref name, ref attributes) = open_tag {
-            if name != "Binary" {
+        let (identifier, compressed, protected, protect_in_memory) =
+            if let SimpleXmlEvent::Start(ref name, ref attributes) = open_tag {
+                if name != "Binary" {
+                    return Err(bad_event("Open Binary tag", open_tag));
+                }
+
+                let identifier = attributes.get("ID").map(|s| s.to_string());
+
+                let compressed = attributes
+                    .get("Compressed")
+                    .map(|v| v.to_lowercase().parse())
+                    .unwrap_or(Ok(false))?;
+
+                let protected = attributes
+                    .get("Protected")
+                    .map(|v| v.to_lowercase().parse())
+                    .unwrap_or(Ok(false))?;
+
+                // standalone XML documents mark protected binaries, but store them unencrypted
+                let protect_in_memory = attributes
+                    .get("ProtectInMemory")
+                    .map(|v| v.to_lowercase().parse())
+                    .unwrap_or(Ok(false))?;
+
+                (identifier, compressed, protected, protect_in_memory)
+            } else {
                 return Err(bad_event("Open Binary tag", open_tag));
-            }
-
-            let identifier = attributes.get("ID").map(|s| s.to_string());
-
-            let compressed = attributes
-                .get("Compressed")
-                .map(|v| v.to_lowercase().parse())
-                .unwrap_or(Ok(false))?;
+            };
 
-            (identifier, compressed)
+        // protected binaries of standalone documents are empty if they were redacted on export
+        let data = if protect_in_memory {
+            Option::<String>::from_xml(iterator, inner_cipher)?.unwrap_or_default()
         } else {
-            return Err(bad_event("Open Binary tag", open_tag));
+            String::from_xml(iterator, inner_cipher)?
         };
-
-        let data = String::from_xml(iterator, inner_cipher)?;
         let buf = base64_engine::STANDARD.decode(&data)?;
+        let buf = if protected {
+            inner_cipher.decrypt(&buf)?
+        } else {
+            buf
+        };
 
         out.identifier = identifier;
         out.compressed = compressed;
+        out.protected = protected || protect_in_memory;
         out.content = if compressed {
             Compression::decompress(&GZipCompression, &buf).map_err(XmlParseError::Compression)?
         } else {
@@ -2,39 +2,30 @@ mod entry;
 mod group;
 mod meta;
 
-use std::{collections::HashMap, iter::Peekable};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::BufRead,
+    iter::Peekable,
+};
 
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use chrono::NaiveDateTime;
+use quick_xml::{errors::IllFormedError, events::Event, Reader};
 use uuid::Uuid;
-use xml::{name::OwnedName, reader::XmlEvent, EventReader};
 
 use crate::{
     crypt::ciphers::Cipher,
     db::{
         Color, CustomData, CustomDataItem, CustomDataItemDenormalized, DeletedObject, DeletedObjects, Group,
-        Meta, Times, Value,
+        Meta, Times, Timestamp, Value, XmlElement, XmlNode,
     },
     error::XmlParseError,
-    xml_db::get_epoch_baseline,
 };
 
-/// Parse a KeePass timestamp string
+/// Parse a KeePass timestamp string in either of the formats, see [`Timestamp::parse`]
 pub fn parse_xml_timestamp(t: &str) -> Result<chrono::NaiveDateTime, XmlParseError> {
-    match chrono::NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%SZ") {
-        // Prior to KDBX4 file format, timestamps were stored as ISO 8601 strings
-        Ok(ndt) => Ok(ndt),
-        // If we don't have a valid ISO 8601 string, assume we have found a Base64 encoded int.
-        _ => {
-            let v = base64_engine::STANDARD.decode(t)?;
-
-            // Cast the decoded base64 Vec into the array expected by i64::from_le_bytes
-            let mut a: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
-            a.copy_from_slice(&v[0..8]);
-            let ndt = get_epoch_baseline() + chrono::Duration::seconds(i64::from_le_bytes(a));
-            Ok(ndt)
-        }
-    }
+    Timestamp::parse(t).map(NaiveDateTime::from)
 }
 
 /// Trait that denotes that a KeePass object can be parsed from a stream of `SimpleXmlEvent`.
@@ -51,60 +42,244 @@ pub(crate) trait FromXml {
     ) -> Result<Self::Parses, XmlParseError>;
 }
 
-/// Helper type to flatten out the Result<XmlEvent> types returned by the EventReader, since many
-/// of the parsers need to do a lot of destructuring
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Helper type to flatten out the events returned by the XML reader, since many of the parsers
+/// need to do a lot of destructuring
+#[derive(Debug, Clone)]
 #[must_use]
 pub enum SimpleXmlEvent {
     Start(String, HashMap<String, String>),
     End(String),
     Characters(String),
-    Err(xml::reader::Error),
+    Err(quick_xml::Error),
 }
 
 pub(crate) fn bad_event(expected: &'static str, event: SimpleXmlEvent) -> XmlParseError {
     XmlParseError::BadEvent { expected, event }
 }
 
+#[cfg(test)]
 pub(crate) fn parse(xml: &[u8], inner_cipher: &mut dyn Cipher) -> Result<KeePassXml, XmlParseError> {
     parse_from_bytes::<KeePassXml>(xml, inner_cipher)
 }
 
+/// Parse the XML document of a database while reading it from `xml`
+///
+/// The reader is buffered here, since the XML parser reads the document one byte at a time.
+pub(crate) fn parse_reader<R: std::io::Read>(
+    xml: R,
+    inner_cipher: &mut dyn Cipher,
+) -> Result<KeePassXml, XmlParseError> {
+    parse_from_reader::<KeePassXml, _>(std::io::BufReader::new(xml), inner_cipher)
+}
+
+#[cfg(test)]
 pub(crate) fn parse_from_bytes<P: FromXml>(
     xml: &[u8],
     inner_cipher: &mut dyn Cipher,
 ) -> Result<<P as FromXml>::Parses, XmlParseError> {
-    let mut reader = EventReader::new(xml)
-        .into_iter()
-        .filter_map(|e| {
-            // simplify iterator by ignoring unneeded events and flattening the structure
-            match e {
-                Ok(XmlEvent::StartElement {
-                    name: OwnedName { local_name, .. },
-                    attributes,
-                    ..
-                }) => Some(SimpleXmlEvent::Start(
-                    local_name,
-                    attributes
-                        .into_iter()
-                        .map(|a| (a.name.local_name, a.value))
-                        .collect(),
-                )),
-                Ok(XmlEvent::EndElement {
-                    name: OwnedName { local_name, .. },
-                }) => Some(SimpleXmlEvent::End(local_name)),
-                Ok(XmlEvent::Characters(c)) => Some(SimpleXmlEvent::Characters(c)),
-                Err(e) => Some(SimpleXmlEvent::Err(e.into())),
-
-                // ignore whitespace, comments, ...
-                _ => None,
-            }
-        })
-        .peekable();
+    parse_from_reader::<P, &[u8]>(xml, inner_cipher)
+}
+
+/// Elements that were skipped by [`IgnoreSubfield`] on the current thread
+#[derive(Default)]
+struct IgnoredElements {
+    /// Whether ignored elements are counted, see [`count_ignored_elements`]
+    counting: bool,
+
+    /// Path of the element that was read last
+    path: Vec<String>,
+
+    counts: BTreeMap<String, usize>,
+}
+
+thread_local! {
+    static IGNORED_ELEMENTS: RefCell<IgnoredElements> = RefCell::new(IgnoredElements::default());
+}
+
+/// Run `f` and count the elements that are ignored while parsing XML documents on the current
+/// thread, by their path
+pub(crate) fn count_ignored_elements<T>(f: impl FnOnce() -> T) -> (T, BTreeMap<String, usize>) {
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IGNORED_ELEMENTS.with(|ignored| *ignored.borrow_mut() = IgnoredElements::default());
+        }
+    }
+
+    IGNORED_ELEMENTS.with(|ignored| ignored.borrow_mut().counting = true);
+    let _reset = Reset;
+    let result = f();
+    let counts = IGNORED_ELEMENTS.with(|ignored| std::mem::take(&mut ignored.borrow_mut().counts));
+    (result, counts)
+}
+
+/// Log and count an element that is skipped, whose start tag was just read
+fn record_ignored_element() {
+    IGNORED_ELEMENTS.with(|ignored| {
+        let mut ignored = ignored.borrow_mut();
+        if !cfg!(feature = "tracing") && !ignored.counting {
+            return;
+        }
+        let path = ignored.path.join("/");
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(path = %path, "ignoring unknown XML element");
+
+        if ignored.counting {
+            *ignored.counts.entry(path).or_default() += 1;
+        }
+    });
+}
+
+fn parse_from_reader<P: FromXml, R: BufRead>(
+    xml: R,
+    inner_cipher: &mut dyn Cipher,
+) -> Result<<P as FromXml>::Parses, XmlParseError> {
+    // the path of the current element is only needed to report ignored elements
+    let track_path = cfg!(feature = "tracing") || IGNORED_ELEMENTS.with(|ignored| ignored.borrow().counting);
+    IGNORED_ELEMENTS.with(|ignored| ignored.borrow_mut().path.clear());
+
+    let mut reader = SimpleXmlEvents::new(xml, track_path).peekable();
 
     P::from_xml(&mut reader, inner_cipher)
 }
 
+/// Iterator that reads an XML document with quick-xml and simplifies its events into
+/// [`SimpleXmlEvent`]s, ignoring comments, processing instructions and whitespace between tags.
+struct SimpleXmlEvents<R> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+
+    /// Events that were read, but not yet returned
+    pending: VecDeque<SimpleXmlEvent>,
+
+    /// Names of the elements that were opened, but not closed yet
+    open_elements: Vec<String>,
+    seen_root: bool,
+
+    track_path: bool,
+    done: bool,
+}
+
+impl<R: BufRead> SimpleXmlEvents<R> {
+    fn new(xml: R, track_path: bool) -> Self {
+        let mut reader = Reader::from_reader(xml);
+        reader.config_mut().expand_empty_elements = true;
+
+        SimpleXmlEvents {
+            reader,
+            buf: Vec::new(),
+            pending: VecDeque::new(),
+            open_elements: Vec::new(),
+            seen_root: false,
+            track_path,
+            done: false,
+        }
+    }
+
+    /// Read from the document until the next start tag, end tag or the end of the document,
+    /// collecting the text before it into a single `Characters` event
+    fn read_events(&mut self) -> Result<(), quick_xml::Error> {
+        let mut text = String::new();
+        let mut whitespace_only = true;
+
+        let event = loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(e) => {
+                    let decoder = self.reader.decoder();
+                    let name = decoder.decode(e.local_name().as_ref())?.into_owned();
+                    let attributes = e
+                        .attributes()
+                        .map(|attribute| {
+                            let attribute = attribute?;
+                            let key = decoder.decode(attribute.key.local_name().as_ref())?.into_owned();
+                            Ok((key, attribute.unescape_value()?.into_owned()))
+                        })
+                        .collect::<Result<HashMap<_, _>, quick_xml::Error>>()?;
+
+                    if self.track_path {
+                        IGNORED_ELEMENTS.with(|ignored| ignored.borrow_mut().path.push(name.clone()));
+                    }
+                    self.open_elements.push(name.clone());
+                    self.seen_root = true;
+                    break Some(SimpleXmlEvent::Start(name, attributes));
+                }
+                Event::End(e) => {
+                    let name = self
+                        .reader
+                        .decoder()
+                        .decode(e.local_name().as_ref())?
+                        .into_owned();
+
+                    if self.track_path {
+                        IGNORED_ELEMENTS.with(|ignored| ignored.borrow_mut().path.pop());
+                    }
+                    self.open_elements.pop();
+                    break Some(SimpleXmlEvent::End(name));
+                }
+                Event::Text(e) => {
+                    // line breaks are normalized before references are resolved, as the XML
+                    // specification requires
+                    let raw = self.reader.decoder().decode(&e)?;
+                    let raw = raw.replace("\r\n", "\n").replace('\r', "\n");
+                    whitespace_only &= raw.chars().all(|c| matches!(c, ' ' | '\t' | '\n'));
+                    text.push_str(&quick_xml::escape::unescape(&raw)?);
+                }
+                Event::CData(e) => {
+                    whitespace_only = false;
+                    text.push_str(&e.decode()?);
+                }
+                Event::Eof => {
+                    // unlike the end tags themselves, their presence is not checked by quick-xml
+                    if let Some(name) = self.open_elements.pop() {
+                        return Err(IllFormedError::MissingEndTag(name).into());
+                    }
+                    if !self.seen_root {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            "XML document has no root element",
+                        )
+                        .into());
+                    }
+                    break None;
+                }
+
+                // ignore comments, processing instructions, ...
+                _ => {}
+            }
+        };
+
+        if !whitespace_only {
+            self.pending.push_back(SimpleXmlEvent::Characters(text));
+        }
+
+        match event {
+            Some(event) => self.pending.push_back(event),
+            None => self.done = true,
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for SimpleXmlEvents<R> {
+    type Item = SimpleXmlEvent;
+
+    fn next(&mut self) -> Option<SimpleXmlEvent> {
+        if self.pending.is_empty() && !self.done {
+            if let Err(e) = self.read_events() {
+                // the reader cannot continue after an error
+                self.done = true;
+                return Some(SimpleXmlEvent::Err(e));
+            }
+        }
+
+        self.pending.pop_front()
+    }
+}
+
 /// Helper trait for converting `SimpleXmlEvent::Characters` into types that can be parsed from
 /// strings.
 ///
@@ -500,6 +675,57 @@ impl FromXml for CustomDataItemDenormalized {
     }
 }
 
+impl FromXml for XmlElement {
+    type Parses = Self;
+
+    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
+        iterator: &mut Peekable<I>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<Self::Parses, XmlParseError> {
+        let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
+        let mut out = if let SimpleXmlEvent::Start(name, attributes) = open_tag {
+            let mut attributes: Vec<_> = attributes.into_iter().collect();
+            attributes.sort();
+
+            XmlElement {
+                name,
+                attributes,
+                children: Vec::new(),
+            }
+        } else {
+            return Err(bad_event("Open tag (to be preserved)", open_tag));
+        };
+
+        while let Some(event) = iterator.peek() {
+            match event {
+                SimpleXmlEvent::Start(_, _) => {
+                    let child = XmlElement::from_xml(iterator, inner_cipher)?;
+                    out.children.push(XmlNode::Element(child));
+                }
+                SimpleXmlEvent::Characters(_) => {
+                    if let Some(SimpleXmlEvent::Characters(text)) = iterator.next() {
+                        let text = if out.is_protected() {
+                            let buf = base64_engine::STANDARD.decode(&text)?;
+                            let buf_decrypted = inner_cipher.decrypt(&buf)?;
+                            String::from_utf8_lossy(&buf_decrypted).to_string()
+                        } else {
+                            text
+                        };
+                        out.children.push(XmlNode::Text(text));
+                    }
+                }
+                SimpleXmlEvent::End(_) => break,
+                SimpleXmlEvent::Err(e) => return Err(e.clone().into()),
+            }
+        }
+
+        // no need to check for the correct closing tag - checked by XmlReader
+        let _close_tag = iterator.next().ok_or(XmlParseError::Eof)?;
+
+        Ok(out)
+    }
+}
+
 /// A helper parser that will ignore everything in its tag.
 pub(crate) struct IgnoreSubfield;
 
@@ -512,6 +738,7 @@ impl FromXml for IgnoreSubfield {
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if let SimpleXmlEvent::Start(_, _) = open_tag {
+            record_ignored_element();
             let mut stack = Vec::new();
 
             while let Some(event) = iterator.next() {
@@ -538,7 +765,7 @@ impl FromXml for IgnoreSubfield {
 }
 
 #[cfg(test)]
-mod parse_test {
+pub(crate) mod parse_test {
     use crate::{
         config::InnerCipherConfig,
         crypt::ciphers::PlainCipher,
@@ -565,6 +792,26 @@ mod parse_test {
         Ok(())
     }
 
+    #[test]
+    fn test_text_content() -> Result<(), XmlParseError> {
+        let value = parse_test_xml::<SimpleTag<String>>("<Notes>a\r\nb\rc&#13;&amp;&lt;d&gt;</Notes>")?;
+        assert_eq!(value.value, "a\nb\nc\r&<d>");
+
+        let value = parse_test_xml::<SimpleTag<String>>("<Notes>a<!-- comment -->b<![CDATA[<c>]]></Notes>")?;
+        assert_eq!(value.value, "ab<c>");
+
+        let value = parse_test_xml::<SimpleTag<String>>("\u{feff}<?xml version=\"1.0\"?>\n<Notes>a</Notes>")?;
+        assert_eq!(value.value, "a");
+
+        let value = parse_test_xml::<SimpleTag<Option<String>>>("<Notes/>")?;
+        assert_eq!(value.value, None);
+
+        let value = parse_test_xml::<SimpleTag<Option<String>>>("<Notes>\n  </Notes>")?;
+        assert_eq!(value.value, None);
+
+        Ok(())
+    }
+
     #[test]
     fn test_simple_tag() -> Result<(), XmlParseError> {
         // String tag
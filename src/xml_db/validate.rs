@@ -0,0 +1,119 @@
+use crate::{
+    config::InnerCipherConfig,
+    db::{Database, Group, Node},
+    error::ValidationError,
+};
+
+/// Parse a generated XML document again and check that it describes the same database as `db`,
+/// so that a serializer bug is reported instead of writing a file that loses data
+pub(crate) fn validate(
+    db: &Database,
+    xml: &[u8],
+    inner_cipher_config: &InnerCipherConfig,
+    inner_random_stream_key: &[u8],
+) -> Result<(), ValidationError> {
+    let mut inner_cipher = inner_cipher_config
+        .get_cipher(inner_random_stream_key)
+        .map_err(|e| ValidationError::Reparse(e.into()))?;
+    let parsed = crate::xml_db::parse::parse_reader(xml, &mut *inner_cipher)?;
+
+    if parsed.meta != db.meta {
+        return Err(ValidationError::MetaMismatch);
+    }
+
+    if parsed.root.deleted_objects != db.deleted_objects {
+        return Err(ValidationError::DeletedObjectsMismatch);
+    }
+
+    match find_mismatch(&db.root, &parsed.root.group) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Find the first node of `expected` that differs in `actual`
+fn find_mismatch(expected: &Group, actual: &Group) -> Option<ValidationError> {
+    if expected == actual {
+        return None;
+    }
+
+    if expected.children.len() == actual.children.len() {
+        for (e, a) in expected.children.iter().zip(actual.children.iter()) {
+            match (e, a) {
+                (Node::Group(e), Node::Group(a)) => {
+                    if let Some(error) = find_mismatch(e, a) {
+                        return Some(error);
+                    }
+                }
+                (Node::Entry(e), Node::Entry(a)) if e == a => {}
+                (Node::Entry(e), _) => return Some(ValidationError::EntryMismatch { uuid: e.uuid }),
+                (Node::Group(_), Node::Entry(_)) => break,
+            }
+        }
+    }
+
+    Some(ValidationError::GroupMismatch { uuid: expected.uuid })
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use secstr::SecStr;
+
+    use super::validate;
+    use crate::{
+        config::InnerCipherConfig,
+        db::{Database, Entry, Group, Value},
+        error::ValidationError,
+    };
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut group = Group::new("Group");
+        let mut entry = Entry::new();
+        entry.fields.insert(
+            "Password".into(),
+            Value::Protected(SecStr::new(b"secret".to_vec())),
+        );
+        group.add_child(entry);
+        db.root.add_child(group);
+        db
+    }
+
+    fn dump(db: &Database) -> Vec<u8> {
+        let mut xml = Vec::new();
+        let mut cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+        crate::xml_db::dump::dump(db, &mut *cipher, &mut xml).unwrap();
+        xml
+    }
+
+    #[test]
+    fn detects_mismatches() {
+        let db = database();
+        let xml = dump(&db);
+        assert!(validate(&db, &xml, &InnerCipherConfig::Plain, &[]).is_ok());
+
+        // a protected value that was written without the Protected attribute
+        let xml = String::from_utf8(xml)
+            .unwrap()
+            .replace(" Protected=\"True\"", "")
+            .into_bytes();
+        let entry_uuid = db.root.groups()[0].entries()[0].uuid;
+        assert!(matches!(
+            validate(&db, &xml, &InnerCipherConfig::Plain, &[]),
+            Err(ValidationError::EntryMismatch { uuid }) if uuid == entry_uuid
+        ));
+
+        let mut renamed = db.clone();
+        renamed.root.groups_mut()[0].name = "Renamed".to_string();
+        let group_uuid = db.root.groups()[0].uuid;
+        assert!(matches!(
+            validate(&renamed, &dump(&db), &InnerCipherConfig::Plain, &[]),
+            Err(ValidationError::GroupMismatch { uuid }) if uuid == group_uuid
+        ));
+
+        assert!(matches!(
+            validate(&db, b"<KeePassFile>", &InnerCipherConfig::Plain, &[]),
+            Err(ValidationError::Reparse(_))
+        ));
+    }
+}
@@ -2,7 +2,7 @@ mod entry_tests {
     use keepass::{
         db::{Database, NodeRef},
         error::{DatabaseKeyError, DatabaseOpenError},
-        DatabaseKey,
+        DatabaseKey, Error, ErrorCode, ErrorKind,
     };
     use std::{fs::File, path::Path};
     use uuid::uuid;
@@ -104,6 +104,24 @@ mod entry_tests {
         Ok(())
     }
 
+    #[test]
+    fn kdbx4_entry_bad_password_kind() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_aes.kdbx");
+        let err: Error = Database::open(
+            &mut File::open(path)?,
+            DatabaseKey::new().with_password("this password is not correct"),
+        )
+        .unwrap_err()
+        .into();
+
+        assert_eq!(err.kind(), ErrorKind::WrongKey);
+        assert_eq!(err.kind().as_str(), "wrong_key");
+        assert_eq!(err.code(), ErrorCode::IncorrectKey);
+        assert_eq!(err.code().id(), "incorrect_key");
+
+        Ok(())
+    }
+
     #[test]
     fn databasekeyerror_into_databaseopenerror() -> Result<(), DatabaseOpenError> {
         let _: DatabaseOpenError = DatabaseKeyError::IncorrectKey.into();
@@ -2,7 +2,7 @@ mod file_read_tests {
     use keepass::{
         db::{Database, NodeRef},
         error::{DatabaseIntegrityError, DatabaseOpenError},
-        DatabaseKey,
+        DatabaseKey, ErrorCode, ErrorKind,
     };
     use uuid::uuid;
 
@@ -46,6 +46,20 @@ mod file_read_tests {
         Ok(())
     }
 
+    #[test]
+    fn open_kdbx3_drops_header_hash() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_with_password.kdbx");
+        let db = Database::open(
+            &mut File::open(path)?,
+            DatabaseKey::new().with_password("demopass"),
+        )?;
+
+        // the hash is stale after saving, so it must not be kept to be written back
+        assert!(!db.meta.unknown_elements.iter().any(|e| e.name == "HeaderHash"));
+
+        Ok(())
+    }
+
     #[test]
     fn open_kdbx3_with_keyfile() -> Result<(), DatabaseOpenError> {
         let path = Path::new("tests/resources/test_db_with_keyfile.kdbx");
@@ -280,6 +294,42 @@ mod file_read_tests {
         Ok(())
     }
 
+    #[test]
+    fn open_kdbx4_with_discovered_keyfile() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_keyfile_v2.kdbx");
+
+        let db = Database::open(
+            &mut File::open(path)?,
+            DatabaseKey::new()
+                .with_password("demopass")
+                .with_keyfile_for(path)?,
+        )?;
+
+        assert_eq!(db.root.name, "Root");
+        assert_eq!(db.root.children.len(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn open_kdbx4_mmap() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_aes.kdbx");
+        // Safety: the test resources are not modified while the tests run
+        let db = unsafe { Database::open_mmap(path, DatabaseKey::new().with_password("demopass"))? };
+
+        assert_eq!(db.root.name, "Root");
+        assert_eq!(
+            db,
+            Database::open(
+                &mut File::open(path)?,
+                DatabaseKey::new().with_password("demopass"),
+            )?
+        );
+
+        Ok(())
+    }
+
     #[test]
     #[should_panic(expected = r#"InvalidKDBXIdentifier"#)]
     fn open_broken_random_data() {
@@ -302,6 +352,113 @@ mod file_read_tests {
         .unwrap();
     }
 
+    #[test]
+    fn open_truncated_kdbx4() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_aes.kdbx");
+        let data = std::fs::read(path)?;
+        let truncated = &data[..data.len() - 100];
+
+        let err = Database::parse(truncated, DatabaseKey::new().with_password("demopass")).unwrap_err();
+        assert!(matches!(
+            err,
+            DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::Truncated { offset }) if offset == truncated.len()
+        ));
+        assert_eq!(err.kind(), ErrorKind::Corrupted);
+        assert_eq!(
+            err.code(),
+            ErrorCode::Truncated {
+                offset: truncated.len()
+            }
+        );
+        assert_eq!(err.code().id(), "truncated");
+        assert_eq!(err.code().params(), vec![("offset", truncated.len().to_string())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_truncated_headers_without_panic() -> Result<(), DatabaseOpenError> {
+        for file in [
+            "tests/resources/test_db_with_password.kdbx",
+            "tests/resources/test_db_kdbx4_with_password_aes.kdbx",
+            "tests/resources/test_db_kdb_with_password.kdb",
+        ] {
+            let data = std::fs::read(file)?;
+
+            // cut off the file within its headers, so that no key derivation is attempted
+            for len in 0..120 {
+                assert!(Database::parse(&data[..len], DatabaseKey::new().with_password("demopass")).is_err());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_malformed_kdbx4() -> Result<(), DatabaseOpenError> {
+        use std::convert::TryInto;
+
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_aes.kdbx");
+        let data = std::fs::read(path)?;
+
+        // find the end of the outer header, whose fields have a type byte and a 4-byte length
+        let mut header_end = 12;
+        loop {
+            let field_type = data[header_end];
+            let length = u32::from_le_bytes(data[header_end + 1..header_end + 5].try_into().unwrap());
+            header_end += 5 + length as usize;
+            if field_type == 0 {
+                break;
+            }
+        }
+
+        let open_flipped = |offset: usize| {
+            let mut data = data.clone();
+            data[offset] ^= 0xff;
+            Database::parse(&data, DatabaseKey::new().with_password("demopass")).unwrap_err()
+        };
+
+        assert_eq!(open_flipped(0).code(), ErrorCode::InvalidKdbxIdentifier);
+        assert_eq!(open_flipped(header_end).code(), ErrorCode::HeaderHashMismatch);
+        assert_eq!(open_flipped(header_end - 1).code(), ErrorCode::HeaderHashMismatch);
+        assert_eq!(open_flipped(header_end + 32).code(), ErrorCode::IncorrectKey);
+        assert_eq!(
+            open_flipped(header_end + 64).code(),
+            ErrorCode::BlockHashMismatch { block_index: 0 }
+        );
+        assert_eq!(
+            open_flipped(header_end + 100).code(),
+            ErrorCode::BlockHashMismatch { block_index: 0 }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_kdbx3_with_empty_transform_seed() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_with_password.kdbx");
+        let data = std::fs::read(path)?;
+
+        // remove the content of the transform seed, whose header field has a 2-byte length
+        let mut pos = 12;
+        let mut malformed = Vec::new();
+        while pos < data.len() {
+            let length = u16::from_le_bytes([data[pos + 1], data[pos + 2]]) as usize;
+            if data[pos] == 5 {
+                malformed.extend_from_slice(&data[..pos]);
+                malformed.extend_from_slice(&[5, 0, 0]);
+                malformed.extend_from_slice(&data[pos + 3 + length..]);
+                break;
+            }
+            pos += 3 + length;
+        }
+
+        let err = Database::parse(&malformed, DatabaseKey::new().with_password("demopass")).unwrap_err();
+        assert_eq!(err.code().id(), "cryptography");
+
+        Ok(())
+    }
+
     #[test]
     fn open_kdb_with_password() -> Result<(), DatabaseOpenError> {
         let path = Path::new("tests/resources/test_db_kdb_with_password.kdb");
@@ -337,6 +494,94 @@ mod file_read_tests {
         Ok(())
     }
 
+    #[test]
+    fn open_kdb_stable_group_uuids() -> Result<(), DatabaseOpenError> {
+        let data = std::fs::read("tests/resources/test_db_kdb_with_password.kdb")?;
+        let open = || Database::parse(&data, DatabaseKey::new().with_password("foobar"));
+
+        // KDB groups have no UUIDs in the file, so they must be derived the same way every time
+        let db = open()?;
+        assert_eq!(db, open()?);
+
+        let general = match db.root.get(&["General"]) {
+            Some(NodeRef::Group(g)) => g,
+            _ => panic!("General group not found"),
+        };
+        assert_ne!(general.uuid, db.root.uuid);
+
+        #[cfg(feature = "_merge")]
+        {
+            let count_groups =
+                |db: &Database| db.root.iter().filter(|n| matches!(n, NodeRef::Group(_))).count();
+            let mut merged = db.clone();
+            merged.merge(&open()?).unwrap();
+            assert_eq!(count_groups(&merged), count_groups(&db));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_kdb_attributes() -> Result<(), DatabaseOpenError> {
+        let data = std::fs::read("tests/resources/test_db_kdb_with_password.kdb")?;
+        let options = keepass::config::OpenOptions {
+            kdb_backup_as_recycle_bin: true,
+            ..Default::default()
+        };
+        let db = Database::parse_with_options(&data, DatabaseKey::new().with_password("foobar"), &options)?;
+
+        let general = match db.root.get(&["General"]) {
+            Some(NodeRef::Group(g)) => g,
+            _ => panic!("General group not found"),
+        };
+        assert_eq!(general.icon_id, Some(48));
+        assert!(general.is_expanded);
+        assert!(!general.times.expires);
+        assert_eq!(
+            general.times.get_creation().map(|t| t.to_string()).as_deref(),
+            Some("2020-05-17 18:34:05")
+        );
+
+        let entry = db
+            .root
+            .iter()
+            .find_map(|node| match node {
+                NodeRef::Entry(e) if e.get_title() == Some("title") => Some(e),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(entry.uuid, uuid!("85aef00d-2056-6155-8ec8-7b89a8db7ee9"));
+        assert_eq!(entry.get_notes(), Some("notes"));
+        assert_eq!(entry.icon_id, Some(0));
+        assert!(entry.get_time("LastModificationTime").is_some());
+        assert!(entry.attachments.is_empty());
+
+        // the meta streams of KeePass 1.x keep their data as attachments
+        let attachments: Vec<_> = db
+            .root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Entry(e) => Some(e.attachments.clone()),
+                NodeRef::Group(_) => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(attachments.len(), 2);
+        assert!(attachments.iter().all(|a| a.name == "bin-stream"));
+        assert_eq!(db.meta.binaries.binaries.len(), 2);
+
+        let backup = match db.root.get(&["Backup"]) {
+            Some(NodeRef::Group(g)) => g,
+            _ => panic!("Backup group not found"),
+        };
+        assert_eq!(db.meta.recyclebin_uuid, Some(backup.uuid));
+
+        let db = Database::parse(&data, DatabaseKey::new().with_password("foobar"))?;
+        assert_eq!(db.meta.recyclebin_uuid, None);
+
+        Ok(())
+    }
+
     #[test]
     fn open_kdb_with_larger_than_1mb_file_does_not_crash() -> Result<(), DatabaseOpenError> {
         let path = Path::new("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx");
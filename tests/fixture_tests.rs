@@ -0,0 +1,31 @@
+#[cfg(feature = "testing")]
+mod fixture_tests {
+    use keepass::{
+        testing::{all_configs, assert_roundtrip, FixtureBuilder},
+        DatabaseKey,
+    };
+
+    #[test]
+    fn fixtures_roundtrip_with_all_configs() {
+        let key = DatabaseKey::new().with_password("fixture");
+
+        for config in all_configs() {
+            let db = FixtureBuilder::new(config)
+                .with_groups(2)
+                .with_entries(2)
+                .with_history(2)
+                .build();
+
+            assert_roundtrip(&db, key.clone());
+        }
+    }
+
+    #[test]
+    fn fixtures_are_deterministic() {
+        let config = all_configs().remove(0);
+        assert_eq!(
+            FixtureBuilder::new(config.clone()).with_history(1).build(),
+            FixtureBuilder::new(config).with_history(1).build()
+        );
+    }
+}
@@ -0,0 +1,210 @@
+#[cfg(feature = "testing")]
+mod interop_tests {
+    use std::{fs::File, path::Path};
+
+    use keepass::{
+        config::{DatabaseVersion, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+        db::Database,
+        testing::{all_configs, check_with_keepassxc, FixtureBuilder, KEEPASSXC_CLI_ENV},
+        DatabaseKey,
+    };
+
+    /// Databases created by other clients, see `tests/resources/generate_interop_fixtures.sh`
+    struct Fixture {
+        file: &'static str,
+        generator: &'static str,
+        outer_cipher: OuterCipherConfig,
+        inner_cipher: InnerCipherConfig,
+        kdf: &'static str,
+    }
+
+    const PASSWORD: &str = "demopass";
+
+    fn fixtures() -> Vec<Fixture> {
+        let fixture = |file, generator, outer_cipher, inner_cipher, kdf| Fixture {
+            file,
+            generator,
+            outer_cipher,
+            inner_cipher,
+            kdf,
+        };
+
+        vec![
+            fixture(
+                "test_db_with_password.kdbx",
+                "KeePass",
+                OuterCipherConfig::AES256,
+                InnerCipherConfig::Salsa20,
+                "aes",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_aes.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::AES256,
+                InnerCipherConfig::ChaCha20,
+                "aes",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_argon2.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::AES256,
+                InnerCipherConfig::ChaCha20,
+                "argon2d",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_argon2_twofish.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::Twofish,
+                InnerCipherConfig::ChaCha20,
+                "argon2d",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_argon2_chacha20.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::ChaCha20,
+                InnerCipherConfig::ChaCha20,
+                "argon2d",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_argon2id.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::AES256,
+                InnerCipherConfig::ChaCha20,
+                "argon2id",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_argon2id_twofish.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::Twofish,
+                InnerCipherConfig::ChaCha20,
+                "argon2id",
+            ),
+            fixture(
+                "test_db_kdbx4_with_password_argon2id_chacha20.kdbx",
+                "KeePassXC",
+                OuterCipherConfig::ChaCha20,
+                InnerCipherConfig::ChaCha20,
+                "argon2id",
+            ),
+        ]
+    }
+
+    fn kdf_name(kdf_config: &KdfConfig) -> &'static str {
+        match kdf_config {
+            KdfConfig::Aes { .. } => "aes",
+            KdfConfig::Argon2 { .. } => "argon2d",
+            KdfConfig::Argon2id { .. } => "argon2id",
+        }
+    }
+
+    /// Cheap settings for the same KDF, so that re-saving the fixtures stays fast
+    fn cheap_kdf(kdf_config: &KdfConfig) -> KdfConfig {
+        match kdf_config {
+            KdfConfig::Aes { .. } => KdfConfig::Aes { rounds: 10 },
+            KdfConfig::Argon2 { version, .. } => KdfConfig::Argon2 {
+                iterations: 1,
+                memory: 64 * 1024,
+                parallelism: 1,
+                version: *version,
+                secret: None,
+                associated_data: None,
+            },
+            KdfConfig::Argon2id { version, .. } => KdfConfig::Argon2id {
+                iterations: 1,
+                memory: 64 * 1024,
+                parallelism: 1,
+                version: *version,
+                secret: None,
+                associated_data: None,
+            },
+        }
+    }
+
+    #[test]
+    fn open_fixtures_of_other_clients() {
+        for fixture in fixtures() {
+            let path = Path::new("tests/resources").join(fixture.file);
+            let db = Database::open(
+                &mut File::open(&path).unwrap(),
+                DatabaseKey::new().with_password(PASSWORD),
+            )
+            .unwrap_or_else(|e| panic!("Cannot open {}: {}", fixture.file, e));
+
+            assert_eq!(
+                db.meta.generator.as_deref(),
+                Some(fixture.generator),
+                "{}",
+                fixture.file
+            );
+            assert_eq!(
+                db.config.outer_cipher_config, fixture.outer_cipher,
+                "{}",
+                fixture.file
+            );
+            assert_eq!(
+                db.config.inner_cipher_config, fixture.inner_cipher,
+                "{}",
+                fixture.file
+            );
+            assert_eq!(kdf_name(&db.config.kdf_config), fixture.kdf, "{}", fixture.file);
+            assert!(!db.root.children.is_empty(), "{}", fixture.file);
+        }
+    }
+
+    #[test]
+    fn resave_fixtures_of_other_clients() {
+        let key = || DatabaseKey::new().with_password(PASSWORD);
+
+        for fixture in fixtures() {
+            let path = Path::new("tests/resources").join(fixture.file);
+            let mut db = Database::open(&mut File::open(&path).unwrap(), key()).unwrap();
+
+            // KDBX3 databases can only be saved as KDBX4
+            if let DatabaseVersion::KDB3(_) = db.config.version {
+                db.upgrade_kdf(KdfConfig::Aes { rounds: 10 });
+            }
+            db.config.kdf_config = cheap_kdf(&db.config.kdf_config);
+
+            let mut buffer = Vec::new();
+            db.save(&mut buffer, key()).unwrap();
+            let reopened = Database::open(&mut buffer.as_slice(), key()).unwrap();
+            assert_eq!(reopened.root, db.root, "{}", fixture.file);
+            assert_eq!(reopened.meta, db.meta, "{}", fixture.file);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs keepassxc-cli, set KEEPASSXC_CLI and run with --ignored"]
+    fn keepassxc_accepts_saved_databases() {
+        assert!(
+            std::env::var_os(KEEPASSXC_CLI_ENV).is_some(),
+            "{} must be set to the path of keepassxc-cli",
+            KEEPASSXC_CLI_ENV
+        );
+
+        let dir = std::env::temp_dir().join(format!("keepass-interop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (i, config) in all_configs().into_iter().enumerate() {
+            let description = format!("{:?}", config);
+            let db = FixtureBuilder::new(config)
+                .with_entries(2)
+                .with_history(1)
+                .build();
+
+            let path = dir.join(format!("{}.kdbx", i));
+            db.save(
+                &mut File::create(&path).unwrap(),
+                DatabaseKey::new().with_password(PASSWORD),
+            )
+            .unwrap();
+
+            let result = check_with_keepassxc(&path, PASSWORD).expect("keepassxc-cli is configured");
+            if let Err(e) = result {
+                panic!("KeePassXC rejected a database saved with {}: {}", description, e)
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -26,13 +26,12 @@ mod large_file_roundtrip_tests {
             let mut entry = Entry::new();
             entry
                 .fields
-                .insert("Title".to_string(), Value::Unprotected(format!("Entry_{i}")));
-            entry.fields.insert(
-                "UserName".to_string(),
-                Value::Unprotected(format!("UserName_{i}")),
-            );
+                .insert("Title".into(), Value::Unprotected(format!("Entry_{i}")));
+            entry
+                .fields
+                .insert("UserName".into(), Value::Unprotected(format!("UserName_{i}")));
             entry.fields.insert(
-                "Password".to_string(),
+                "Password".into(),
                 Value::Protected(format!("Password_{i}").as_bytes().into()),
             );
             db.root.add_child(entry);
@@ -40,10 +39,12 @@ mod large_file_roundtrip_tests {
 
         // Define database key.
         let key = DatabaseKey::new().with_password(TEST_DATABASE_PASSWORD);
-        db.save(&mut File::create(TEST_DATABASE_FILE_NAME)?, key.clone())?;
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), TEST_DATABASE_FILE_NAME));
+        db.save(&mut File::create(&path)?, key.clone())?;
 
         // Read the database that was written in the previous block.
-        let db = Database::open(&mut File::open(TEST_DATABASE_FILE_NAME)?, key)?;
+        let db = Database::open(&mut File::open(&path)?, key)?;
+        std::fs::remove_file(&path)?;
         // Validate that the data is what we expect.
         let mut entry_counter = 0;
         for node in &db.root {
@@ -0,0 +1,55 @@
+#!/bin/sh
+# Generate databases with KeePassXC for the interop tests in tests/interop_tests.rs.
+#
+# keepassxc-cli can only create databases with its default settings (KDBX 4, AES-256, Argon2d,
+# ChaCha20 inner stream). The fixtures for the other outer ciphers and KDFs are created from this
+# database by changing the encryption settings in KeePassXC under Database > Database Security
+# > Encryption Settings and saving a copy named as listed below.
+#
+# The databases are written with new names to the given directory, and existing files are never
+# overwritten, since the tests check the contents of the committed fixtures. To add a fixture,
+# copy it to tests/resources and list it in the fixtures of tests/interop_tests.rs.
+#
+# Usage: generate_interop_fixtures.sh <output directory>
+
+set -eu
+
+if [ $# -ne 1 ]; then
+    echo "Usage: $0 <output directory>" >&2
+    exit 2
+fi
+
+CLI=${KEEPASSXC_CLI:-keepassxc-cli}
+OUT=$1
+PASSWORD=demopass
+
+create() {
+    db="$OUT/$1"
+    if [ -e "$db" ]; then
+        echo "$db already exists" >&2
+        exit 1
+    fi
+
+    printf '%s\n%s\n' "$PASSWORD" "$PASSWORD" | "$CLI" db-create --set-password "$db"
+    printf '%s\n' "$PASSWORD" | "$CLI" mkdir -q "$db" "General"
+    printf '%s\n%s\n' "$PASSWORD" "secret" \
+        | "$CLI" add -q -u "user" --url "https://example.com" -p "$db" "General/Sample Entry"
+    printf '%s\n%s\n' "$PASSWORD" "password2" | "$CLI" add -q -u "user2" -p "$db" "Sample Entry #2"
+}
+
+create keepassxc_kdbx4_argon2.kdbx
+
+cat <<MESSAGE
+Created $OUT/keepassxc_kdbx4_argon2.kdbx.
+
+Create the remaining fixtures with KeePassXC from a copy of it, with these encryption settings:
+
+  keepassxc_kdbx4_aes.kdbx               AES-256,  AES-KDF (KDBX 4)
+  keepassxc_kdbx4_argon2_twofish.kdbx    Twofish,  Argon2d
+  keepassxc_kdbx4_argon2_chacha20.kdbx   ChaCha20, Argon2d
+  keepassxc_kdbx4_argon2id.kdbx          AES-256,  Argon2id
+  keepassxc_kdbx4_argon2id_twofish.kdbx  Twofish,  Argon2id
+  keepassxc_kdbx4_argon2id_chacha20.kdbx ChaCha20, Argon2id
+
+The password of all fixtures is "$PASSWORD".
+MESSAGE